@@ -0,0 +1,141 @@
+//! Camera
+//!
+//! One camera looking down at the world at an angle, in one of two modes
+//! (Tab switches between them):
+//!
+//! - **Follow** keeps our own avatar in the middle of the screen
+//! - **Free** stays put until moved with the arrow keys
+//!
+//! The mouse wheel zooms in either mode.
+
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+
+use client::{Prediction, Smoothed, Typing};
+
+use crate::render::to_scene;
+
+/// Closest and furthest the camera gets, in world units
+const ZOOM_RANGE: (f32, f32) = (4.0, 80.0);
+
+/// Free camera speed, in world units per second at the default zoom
+const PAN_SPEED: f32 = 16.0;
+
+/// Direction from the point looked at to the camera: behind and above
+const VIEW_DIRECTION: Vec3 = Vec3::new(0.0, 0.8, 0.6);
+
+/// Camera modes and their controls
+pub struct FollowCameraPlugin;
+
+impl Plugin for FollowCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup).add_systems(
+            Update,
+            (
+                switch_mode.run_if(not(resource_exists::<Typing>)),
+                (follow, pan.run_if(not(resource_exists::<Typing>)), zoom),
+                aim,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// How the camera picks what to look at
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Centered on our own avatar
+    #[default]
+    Follow,
+    /// Moved by hand
+    Free,
+}
+
+/// The camera's mode and where it looks, in scene coordinates
+#[derive(Component, Debug)]
+pub struct ViewCamera {
+    /// Current mode
+    pub mode: CameraMode,
+    /// Point looked at
+    pub focus: Vec3,
+    /// Distance from the focus
+    pub distance: f32,
+}
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        Camera3d::default(),
+        ViewCamera {
+            mode: CameraMode::Follow,
+            focus: Vec3::ZERO,
+            distance: 20.0,
+        },
+    ));
+}
+
+fn switch_mode(keys: Res<ButtonInput<KeyCode>>, mut cameras: Query<&mut ViewCamera>) {
+    if !keys.just_pressed(KeyCode::Tab) {
+        return;
+    }
+    for mut camera in &mut cameras {
+        camera.mode = match camera.mode {
+            CameraMode::Follow => CameraMode::Free,
+            CameraMode::Free => CameraMode::Follow,
+        };
+        info!("Camera: {:?}", camera.mode);
+    }
+}
+
+/// Ease the focus toward our avatar
+fn follow(
+    time: Res<Time>,
+    avatars: Query<&Smoothed, With<Prediction>>,
+    mut cameras: Query<&mut ViewCamera>,
+) {
+    let Ok(avatar) = avatars.get_single() else {
+        return;
+    };
+    let target = to_scene(avatar.0);
+    // Frame-rate independent: the same share of the gap closes per second
+    let t = 1.0 - (-8.0 * time.delta_secs()).exp();
+    for mut camera in &mut cameras {
+        if camera.mode == CameraMode::Follow {
+            camera.focus = camera.focus.lerp(target, t);
+        }
+    }
+}
+
+fn pan(time: Res<Time>, keys: Res<ButtonInput<KeyCode>>, mut cameras: Query<&mut ViewCamera>) {
+    let axis = |negative: KeyCode, positive: KeyCode| {
+        keys.pressed(positive) as i8 as f32 - keys.pressed(negative) as i8 as f32
+    };
+    let direction = Vec3::new(
+        axis(KeyCode::ArrowLeft, KeyCode::ArrowRight),
+        0.0,
+        axis(KeyCode::ArrowUp, KeyCode::ArrowDown),
+    );
+    for mut camera in &mut cameras {
+        if camera.mode == CameraMode::Free {
+            let speed = PAN_SPEED * camera.distance / 20.0;
+            camera.focus += direction * speed * time.delta_secs();
+        }
+    }
+}
+
+fn zoom(mut wheel: EventReader<MouseWheel>, mut cameras: Query<&mut ViewCamera>) {
+    let scrolled: f32 = wheel.read().map(|event| event.y).sum();
+    if scrolled == 0.0 {
+        return;
+    }
+    for mut camera in &mut cameras {
+        camera.distance =
+            (camera.distance * 0.9f32.powf(scrolled)).clamp(ZOOM_RANGE.0, ZOOM_RANGE.1);
+    }
+}
+
+fn aim(mut cameras: Query<(&ViewCamera, &mut Transform)>) {
+    for (camera, mut transform) in &mut cameras {
+        let eye = camera.focus + VIEW_DIRECTION.normalize() * camera.distance;
+        *transform = Transform::from_translation(eye).looking_at(camera.focus, Vec3::Y);
+    }
+}
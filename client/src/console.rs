@@ -0,0 +1,214 @@
+//! Console Overlay
+//!
+//! A text console over the 3D view. It runs the same commands text
+//! clients type (`look`, `say`, `@create`, ...). Lines go to
+//! graphics-gateway as `ConsoleInput`, which hands them to world-state
+//! exactly like text-gateway does, and whatever comes back is shown
+//! here. (The standalone server has no commands and ignores them.)
+//!
+//! Enter opens the prompt and Enter again sends the line; Escape closes
+//! it without sending. While the prompt is open the keyboard types
+//! instead of moving the avatar (see `client::Typing`).
+
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::prelude::*;
+use lightyear::prelude::client::{ConnectionManager, MessageEvent};
+use shared::net::ReliableChannel;
+use shared::protocol::{ConsoleInput, ConsoleOutput};
+use std::collections::VecDeque;
+
+use client::{LocalInput, Typing};
+
+/// Lines of output kept
+pub const SCROLLBACK: usize = 200;
+
+/// Lines of output shown at once
+pub const VISIBLE_LINES: usize = 12;
+
+/// The console overlay and its controls
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .add_systems(Startup, setup)
+            .add_systems(Update, (type_line, receive_output, draw).chain());
+    }
+}
+
+/// What the console shows and the line being typed
+#[derive(Resource, Debug, Default)]
+pub struct Console {
+    /// Whether the prompt is open
+    pub open: bool,
+    /// The line typed so far
+    pub line: String,
+    /// Output, oldest first
+    scrollback: VecDeque<String>,
+}
+
+impl Console {
+    /// Add output, one scrollback line per line of text
+    pub fn push(&mut self, text: &str) {
+        self.scrollback.extend(text.lines().map(str::to_string));
+        while self.scrollback.len() > SCROLLBACK {
+            self.scrollback.pop_front();
+        }
+    }
+
+    /// Close the prompt, returning the typed line unless it is blank
+    ///
+    /// The line is echoed to the scrollback, like a terminal would.
+    pub fn submit(&mut self) -> Option<String> {
+        self.open = false;
+        let line = std::mem::take(&mut self.line).trim().to_string();
+        if line.is_empty() {
+            return None;
+        }
+        self.push(&format!("> {}", line));
+        Some(line)
+    }
+
+    /// The overlay text: the latest output, then the prompt if open
+    pub fn text(&self) -> String {
+        let skip = self.scrollback.len().saturating_sub(VISIBLE_LINES);
+        let mut lines: Vec<&str> = self
+            .scrollback
+            .iter()
+            .skip(skip)
+            .map(String::as_str)
+            .collect();
+        let prompt = format!("> {}_", self.line);
+        if self.open {
+            lines.push(&prompt);
+        }
+        lines.join("\n")
+    }
+}
+
+/// The overlay's text node
+#[derive(Component)]
+struct ConsoleText;
+
+fn setup(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(8.0),
+                bottom: Val::Px(8.0),
+                width: Val::Percent(50.0),
+                padding: UiRect::all(Val::Px(6.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::default(),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+                ConsoleText,
+            ));
+        });
+}
+
+/// Open, edit, send or close the prompt from keyboard events
+fn type_line(
+    mut commands: Commands,
+    mut keys: EventReader<KeyboardInput>,
+    mut console: ResMut<Console>,
+    mut input: ResMut<LocalInput>,
+    mut connection: ResMut<ConnectionManager>,
+) {
+    for key in keys.read() {
+        if !key.state.is_pressed() {
+            continue;
+        }
+        if !console.open {
+            if key.logical_key == Key::Enter {
+                console.open = true;
+                commands.insert_resource(Typing);
+                // Let go of whatever was held when the prompt opened
+                *input = LocalInput::default();
+            }
+            continue;
+        }
+        match &key.logical_key {
+            Key::Enter => {
+                if let Some(line) = console.submit() {
+                    let mut message = ConsoleInput(line);
+                    if let Err(e) = connection.send_message::<ReliableChannel, _>(&mut message) {
+                        warn!("Failed to send console line: {}", e);
+                    }
+                }
+                commands.remove_resource::<Typing>();
+            }
+            Key::Escape => {
+                console.open = false;
+                console.line.clear();
+                commands.remove_resource::<Typing>();
+            }
+            Key::Backspace => {
+                console.line.pop();
+            }
+            Key::Space => console.line.push(' '),
+            Key::Character(text) => {
+                console
+                    .line
+                    .extend(text.chars().filter(|c| !c.is_control()));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn receive_output(
+    mut output: EventReader<MessageEvent<ConsoleOutput>>,
+    mut console: ResMut<Console>,
+) {
+    for message in output.read() {
+        console.push(&message.message().0);
+    }
+}
+
+fn draw(console: Res<Console>, mut texts: Query<&mut Text, With<ConsoleText>>) {
+    if !console.is_changed() {
+        return;
+    }
+    for mut text in &mut texts {
+        text.0 = console.text();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_console_lines() {
+        let mut console = Console::default();
+        for i in 0..SCROLLBACK {
+            console.push(&format!("line {}", i));
+        }
+        console.push("two\nlines");
+        assert_eq!(console.scrollback.len(), SCROLLBACK);
+        assert_eq!(console.scrollback.front().unwrap(), "line 2");
+
+        console.open = true;
+        console.line = "  say hi ".to_string();
+        let text = console.text();
+        assert_eq!(text.lines().count(), VISIBLE_LINES + 1);
+        assert!(text.ends_with("two\nlines\n>   say hi _"));
+
+        assert_eq!(console.submit().as_deref(), Some("say hi"));
+        assert!(!console.open && console.line.is_empty());
+        assert!(console.text().ends_with("lines\n> say hi"));
+
+        console.open = true;
+        console.line = "   ".to_string();
+        assert_eq!(console.submit(), None);
+    }
+}
@@ -0,0 +1,303 @@
+//! Game Client
+//!
+//! Networking for the Bevy client: connects to the game server or
+//! graphics-gateway over Lightyear and receives the replicated world.
+//! [`NetClientPlugin`] doesn't draw anything, so it also runs headless
+//! (tests, bots).
+//!
+//! Entities the server replicates arrive with Lightyear's `Replicated`
+//! marker and the shared components (`ObjectId`, `ObjectName`,
+//! `Position`, ...). The one carrying this client's `PlayerId` is our own
+//! avatar.
+//!
+//! Our own avatar is predicted: each fixed tick the local input moves it
+//! at once and is sent to the server, and the server's replies are
+//! reconciled with the prediction (`shared::prediction`). Other avatars
+//! are interpolated between the states received. Either way the position
+//! to draw ends up in [`Smoothed`].
+//!
+//! graphics-gateway only serves logged-in players: insert a
+//! [`SessionToken`] and it is sent as a `Login` once connected. It sends
+//! positions in delta-compressed state snapshots rather than replicating
+//! them, so an entity from the gateway gets its `Position`, `Rotation`
+//! and `LastInput` from the latest snapshot, and each snapshot decoded is
+//! acknowledged.
+//!
+//! # Learning Note
+//! Connecting is asynchronous: `connect_client` only starts the netcode
+//! handshake, and the client is connected some frames later, once
+//! Lightyear has heard back from the server.
+
+use bevy::prelude::*;
+use lightyear::prelude::client::{self, ClientCommands};
+use lightyear::prelude::*;
+use std::net::SocketAddr;
+
+use shared::components::{ObjectId, ObjectName, Position, Rotation, Velocity};
+use shared::net::{self as net, InputChannel, ProtocolPlugin, ReliableChannel, SnapshotChannel};
+use shared::prediction::{Interpolator, MoveState, Predictor};
+use shared::protocol::{
+    ConsoleOutput, InputMessage, LastInput, Login, MoveInput, PlayerId, SnapshotAck, StateSnapshot,
+    INPUT_REDUNDANCY,
+};
+use shared::snapshot::DeltaDecoder;
+
+/// Networking for the game client
+pub struct NetClientPlugin {
+    /// Game server or graphics-gateway to connect to
+    pub server_addr: SocketAddr,
+    /// Netcode client id; must be unique among connected clients
+    pub client_id: u64,
+}
+
+impl Plugin for NetClientPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(client::ClientPlugins::new(net::client_config(
+            self.server_addr,
+            self.client_id,
+        )))
+        .add_plugins(ProtocolPlugin)
+        .insert_resource(LocalPlayer(PlayerId(self.client_id)))
+        .init_resource::<LocalInput>()
+        .init_resource::<Snapshots>()
+        .add_systems(Startup, connect)
+        .add_systems(
+            Update,
+            (
+                log_in.run_if(resource_exists::<SessionToken>),
+                log_spawns,
+                log_console,
+                read_keyboard.run_if(
+                    resource_exists::<ButtonInput<KeyCode>>.and(not(resource_exists::<Typing>)),
+                ),
+                receive_snapshots.before(track_avatars),
+                track_avatars,
+                (reconcile, interpolate).after(track_avatars),
+            ),
+        )
+        .add_systems(FixedUpdate, predict);
+    }
+}
+
+/// The `PlayerId` of this client's own avatar
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct LocalPlayer(pub PlayerId);
+
+/// Session token to log in to graphics-gateway with
+#[derive(Resource, Clone, Debug)]
+pub struct SessionToken(pub String);
+
+/// Movement the player is asking for right now
+///
+/// Set from the keyboard when there is one; headless clients (bots,
+/// tests) set it directly.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct LocalInput(pub MoveInput);
+
+/// Present while the player is typing into a text field, so keys don't
+/// also move the avatar
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct Typing;
+
+/// Where to draw an object: the predicted position for our own avatar, an
+/// interpolated one for everything else
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Smoothed(pub Vec3);
+
+/// Prediction state for our own avatar
+#[derive(Component)]
+pub struct Prediction(pub Predictor);
+
+/// Received positions for an object moved by someone else
+#[derive(Component, Default)]
+struct Interpolation(Interpolator);
+
+/// State snapshots received from graphics-gateway
+#[derive(Resource, Default)]
+struct Snapshots(DeltaDecoder);
+
+fn connect(mut commands: Commands) {
+    commands.connect_client();
+}
+
+fn log_in(
+    mut connects: EventReader<client::ConnectEvent>,
+    token: Res<SessionToken>,
+    mut connection: ResMut<client::ConnectionManager>,
+) {
+    for _ in connects.read() {
+        let mut login = Login(token.0.clone());
+        if let Err(e) = connection.send_message::<ReliableChannel, _>(&mut login) {
+            warn!("Failed to send login: {}", e);
+        }
+    }
+}
+
+fn log_spawns(
+    local: Res<LocalPlayer>,
+    spawned: Query<(&ObjectId, &ObjectName, Option<&PlayerId>), Added<Replicated>>,
+) {
+    for (id, name, player) in &spawned {
+        if player == Some(&local.0) {
+            info!("Our avatar is {} ({})", name.0, id);
+        } else {
+            info!("{} ({}) is here", name.0, id);
+        }
+    }
+}
+
+fn log_console(mut output: EventReader<client::MessageEvent<ConsoleOutput>>) {
+    for message in output.read() {
+        info!("{}", message.message().0);
+    }
+}
+
+fn read_keyboard(keys: Res<ButtonInput<KeyCode>>, mut input: ResMut<LocalInput>) {
+    let axis = |negative: KeyCode, positive: KeyCode| {
+        keys.pressed(positive) as i8 - keys.pressed(negative) as i8
+    };
+    input.0 = MoveInput {
+        x: axis(KeyCode::KeyA, KeyCode::KeyD),
+        y: axis(KeyCode::KeyS, KeyCode::KeyW),
+    };
+}
+
+/// What a snapshot may update on a replicated object
+type SnapshotTarget = (
+    Entity,
+    &'static ObjectId,
+    Option<&'static Position>,
+    Option<&'static Rotation>,
+    Option<&'static LastInput>,
+    Option<&'static PlayerId>,
+);
+
+/// Decode and acknowledge new snapshots, then give every replicated
+/// object in the latest one its state
+///
+/// Objects Lightyear hasn't spawned yet are picked up by a later
+/// snapshot; every snapshot holds everything visible.
+fn receive_snapshots(
+    mut commands: Commands,
+    mut messages: EventReader<client::MessageEvent<StateSnapshot>>,
+    mut snapshots: ResMut<Snapshots>,
+    mut connection: ResMut<client::ConnectionManager>,
+    local: Res<LocalPlayer>,
+    objects: Query<SnapshotTarget, With<Replicated>>,
+) {
+    let mut decoded = false;
+    for message in messages.read() {
+        match snapshots.0.decode(&message.message().0) {
+            Ok(snapshot) => {
+                let mut ack = SnapshotAck(snapshot.id);
+                if let Err(e) = connection.send_message::<SnapshotChannel, _>(&mut ack) {
+                    warn!("Failed to acknowledge snapshot: {}", e);
+                }
+                decoded = true;
+            }
+            Err(e) => debug!("Dropped snapshot: {}", e),
+        }
+    }
+    let Some(snapshot) = snapshots.0.latest().filter(|_| decoded) else {
+        return;
+    };
+    for (entity, id, position, rotation, last, player) in &objects {
+        let Some(state) = snapshot.objects.get(id) else {
+            continue;
+        };
+        // Only touch what changed, so change detection means something
+        let mut entity = commands.entity(entity);
+        if position.map(|position| position.0) != Some(state.position()) {
+            entity.insert(Position(state.position()));
+        }
+        if rotation.map(|rotation| rotation.0) != Some(state.rotation()) {
+            entity.insert(Rotation(state.rotation()));
+        }
+        if player == Some(&local.0) && last.map(|last| last.0) != Some(snapshot.last_input) {
+            entity.insert(LastInput(snapshot.last_input));
+        }
+    }
+}
+
+/// A replicated object's movement state and owner
+type Mover = (
+    Entity,
+    &'static Position,
+    Option<&'static Velocity>,
+    Option<&'static PlayerId>,
+);
+
+/// Start predicting our own avatar and interpolating everything else as
+/// soon as it has a position: replicated along with it by the standalone
+/// server, or from a later snapshot by graphics-gateway
+fn track_avatars(
+    mut commands: Commands,
+    local: Res<LocalPlayer>,
+    spawned: Query<Mover, (With<Replicated>, Added<Position>)>,
+) {
+    for (entity, position, velocity, player) in &spawned {
+        let mut entity = commands.entity(entity);
+        entity.insert(Smoothed(position.0));
+        if player == Some(&local.0) {
+            entity.insert(Prediction(Predictor::new(MoveState {
+                position: *position,
+                velocity: velocity.copied().unwrap_or_default(),
+            })));
+        } else {
+            entity.insert(Interpolation::default());
+        }
+    }
+}
+
+/// Move our avatar by the local input and send the last few inputs
+///
+/// Sending several inputs per message means a lost packet costs nothing
+/// as long as the next one arrives.
+fn predict(
+    input: Res<LocalInput>,
+    mut connection: ResMut<client::ConnectionManager>,
+    mut avatars: Query<(&mut Prediction, &mut Smoothed)>,
+) {
+    for (mut prediction, mut smoothed) in &mut avatars {
+        prediction.0.predict(input.0);
+        smoothed.0 = prediction.0.displayed();
+        let mut message = InputMessage(prediction.0.recent(INPUT_REDUNDANCY));
+        if let Err(e) = connection.send_message::<InputChannel, _>(&mut message) {
+            warn!("Failed to send input: {}", e);
+        }
+    }
+}
+
+/// Avatars with a new server state or input confirmation
+type ServerUpdate = Or<(Changed<Position>, Changed<LastInput>)>;
+
+/// Compare each server update of our avatar with what we predicted
+fn reconcile(
+    mut avatars: Query<(&Position, Option<&Velocity>, &LastInput, &mut Prediction), ServerUpdate>,
+) {
+    for (position, velocity, last, mut prediction) in &mut avatars {
+        let server = MoveState {
+            position: *position,
+            velocity: velocity.copied().unwrap_or_default(),
+        };
+        if prediction.0.reconcile(last.0, server) {
+            debug!("Prediction corrected at tick {}", last.0);
+        }
+    }
+}
+
+/// Draw other objects slightly in the past, between received positions
+fn interpolate(
+    time: Res<Time>,
+    mut objects: Query<(Ref<Position>, &mut Interpolation, &mut Smoothed)>,
+) {
+    let now = time.elapsed_secs_f64();
+    for (position, mut interpolation, mut smoothed) in &mut objects {
+        if position.is_changed() {
+            interpolation.0.push(now, position.0);
+        }
+        if let Some(sampled) = interpolation.0.sample(now) {
+            smoothed.0 = sampled;
+        }
+    }
+}
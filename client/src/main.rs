@@ -1,3 +1,67 @@
+//! Graphical game client
+//!
+//! Connects to `SERVER_ADDR` (default `127.0.0.1:5000`, the standalone
+//! server; point it at graphics-gateway in the full deployment) as
+//! `CLIENT_ID` (random by default). graphics-gateway also needs
+//! `SESSION_TOKEN`, a token for the player.
+//!
+//! What it shows:
+//! - every replicated object, as its model or a placeholder shape
+//!   ([`render`])
+//! - a camera following our own avatar, or moved freely ([`camera`])
+//! - a console overlay for typed commands ([`console`])
+//!
+//! WASD moves our own avatar, Tab switches camera mode, and Enter opens
+//! the console. Models are loaded from the `assets` folder next to the
+//! executable (Bevy's default asset path).
+
+use bevy::prelude::*;
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use client::{NetClientPlugin, SessionToken};
+use shared::protocol::DEFAULT_PORT;
+
+mod camera;
+mod console;
+mod render;
+
 fn main() {
-    println!("Hello, world!");
+    let server_addr = std::env::var("SERVER_ADDR")
+        .ok()
+        .and_then(|addr| addr.parse().ok())
+        .unwrap_or(SocketAddr::from(([127, 0, 0, 1], DEFAULT_PORT)));
+    let client_id = std::env::var("CLIENT_ID")
+        .ok()
+        .and_then(|id| id.parse().ok())
+        .unwrap_or_else(random_client_id);
+
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(Window {
+            title: "MUSH".to_string(),
+            ..default()
+        }),
+        ..default()
+    }))
+    .add_plugins(NetClientPlugin {
+        server_addr,
+        client_id,
+    })
+    .add_plugins((
+        render::ObjectViewPlugin,
+        camera::FollowCameraPlugin,
+        console::ConsolePlugin,
+    ));
+    if let Ok(token) = std::env::var("SESSION_TOKEN") {
+        app.insert_resource(SessionToken(token));
+    }
+    app.run();
+}
+
+/// Good enough to keep two local clients apart
+fn random_client_id() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(1, |elapsed| elapsed.as_nanos() as u64)
 }
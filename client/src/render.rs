@@ -0,0 +1,255 @@
+//! Object Rendering
+//!
+//! Draws every replicated object that has a position. An object with a
+//! `Model` is drawn as that glTF scene. Everything else gets a
+//! placeholder shape for its kind: a capsule for players, a box for
+//! things and a ring for exits. A model that fails to load falls back to
+//! the placeholder too. The room itself is a floor under everything.
+//!
+//! The world is laid out like a map: x east, y north, z up. Bevy's 3D
+//! scenes are y up, so positions and rotations go through
+//! [`to_scene`] and [`rotation_to_scene`] on their way to a `Transform`.
+//!
+//! # Learning Note
+//! `Mesh3d` and `SceneRoot` both require a `Transform`, so Bevy adds
+//! one when either is inserted. [`place_objects`] then only has to keep
+//! it in step with the smoothed position.
+
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_2;
+
+use client::{LocalPlayer, Smoothed};
+use shared::components::{Model, ObjectKind, Rotation};
+use shared::protocol::PlayerId;
+
+/// Side of the floor drawn under the room, in world units
+pub const FLOOR_SIZE: f32 = 64.0;
+
+/// Draws replicated objects and the room they are in
+pub struct ObjectViewPlugin;
+
+impl Plugin for ObjectViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AmbientLight {
+            color: Color::WHITE,
+            brightness: 300.0,
+        })
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                (
+                    show_objects,
+                    show_placeholders,
+                    fall_back_from_failed_models,
+                ),
+                place_objects,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// A point in the world, in Bevy's scene axes
+pub fn to_scene(position: Vec3) -> Vec3 {
+    world_to_scene() * position
+}
+
+/// A rotation in the world, in Bevy's scene axes
+pub fn rotation_to_scene(rotation: Quat) -> Quat {
+    world_to_scene() * rotation * world_to_scene().inverse()
+}
+
+/// Turns world z (up) into scene y and world y (north) into scene -z
+fn world_to_scene() -> Quat {
+    Quat::from_rotation_x(-FRAC_PI_2)
+}
+
+/// Meshes and materials for objects without a model
+#[derive(Resource)]
+struct Placeholders {
+    shapes: HashMap<ObjectKind, (Handle<Mesh>, Handle<StandardMaterial>)>,
+    /// Our own avatar stands out from the other players
+    local: Handle<StandardMaterial>,
+}
+
+impl Placeholders {
+    fn get(&self, kind: ObjectKind, local: bool) -> (Mesh3d, MeshMaterial3d<StandardMaterial>) {
+        let (mesh, material) = &self.shapes[&kind];
+        let material = if local { &self.local } else { material };
+        (Mesh3d(mesh.clone()), MeshMaterial3d(material.clone()))
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut material = |color: Color| {
+        materials.add(StandardMaterial {
+            base_color: color,
+            perceptual_roughness: 0.8,
+            ..default()
+        })
+    };
+    let shapes = HashMap::from([
+        (
+            ObjectKind::Room,
+            (
+                meshes.add(Plane3d::default().mesh().size(FLOOR_SIZE, FLOOR_SIZE)),
+                material(Color::srgb(0.3, 0.35, 0.3)),
+            ),
+        ),
+        (
+            ObjectKind::Exit,
+            (
+                meshes.add(Torus::new(0.6, 0.8)),
+                material(Color::srgb(0.3, 0.5, 0.9)),
+            ),
+        ),
+        (
+            ObjectKind::Thing,
+            (
+                meshes.add(Cuboid::from_length(0.8)),
+                material(Color::srgb(0.8, 0.6, 0.3)),
+            ),
+        ),
+        (
+            ObjectKind::Player,
+            (
+                meshes.add(Capsule3d::new(0.4, 1.0)),
+                material(Color::srgb(0.8, 0.8, 0.8)),
+            ),
+        ),
+    ]);
+    let placeholders = Placeholders {
+        shapes,
+        local: material(Color::srgb(0.3, 0.8, 0.3)),
+    };
+
+    // Rooms have no position; whichever we are in lies under the origin
+    commands.spawn(placeholders.get(ObjectKind::Room, false));
+    commands.spawn((
+        DirectionalLight {
+            shadows_enabled: true,
+            ..default()
+        },
+        Transform::from_xyz(8.0, 16.0, 4.0).looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+    commands.insert_resource(placeholders);
+}
+
+/// What decides how an object is drawn
+type Look = (
+    Entity,
+    Option<&'static ObjectKind>,
+    Option<&'static Model>,
+    Option<&'static PlayerId>,
+);
+
+/// Drawn objects that just got a position or a new model
+type Redrawn = (With<Smoothed>, Or<(Added<Smoothed>, Changed<Model>)>);
+
+/// Give objects that just got a position, or a new model, something to
+/// draw
+fn show_objects(
+    mut commands: Commands,
+    assets: Res<AssetServer>,
+    placeholders: Res<Placeholders>,
+    local: Res<LocalPlayer>,
+    objects: Query<Look, Redrawn>,
+) {
+    for (entity, kind, model, player) in &objects {
+        let mut entity = commands.entity(entity);
+        match model {
+            Some(model) => {
+                let scene = assets.load(GltfAssetLabel::Scene(0).from_asset(model.0.clone()));
+                entity
+                    .remove::<(Mesh3d, MeshMaterial3d<StandardMaterial>)>()
+                    .insert(SceneRoot(scene));
+            }
+            None => {
+                let kind = kind.copied().unwrap_or(ObjectKind::Thing);
+                entity.insert(placeholders.get(kind, player == Some(&local.0)));
+            }
+        }
+    }
+}
+
+/// Objects whose model was taken away go back to their placeholder
+fn show_placeholders(
+    mut commands: Commands,
+    mut removed: RemovedComponents<Model>,
+    placeholders: Res<Placeholders>,
+    local: Res<LocalPlayer>,
+    objects: Query<(Option<&ObjectKind>, Option<&PlayerId>), With<Smoothed>>,
+) {
+    for entity in removed.read() {
+        let Ok((kind, player)) = objects.get(entity) else {
+            continue;
+        };
+        let kind = kind.copied().unwrap_or(ObjectKind::Thing);
+        commands
+            .entity(entity)
+            .remove::<SceneRoot>()
+            .insert(placeholders.get(kind, player == Some(&local.0)));
+    }
+}
+
+/// An object drawn with a model, and what to draw instead
+type Modelled = (
+    Entity,
+    &'static SceneRoot,
+    &'static Model,
+    Option<&'static ObjectKind>,
+    Option<&'static PlayerId>,
+);
+
+/// Models that don't exist or can't be read are drawn as placeholders
+fn fall_back_from_failed_models(
+    mut commands: Commands,
+    assets: Res<AssetServer>,
+    placeholders: Res<Placeholders>,
+    local: Res<LocalPlayer>,
+    objects: Query<Modelled>,
+) {
+    for (entity, scene, model, kind, player) in &objects {
+        if let LoadState::Failed(e) = assets.load_state(&scene.0) {
+            warn!("Could not load model {}: {}", model.0, e);
+            let kind = kind.copied().unwrap_or(ObjectKind::Thing);
+            commands
+                .entity(entity)
+                .remove::<SceneRoot>()
+                .insert(placeholders.get(kind, player == Some(&local.0)));
+        }
+    }
+}
+
+/// Keep each drawn object at its smoothed position
+fn place_objects(mut objects: Query<(&Smoothed, Option<&Rotation>, &mut Transform)>) {
+    for (position, rotation, mut transform) in &mut objects {
+        transform.translation = to_scene(position.0);
+        transform.rotation = rotation_to_scene(rotation.map_or(Quat::IDENTITY, |r| r.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_world_axes_in_the_scene() {
+        // North is into the screen, up is up
+        assert!(to_scene(Vec3::Y).abs_diff_eq(Vec3::NEG_Z, 1e-6));
+        assert!(to_scene(Vec3::Z).abs_diff_eq(Vec3::Y, 1e-6));
+        assert!(to_scene(Vec3::X).abs_diff_eq(Vec3::X, 1e-6));
+
+        // Turning to face north turns about the scene's vertical axis
+        let turn = rotation_to_scene(Quat::from_rotation_z(FRAC_PI_2));
+        assert!((turn * Vec3::X).abs_diff_eq(Vec3::NEG_Z, 1e-6));
+        assert!((turn * Vec3::Y).abs_diff_eq(Vec3::Y, 1e-6));
+    }
+}
@@ -0,0 +1,178 @@
+//! Game Server
+//!
+//! Headless Bevy app that accepts Lightyear connections (netcode over
+//! UDP) and replicates the world to them. Every client that connects gets
+//! an avatar: a player object carrying the shared components, replicated
+//! to everyone and controlled by that client. Lightyear despawns it again
+//! when the client disconnects.
+//!
+//! Clients send their movement inputs numbered by tick. The server queues
+//! them per avatar, applies one per fixed tick with the same
+//! `shared::systems::apply_input` the client predicts with, and replicates
+//! the tick of the last input applied (`LastInput`) next to the position,
+//! so the client knows which of its predictions to compare against.
+//!
+//! This is the standalone server from before the services split; in the
+//! full deployment graphical clients connect to graphics-gateway instead.
+//! Both speak the same protocol (`shared::net`), so the client can talk to
+//! either.
+//!
+//! # Learning Note
+//! `Replicate` is a bundle: inserting it is what makes Lightyear start
+//! sending an entity's registered components, and removing it (or
+//! despawning the entity) sends a despawn to the clients that had it.
+
+use bevy::prelude::*;
+use lightyear::prelude::server::{self, ServerCommands};
+use lightyear::prelude::*;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+use shared::components::{ObjectId, ObjectKind, ObjectName, Position, Velocity};
+use shared::net::{self as net, ProtocolPlugin};
+use shared::protocol::{InputMessage, LastInput, PlayerId, TickInput};
+use shared::systems::apply_input;
+
+/// Where avatars appear
+const SPAWN_POINT: Vec3 = Vec3::ZERO;
+
+/// Networking and avatar spawning for the standalone server
+pub struct GameServerPlugin {
+    /// UDP address to listen on
+    pub addr: SocketAddr,
+}
+
+impl Plugin for GameServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(server::ServerPlugins::new(net::server_config(self.addr)))
+            .add_plugins(ProtocolPlugin)
+            .init_resource::<NextObjectId>()
+            .add_systems(Startup, start)
+            .add_systems(Update, (spawn_avatars, receive_inputs, log_disconnects))
+            .add_systems(FixedUpdate, apply_inputs);
+    }
+}
+
+/// Object ids for objects this server creates
+///
+/// The standalone server has no database, so ids only have to be unique
+/// while it runs.
+#[derive(Resource)]
+struct NextObjectId(u64);
+
+impl Default for NextObjectId {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+impl NextObjectId {
+    fn take(&mut self) -> ObjectId {
+        let id = ObjectId(self.0);
+        self.0 += 1;
+        id
+    }
+}
+
+/// Inputs received for an avatar but not applied yet, oldest first
+///
+/// Server-only: not registered with the protocol, so never replicated.
+#[derive(Component, Default)]
+struct PendingInputs(VecDeque<TickInput>);
+
+/// Inputs a client may be ahead of the server before older ones are
+/// dropped, so a flooding client can't grow the queue without bound
+const MAX_PENDING_INPUTS: usize = 32;
+
+fn start(mut commands: Commands) {
+    commands.start_server();
+}
+
+fn spawn_avatars(
+    mut commands: Commands,
+    mut connects: EventReader<server::ConnectEvent>,
+    mut ids: ResMut<NextObjectId>,
+) {
+    for connect in connects.read() {
+        let client_id = connect.client_id;
+        let object = ids.take();
+        info!(
+            "Client {:?} connected; spawning avatar {}",
+            client_id, object
+        );
+        commands.spawn((
+            object,
+            ObjectKind::Player,
+            ObjectName(format!("Guest{}", object.0)),
+            PlayerId(client_id.to_bits()),
+            Position(SPAWN_POINT),
+            Velocity::default(),
+            LastInput(0),
+            PendingInputs::default(),
+            server::Replicate {
+                controlled_by: server::ControlledBy {
+                    target: NetworkTarget::Single(client_id),
+                    ..default()
+                },
+                ..default()
+            },
+        ));
+    }
+}
+
+/// Queue each client's new inputs on its avatar
+///
+/// Inputs arrive more than once (every message repeats the last few), so
+/// only ticks newer than anything queued or applied are kept.
+fn receive_inputs(
+    mut messages: EventReader<server::MessageEvent<InputMessage>>,
+    mut avatars: Query<(&PlayerId, &LastInput, &mut PendingInputs)>,
+) {
+    for message in messages.read() {
+        let player = PlayerId(message.context().to_bits());
+        let Some((_, last, mut pending)) = avatars.iter_mut().find(|(id, ..)| **id == player)
+        else {
+            continue;
+        };
+        for input in &message.message().0 {
+            let newest = pending.0.back().map_or(last.0, |queued| queued.tick);
+            if input.tick > newest {
+                pending.0.push_back(*input);
+            }
+        }
+        while pending.0.len() > MAX_PENDING_INPUTS {
+            pending.0.pop_front();
+        }
+    }
+}
+
+/// Move every avatar by one queued input per tick
+///
+/// An avatar with nothing queued stands still; its `LastInput` stays put,
+/// which tells the client that no further input was applied.
+fn apply_inputs(
+    mut avatars: Query<(
+        &mut Position,
+        &mut Velocity,
+        &mut LastInput,
+        &mut PendingInputs,
+    )>,
+) {
+    for (mut position, mut velocity, mut last, mut pending) in &mut avatars {
+        match pending.0.pop_front() {
+            Some(input) => {
+                apply_input(&mut position, &mut velocity, input.input);
+                last.0 = input.tick;
+            }
+            // Only write when it changes, or it would be replicated again
+            None if velocity.0 != Vec3::ZERO => velocity.0 = Vec3::ZERO,
+            None => {}
+        }
+    }
+}
+
+fn log_disconnects(mut disconnects: EventReader<server::DisconnectEvent>) {
+    for disconnect in disconnects.read() {
+        info!("Client {:?} disconnected", disconnect.client_id);
+    }
+}
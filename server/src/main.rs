@@ -1,3 +1,35 @@
+//! Standalone game server
+//!
+//! Listens on `SERVER_ADDR` (default `0.0.0.0:5000`); see the library for
+//! what it does.
+
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use server::GameServerPlugin;
+use shared::physics::PHYSICS_TIMESTEP;
+use shared::protocol::DEFAULT_PORT;
+
 fn main() {
-    println!("Hello, world!");
+    let addr = std::env::var("SERVER_ADDR")
+        .ok()
+        .and_then(|addr| addr.parse().ok())
+        .unwrap_or(SocketAddr::from(([0, 0, 0, 0], DEFAULT_PORT)));
+
+    App::new()
+        // Lightyear tracks connection state with Bevy states, which the
+        // minimal (windowless) plugin set doesn't include
+        .add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f32(
+                PHYSICS_TIMESTEP,
+            ))),
+            StatesPlugin,
+            LogPlugin::default(),
+        ))
+        .add_plugins(GameServerPlugin { addr })
+        .run();
 }
@@ -0,0 +1,114 @@
+//! Replication Tests
+//!
+//! Runs the server and a headless client in one process over real UDP
+//! sockets on localhost, stepping both apps by hand.
+
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use lightyear::prelude::Replicated;
+use std::net::{SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use client::{LocalInput, NetClientPlugin, Prediction};
+use server::GameServerPlugin;
+use shared::components::{ObjectKind, ObjectName, Position};
+use shared::protocol::{LastInput, MoveInput, PlayerId};
+
+/// A localhost address with a UDP port nobody is using
+fn free_addr() -> SocketAddr {
+    UdpSocket::bind("127.0.0.1:0")
+        .and_then(|socket| socket.local_addr())
+        .unwrap()
+}
+
+/// A windowless app with `plugin`, ready to be stepped with `update`
+fn headless(plugin: impl Plugin) -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, StatesPlugin, plugin));
+    // `App::run` would do this; stepping by hand has to
+    app.finish();
+    app.cleanup();
+    app
+}
+
+/// Step both apps until `done` holds for the client's world
+fn run_until(server: &mut App, client: &mut App, mut done: impl FnMut(&mut World) -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done(client.world_mut()) {
+        assert!(
+            Instant::now() < deadline,
+            "timed out waiting for the client"
+        );
+        server.update();
+        client.update();
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn test_client_sees_its_avatar() {
+    let addr = free_addr();
+    let mut server = headless(GameServerPlugin { addr });
+    let mut client = headless(NetClientPlugin {
+        server_addr: addr,
+        client_id: 7,
+    });
+
+    run_until(&mut server, &mut client, |world| {
+        world
+            .query_filtered::<&PlayerId, With<Replicated>>()
+            .iter(world)
+            .any(|player| *player == PlayerId(7))
+    });
+
+    let world = client.world_mut();
+    let (kind, name, position) = world
+        .query_filtered::<(&ObjectKind, &ObjectName, &Position), With<PlayerId>>()
+        .single(world);
+    assert_eq!(*kind, ObjectKind::Player);
+    assert!(name.0.starts_with("Guest"), "{}", name.0);
+    assert_eq!(*position, Position(Vec3::ZERO));
+
+    // A second client sees the first one's avatar as well as its own
+    let mut other = headless(NetClientPlugin {
+        server_addr: addr,
+        client_id: 8,
+    });
+    run_until(&mut server, &mut other, |world| {
+        world
+            .query_filtered::<&PlayerId, With<Replicated>>()
+            .iter(world)
+            .count()
+            == 2
+    });
+}
+
+#[test]
+fn test_client_predicts_its_movement() {
+    let addr = free_addr();
+    let mut server = headless(GameServerPlugin { addr });
+    let mut client = headless(NetClientPlugin {
+        server_addr: addr,
+        client_id: 9,
+    });
+    client.insert_resource(LocalInput(MoveInput { x: 1, y: 0 }));
+
+    // Walk right until the server has applied a good number of inputs
+    run_until(&mut server, &mut client, |world| {
+        world
+            .query_filtered::<&LastInput, With<Prediction>>()
+            .iter(world)
+            .any(|last| last.0 > 30)
+    });
+
+    let world = client.world_mut();
+    let (position, last, prediction) = world
+        .query::<(&Position, &LastInput, &Prediction)>()
+        .single(world);
+    assert!(position.0.x > 0.0);
+    assert_eq!(position.0.y, 0.0);
+    // The prediction is ahead of the server, never behind it
+    assert!(prediction.0.tick() >= last.0);
+    assert!(prediction.0.state().position.0.x >= position.0.x);
+}
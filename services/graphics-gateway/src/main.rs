@@ -1,15 +1,45 @@
 //! Graphics Gateway - UDP graphical clients
+//!
+//! Terminates Lightyear (netcode over UDP) connections from graphical
+//! clients so world-state never handles client sockets. Clients log in
+//! with a session token; their inputs are forwarded to world-state as
+//! commands and world-state's change stream comes back to them as
+//! replicated entities.
+//!
+//! Configuration:
+//! - `GAME_ADDR`: UDP address for clients (default `0.0.0.0:5000`)
+//! - `WORLD_STATE_URL`: world-state's HTTP API (default `http://localhost:8080`)
+//! - `SESSION_SECRET`: secret session tokens are signed with; the
+//!   gateway refuses to start without it
+//! - `ALLOW_DEV_SESSIONS`: set to `1` to accept tokens signed with the
+//!   development secret when `SESSION_SECRET` is unset, for local runs
 
 use axum::{routing::get, Router};
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use shared::auth::DEV_SESSION_SECRET;
+use shared::physics::PHYSICS_TIMESTEP;
+use shared::protocol::DEFAULT_PORT;
 use std::net::SocketAddr;
-use tracing::info;
+use std::time::Duration;
+use tracing::{info, warn};
+
+mod relay;
+mod sessions;
+mod snapshots;
+mod visibility;
+mod world;
+
+use relay::{RelayPlugin, WorldLink};
+use world::WorldClient;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "graphics-gateway=debug,info".into()),
+                .unwrap_or_else(|_| "graphics_gateway=debug,info".into()),
         )
         .init();
 
@@ -17,6 +47,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     dotenvy::dotenv().ok();
 
+    let game_addr = std::env::var("GAME_ADDR")
+        .ok()
+        .and_then(|addr| addr.parse().ok())
+        .unwrap_or(SocketAddr::from(([0, 0, 0, 0], DEFAULT_PORT)));
+    let world_url =
+        std::env::var("WORLD_STATE_URL").unwrap_or_else(|_| "http://localhost:8080".to_string());
+    let allow_dev_sessions = std::env::var("ALLOW_DEV_SESSIONS").is_ok_and(|flag| flag == "1");
+    let secret = match std::env::var("SESSION_SECRET") {
+        Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+        _ if allow_dev_sessions => {
+            warn!("SESSION_SECRET is not set; accepting development tokens");
+            DEV_SESSION_SECRET.to_vec()
+        }
+        _ => {
+            return Err(
+                "SESSION_SECRET is not set; set ALLOW_DEV_SESSIONS=1 for development".into(),
+            )
+        }
+    };
+
     let health_app = Router::new()
         .route("/health", get(health_check))
         .route("/ready", get(readiness_check));
@@ -24,7 +74,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr = SocketAddr::from(([0, 0, 0, 0], 8082));
     info!("Health check server listening on {}", addr);
 
-    axum::serve(tokio::net::TcpListener::bind(addr).await?, health_app).await?;
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(
+            tokio::net::TcpListener::bind(addr).await.unwrap(),
+            health_app,
+        )
+        .await
+        {
+            warn!("Health check server error: {}", e);
+        }
+    });
+
+    let (outgoing, incoming, subscription, _link_tasks) =
+        world::spawn(WorldClient::new(&world_url));
+    info!(
+        "Relaying clients on {} to world-state at {}",
+        game_addr, world_url
+    );
+
+    // Bevy's schedule runner loops until the app exits, so it gets a
+    // thread of its own rather than a tokio worker
+    tokio::task::spawn_blocking(move || {
+        App::new()
+            // Logging is already set up through tracing, so no LogPlugin
+            .add_plugins((
+                MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f32(
+                    PHYSICS_TIMESTEP,
+                ))),
+                StatesPlugin,
+            ))
+            .insert_resource(WorldLink {
+                outgoing,
+                incoming,
+                subscription,
+            })
+            .add_plugins(RelayPlugin {
+                addr: game_addr,
+                secret,
+            })
+            .run();
+    })
+    .await?;
 
     Ok(())
 }
@@ -0,0 +1,396 @@
+//! Client Relay
+//!
+//! The Bevy side of the gateway. [`RelayPlugin`] terminates Lightyear
+//! connections and keeps a mirror of world-state's objects that it
+//! replicates to logged-in clients:
+//!
+//! 1. A client connects over netcode and sends a [`Login`] with its
+//!    session token. Until the token checks out it is sent nothing and
+//!    anything else it sends is ignored.
+//! 2. Its console lines become `Console` commands and its movement
+//!    inputs `Move` commands, one per fixed tick, batched to world-state.
+//! 3. Changes from world-state's stream spawn, update or despawn mirror
+//!    entities; Lightyear replicates each to the clients whose interest
+//!    covers it (see [`crate::visibility`]). The entity of a logged-in
+//!    player also gets the client's `PlayerId`.
+//! 4. Positions, and the last input world-state has confirmed, go out in
+//!    per-client state snapshots (see [`crate::snapshots`]), so the
+//!    client predicts exactly as it does against the standalone server.
+//!
+//! # Learning Note
+//! The mirror only ever changes because world-state said so. Even a
+//! client's own movement goes there and back before it is replicated,
+//! which is why the client predicts it meanwhile.
+
+use bevy::prelude::*;
+use lightyear::prelude::server::{self, ServerCommands};
+use lightyear::prelude::*;
+use shared::auth;
+use shared::components::{Model, ObjectId, ObjectKind, ObjectName};
+use shared::gateway::{
+    ChangeBatch, CommandReply, ObjectChange, ObjectState, Subscription, WorldCommand,
+};
+use shared::net::{self as net, ProtocolPlugin, ReliableChannel};
+use shared::protocol::{ConsoleInput, ConsoleOutput, InputMessage, Login, PlayerId};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, watch};
+
+use crate::sessions::Session;
+use crate::snapshots;
+use crate::visibility::{self, client_room, Interests};
+use crate::world::{Incoming, Outgoing, Route};
+
+/// Lightyear server relaying between clients and world-state
+pub struct RelayPlugin {
+    /// UDP address clients connect to
+    pub addr: SocketAddr,
+    /// Secret session tokens are signed with
+    pub secret: Vec<u8>,
+}
+
+impl Plugin for RelayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(server::ServerPlugins::new(net::server_config(self.addr)))
+            .add_plugins(ProtocolPlugin)
+            .insert_resource(SessionSecret(self.secret.clone()))
+            .init_resource::<Sessions>()
+            .init_resource::<Mirror>()
+            .init_resource::<Outbox>()
+            .init_resource::<Interests>()
+            .add_systems(Startup, start)
+            .add_systems(
+                Update,
+                (
+                    (
+                        connect,
+                        log_in,
+                        receive_console,
+                        receive_inputs,
+                        snapshots::receive_acks,
+                        disconnect,
+                    ),
+                    receive_world,
+                    visibility::update_interest,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                FixedUpdate,
+                (send_commands, snapshots::send_snapshots).chain(),
+            );
+    }
+}
+
+/// Channels to the world-state tasks (see [`crate::world::spawn`])
+#[derive(Resource)]
+pub struct WorldLink {
+    /// Command batches to post
+    pub outgoing: mpsc::UnboundedSender<Outgoing>,
+    /// Replies and changes coming back
+    pub incoming: mpsc::UnboundedReceiver<Incoming>,
+    /// The part of the world to follow
+    pub subscription: watch::Sender<Subscription>,
+}
+
+#[derive(Resource)]
+struct SessionSecret(Vec<u8>);
+
+/// Connected clients by netcode client id
+#[derive(Resource, Default)]
+pub(crate) struct Sessions(pub HashMap<ClientId, Session>);
+
+impl Sessions {
+    /// The client logged in as `player`, if any
+    fn client_of(&self, player: ObjectId) -> Option<ClientId> {
+        self.0
+            .iter()
+            .find(|(_, session)| session.player == Some(player))
+            .map(|(client, _)| *client)
+    }
+}
+
+/// Mirror entities by object id
+#[derive(Resource, Default)]
+pub(crate) struct Mirror(pub HashMap<ObjectId, Entity>);
+
+/// Commands waiting for the next batch
+#[derive(Resource, Default)]
+struct Outbox(Outgoing);
+
+fn start(mut commands: Commands) {
+    commands.start_server();
+}
+
+fn connect(mut connects: EventReader<server::ConnectEvent>, mut sessions: ResMut<Sessions>) {
+    for connect in connects.read() {
+        debug!(
+            "Client {:?} connected, waiting for its token",
+            connect.client_id
+        );
+        sessions.0.insert(connect.client_id, Session::default());
+    }
+}
+
+fn log_in(
+    mut commands: Commands,
+    mut logins: EventReader<server::MessageEvent<Login>>,
+    secret: Res<SessionSecret>,
+    mut sessions: ResMut<Sessions>,
+    mirror: Res<Mirror>,
+    mut rooms: ResMut<server::RoomManager>,
+    mut connection: ResMut<server::ConnectionManager>,
+) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    for login in logins.read() {
+        let client = *login.context();
+        let reply = match auth::verify(&secret.0, &login.message().0, now) {
+            Ok(player) if sessions.client_of(player).is_some() => {
+                format!("{} is already connected.", player)
+            }
+            Ok(player) => {
+                let Some(session) = sessions.0.get_mut(&client) else {
+                    continue;
+                };
+                if session.player.is_some() {
+                    continue;
+                }
+                info!("Client {:?} logged in as {}", client, player);
+                session.player = Some(player);
+                rooms.add_client(client, client_room(client));
+                if let Some(&entity) = mirror.0.get(&player) {
+                    commands.entity(entity).insert(controls(client));
+                }
+                format!("Logged in as {}.", player)
+            }
+            Err(e) => {
+                warn!("Client {:?} failed to log in: {}", client, e);
+                format!("Login failed: {}.", e)
+            }
+        };
+        send(&mut connection, client, reply);
+    }
+}
+
+fn receive_console(
+    mut lines: EventReader<server::MessageEvent<ConsoleInput>>,
+    sessions: Res<Sessions>,
+    mut outbox: ResMut<Outbox>,
+) {
+    for line in lines.read() {
+        let client = *line.context();
+        if let Some(actor) = sessions.0.get(&client).and_then(|session| session.player) {
+            outbox.0.push(
+                Route::Console(client.to_bits()),
+                WorldCommand::Console {
+                    actor,
+                    line: line.message().0.clone(),
+                },
+            );
+        }
+    }
+}
+
+fn receive_inputs(
+    mut messages: EventReader<server::MessageEvent<InputMessage>>,
+    mut sessions: ResMut<Sessions>,
+) {
+    for message in messages.read() {
+        if let Some(session) = sessions.0.get_mut(message.context()) {
+            if session.player.is_some() {
+                session.receive(&message.message().0);
+            }
+        }
+    }
+}
+
+fn disconnect(
+    mut commands: Commands,
+    mut disconnects: EventReader<server::DisconnectEvent>,
+    mut sessions: ResMut<Sessions>,
+    mirror: Res<Mirror>,
+    mut interests: ResMut<Interests>,
+) {
+    for disconnect in disconnects.read() {
+        let Some(session) = sessions.0.remove(&disconnect.client_id) else {
+            continue;
+        };
+        interests.0.unsubscribe(disconnect.client_id.to_bits());
+        info!("Client {:?} disconnected", disconnect.client_id);
+        if let Some(&entity) = session.player.and_then(|player| mirror.0.get(&player)) {
+            commands
+                .entity(entity)
+                .remove::<(PlayerId, server::ControlledBy)>();
+        }
+    }
+}
+
+/// Forward one movement input per logged-in client, plus any console
+/// lines, as one batch per tick
+fn send_commands(mut sessions: ResMut<Sessions>, mut outbox: ResMut<Outbox>, link: Res<WorldLink>) {
+    for (client, session) in sessions.0.iter_mut() {
+        let Some(actor) = session.player else {
+            continue;
+        };
+        if let Some(input) = session.next_input() {
+            outbox.0.push(
+                Route::Move(client.to_bits(), input.tick),
+                WorldCommand::Move {
+                    actor,
+                    input: input.input,
+                },
+            );
+        }
+    }
+    if !outbox.0.is_empty() && link.outgoing.send(std::mem::take(&mut outbox.0)).is_err() {
+        error!("World-state link is gone; dropping commands");
+    }
+}
+
+/// Apply what world-state sent back: console replies, confirmed inputs
+/// and changes to the mirror
+fn receive_world(
+    mut commands: Commands,
+    mut link: ResMut<WorldLink>,
+    mut sessions: ResMut<Sessions>,
+    mut mirror: ResMut<Mirror>,
+    mut interests: ResMut<Interests>,
+    mut connection: ResMut<server::ConnectionManager>,
+) {
+    while let Ok(incoming) = link.incoming.try_recv() {
+        match incoming {
+            Incoming::Replies(routes, replies) => {
+                for (route, reply) in routes.into_iter().zip(replies.replies) {
+                    match (route, reply) {
+                        (Route::Console(client), CommandReply::Output(text))
+                        | (Route::Console(client), CommandReply::Failed(text)) => {
+                            send(&mut connection, netcode_client(client), text);
+                        }
+                        (Route::Move(client, tick), _) => {
+                            if let Some(session) = sessions.0.get_mut(&netcode_client(client)) {
+                                session.forwarded(replies.seq, tick);
+                            }
+                        }
+                        (Route::Console(_), CommandReply::Done) => {}
+                    }
+                }
+            }
+            Incoming::Changes(batch) => {
+                apply_changes(
+                    &mut commands,
+                    &batch,
+                    &mut mirror,
+                    &sessions,
+                    &mut interests,
+                );
+                // Positions are now current up to `batch.seq`
+                for session in sessions.0.values_mut() {
+                    if let Some(tick) = session.confirmed(batch.seq) {
+                        session.last_input = tick;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn apply_changes(
+    commands: &mut Commands,
+    batch: &ChangeBatch,
+    mirror: &mut Mirror,
+    sessions: &Sessions,
+    interests: &mut Interests,
+) {
+    // A snapshot replaces the mirror, but objects in both stay put rather
+    // than being despawned and spawned again on every client
+    if batch.reset {
+        let kept: HashSet<ObjectId> = batch
+            .changes
+            .iter()
+            .filter_map(|change| match change {
+                ObjectChange::Upsert(state) => Some(state.id),
+                ObjectChange::Removed(_) => None,
+            })
+            .collect();
+        mirror.0.retain(|id, entity| {
+            let keep = kept.contains(id);
+            if !keep {
+                commands.entity(*entity).despawn();
+                interests.0.remove(*id);
+            }
+            keep
+        });
+    }
+    for change in &batch.changes {
+        match change {
+            ObjectChange::Upsert(state) => {
+                interests.0.upsert(state.id, state.location, state.position);
+                match mirror.0.get(&state.id) {
+                    Some(&entity) => {
+                        commands.entity(entity).insert(components(state));
+                    }
+                    None => {
+                        let entity = commands
+                            .spawn((
+                                components(state),
+                                server::Replicate {
+                                    relevance_mode: NetworkRelevanceMode::InterestManagement,
+                                    ..default()
+                                },
+                            ))
+                            .id();
+                        if let Some(client) = sessions.client_of(state.id) {
+                            commands.entity(entity).insert(controls(client));
+                        }
+                        mirror.0.insert(state.id, entity);
+                    }
+                }
+                let mut entity = commands.entity(mirror.0[&state.id]);
+                match &state.model {
+                    Some(model) => entity.insert(Model(model.clone())),
+                    None => entity.remove::<Model>(),
+                };
+            }
+            ObjectChange::Removed(id) => {
+                interests.0.remove(*id);
+                if let Some(entity) = mirror.0.remove(id) {
+                    commands.entity(entity).despawn();
+                }
+            }
+        }
+    }
+}
+
+/// The replicated components of a mirrored object; its position goes in
+/// snapshots instead
+fn components(state: &ObjectState) -> (ObjectId, ObjectKind, ObjectName) {
+    (state.id, state.kind, ObjectName(state.name.clone()))
+}
+
+/// What marks an object as the avatar `client` controls
+fn controls(client: ClientId) -> (PlayerId, server::ControlledBy) {
+    (
+        PlayerId(client.to_bits()),
+        server::ControlledBy {
+            target: NetworkTarget::Single(client),
+            ..default()
+        },
+    )
+}
+
+/// The client with netcode id `bits`, as kept in routes and interests
+///
+/// Clients only ever connect over netcode, so the id is all there is.
+pub(crate) fn netcode_client(bits: u64) -> ClientId {
+    ClientId::Netcode(bits)
+}
+
+fn send(connection: &mut server::ConnectionManager, client: ClientId, text: String) {
+    let mut message = ConsoleOutput(text);
+    if let Err(e) = connection.send_message::<ReliableChannel, _>(client, &mut message) {
+        warn!("Failed to send to client {:?}: {}", client, e);
+    }
+}
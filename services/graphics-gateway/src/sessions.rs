@@ -0,0 +1,125 @@
+//! Player Sessions
+//!
+//! What the gateway remembers about each connected client: which player
+//! it logged in as, the movement inputs it sent that haven't been
+//! forwarded yet, and which forwarded inputs world-state hasn't confirmed.
+//!
+//! Inputs go to world-state one per tick. Its reply to each batch says
+//! which change-stream sequence number includes the result, so once the
+//! gateway has applied changes up to that number it can tell the client
+//! the input was applied ([`Session::confirmed`]), which is what the
+//! client reconciles its prediction against.
+//!
+//! Each session also carries the client's snapshot stream: the confirmed
+//! tick goes out in the next state snapshot, encoded against whatever the
+//! client last acknowledged (see `shared::snapshot`).
+
+use shared::components::ObjectId;
+use shared::protocol::TickInput;
+use shared::snapshot::DeltaEncoder;
+use std::collections::VecDeque;
+
+/// Inputs a client may be ahead of the gateway before older ones are
+/// dropped, so a flooding client can't grow the queue without bound
+pub const MAX_PENDING_INPUTS: usize = 32;
+
+/// One connected client
+#[derive(Debug, Default)]
+pub struct Session {
+    /// The player it logged in as; `None` until a valid token arrives
+    pub player: Option<ObjectId>,
+    /// Received inputs not forwarded yet, oldest first
+    pending: VecDeque<TickInput>,
+    /// Newest tick received, so repeated inputs are only queued once
+    newest: u32,
+    /// Forwarded inputs as (sequence number, tick), oldest first
+    unconfirmed: VecDeque<(u64, u32)>,
+    /// Newest tick confirmed, sent with every snapshot
+    pub last_input: u32,
+    /// Snapshots sent to this client and its acknowledged baseline
+    pub snapshots: DeltaEncoder,
+}
+
+impl Session {
+    /// Queue the inputs in a client message that are newer than any
+    /// received before
+    pub fn receive(&mut self, inputs: &[TickInput]) {
+        for input in inputs {
+            if input.tick > self.newest {
+                self.newest = input.tick;
+                self.pending.push_back(*input);
+            }
+        }
+        while self.pending.len() > MAX_PENDING_INPUTS {
+            self.pending.pop_front();
+        }
+    }
+
+    /// The next input to forward, if any
+    pub fn next_input(&mut self) -> Option<TickInput> {
+        self.pending.pop_front()
+    }
+
+    /// World-state applied the input for `tick`; its effects are in the
+    /// change stream up to `seq`
+    pub fn forwarded(&mut self, seq: u64, tick: u32) {
+        self.unconfirmed.push_back((seq, tick));
+    }
+
+    /// The newest tick whose effects are in changes up to `seq`, if that
+    /// confirms anything new
+    pub fn confirmed(&mut self, seq: u64) -> Option<u32> {
+        let mut tick = None;
+        while let Some(&(at, forwarded)) = self.unconfirmed.front() {
+            if at > seq {
+                break;
+            }
+            tick = Some(forwarded);
+            self.unconfirmed.pop_front();
+        }
+        tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::protocol::MoveInput;
+
+    fn inputs(ticks: std::ops::RangeInclusive<u32>) -> Vec<TickInput> {
+        ticks
+            .map(|tick| TickInput {
+                tick,
+                input: MoveInput { x: 1, y: 0 },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_inputs_are_forwarded_once_and_confirmed_in_order() {
+        let mut session = Session::default();
+        // Each message repeats the previous inputs
+        session.receive(&inputs(1..=3));
+        session.receive(&inputs(2..=5));
+        let forwarded: Vec<u32> = std::iter::from_fn(|| session.next_input())
+            .map(|input| input.tick)
+            .collect();
+        assert_eq!(forwarded, [1, 2, 3, 4, 5]);
+
+        session.forwarded(10, 1);
+        session.forwarded(11, 2);
+        session.forwarded(13, 3);
+        assert_eq!(session.confirmed(9), None);
+        assert_eq!(session.confirmed(12), Some(2));
+        assert_eq!(session.confirmed(12), None);
+        assert_eq!(session.confirmed(20), Some(3));
+    }
+
+    #[test]
+    fn test_pending_inputs_are_bounded() {
+        let mut session = Session::default();
+        session.receive(&inputs(1..=100));
+        assert_eq!(session.pending.len(), MAX_PENDING_INPUTS);
+        assert_eq!(session.next_input().unwrap().tick, 69);
+    }
+}
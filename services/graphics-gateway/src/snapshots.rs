@@ -0,0 +1,65 @@
+//! State Snapshots
+//!
+//! Positions don't go through Lightyear's replication. The mirror
+//! entities only carry what rarely changes (id, kind, name, who controls
+//! them). Once per fixed tick, each logged-in client gets a
+//! `StateSnapshot` instead. It holds the position of every object its
+//! interest covers, plus the last input world-state confirmed for its
+//! avatar. Each snapshot is delta-compressed against the last one the
+//! client acknowledged (see `shared::snapshot`).
+//!
+//! # Learning Note
+//! Replicating `Position` as a component sends every changed value in
+//! full, to every client, as its own update. A snapshot per client packs
+//! the whole view into one small packet, and it is cheapest exactly when
+//! little has moved.
+
+use bevy::prelude::*;
+use lightyear::prelude::server;
+use shared::net::SnapshotChannel;
+use shared::protocol::{SnapshotAck, StateSnapshot};
+use shared::snapshot::QuantizedState;
+
+use crate::relay::Sessions;
+use crate::visibility::Interests;
+
+/// Move each client's baseline up to the snapshots it has decoded
+pub(crate) fn receive_acks(
+    mut acks: EventReader<server::MessageEvent<SnapshotAck>>,
+    mut sessions: ResMut<Sessions>,
+) {
+    for ack in acks.read() {
+        if let Some(session) = sessions.0.get_mut(ack.context()) {
+            session.snapshots.ack(ack.message().0);
+        }
+    }
+}
+
+/// Send every logged-in client the state of what it sees
+///
+/// Rooms have no position and aren't included; world-state has no
+/// rotations yet, so every object faces the same way.
+pub(crate) fn send_snapshots(
+    mut sessions: ResMut<Sessions>,
+    interests: Res<Interests>,
+    mut connection: ResMut<server::ConnectionManager>,
+) {
+    for (client, session) in sessions.0.iter_mut() {
+        if session.player.is_none() {
+            continue;
+        }
+        let objects = interests
+            .0
+            .visible(client.to_bits())
+            .filter_map(|id| {
+                let (_, position) = interests.0.location(id)?;
+                Some((id, QuantizedState::new(position, Quat::IDENTITY)))
+            })
+            .collect();
+        let (_, bytes) = session.snapshots.encode(session.last_input, objects);
+        let mut message = StateSnapshot(bytes);
+        if let Err(e) = connection.send_message::<SnapshotChannel, _>(*client, &mut message) {
+            warn!("Failed to send snapshot to client {:?}: {}", client, e);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Client Visibility
+//!
+//! Decides what each logged-in client is sent, with the interest map from
+//! `shared::interest`: every client sees its own room within
+//! [`VIEW_RADIUS`] of its avatar.
+//!
+//! The same map drives both ends of the relay:
+//!
+//! - each client has a Lightyear room of its own, and objects entering or
+//!   leaving its interest are added to or removed from that room, which
+//!   is what makes Lightyear spawn or despawn them on the client
+//! - the gateway's [`Subscription`] to world-state covers the rooms its
+//!   clients are in plus their avatars, so changes in rooms nobody here
+//!   can see are never sent to the gateway at all
+//!
+//! # Learning Note
+//! Lightyear's `NetworkRelevanceMode::InterestManagement` only replicates
+//! an entity to clients that share a room with it. A room per client turns
+//! "which clients see this entity" into plain room membership.
+
+use bevy::prelude::*;
+use lightyear::prelude::server::{self, RoomId};
+use lightyear::prelude::*;
+use shared::gateway::Subscription;
+use shared::interest::{Interest, InterestMap, Visibility, VIEW_RADIUS};
+
+use crate::relay::{netcode_client, Mirror, Sessions, WorldLink};
+
+/// Who sees what, keyed by the client id's bits
+#[derive(Resource, Default)]
+pub(crate) struct Interests(pub InterestMap<u64>);
+
+/// The Lightyear room holding what `client` can see
+pub(crate) fn client_room(client: ClientId) -> RoomId {
+    RoomId(client.to_bits())
+}
+
+/// Follow each avatar with its client's interest, move objects in and
+/// out of client rooms, and keep the world-state subscription in step
+pub(crate) fn update_interest(
+    sessions: Res<Sessions>,
+    mirror: Res<Mirror>,
+    mut interests: ResMut<Interests>,
+    mut rooms: ResMut<server::RoomManager>,
+    link: Res<WorldLink>,
+) {
+    for (client, session) in &sessions.0 {
+        // An avatar not mirrored yet has no place to see from
+        let Some((room, center)) = session
+            .player
+            .and_then(|player| interests.0.location(player))
+        else {
+            continue;
+        };
+        interests.0.subscribe(
+            client.to_bits(),
+            Interest::Area {
+                room,
+                center,
+                radius: VIEW_RADIUS,
+            },
+        );
+    }
+
+    for (client, visibility) in interests.0.refresh() {
+        let room = client_room(netcode_client(client));
+        match visibility {
+            Visibility::Enter(id) => {
+                if let Some(&entity) = mirror.0.get(&id) {
+                    rooms.add_entity(entity, room);
+                }
+            }
+            Visibility::Leave(id) => {
+                if let Some(&entity) = mirror.0.get(&id) {
+                    rooms.remove_entity(entity, room);
+                }
+            }
+        }
+    }
+
+    let subscription = Subscription {
+        rooms: interests.0.rooms(),
+        objects: sessions
+            .0
+            .values()
+            .filter_map(|session| session.player)
+            .collect(),
+    };
+    link.subscription.send_if_modified(|current| {
+        let changed = *current != subscription;
+        *current = subscription;
+        changed
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::Session;
+    use bevy::ecs::system::RunSystemOnce;
+    use shared::components::ObjectId;
+    use tokio::sync::{mpsc, watch};
+
+    #[test]
+    fn test_interest_moves_objects_between_client_rooms() {
+        let hall = ObjectId(1);
+        let (alice, lamp, statue) = (ObjectId(10), ObjectId(11), ObjectId(12));
+        let far = VIEW_RADIUS * 3.0;
+        let client = netcode_client(7);
+
+        let mut world = World::new();
+        let mut mirror = Mirror::default();
+        let mut interests = Interests::default();
+        for (id, x) in [(alice, 0.0), (lamp, 5.0), (statue, far)] {
+            mirror.0.insert(id, world.spawn_empty().id());
+            interests
+                .0
+                .upsert(id, Some(hall), Some(Vec3::new(x, 0.0, 0.0)));
+        }
+        let mut sessions = Sessions::default();
+        let mut session = Session::default();
+        session.player = Some(alice);
+        sessions.0.insert(client, session);
+        let (outgoing, _) = mpsc::unbounded_channel();
+        let (_, incoming) = mpsc::unbounded_channel();
+        let (subscription, followed) = watch::channel(Subscription::default());
+        world.insert_resource(WorldLink {
+            outgoing,
+            incoming,
+            subscription,
+        });
+        world.insert_resource(sessions);
+        world.insert_resource(mirror);
+        world.insert_resource(interests);
+        world.init_resource::<server::RoomManager>();
+
+        let sees = |world: &World, id| {
+            let entity = world.resource::<Mirror>().0[&id];
+            world
+                .resource::<server::RoomManager>()
+                .has_entity(entity, client_room(client))
+        };
+        world.run_system_once(update_interest).unwrap();
+        assert!(sees(&world, lamp));
+        assert!(!sees(&world, statue));
+        assert_eq!(
+            *followed.borrow(),
+            Subscription {
+                rooms: [hall].into(),
+                objects: [alice].into(),
+            }
+        );
+
+        // The statue is carried into view and the lamp out of it
+        let mut interests = world.resource_mut::<Interests>();
+        interests.0.upsert(statue, Some(hall), Some(Vec3::X));
+        interests
+            .0
+            .upsert(lamp, Some(hall), Some(Vec3::new(far, 0.0, 0.0)));
+        world.run_system_once(update_interest).unwrap();
+        assert!(sees(&world, statue));
+        assert!(!sees(&world, lamp));
+    }
+}
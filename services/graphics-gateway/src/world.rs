@@ -0,0 +1,200 @@
+//! World-State Link
+//!
+//! Two background tasks connect the gateway's Bevy app to world-state
+//! (see `shared::gateway` for the protocol):
+//!
+//! - the command task posts each [`Outgoing`] batch to `/commands` and
+//!   hands the replies back with the batch's routes
+//! - the change task polls `/changes` every [`POLL_INTERVAL`] for the
+//!   current [`Subscription`] and hands back each non-empty batch; when
+//!   the subscription changes it starts over from a snapshot
+//!
+//! Both talk to the Bevy side over unbounded channels, which can be used
+//! from a system without blocking. When world-state is unreachable they
+//! log, wait and retry; the players simply see the world stand still.
+
+use shared::gateway::{ChangeBatch, CommandReplies, Subscription, WorldCommand};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// How often the change stream is polled
+pub const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long to wait for world-state before giving up on a request
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before retrying after world-state failed to answer
+pub const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Where the reply to a command should go
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Route {
+    /// Console output for a client
+    Console(u64),
+    /// Movement input `tick` of a client, to confirm later
+    Move(u64, u32),
+}
+
+/// Commands collected during one tick, with where each reply goes
+#[derive(Debug, Default)]
+pub struct Outgoing {
+    /// Reply destinations, one per command
+    pub routes: Vec<Route>,
+    /// Commands in the order they should run
+    pub commands: Vec<WorldCommand>,
+}
+
+impl Outgoing {
+    /// Add `command`, routing its reply to `route`
+    pub fn push(&mut self, route: Route, command: WorldCommand) {
+        self.routes.push(route);
+        self.commands.push(command);
+    }
+
+    /// True if there is nothing to send
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+/// Something world-state said
+#[derive(Debug)]
+pub enum Incoming {
+    /// Replies to an [`Outgoing`] batch, with its routes
+    Replies(Vec<Route>, CommandReplies),
+    /// New changes to mirror
+    Changes(ChangeBatch),
+}
+
+/// HTTP client for world-state's gateway API
+#[derive(Clone)]
+pub struct WorldClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl WorldClient {
+    /// Talk to world-state at `base_url`
+    pub fn new(base_url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    async fn commands(&self, commands: &[WorldCommand]) -> Result<CommandReplies, reqwest::Error> {
+        self.client
+            .post(format!("{}/commands", self.base_url))
+            .json(commands)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn changes(
+        &self,
+        after: u64,
+        subscription: &Subscription,
+    ) -> Result<ChangeBatch, reqwest::Error> {
+        self.client
+            .get(format!("{}/changes", self.base_url))
+            .query(&[("after", after)])
+            .query(&subscription.to_query())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
+
+/// Start both tasks, returning the Bevy side's ends of the channels
+///
+/// The subscription starts out empty: nothing is followed until a player
+/// logs in.
+pub fn spawn(
+    client: WorldClient,
+) -> (
+    mpsc::UnboundedSender<Outgoing>,
+    mpsc::UnboundedReceiver<Incoming>,
+    watch::Sender<Subscription>,
+    [JoinHandle<()>; 2],
+) {
+    let (outgoing_tx, outgoing_rx) = mpsc::unbounded_channel();
+    let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+    let (subscription_tx, subscription_rx) = watch::channel(Subscription::default());
+    let commands = tokio::spawn(send_commands(
+        client.clone(),
+        outgoing_rx,
+        incoming_tx.clone(),
+    ));
+    let changes = tokio::spawn(poll_changes(client, subscription_rx, incoming_tx));
+    (
+        outgoing_tx,
+        incoming_rx,
+        subscription_tx,
+        [commands, changes],
+    )
+}
+
+async fn send_commands(
+    client: WorldClient,
+    mut outgoing: mpsc::UnboundedReceiver<Outgoing>,
+    incoming: mpsc::UnboundedSender<Incoming>,
+) {
+    while let Some(batch) = outgoing.recv().await {
+        // Retrying could run a console command twice, so a failed batch
+        // is dropped; lost movement is corrected by reconciliation
+        match client.commands(&batch.commands).await {
+            Ok(replies) => {
+                if incoming
+                    .send(Incoming::Replies(batch.routes, replies))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            Err(e) => warn!("Dropped {} commands: {}", batch.commands.len(), e),
+        }
+    }
+}
+
+async fn poll_changes(
+    client: WorldClient,
+    mut subscription: watch::Receiver<Subscription>,
+    incoming: mpsc::UnboundedSender<Incoming>,
+) {
+    let mut seq = 0;
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        poll.tick().await;
+        // Rooms just followed have no history here; start from a snapshot
+        if subscription.has_changed().unwrap_or(false) {
+            seq = 0;
+        }
+        let current = subscription.borrow_and_update().clone();
+        match client.changes(seq, &current).await {
+            Ok(batch) => {
+                seq = batch.seq;
+                if (batch.reset || !batch.changes.is_empty())
+                    && incoming.send(Incoming::Changes(batch)).is_err()
+                {
+                    return;
+                }
+            }
+            Err(e) => {
+                warn!("Could not fetch world changes: {}", e);
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+}
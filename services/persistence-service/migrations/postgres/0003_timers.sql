@@ -0,0 +1,12 @@
+-- Pending script timers (`wait` / `schedule_at`). Rows are replaced
+-- whenever their object is written, and removed once they fire.
+-- Keep in sync with the other backend's migration.
+
+CREATE TABLE timers (
+    id BIGINT PRIMARY KEY,
+    object_id BIGINT NOT NULL REFERENCES objects (id) ON DELETE CASCADE,
+    function TEXT NOT NULL,
+    due_at BIGINT NOT NULL
+);
+
+CREATE INDEX timers_object ON timers (object_id);
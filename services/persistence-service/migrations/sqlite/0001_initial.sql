@@ -0,0 +1,46 @@
+-- World objects, attributes, scripts, accounts and characters.
+-- Object ids are assigned by world-state, so they are not generated here.
+-- Keep in sync with migrations/postgres; only id generation differs.
+
+CREATE TABLE objects (
+    id BIGINT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    owner BIGINT NOT NULL,
+    location BIGINT,
+    destination BIGINT,
+    flags BIGINT NOT NULL DEFAULT 0,
+    locks TEXT NOT NULL DEFAULT '{}',
+    version BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE INDEX objects_location ON objects (location);
+CREATE INDEX objects_owner ON objects (owner);
+
+CREATE TABLE attributes (
+    object_id BIGINT NOT NULL REFERENCES objects (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    owner BIGINT NOT NULL,
+    flags BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (object_id, name)
+);
+
+CREATE TABLE scripts (
+    object_id BIGINT PRIMARY KEY REFERENCES objects (id) ON DELETE CASCADE,
+    source TEXT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE TABLE accounts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE TABLE characters (
+    account_id BIGINT NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    object_id BIGINT NOT NULL REFERENCES objects (id) ON DELETE CASCADE,
+    PRIMARY KEY (account_id, object_id)
+);
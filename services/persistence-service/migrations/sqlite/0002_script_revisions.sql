@@ -0,0 +1,12 @@
+-- Append-only history of every script change.
+-- Keep in sync with the other backend's migration.
+
+CREATE TABLE script_revisions (
+    object_id BIGINT NOT NULL REFERENCES objects (id) ON DELETE CASCADE,
+    revision BIGINT NOT NULL,
+    source TEXT NOT NULL,
+    author BIGINT NOT NULL,
+    created_at BIGINT NOT NULL,
+    message TEXT NOT NULL DEFAULT '',
+    PRIMARY KEY (object_id, revision)
+);
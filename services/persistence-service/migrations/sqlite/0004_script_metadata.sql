@@ -0,0 +1,7 @@
+-- Who last set each script and its attribute flags, kept with the source
+-- so a restored SCRIPT attribute matches the one that was saved. Rows
+-- written before this have no owner; loaders use the object's owner.
+-- Keep in sync with the other backend's migration.
+
+ALTER TABLE scripts ADD COLUMN owner BIGINT;
+ALTER TABLE scripts ADD COLUMN flags BIGINT NOT NULL DEFAULT 0;
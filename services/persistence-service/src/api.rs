@@ -0,0 +1,284 @@
+//! HTTP API
+//!
+//! JSON endpoints used by world-state and auth-service:
+//!
+//! | Method | Path                         | Body / Response                  |
+//! |--------|------------------------------|----------------------------------|
+//! | GET    | `/objects/:id`               | `ObjectRecord` or 404            |
+//! | GET    | `/areas/:room`               | `[ObjectRecord]`                 |
+//! | POST   | `/objects/batch`             | `WriteBatch` -> `BatchResult`    |
+//! | POST   | `/accounts`                  | `NewAccount` -> `AccountRecord`  |
+//! | GET    | `/accounts/:username`        | `AccountRecord` or 404           |
+//! | GET    | `/accounts/:id/characters`   | `[ObjectId]`                     |
+//! | POST   | `/accounts/:id/characters`   | `ObjectId`                       |
+//! | GET    | `/dump`                      | world dump (JSON lines)          |
+//! | POST   | `/dump`                      | world dump -> `BatchResult`      |
+//! | POST   | `/import/flatfile`           | MUSH flatfile -> `FlatfileImport`|
+//!
+//! Importing a dump or flatfile replaces the whole world; see
+//! `shared::dump` and [`crate::flatfile`] for the formats. Pass
+//! `?dry_run=true` to `/import/flatfile` to get the report without
+//! changing anything.
+//!
+//! Because they replace everything, the two imports also need an
+//! `Authorization: Bearer <token>` header matching the service's admin
+//! token (see [`AdminToken`]). So do all the `/accounts` routes, since
+//! account records carry password hashes. Without a configured token
+//! these routes are refused outright rather than left open.
+
+use axum::extract::Query;
+use axum::extract::Request;
+use axum::extract::{DefaultBodyLimit, Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use shared::components::ObjectId;
+use shared::dump::{read_dump, write_dump};
+use shared::records::{AccountRecord, BatchResult, ObjectRecord, WriteBatch};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::flatfile::{self, ImportReport};
+use crate::store::{StoreError, WorldStore};
+
+/// Largest dump or flatfile accepted for import
+const MAX_DUMP_BYTES: usize = 512 * 1024 * 1024;
+
+/// Shared handle to the configured store
+pub type AppState = Arc<dyn WorldStore>;
+
+/// Token admin requests must carry, if one is configured
+pub type AdminToken = Arc<Option<String>>;
+
+/// Request body for creating an account
+#[derive(Debug, Deserialize)]
+pub struct NewAccount {
+    /// Unique login name
+    pub username: String,
+    /// Hash produced by auth-service
+    pub password_hash: String,
+}
+
+/// Query options for `POST /import/flatfile`
+#[derive(Debug, Default, Deserialize)]
+pub struct FlatfileOptions {
+    /// Convert and report without writing anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Response from `POST /import/flatfile`
+#[derive(Debug, Serialize)]
+pub struct FlatfileImport {
+    /// What was converted and what couldn't be
+    pub report: ImportReport,
+    /// What the store did, unless this was a dry run
+    pub result: Option<BatchResult>,
+}
+
+/// Store errors mapped onto HTTP status codes
+struct ApiError(StoreError);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self.0 {
+            StoreError::Conflict(_) => StatusCode::CONFLICT,
+            _ => {
+                warn!("Store error: {}", self.0);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        (status, self.0.to_string()).into_response()
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(e: StoreError) -> Self {
+        Self(e)
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// Build the API router, guarding the imports with `admin_token`
+pub fn router(store: AppState, admin_token: AdminToken) -> Router {
+    let admin_only = middleware::from_fn_with_state(admin_token, require_admin);
+    let accounts = Router::new()
+        .route("/accounts", post(create_account))
+        .route("/accounts/:username", get(find_account))
+        .route(
+            "/accounts/:id/characters",
+            get(characters).post(add_character),
+        )
+        .route_layer(admin_only.clone());
+    Router::new()
+        .route("/objects/batch", post(apply_batch))
+        .route("/objects/:id", get(load_object))
+        .route("/areas/:room", get(load_area))
+        .merge(accounts)
+        .route(
+            "/dump",
+            get(export_dump)
+                .merge(post(import_dump).route_layer(admin_only.clone()))
+                .layer(DefaultBodyLimit::max(MAX_DUMP_BYTES)),
+        )
+        .route(
+            "/import/flatfile",
+            post(import_flatfile)
+                .route_layer(admin_only)
+                .layer(DefaultBodyLimit::max(MAX_DUMP_BYTES)),
+        )
+        .with_state(store)
+}
+
+/// Let a request through only if it carries the admin token
+async fn require_admin(State(token): State<AdminToken>, request: Request, next: Next) -> Response {
+    match token.as_deref() {
+        None => (
+            StatusCode::FORBIDDEN,
+            "admin routes are disabled: no admin token is configured",
+        )
+            .into_response(),
+        Some(token) if bearer_matches(request.headers(), token) => next.run(request).await,
+        Some(_) => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+/// Whether `headers` carry `Authorization: Bearer <token>`
+fn bearer_matches(headers: &HeaderMap, token: &str) -> bool {
+    let Some(given) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    // Compare every byte so the time taken doesn't leak the matching prefix
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn load_object(
+    State(store): State<AppState>,
+    Path(id): Path<u64>,
+) -> ApiResult<Result<Json<ObjectRecord>, StatusCode>> {
+    Ok(store
+        .load_object(ObjectId(id))
+        .await?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND))
+}
+
+async fn load_area(
+    State(store): State<AppState>,
+    Path(room): Path<u64>,
+) -> ApiResult<Json<Vec<ObjectRecord>>> {
+    Ok(Json(store.load_area(ObjectId(room)).await?))
+}
+
+async fn apply_batch(
+    State(store): State<AppState>,
+    Json(batch): Json<WriteBatch>,
+) -> ApiResult<Json<BatchResult>> {
+    Ok(Json(store.apply_batch(&batch).await?))
+}
+
+async fn create_account(
+    State(store): State<AppState>,
+    Json(account): Json<NewAccount>,
+) -> ApiResult<(StatusCode, Json<AccountRecord>)> {
+    let record = store
+        .create_account(&account.username, &account.password_hash)
+        .await?;
+    Ok((StatusCode::CREATED, Json(record)))
+}
+
+async fn find_account(
+    State(store): State<AppState>,
+    Path(username): Path<String>,
+) -> ApiResult<Result<Json<AccountRecord>, StatusCode>> {
+    Ok(store
+        .find_account(&username)
+        .await?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND))
+}
+
+async fn characters(
+    State(store): State<AppState>,
+    Path(account): Path<i64>,
+) -> ApiResult<Json<Vec<ObjectId>>> {
+    Ok(Json(store.characters(account).await?))
+}
+
+async fn add_character(
+    State(store): State<AppState>,
+    Path(account): Path<i64>,
+    Json(character): Json<ObjectId>,
+) -> ApiResult<StatusCode> {
+    store.add_character(account, character).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn export_dump(State(store): State<AppState>) -> ApiResult<impl IntoResponse> {
+    let dump = write_dump(&store.load_all().await?);
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], dump))
+}
+
+async fn import_dump(
+    State(store): State<AppState>,
+    dump: String,
+) -> ApiResult<Result<Json<BatchResult>, (StatusCode, String)>> {
+    let records = match read_dump(&dump) {
+        Ok(records) => records,
+        Err(e) => return Ok(Err((StatusCode::BAD_REQUEST, e.to_string()))),
+    };
+    Ok(Ok(Json(store.import(&records).await?)))
+}
+
+async fn import_flatfile(
+    State(store): State<AppState>,
+    Query(options): Query<FlatfileOptions>,
+    text: String,
+) -> ApiResult<Result<Json<FlatfileImport>, (StatusCode, String)>> {
+    let import = match flatfile::import(&text) {
+        Ok(import) => import,
+        Err(e) => return Ok(Err((StatusCode::BAD_REQUEST, e.to_string()))),
+    };
+    let result = if options.dry_run {
+        None
+    } else {
+        Some(store.import(&import.records).await?)
+    };
+    Ok(Ok(Json(FlatfileImport {
+        report: import.report,
+        result,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_bearer_matches() {
+        let with = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, HeaderValue::from_str(value).unwrap());
+            headers
+        };
+        assert!(bearer_matches(&with("Bearer s3cret"), "s3cret"));
+        assert!(!bearer_matches(&with("Bearer s3cre"), "s3cret"));
+        assert!(!bearer_matches(&with("Bearer s3creT"), "s3cret"));
+        assert!(!bearer_matches(&with("s3cret"), "s3cret"));
+        assert!(!bearer_matches(&HeaderMap::new(), "s3cret"));
+    }
+}
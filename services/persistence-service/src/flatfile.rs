@@ -0,0 +1,1085 @@
+//! Legacy Flatfile Import
+//!
+//! Converts PennMUSH and TinyMUSH flatfile dumps into [`ObjectRecord`]s so
+//! existing worlds can be loaded with [`WorldStore::import`].
+//!
+//! Both formats are parsed into the same intermediate [`LegacyObject`] and
+//! then mapped onto the new object model. MUSH dbrefs become object ids
+//! unchanged, so `#0` is still the starting room and `#1` is still God.
+//!
+//! Attribute text is never rewritten. Attributes that look like softcode
+//! (`$commands`, `^listens`, `[functions()]`, action lists) are tagged
+//! [`AttrFlags::SOFTCODE`] so builders can find and port them later.
+//! Everything without an equivalent (zones, parents, powers, unknown flags
+//! and lock types, ...) is listed in the [`ImportReport`].
+//!
+//! # Learning Note
+//! Locks that fail to translate are replaced with one only wizards pass.
+//! When an importer has to guess, failing closed means a world can be
+//! imported without silently unlocking anyone's private rooms.
+//!
+//! [`WorldStore::import`]: crate::store::WorldStore::import
+
+use serde::Serialize;
+use shared::components::{AttrFlags, Attributes, Flags, LockKind, ObjectId, ObjectKind};
+use shared::locks::LockExpr;
+use shared::records::{AttributeRecord, ObjectRecord};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// Dbref for "no object" in both formats
+const NOTHING: i64 = -1;
+
+/// Dbref for an exit leading to the user's home
+const HOME: i64 = -3;
+
+/// Object ids that always exist in a MUSH database
+const ROOM_ZERO: ObjectId = ObjectId(0);
+const GOD: ObjectId = ObjectId(1);
+
+/// Attribute name reserved for scripts in the new model
+const SCRIPT_ATTR: &str = "SCRIPT";
+
+/// Which server wrote a flatfile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum FlatfileFormat {
+    /// PennMUSH 1.7.7+ labelled format (`+V` header)
+    PennMush,
+    /// TinyMUSH 2.x/3.x numeric format (`+T` or `+X` header)
+    TinyMush,
+}
+
+/// A flatfile could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlatfileError {
+    /// The header does not match any supported format
+    UnknownFormat,
+    /// The file ended before `***END OF DUMP***`
+    Truncated,
+    /// A line could not be understood (1-based line number)
+    Syntax(usize, String),
+}
+
+impl fmt::Display for FlatfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFormat => write!(f, "not a PennMUSH or TinyMUSH flatfile"),
+            Self::Truncated => write!(f, "flatfile is truncated"),
+            Self::Syntax(line, message) => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for FlatfileError {}
+
+/// What the importer did, and what it couldn't carry over
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportReport {
+    /// Format that was detected
+    pub format: FlatfileFormat,
+    /// Objects converted
+    pub objects: usize,
+    /// Attributes converted
+    pub attributes: usize,
+    /// Attributes tagged as legacy softcode
+    pub softcode: usize,
+    /// Each unmapped item, with the objects it appeared on
+    pub unmapped: BTreeMap<String, Vec<ObjectId>>,
+}
+
+impl ImportReport {
+    fn note(&mut self, what: impl Into<String>, id: ObjectId) {
+        self.unmapped.entry(what.into()).or_default().push(id);
+    }
+}
+
+/// Result of converting a flatfile
+#[derive(Debug, Clone)]
+pub struct Import {
+    /// Converted objects, in id order
+    pub records: Vec<ObjectRecord>,
+    /// Summary and unmapped items
+    pub report: ImportReport,
+}
+
+/// One object as read from either format, before mapping
+#[derive(Debug, Default)]
+struct LegacyObject {
+    id: i64,
+    name: String,
+    /// `None` for garbage and unknown types
+    kind: Option<ObjectKind>,
+    location: i64,
+    exits: i64,
+    next: i64,
+    owner: i64,
+    parent: i64,
+    zone: i64,
+    pennies: i64,
+    flags: Vec<String>,
+    powers: Vec<String>,
+    /// (lock type, key)
+    locks: Vec<(String, String)>,
+    attributes: Vec<LegacyAttribute>,
+}
+
+#[derive(Debug, Default)]
+struct LegacyAttribute {
+    name: String,
+    owner: Option<i64>,
+    flags: Vec<String>,
+    value: String,
+}
+
+/// Detect the format of `text` and convert it
+pub fn import(text: &str) -> Result<Import, FlatfileError> {
+    let (format, objects) = if text.starts_with("+V") {
+        (FlatfileFormat::PennMush, parse_penn(text)?)
+    } else if text.starts_with("+T") || text.starts_with("+X") {
+        (FlatfileFormat::TinyMush, parse_tiny(text)?)
+    } else {
+        return Err(FlatfileError::UnknownFormat);
+    };
+    Ok(convert(format, objects))
+}
+
+/// Map legacy objects onto records
+fn convert(format: FlatfileFormat, objects: Vec<LegacyObject>) -> Import {
+    let mut report = ImportReport {
+        format,
+        objects: 0,
+        attributes: 0,
+        softcode: 0,
+        unmapped: BTreeMap::new(),
+    };
+
+    let live: HashMap<i64, &LegacyObject> = objects
+        .iter()
+        .filter(|object| object.kind.is_some())
+        .map(|object| (object.id, object))
+        .collect();
+    let exists = |dbref: i64| live.contains_key(&dbref);
+
+    // Exits only know where they lead; their source is the object whose
+    // exit list they are on
+    let mut exit_sources: HashMap<i64, i64> = HashMap::new();
+    for object in live.values() {
+        if object.kind == Some(ObjectKind::Exit) {
+            continue;
+        }
+        let mut seen = HashSet::new();
+        let mut exit = object.exits;
+        while let Some(next) = live.get(&exit) {
+            if next.kind != Some(ObjectKind::Exit) || !seen.insert(exit) {
+                break;
+            }
+            exit_sources.insert(exit, object.id);
+            exit = next.next;
+        }
+    }
+
+    let mut records = Vec::with_capacity(live.len());
+    for object in &objects {
+        let id = ObjectId(object.id as u64);
+        let Some(kind) = object.kind else {
+            report.note("garbage or unknown object type (skipped)", id);
+            continue;
+        };
+
+        let owner = if exists(object.owner) {
+            ObjectId(object.owner as u64)
+        } else {
+            report.note("missing owner (given to #1)", id);
+            GOD
+        };
+
+        let mut location = None;
+        let mut destination = None;
+        match kind {
+            ObjectKind::Room => {
+                if object.location != NOTHING {
+                    report.note("room drop-to", id);
+                }
+            }
+            ObjectKind::Exit => {
+                location = exit_sources.get(&object.id).map(|&s| ObjectId(s as u64));
+                if location.is_none() {
+                    report.note("exit not on any exit list", id);
+                }
+                match object.location {
+                    NOTHING => {}
+                    HOME => report.note("exit leading home", id),
+                    dbref if exists(dbref) => destination = Some(ObjectId(dbref as u64)),
+                    _ => report.note("exit to missing or variable destination", id),
+                }
+            }
+            ObjectKind::Thing | ObjectKind::Player => {
+                location = Some(if exists(object.location) {
+                    ObjectId(object.location as u64)
+                } else {
+                    report.note("missing location (moved to #0)", id);
+                    ROOM_ZERO
+                });
+                if object.exits != NOTHING {
+                    report.note("home", id);
+                }
+            }
+        }
+        if object.parent != NOTHING {
+            report.note("parent", id);
+        }
+        if object.zone != NOTHING {
+            report.note("zone", id);
+        }
+        if object.pennies != 0 {
+            report.note("pennies", id);
+        }
+
+        let mut flags = Flags::empty();
+        for name in &object.flags {
+            match Flags::from_name(name) {
+                Some(flag) => flags.insert(flag),
+                None => report.note(format!("flag {}", name.to_ascii_uppercase()), id),
+            }
+        }
+        for power in &object.powers {
+            if power.eq_ignore_ascii_case("builder") {
+                flags.insert(Flags::BUILDER);
+            } else {
+                report.note(format!("power {}", power.to_ascii_uppercase()), id);
+            }
+        }
+
+        let mut locks = BTreeMap::new();
+        for (kind, key) in &object.locks {
+            let key = key.trim();
+            let Some(lock_kind) = LockKind::from_name(kind) else {
+                report.note(format!("{} lock", kind.to_ascii_lowercase()), id);
+                continue;
+            };
+            if key.is_empty() || key.eq_ignore_ascii_case("#TRUE") {
+                continue;
+            }
+            let expr = LockExpr::parse_canonical(key).unwrap_or_else(|_| {
+                report.note(
+                    format!(
+                        "untranslatable {} lock (wizards only)",
+                        kind.to_ascii_lowercase()
+                    ),
+                    id,
+                );
+                wizards_only(id)
+            });
+            locks.insert(lock_kind, expr.to_string());
+        }
+
+        let mut attributes = Vec::new();
+        for attr in &object.attributes {
+            let mut name = Attributes::normalize(&attr.name);
+            if name == "XYXXY" || name == "PASS" {
+                report.note("password (reset through auth-service)", id);
+                continue;
+            }
+            if name == SCRIPT_ATTR {
+                report.note("SCRIPT attribute renamed to LEGACY_SCRIPT", id);
+                name = format!("LEGACY_{}", SCRIPT_ATTR);
+            }
+
+            let mut attr_flags = AttrFlags::empty();
+            for flag in &attr.flags {
+                match flag.to_ascii_lowercase().as_str() {
+                    "wizard" | "god" => attr_flags.insert(AttrFlags::WIZARD),
+                    "locked" | "lock" => attr_flags.insert(AttrFlags::LOCKED),
+                    "mortal_dark" | "mdark" | "dark" => attr_flags.insert(AttrFlags::HIDDEN),
+                    "visual" => attr_flags.insert(AttrFlags::VISUAL),
+                    // Visibility defaults that the new model already has
+                    "odark" | "is_lock" => {}
+                    other => report.note(format!("attribute flag {}", other), id),
+                }
+            }
+            if is_softcode(&attr.value) {
+                attr_flags.insert(AttrFlags::SOFTCODE);
+                report.softcode += 1;
+            }
+
+            attributes.push(AttributeRecord {
+                name,
+                value: attr.value.clone(),
+                owner: attr
+                    .owner
+                    .filter(|&owner| exists(owner))
+                    .map_or(owner, |owner| ObjectId(owner as u64)),
+                flags: attr_flags.bits(),
+            });
+        }
+        attributes.sort_by(|a, b| a.name.cmp(&b.name));
+        report.attributes += attributes.len();
+
+        records.push(ObjectRecord {
+            id,
+            kind,
+            name: object.name.clone(),
+            owner,
+            location,
+            destination,
+            flags: flags.bits(),
+            locks,
+            attributes,
+            script: None,
+            script_owner: None,
+            script_flags: 0,
+            script_revisions: Vec::new(),
+            timers: Vec::new(),
+            version: 1,
+        });
+    }
+
+    records.sort_by_key(|record| record.id);
+    report.objects = records.len();
+    Import { records, report }
+}
+
+/// A lock nobody but wizards (who ignore locks) can pass
+fn wizards_only(id: ObjectId) -> LockExpr {
+    LockExpr::And(
+        Box::new(LockExpr::Is(id)),
+        Box::new(LockExpr::Not(Box::new(LockExpr::Is(id)))),
+    )
+}
+
+/// Heuristic for attributes holding code rather than plain text
+fn is_softcode(value: &str) -> bool {
+    let value = value.trim_start();
+    if value.starts_with(['$', '^', '@']) || value.contains('[') {
+        return true;
+    }
+    // %r, %t and %b are just formatting; other substitutions are code
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            match chars.next() {
+                Some(c) if !"rRtTbB%".contains(c) => return true,
+                _ => {}
+            }
+        }
+    }
+    false
+}
+
+/// Line-oriented cursor over a flatfile
+struct Reader<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        let rest = &self.text[self.pos..];
+        if rest.is_empty() {
+            return None;
+        }
+        Some(
+            rest.split('\n')
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches('\r'),
+        )
+    }
+
+    fn next_line(&mut self) -> Result<&'a str, FlatfileError> {
+        let line = self.peek().ok_or(FlatfileError::Truncated)?;
+        let rest = &self.text[self.pos..];
+        self.pos += rest.find('\n').map_or(rest.len(), |i| i + 1);
+        self.line += 1;
+        Ok(line)
+    }
+
+    /// Read a `"..."` string starting at the cursor, which may span lines,
+    /// then skip the rest of the line
+    fn quoted(&mut self) -> Result<String, FlatfileError> {
+        let rest = &self.text[self.pos..];
+        if !rest.starts_with('"') {
+            return Err(self.error("expected a quoted string"));
+        }
+        let mut value = String::new();
+        let mut chars = rest.char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        value.push(escaped);
+                    }
+                }
+                '"' => {
+                    self.line += rest[..i].matches('\n').count();
+                    self.pos += i + 1;
+                    self.next_line()?;
+                    return Ok(value);
+                }
+                c => value.push(c),
+            }
+        }
+        Err(FlatfileError::Truncated)
+    }
+
+    fn error(&self, message: impl Into<String>) -> FlatfileError {
+        FlatfileError::Syntax(self.line, message.into())
+    }
+}
+
+fn parse_dbref(reader: &Reader, text: &str) -> Result<i64, FlatfileError> {
+    text.trim()
+        .trim_start_matches('#')
+        .parse()
+        .map_err(|_| reader.error(format!("expected a number, found '{}'", text.trim())))
+}
+
+/// Parse the labelled PennMUSH format
+///
+/// ```text
+/// !0
+/// name "Room Zero"
+/// location #-1
+/// lockcount 1
+///  type "Basic"
+///   key "#1"
+/// attrcount 1
+///  name "DESCRIBE"
+///   value "A plain room."
+/// ```
+fn parse_penn(text: &str) -> Result<Vec<LegacyObject>, FlatfileError> {
+    let mut reader = Reader::new(text);
+
+    // Flag, power and attribute tables precede the object count line
+    loop {
+        if reader.next_line()?.starts_with('~') {
+            break;
+        }
+    }
+
+    enum Section {
+        Locks,
+        Attributes,
+    }
+
+    let mut objects: Vec<LegacyObject> = Vec::new();
+    let mut section = Section::Attributes;
+    loop {
+        let line = reader.peek().ok_or(FlatfileError::Truncated)?;
+        if line.starts_with("***END OF DUMP***") {
+            break;
+        }
+        if let Some(dbref) = line.strip_prefix('!') {
+            let id = parse_dbref(&reader, dbref)?;
+            reader.next_line()?;
+            objects.push(LegacyObject {
+                id,
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let depth = line.len() - line.trim_start_matches(' ').len();
+        let (key, value) = line
+            .trim_start()
+            .split_once(' ')
+            .unwrap_or((line.trim_start(), ""));
+        let key = key.to_string();
+        // Skip past the key so quoted values can be read in place
+        let offset = depth + key.len() + usize::from(!value.is_empty());
+        let value = if value.starts_with('"') {
+            reader.pos += offset;
+            reader.quoted()?
+        } else {
+            reader.next_line()?;
+            value.to_string()
+        };
+
+        let object = objects
+            .last_mut()
+            .ok_or_else(|| reader.error("field before the first object"))?;
+        match (depth, key.as_str()) {
+            (0, "name") => object.name = value,
+            (0, "location") => object.location = parse_dbref(&reader, &value)?,
+            (0, "exits") => object.exits = parse_dbref(&reader, &value)?,
+            (0, "next") => object.next = parse_dbref(&reader, &value)?,
+            (0, "owner") => object.owner = parse_dbref(&reader, &value)?,
+            (0, "parent") => object.parent = parse_dbref(&reader, &value)?,
+            (0, "zone") => object.zone = parse_dbref(&reader, &value)?,
+            (0, "pennies") => object.pennies = parse_dbref(&reader, &value)?,
+            (0, "type") => {
+                object.kind = match parse_dbref(&reader, &value)? {
+                    0x1 => Some(ObjectKind::Room),
+                    0x2 => Some(ObjectKind::Thing),
+                    0x4 => Some(ObjectKind::Exit),
+                    0x8 => Some(ObjectKind::Player),
+                    _ => None,
+                }
+            }
+            (0, "flags") => object.flags = value.split_whitespace().map(String::from).collect(),
+            (0, "powers") => object.powers = value.split_whitespace().map(String::from).collect(),
+            (0, "lockcount") => section = Section::Locks,
+            (0, "attrcount") => section = Section::Attributes,
+            (1, "type") if matches!(section, Section::Locks) => {
+                object.locks.push((value, String::new()))
+            }
+            (2, "key") if matches!(section, Section::Locks) => {
+                if let Some(lock) = object.locks.last_mut() {
+                    lock.1 = value;
+                }
+            }
+            (1, "name") if matches!(section, Section::Attributes) => {
+                object.attributes.push(LegacyAttribute {
+                    name: value,
+                    ..Default::default()
+                })
+            }
+            (2, field) if matches!(section, Section::Attributes) => {
+                if let Some(attr) = object.attributes.last_mut() {
+                    match field {
+                        "owner" => attr.owner = Some(parse_dbref(&reader, &value)?),
+                        "flags" => {
+                            attr.flags = value.split_whitespace().map(String::from).collect()
+                        }
+                        "value" => attr.value = value,
+                        _ => {}
+                    }
+                }
+            }
+            // contents, warnings, timestamps, lock creators, ... are derived
+            // or have no equivalent
+            _ => {}
+        }
+    }
+    Ok(objects)
+}
+
+/// TinyMUSH database header flags (`V_*` in db.h)
+const V_ZONE: u32 = 0x0000_0100;
+const V_LINK: u32 = 0x0000_0200;
+const V_ATRNAME: u32 = 0x0000_0800;
+const V_ATRKEY: u32 = 0x0000_1000;
+const V_PARENT: u32 = 0x0000_2000;
+const V_ATRMONEY: u32 = 0x0000_8000;
+const V_XFLAGS: u32 = 0x0001_0000;
+const V_POWERS: u32 = 0x0002_0000;
+const V_3FLAGS: u32 = 0x0004_0000;
+const V_QUOTED: u32 = 0x0008_0000;
+const V_TIMESTAMPS: u32 = 0x0020_0000;
+
+/// TinyMUSH first flag word, from bit 3 up (bits 0-2 are the type)
+const TINY_FLAGS: [&str; 29] = [
+    "SEETHRU",
+    "WIZARD",
+    "LINK_OK",
+    "DARK",
+    "JUMP_OK",
+    "STICKY",
+    "DESTROY_OK",
+    "HAVEN",
+    "QUIET",
+    "HALT",
+    "TRACE",
+    "GOING",
+    "MONITOR",
+    "MYOPIC",
+    "PUPPET",
+    "CHOWN_OK",
+    "ENTER_OK",
+    "VISUAL",
+    "IMMORTAL",
+    "HAS_STARTUP",
+    "OPAQUE",
+    "VERBOSE",
+    "INHERIT",
+    "NOSPOOF",
+    "ROBOT",
+    "SAFE",
+    "ROYALTY",
+    "HEARTHRU",
+    "TERSE",
+];
+
+/// TinyMUSH attribute flags, from bit 0 up
+const TINY_ATTR_FLAGS: [&str; 12] = [
+    "odark",
+    "dark",
+    "wizard",
+    "mdark",
+    "internal",
+    "no_command",
+    "lock",
+    "deleted",
+    "no_prog",
+    "god",
+    "is_lock",
+    "visual",
+];
+
+/// TinyMUSH built-in attribute names, numbered from 1
+const TINY_ATTRS: [&str; 63] = [
+    "OSUCC",
+    "OFAIL",
+    "FAIL",
+    "SUCC",
+    "PASS",
+    "DESCRIBE",
+    "SEX",
+    "ODROP",
+    "DROP",
+    "OKILL",
+    "KILL",
+    "ASUCC",
+    "AFAIL",
+    "ADROP",
+    "AKILL",
+    "AUSE",
+    "CHARGES",
+    "RUNOUT",
+    "STARTUP",
+    "ACLONE",
+    "APAY",
+    "OPAY",
+    "PAY",
+    "COST",
+    "MONEY",
+    "LISTEN",
+    "AAHEAR",
+    "AMHEAR",
+    "AHEAR",
+    "LAST",
+    "QUEUEMAX",
+    "IDESC",
+    "ENTER",
+    "OXENTER",
+    "AENTER",
+    "ADESC",
+    "ODESC",
+    "RQUOTA",
+    "ACONNECT",
+    "ADISCONNECT",
+    "ALLOWANCE",
+    "LOCK",
+    "NAME",
+    "COMMENT",
+    "USE",
+    "OUSE",
+    "SEMAPHORE",
+    "TIMEOUT",
+    "QUOTA",
+    "LEAVE",
+    "OLEAVE",
+    "ALEAVE",
+    "OENTER",
+    "OXLEAVE",
+    "MOVE",
+    "OMOVE",
+    "AMOVE",
+    "ALIAS",
+    "LENTER",
+    "LLEAVE",
+    "LPAGE",
+    "LUSE",
+    "LGIVE",
+];
+
+/// Lock attributes in TinyMUSH, by attribute number
+fn tiny_lock(number: u32) -> Option<&'static str> {
+    match number {
+        42 => Some("basic"),
+        59 => Some("enter"),
+        60 => Some("leave"),
+        61 => Some("page"),
+        62 => Some("use"),
+        63 => Some("give"),
+        _ => None,
+    }
+}
+
+/// Parse the numeric TinyMUSH format
+///
+/// ```text
+/// +X<version flags>
+/// +A256
+/// 0:MYATTR
+/// !0
+/// Room Zero
+/// -1          location
+/// ...
+/// >6
+/// A plain room.
+/// <
+/// ```
+fn parse_tiny(text: &str) -> Result<Vec<LegacyObject>, FlatfileError> {
+    let mut reader = Reader::new(text);
+    let header = reader.next_line()?;
+    let version: u32 = header[2..]
+        .trim()
+        .parse()
+        .map_err(|_| reader.error("bad version header"))?;
+    let has = |flag: u32| version & flag != 0;
+
+    let string = |reader: &mut Reader| -> Result<String, FlatfileError> {
+        if has(V_QUOTED) {
+            reader.quoted()
+        } else {
+            reader.next_line().map(String::from)
+        }
+    };
+    let number = |reader: &mut Reader| -> Result<i64, FlatfileError> {
+        let line = reader.next_line()?;
+        parse_dbref(reader, line)
+    };
+
+    // User-defined attribute names
+    let mut attr_names: HashMap<u32, String> = HashMap::new();
+    loop {
+        let line = reader.peek().ok_or(FlatfileError::Truncated)?;
+        if line.starts_with('!') || line.starts_with("***END OF DUMP***") {
+            break;
+        }
+        reader.next_line()?;
+        if let Some(number) = line.strip_prefix("+A") {
+            let number = parse_dbref(&reader, number)? as u32;
+            let definition = string(&mut reader)?;
+            let name = definition
+                .split_once(':')
+                .map_or(definition.as_str(), |(_, name)| name);
+            attr_names.insert(number, name.to_string());
+        }
+    }
+
+    let mut objects = Vec::new();
+    loop {
+        let line = reader.next_line()?;
+        if line.starts_with("***END OF DUMP***") {
+            break;
+        }
+        let dbref = line
+            .strip_prefix('!')
+            .ok_or_else(|| reader.error("expected '!<dbref>'"))?;
+        let mut object = LegacyObject {
+            id: parse_dbref(&reader, dbref)?,
+            ..Default::default()
+        };
+
+        if !has(V_ATRNAME) {
+            object.name = string(&mut reader)?;
+        }
+        object.location = number(&mut reader)?;
+        object.zone = if has(V_ZONE) {
+            number(&mut reader)?
+        } else {
+            NOTHING
+        };
+        number(&mut reader)?; // contents, rebuilt from locations
+        object.exits = number(&mut reader)?;
+        if has(V_LINK) {
+            number(&mut reader)?;
+        }
+        object.next = number(&mut reader)?;
+        if !has(V_ATRKEY) {
+            let key = reader.next_line()?.to_string();
+            object.locks.push(("basic".to_string(), key));
+        }
+        object.owner = number(&mut reader)?;
+        object.parent = if has(V_PARENT) {
+            number(&mut reader)?
+        } else {
+            NOTHING
+        };
+        if !has(V_ATRMONEY) {
+            object.pennies = number(&mut reader)?;
+        }
+
+        let flags = number(&mut reader)? as u32;
+        object.kind = match flags & 0x7 {
+            0 => Some(ObjectKind::Room),
+            1 => Some(ObjectKind::Thing),
+            2 => Some(ObjectKind::Exit),
+            3 => Some(ObjectKind::Player),
+            _ => None,
+        };
+        object.flags = TINY_FLAGS
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| flags & (1 << (bit + 3)) != 0)
+            .map(|(_, name)| name.to_string())
+            .collect();
+        for (word, present) in [(2, has(V_XFLAGS)), (3, has(V_3FLAGS))] {
+            if !present {
+                continue;
+            }
+            let bits = number(&mut reader)? as u32;
+            for bit in (0..32).filter(|bit| bits & (1 << bit) != 0) {
+                object.flags.push(format!("FLAGS{}_BIT{}", word, bit));
+            }
+        }
+        if has(V_POWERS) {
+            for word in 1..=2 {
+                let bits = number(&mut reader)? as u32;
+                for bit in (0..32).filter(|bit| bits & (1 << bit) != 0) {
+                    object.powers.push(format!("POWERS{}_BIT{}", word, bit));
+                }
+            }
+        }
+        if has(V_TIMESTAMPS) {
+            number(&mut reader)?;
+            number(&mut reader)?;
+        }
+
+        loop {
+            let line = reader.next_line()?;
+            if line == "<" {
+                break;
+            }
+            let number = line
+                .strip_prefix('>')
+                .ok_or_else(|| reader.error("expected '>' or '<'"))
+                .and_then(|n| parse_dbref(&reader, n))? as u32;
+            let raw = string(&mut reader)?;
+            let mut attr = tiny_attribute(&raw);
+
+            if let Some(kind) = tiny_lock(number) {
+                object.locks.push((kind.to_string(), attr.value));
+                continue;
+            }
+            match number {
+                25 => object.pennies = attr.value.trim().parse().unwrap_or_default(),
+                43 => object.name = attr.value,
+                _ => {
+                    attr.name = match attr_names.get(&number) {
+                        Some(name) => name.clone(),
+                        None => TINY_ATTRS
+                            .get(number.wrapping_sub(1) as usize)
+                            .map_or_else(|| format!("ATTR{}", number), |name| name.to_string()),
+                    };
+                    object.attributes.push(attr);
+                }
+            }
+        }
+        objects.push(object);
+    }
+    Ok(objects)
+}
+
+/// Split TinyMUSH's `\x01owner:flags:value` attribute prefix
+fn tiny_attribute(raw: &str) -> LegacyAttribute {
+    let mut attr = LegacyAttribute {
+        value: raw.to_string(),
+        ..Default::default()
+    };
+    let Some(rest) = raw.strip_prefix('\x01') else {
+        return attr;
+    };
+    let mut parts = rest.splitn(3, ':');
+    if let (Some(owner), Some(flags), Some(value)) = (parts.next(), parts.next(), parts.next()) {
+        if let (Ok(owner), Ok(flags)) = (owner.parse::<i64>(), flags.parse::<u32>()) {
+            attr.owner = Some(owner);
+            attr.flags = TINY_ATTR_FLAGS
+                .iter()
+                .enumerate()
+                .filter(|(bit, _)| flags & (1 << bit) != 0)
+                .map(|(_, name)| name.to_string())
+                .collect();
+            for bit in TINY_ATTR_FLAGS.len()..32 {
+                if flags & (1 << bit) != 0 {
+                    attr.flags.push(format!("bit{}", bit));
+                }
+            }
+            attr.value = value.to_string();
+        }
+    }
+    attr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PENN: &str = r##"+V74247
+dbversion 5
++FLAGS LIST
+flagcount 1
+ name "WIZARD"
+  letter "W"
+~4
+!0
+name "Room Zero"
+location #-1
+contents #1
+exits #2
+next #-1
+parent #-1
+lockcount 0
+owner #1
+zone #-1
+pennies 0
+type 1
+flags ""
+powers ""
+attrcount 1
+ name "DESCRIBE"
+  owner #1
+  flags "visual no_command"
+  derefs 0
+  value "A plain room.%rNothing \"special\"."
+!1
+name "One"
+location #0
+contents #-1
+exits #0
+next #3
+parent #-1
+lockcount 2
+ type "Basic"
+  creator #1
+  flags ""
+  derefs 0
+  key "=#1"
+ type "Zone"
+  creator #1
+  flags ""
+  derefs 0
+  key "#1"
+owner #1
+zone #-1
+pennies 150
+type 8
+flags "WIZARD CONNECTED"
+powers "Builder Boot"
+attrcount 2
+ name "XYXXY"
+  owner #1
+  flags ""
+  derefs 0
+  value "hash"
+ name "CMD_HELLO"
+  owner #1
+  flags "wizard"
+  derefs 0
+  value "$hello:@pemit %#=Hi
+there."
+!2
+name "Out;o"
+location #0
+contents #-1
+exits #0
+next #-1
+parent #-1
+lockcount 1
+ type "Basic"
+  creator #1
+  flags ""
+  derefs 0
+  key "FLAG^WIZARD"
+owner #1
+zone #-1
+pennies 0
+type 4
+flags ""
+powers ""
+attrcount 0
+!3
+name "Junk"
+location #-1
+type 16
+***END OF DUMP***
+"##;
+
+    #[test]
+    fn test_pennmush() {
+        let import = import(PENN).unwrap();
+        let report = &import.report;
+        assert_eq!(report.format, FlatfileFormat::PennMush);
+        assert_eq!(import.records.len(), 3);
+
+        let room = &import.records[0];
+        assert_eq!(room.kind, ObjectKind::Room);
+        assert_eq!(
+            room.attributes[0].value,
+            "A plain room.%rNothing \"special\"."
+        );
+        assert_eq!(room.attributes[0].flags, AttrFlags::VISUAL.bits());
+
+        let wizard = &import.records[1];
+        assert_eq!(wizard.location, Some(ObjectId(0)));
+        assert_eq!(wizard.flags, (Flags::WIZARD | Flags::BUILDER).bits());
+        assert_eq!(wizard.locks[&LockKind::Basic], "=#1");
+        assert_eq!(wizard.attributes.len(), 1);
+        let command = &wizard.attributes[0];
+        assert_eq!(command.value, "$hello:@pemit %#=Hi\nthere.");
+        assert_eq!(
+            command.flags,
+            (AttrFlags::WIZARD | AttrFlags::SOFTCODE).bits()
+        );
+
+        let exit = &import.records[2];
+        assert_eq!(exit.location, Some(ObjectId(0)));
+        assert_eq!(exit.destination, Some(ObjectId(0)));
+        assert_eq!(exit.locks[&LockKind::Basic], "=#2 & !=#2");
+
+        for unmapped in [
+            "flag CONNECTED",
+            "power BOOT",
+            "zone lock",
+            "pennies",
+            "attribute flag no_command",
+            "password (reset through auth-service)",
+            "untranslatable basic lock (wizards only)",
+            "garbage or unknown object type (skipped)",
+        ] {
+            assert!(report.unmapped.contains_key(unmapped), "{}", unmapped);
+        }
+        assert_eq!(report.softcode, 1);
+    }
+
+    #[test]
+    fn test_tinymush() {
+        let version = V_ZONE | V_LINK | V_PARENT | V_XFLAGS | V_ATRKEY | V_ATRNAME | V_ATRMONEY;
+        let tiny = format!(
+            "+X{version}\n+S3\n+A256\n0:SCRIPT\n!0\n-1\n-1\n1\n2\n-1\n-1\n1\n-1\n0\n0\n\
+             >43\nRoom Zero\n>6\n\x011:2:Secret desc\n<\n\
+             !1\n0\n-1\n-1\n-1\n0\n-1\n1\n-1\n{wizard}\n0\n>43\nWizard\n>256\nold code\n<\n\
+             !2\n0\n-1\n-1\n0\n-1\n-1\n1\n-1\n2\n0\n>43\nDown\n>42\n#1|+#5\n<\n\
+             ***END OF DUMP***\n",
+            wizard = 3 | 0x10 | 0x20,
+        );
+        let import = import(&tiny).unwrap();
+        assert_eq!(import.report.format, FlatfileFormat::TinyMush);
+
+        let room = &import.records[0];
+        assert_eq!(room.name, "Room Zero");
+        assert_eq!(room.attributes[0].name, "DESCRIBE");
+        assert_eq!(room.attributes[0].flags, AttrFlags::HIDDEN.bits());
+
+        let wizard = &import.records[1];
+        assert_eq!(wizard.kind, ObjectKind::Player);
+        assert_eq!(wizard.flags, Flags::WIZARD.bits());
+        assert_eq!(wizard.attributes[0].name, "LEGACY_SCRIPT");
+
+        let exit = &import.records[2];
+        assert_eq!(
+            (exit.location, exit.destination),
+            (Some(ObjectId(0)), Some(ObjectId(0)))
+        );
+        assert_eq!(exit.locks[&LockKind::Basic], "#1 | +#5");
+        assert!(import.report.unmapped.contains_key("flag LINK_OK"));
+    }
+
+    #[test]
+    fn test_unknown_and_truncated() {
+        assert_eq!(import("hello").unwrap_err(), FlatfileError::UnknownFormat);
+        let truncated = &PENN[..PENN.find("!2").unwrap()];
+        assert_eq!(import(truncated).unwrap_err(), FlatfileError::Truncated);
+    }
+}
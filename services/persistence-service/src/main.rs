@@ -1,8 +1,29 @@
 //! Persistence Service - Database operations
+//!
+//! Owns the world database. world-state loads areas and writes batches of
+//! changed objects through the HTTP API in [`api`]; auth-service stores
+//! accounts and their characters.
+//!
+//! `DATABASE_URL` selects the backend: `postgres://...` for PostgreSQL,
+//! `sqlite:...` for an embedded SQLite file (the default for local runs).
+//! `ADMIN_TOKEN` is the bearer token the whole-world imports and the
+//! account routes require; they are disabled when it is not set.
 
-use axum::{routing::get, Router};
+mod api;
+mod flatfile;
+mod sql;
+mod store;
+
+use axum::{extract::State, http::StatusCode, routing::get, Router};
 use std::net::SocketAddr;
-use tracing::info;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::api::AppState;
+use crate::sql::SqlStore;
+
+/// Used when `DATABASE_URL` is not set
+const DEFAULT_DATABASE_URL: &str = "sqlite://worldengine.db?mode=rwc";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -17,14 +38,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     dotenvy::dotenv().ok();
 
-    let health_app = Router::new()
+    let url = std::env::var("DATABASE_URL").unwrap_or_else(|_| DEFAULT_DATABASE_URL.to_string());
+    let store: AppState = Arc::new(SqlStore::connect(&url).await?);
+    let admin_token = std::env::var("ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty());
+    if admin_token.is_none() {
+        warn!("ADMIN_TOKEN is not set; imports and account routes are disabled");
+    }
+
+    let app = Router::new()
         .route("/health", get(health_check))
-        .route("/ready", get(readiness_check));
+        .route("/ready", get(readiness_check))
+        .with_state(store.clone())
+        .merge(api::router(store, Arc::new(admin_token)));
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 8087));
-    info!("Health check server listening on {}", addr);
+    info!("Persistence API listening on {}", addr);
 
-    axum::serve(tokio::net::TcpListener::bind(addr).await?, health_app).await?;
+    axum::serve(tokio::net::TcpListener::bind(addr).await?, app).await?;
 
     Ok(())
 }
@@ -33,6 +65,10 @@ async fn health_check() -> &'static str {
     "OK"
 }
 
-async fn readiness_check() -> &'static str {
-    "READY"
+async fn readiness_check(State(store): State<AppState>) -> Result<&'static str, StatusCode> {
+    store
+        .ping()
+        .await
+        .map(|_| "READY")
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)
 }
@@ -0,0 +1,698 @@
+//! SQL Store
+//!
+//! [`WorldStore`] implementation on top of sqlx's `Any` driver. The same
+//! queries run against PostgreSQL (production) and SQLite (tests and
+//! single-node setups); the backend is picked from the database URL.
+//!
+//! Only the migrations differ between the two, because PostgreSQL and
+//! SQLite generate ids differently. Every integer column is `BIGINT` so
+//! both backends decode to `i64`.
+
+use async_trait::async_trait;
+use shared::components::{LockKind, ObjectId, ObjectKind};
+use shared::records::{
+    AccountRecord, AttributeRecord, BatchResult, ObjectRecord, ScriptRevisionRecord, TimerRecord,
+    WriteBatch,
+};
+use sqlx::any::{AnyPoolOptions, AnyRow};
+use sqlx::{AnyConnection, AnyPool, Row};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+use crate::store::{StoreError, StoreResult, WorldStore};
+
+/// Recursive query selecting a room and everything contained in it
+const AREA_CTE: &str = "WITH RECURSIVE area(id) AS (
+    SELECT id FROM objects WHERE id = $1
+    UNION
+    SELECT o.id FROM objects o JOIN area a ON o.location = a.id
+) ";
+
+/// Insert an object, or overwrite the stored one
+const UPSERT_OBJECT: &str = "INSERT INTO objects
+    (id, kind, name, owner, location, destination, flags, locks, version)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+    ON CONFLICT (id) DO UPDATE SET
+        kind = excluded.kind,
+        name = excluded.name,
+        owner = excluded.owner,
+        location = excluded.location,
+        destination = excluded.destination,
+        flags = excluded.flags,
+        locks = excluded.locks,
+        version = excluded.version";
+
+/// Appended to [`UPSERT_OBJECT`] so stale or repeated writes are ignored
+const ONLY_NEWER: &str = " WHERE objects.version < excluded.version";
+
+/// SQL-backed world store
+#[derive(Clone)]
+pub struct SqlStore {
+    pool: AnyPool,
+}
+
+impl SqlStore {
+    /// Connect to `url` and bring the schema up to date
+    ///
+    /// `postgres://...` uses PostgreSQL; `sqlite:...` (including
+    /// `sqlite::memory:`) uses embedded SQLite.
+    pub async fn connect(url: &str) -> StoreResult<Self> {
+        sqlx::any::install_default_drivers();
+
+        // Every connection to an in-memory SQLite database gets its own
+        // empty database, so keep exactly one connection alive forever
+        let in_memory = url.contains(":memory:");
+        let mut options = AnyPoolOptions::new().max_connections(if in_memory { 1 } else { 10 });
+        if in_memory {
+            options = options.idle_timeout(None).max_lifetime(None);
+        }
+        let pool = options.connect(url).await?;
+
+        if url.starts_with("sqlite") {
+            sqlx::migrate!("./migrations/sqlite").run(&pool).await?;
+        } else {
+            sqlx::migrate!("./migrations/postgres").run(&pool).await?;
+        }
+        info!("Database schema is up to date");
+
+        Ok(Self { pool })
+    }
+
+    /// Load every object matching `filter` (SQL over `objects`, binding
+    /// `$1` to `id`), optionally preceded by a `WITH` clause
+    async fn load_matching(
+        &self,
+        with: &str,
+        filter: &str,
+        id: ObjectId,
+    ) -> StoreResult<Vec<ObjectRecord>> {
+        let id = to_db(id);
+        let objects = sqlx::query(&format!(
+            "{with}SELECT id, kind, name, owner, location, destination, flags, locks, version
+             FROM objects WHERE {filter} ORDER BY id"
+        ))
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let matching = format!("SELECT id FROM objects WHERE {filter}");
+        let attributes = sqlx::query(&format!(
+            "{with}SELECT object_id, name, value, owner, flags FROM attributes
+             WHERE object_id IN ({matching}) ORDER BY object_id, name"
+        ))
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        let scripts = sqlx::query(&format!(
+            "{with}SELECT object_id, source, owner, flags FROM scripts
+             WHERE object_id IN ({matching})"
+        ))
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        let revisions = sqlx::query(&format!(
+            "{with}SELECT object_id, revision, source, author, created_at, message
+             FROM script_revisions WHERE object_id IN ({matching})
+             ORDER BY object_id, revision"
+        ))
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        let timers = sqlx::query(&format!(
+            "{with}SELECT object_id, id, function, due_at FROM timers
+             WHERE object_id IN ({matching}) ORDER BY object_id, due_at, id"
+        ))
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut attrs_by_object: HashMap<i64, Vec<AttributeRecord>> = HashMap::new();
+        for row in &attributes {
+            attrs_by_object
+                .entry(row.try_get("object_id")?)
+                .or_default()
+                .push(attribute_from_row(row)?);
+        }
+        let mut scripts_by_object: HashMap<i64, (String, Option<ObjectId>, u8)> = HashMap::new();
+        for row in &scripts {
+            let owner = row.try_get::<Option<i64>, _>("owner")?.map(from_db);
+            let flags = row.try_get::<i64, _>("flags")? as u8;
+            scripts_by_object.insert(
+                row.try_get("object_id")?,
+                (row.try_get("source")?, owner, flags),
+            );
+        }
+        let mut revisions_by_object: HashMap<i64, Vec<ScriptRevisionRecord>> = HashMap::new();
+        for row in &revisions {
+            revisions_by_object
+                .entry(row.try_get("object_id")?)
+                .or_default()
+                .push(revision_from_row(row)?);
+        }
+        let mut timers_by_object: HashMap<i64, Vec<TimerRecord>> = HashMap::new();
+        for row in &timers {
+            timers_by_object
+                .entry(row.try_get("object_id")?)
+                .or_default()
+                .push(timer_from_row(row)?);
+        }
+
+        objects
+            .iter()
+            .map(|row| {
+                let mut record = object_from_row(row)?;
+                let key = to_db(record.id);
+                record.attributes = attrs_by_object.remove(&key).unwrap_or_default();
+                if let Some((source, owner, flags)) = scripts_by_object.remove(&key) {
+                    record.script = Some(source);
+                    record.script_owner = owner;
+                    record.script_flags = flags;
+                }
+                record.script_revisions = revisions_by_object.remove(&key).unwrap_or_default();
+                record.timers = timers_by_object.remove(&key).unwrap_or_default();
+                Ok(record)
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl WorldStore for SqlStore {
+    async fn load_object(&self, id: ObjectId) -> StoreResult<Option<ObjectRecord>> {
+        Ok(self.load_matching("", "id = $1", id).await?.pop())
+    }
+
+    async fn load_area(&self, room: ObjectId) -> StoreResult<Vec<ObjectRecord>> {
+        self.load_matching(AREA_CTE, "id IN (SELECT id FROM area)", room)
+            .await
+    }
+
+    async fn load_all(&self) -> StoreResult<Vec<ObjectRecord>> {
+        self.load_matching("", "id >= $1", ObjectId(0)).await
+    }
+
+    async fn apply_batch(&self, batch: &WriteBatch) -> StoreResult<BatchResult> {
+        let mut result = BatchResult::default();
+        let mut tx = self.pool.begin().await?;
+
+        for record in &batch.upserts {
+            if write_object(&mut tx, record, true).await? {
+                result.applied += 1;
+            } else {
+                result.skipped += 1;
+            }
+        }
+        for id in &batch.deletes {
+            result.deleted += delete_object(&mut tx, *id).await?;
+        }
+
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn import(&self, records: &[ObjectRecord]) -> StoreResult<BatchResult> {
+        let mut result = BatchResult::default();
+        let mut tx = self.pool.begin().await?;
+
+        let keep: HashSet<ObjectId> = records.iter().map(|record| record.id).collect();
+        let existing = sqlx::query("SELECT id FROM objects")
+            .fetch_all(&mut *tx)
+            .await?;
+        for row in &existing {
+            let id = from_db(row.try_get("id")?);
+            if !keep.contains(&id) {
+                result.deleted += delete_object(&mut tx, id).await?;
+            }
+        }
+        for record in records {
+            write_object(&mut tx, record, false).await?;
+            result.applied += 1;
+        }
+
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn create_account(
+        &self,
+        username: &str,
+        password_hash: &str,
+    ) -> StoreResult<AccountRecord> {
+        let created_at = now();
+        let row = sqlx::query(
+            "INSERT INTO accounts (username, password_hash, created_at)
+             VALUES ($1, $2, $3) RETURNING id",
+        )
+        .bind(username)
+        .bind(password_hash)
+        .bind(created_at)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                StoreError::Conflict(format!("account '{}'", username))
+            }
+            e => e.into(),
+        })?;
+
+        Ok(AccountRecord {
+            id: row.try_get("id")?,
+            username: username.to_string(),
+            password_hash: password_hash.to_string(),
+            created_at,
+        })
+    }
+
+    async fn find_account(&self, username: &str) -> StoreResult<Option<AccountRecord>> {
+        let row = sqlx::query(
+            "SELECT id, username, password_hash, created_at FROM accounts WHERE username = $1",
+        )
+        .bind(username)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            Ok(AccountRecord {
+                id: row.try_get("id")?,
+                username: row.try_get("username")?,
+                password_hash: row.try_get("password_hash")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn add_character(&self, account: i64, character: ObjectId) -> StoreResult<()> {
+        sqlx::query(
+            "INSERT INTO characters (account_id, object_id) VALUES ($1, $2)
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(account)
+        .bind(to_db(character))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn characters(&self, account: i64) -> StoreResult<Vec<ObjectId>> {
+        let rows = sqlx::query(
+            "SELECT object_id FROM characters WHERE account_id = $1 ORDER BY object_id",
+        )
+        .bind(account)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| Ok(from_db(row.try_get("object_id")?)))
+            .collect()
+    }
+
+    async fn ping(&self) -> StoreResult<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+/// Write one object with its attributes, script and script history
+///
+/// With `only_newer`, nothing is written unless the record's version is
+/// newer than the stored one, and revisions already stored are kept as
+/// they are. Otherwise the stored history is replaced. Returns whether
+/// the object was written.
+async fn write_object(
+    conn: &mut AnyConnection,
+    record: &ObjectRecord,
+    only_newer: bool,
+) -> StoreResult<bool> {
+    let locks =
+        serde_json::to_string(&record.locks).map_err(|e| StoreError::Corrupt(e.to_string()))?;
+    let sql = if only_newer {
+        format!("{UPSERT_OBJECT}{ONLY_NEWER}")
+    } else {
+        UPSERT_OBJECT.to_string()
+    };
+    let written = sqlx::query(&sql)
+        .bind(to_db(record.id))
+        .bind(record.kind.as_str())
+        .bind(&record.name)
+        .bind(to_db(record.owner))
+        .bind(record.location.map(to_db))
+        .bind(record.destination.map(to_db))
+        .bind(i64::from(record.flags))
+        .bind(locks)
+        .bind(record.version as i64)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+    if written == 0 {
+        return Ok(false);
+    }
+
+    let id = to_db(record.id);
+    sqlx::query("DELETE FROM attributes WHERE object_id = $1")
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+    for attr in &record.attributes {
+        sqlx::query(
+            "INSERT INTO attributes (object_id, name, value, owner, flags)
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(id)
+        .bind(&attr.name)
+        .bind(&attr.value)
+        .bind(to_db(attr.owner))
+        .bind(i64::from(attr.flags))
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    match &record.script {
+        Some(source) => {
+            sqlx::query(
+                "INSERT INTO scripts (object_id, source, owner, flags, updated_at)
+                 VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (object_id) DO UPDATE SET
+                     source = excluded.source, owner = excluded.owner,
+                     flags = excluded.flags, updated_at = excluded.updated_at",
+            )
+            .bind(id)
+            .bind(source)
+            .bind(record.script_owner.map(to_db))
+            .bind(i64::from(record.script_flags))
+            .bind(now())
+            .execute(&mut *conn)
+            .await?;
+        }
+        None => {
+            sqlx::query("DELETE FROM scripts WHERE object_id = $1")
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+
+    if !only_newer {
+        sqlx::query("DELETE FROM script_revisions WHERE object_id = $1")
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    for revision in &record.script_revisions {
+        sqlx::query(
+            "INSERT INTO script_revisions
+                 (object_id, revision, source, author, created_at, message)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (object_id, revision) DO NOTHING",
+        )
+        .bind(id)
+        .bind(i64::from(revision.number))
+        .bind(&revision.source)
+        .bind(to_db(revision.author))
+        .bind(revision.timestamp)
+        .bind(&revision.message)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    // Timers come and go, so the record's list replaces what is stored
+    sqlx::query("DELETE FROM timers WHERE object_id = $1")
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+    for timer in &record.timers {
+        sqlx::query("INSERT INTO timers (id, object_id, function, due_at) VALUES ($1, $2, $3, $4)")
+            .bind(timer.id as i64)
+            .bind(id)
+            .bind(&timer.function)
+            .bind(timer.due)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(true)
+}
+
+/// Remove an object and everything hanging off it, returning rows removed
+async fn delete_object(conn: &mut AnyConnection, id: ObjectId) -> StoreResult<usize> {
+    let id = to_db(id);
+    for table in [
+        "attributes",
+        "scripts",
+        "script_revisions",
+        "timers",
+        "characters",
+    ] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE object_id = $1"))
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(sqlx::query("DELETE FROM objects WHERE id = $1")
+        .bind(id)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected() as usize)
+}
+
+/// Object ids are stored as signed `BIGINT`
+fn to_db(id: ObjectId) -> i64 {
+    id.0 as i64
+}
+
+/// Inverse of [`to_db`]
+fn from_db(id: i64) -> ObjectId {
+    ObjectId(id as u64)
+}
+
+/// Current time in Unix seconds
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Build a record (without attributes or script) from an `objects` row
+fn object_from_row(row: &AnyRow) -> StoreResult<ObjectRecord> {
+    let kind: String = row.try_get("kind")?;
+    let locks: String = row.try_get("locks")?;
+    let id = from_db(row.try_get("id")?);
+
+    Ok(ObjectRecord {
+        id,
+        kind: ObjectKind::from_name(&kind)
+            .ok_or_else(|| StoreError::Corrupt(format!("{} has unknown kind '{}'", id, kind)))?,
+        name: row.try_get("name")?,
+        owner: from_db(row.try_get("owner")?),
+        location: row.try_get::<Option<i64>, _>("location")?.map(from_db),
+        destination: row.try_get::<Option<i64>, _>("destination")?.map(from_db),
+        flags: row.try_get::<i64, _>("flags")? as u32,
+        locks: serde_json::from_str::<BTreeMap<LockKind, String>>(&locks)
+            .map_err(|e| StoreError::Corrupt(format!("{} locks: {}", id, e)))?,
+        attributes: Vec::new(),
+        script: None,
+        script_owner: None,
+        script_flags: 0,
+        script_revisions: Vec::new(),
+        timers: Vec::new(),
+        version: row.try_get::<i64, _>("version")? as u64,
+    })
+}
+
+/// Build an attribute record from an `attributes` row
+fn attribute_from_row(row: &AnyRow) -> StoreResult<AttributeRecord> {
+    Ok(AttributeRecord {
+        name: row.try_get("name")?,
+        value: row.try_get("value")?,
+        owner: from_db(row.try_get("owner")?),
+        flags: row.try_get::<i64, _>("flags")? as u8,
+    })
+}
+
+/// Build a timer record from a `timers` row
+fn timer_from_row(row: &AnyRow) -> StoreResult<TimerRecord> {
+    Ok(TimerRecord {
+        id: row.try_get::<i64, _>("id")? as u64,
+        function: row.try_get("function")?,
+        due: row.try_get("due_at")?,
+    })
+}
+
+/// Build a revision record from a `script_revisions` row
+fn revision_from_row(row: &AnyRow) -> StoreResult<ScriptRevisionRecord> {
+    Ok(ScriptRevisionRecord {
+        number: row.try_get::<i64, _>("revision")? as u32,
+        source: row.try_get("source")?,
+        author: from_db(row.try_get("author")?),
+        timestamp: row.try_get("created_at")?,
+        message: row.try_get("message")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn memory_store() -> SqlStore {
+        SqlStore::connect("sqlite::memory:").await.unwrap()
+    }
+
+    fn record(id: u64, kind: ObjectKind, location: Option<u64>, version: u64) -> ObjectRecord {
+        ObjectRecord {
+            id: ObjectId(id),
+            kind,
+            name: format!("Object {}", id),
+            owner: ObjectId(1),
+            location: location.map(ObjectId),
+            destination: None,
+            flags: 0,
+            locks: BTreeMap::new(),
+            attributes: Vec::new(),
+            script: None,
+            script_owner: None,
+            script_flags: 0,
+            script_revisions: Vec::new(),
+            timers: Vec::new(),
+            version,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let store = memory_store().await;
+        let mut lamp = record(10, ObjectKind::Thing, Some(0), 1);
+        lamp.flags = 0b101;
+        lamp.locks.insert(LockKind::Basic, "=#1 | +#7".to_string());
+        lamp.attributes.push(AttributeRecord {
+            name: "DESC".to_string(),
+            value: "A brass lamp.".to_string(),
+            owner: ObjectId(1),
+            flags: 0b1000,
+        });
+        lamp.script = Some("fn on_use(actor) { 42 }".to_string());
+        lamp.script_owner = Some(ObjectId(7));
+        lamp.script_flags = 0b110;
+        lamp.script_revisions.push(ScriptRevisionRecord {
+            number: 1,
+            source: "fn on_use(actor) { 42 }".to_string(),
+            author: ObjectId(1),
+            timestamp: 1_700_000_000,
+            message: "first light".to_string(),
+        });
+        lamp.timers.push(TimerRecord {
+            id: 3,
+            function: "flicker".to_string(),
+            due: 1_700_000_060_000,
+        });
+
+        let batch = WriteBatch {
+            upserts: vec![lamp.clone()],
+            deletes: Vec::new(),
+        };
+        assert_eq!(store.apply_batch(&batch).await.unwrap().applied, 1);
+        assert_eq!(store.load_object(ObjectId(10)).await.unwrap(), Some(lamp));
+        assert_eq!(store.load_object(ObjectId(99)).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_stale_versions_are_skipped() {
+        let store = memory_store().await;
+        let newer = record(10, ObjectKind::Thing, None, 5);
+        let mut older = newer.clone();
+        older.name = "Stale".to_string();
+        older.version = 4;
+
+        let batch = |record: &ObjectRecord| WriteBatch {
+            upserts: vec![record.clone()],
+            deletes: Vec::new(),
+        };
+        store.apply_batch(&batch(&newer)).await.unwrap();
+        // A retried batch and an out-of-order older write are both no-ops
+        assert_eq!(store.apply_batch(&batch(&newer)).await.unwrap().skipped, 1);
+        assert_eq!(store.apply_batch(&batch(&older)).await.unwrap().skipped, 1);
+        assert_eq!(
+            store.load_object(ObjectId(10)).await.unwrap().unwrap().name,
+            "Object 10"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_area_and_delete() {
+        let store = memory_store().await;
+        let batch = WriteBatch {
+            upserts: vec![
+                record(0, ObjectKind::Room, None, 1),
+                record(1, ObjectKind::Player, Some(0), 1),
+                record(2, ObjectKind::Thing, Some(1), 1),
+                record(3, ObjectKind::Room, None, 1),
+                record(4, ObjectKind::Thing, Some(3), 1),
+            ],
+            deletes: Vec::new(),
+        };
+        store.apply_batch(&batch).await.unwrap();
+
+        let area: Vec<_> = store
+            .load_area(ObjectId(0))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|record| record.id.0)
+            .collect();
+        assert_eq!(area, vec![0, 1, 2]);
+
+        let deletes = WriteBatch {
+            upserts: Vec::new(),
+            deletes: vec![ObjectId(2), ObjectId(42)],
+        };
+        assert_eq!(store.apply_batch(&deletes).await.unwrap().deleted, 1);
+        assert_eq!(store.load_area(ObjectId(0)).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_import_replaces_world() {
+        let store = memory_store().await;
+        let batch = WriteBatch {
+            upserts: vec![
+                record(0, ObjectKind::Room, None, 1),
+                record(7, ObjectKind::Thing, Some(0), 9),
+            ],
+            deletes: Vec::new(),
+        };
+        store.apply_batch(&batch).await.unwrap();
+
+        // Imported versions win even when older than what is stored
+        let mut lamp = record(7, ObjectKind::Thing, Some(0), 2);
+        lamp.name = "Lamp".to_string();
+        let result = store.import(&[lamp.clone()]).await.unwrap();
+        assert_eq!((result.applied, result.deleted), (1, 1));
+        assert_eq!(store.load_all().await.unwrap(), vec![lamp]);
+    }
+
+    #[tokio::test]
+    async fn test_accounts_and_characters() {
+        let store = memory_store().await;
+        let batch = WriteBatch {
+            upserts: vec![record(5, ObjectKind::Player, Some(0), 1)],
+            deletes: Vec::new(),
+        };
+        store.apply_batch(&batch).await.unwrap();
+
+        let account = store.create_account("alice", "hash").await.unwrap();
+        assert!(matches!(
+            store.create_account("alice", "other").await,
+            Err(StoreError::Conflict(_))
+        ));
+        assert_eq!(
+            store.find_account("alice").await.unwrap(),
+            Some(account.clone())
+        );
+
+        store.add_character(account.id, ObjectId(5)).await.unwrap();
+        store.add_character(account.id, ObjectId(5)).await.unwrap();
+        assert_eq!(
+            store.characters(account.id).await.unwrap(),
+            vec![ObjectId(5)]
+        );
+    }
+}
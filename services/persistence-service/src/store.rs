@@ -0,0 +1,95 @@
+//! Storage Trait
+//!
+//! The interface every storage backend implements. The HTTP API and the
+//! rest of the service only see `dyn WorldStore`, so PostgreSQL in
+//! production and SQLite in tests are interchangeable.
+
+use async_trait::async_trait;
+use shared::components::ObjectId;
+use shared::records::{AccountRecord, BatchResult, ObjectRecord, WriteBatch};
+use std::fmt;
+
+/// Errors returned by a store
+#[derive(Debug)]
+pub enum StoreError {
+    /// The database rejected or failed a query
+    Database(sqlx::Error),
+    /// Schema migrations could not be applied
+    Migration(sqlx::migrate::MigrateError),
+    /// A unique constraint was violated (e.g. duplicate username)
+    Conflict(String),
+    /// A stored row could not be turned back into a record
+    Corrupt(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Database(e) => write!(f, "database error: {}", e),
+            Self::Migration(e) => write!(f, "migration error: {}", e),
+            Self::Conflict(what) => write!(f, "already exists: {}", what),
+            Self::Corrupt(what) => write!(f, "corrupt row: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<sqlx::Error> for StoreError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Database(e)
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for StoreError {
+    fn from(e: sqlx::migrate::MigrateError) -> Self {
+        Self::Migration(e)
+    }
+}
+
+/// Result type for store operations
+pub type StoreResult<T> = Result<T, StoreError>;
+
+/// Storage for world objects, accounts and characters
+#[async_trait]
+pub trait WorldStore: Send + Sync {
+    /// Load one object with its attributes and script
+    async fn load_object(&self, id: ObjectId) -> StoreResult<Option<ObjectRecord>>;
+
+    /// Load a room and everything inside it, recursively, ordered by id
+    async fn load_area(&self, room: ObjectId) -> StoreResult<Vec<ObjectRecord>>;
+
+    /// Load every object, ordered by id
+    async fn load_all(&self) -> StoreResult<Vec<ObjectRecord>>;
+
+    /// Apply a batch of upserts and deletes in one transaction
+    ///
+    /// Upserts are idempotent: a record is only written if its version is
+    /// newer than the stored one, so retried batches are harmless.
+    async fn apply_batch(&self, batch: &WriteBatch) -> StoreResult<BatchResult>;
+
+    /// Replace the whole world with `records` in one transaction
+    ///
+    /// Used to restore dumps: versions are not compared, and stored objects
+    /// missing from `records` are deleted.
+    async fn import(&self, records: &[ObjectRecord]) -> StoreResult<BatchResult>;
+
+    /// Create a login account
+    async fn create_account(
+        &self,
+        username: &str,
+        password_hash: &str,
+    ) -> StoreResult<AccountRecord>;
+
+    /// Look up an account by username
+    async fn find_account(&self, username: &str) -> StoreResult<Option<AccountRecord>>;
+
+    /// Attach a player object to an account
+    async fn add_character(&self, account: i64, character: ObjectId) -> StoreResult<()>;
+
+    /// Player objects attached to an account
+    async fn characters(&self, account: i64) -> StoreResult<Vec<ObjectId>>;
+
+    /// Check that the backend is reachable
+    async fn ping(&self) -> StoreResult<()>;
+}
@@ -1,8 +1,30 @@
 //! Physics Service - Physics simulation
+//!
+//! Runs a headless Bevy + Avian world with a body for every object
+//! world-state gives one (see `world-state/src/physics.rs`), so physics
+//! load scales apart from world logic. world-state syncs bodies and
+//! follows the simulation over gRPC (`shared/proto/physics.proto`).
+//!
+//! Configuration:
+//! - `GRPC_ADDR`: address for world-state to connect to (default
+//!   `0.0.0.0:50055`)
 
 use axum::{routing::get, Router};
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::prelude::*;
+use shared::physics::PHYSICS_TIMESTEP;
 use std::net::SocketAddr;
-use tracing::info;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{info, warn};
+
+mod proto;
+mod service;
+mod simulation;
+
+use proto::physics_server::PhysicsServer;
+use service::{PhysicsService, FRAME_QUEUE};
+use simulation::{Inbox, Outbox, SimulationPlugin};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -17,6 +39,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     dotenvy::dotenv().ok();
 
+    let grpc_addr = std::env::var("GRPC_ADDR")
+        .ok()
+        .and_then(|addr| addr.parse().ok())
+        .unwrap_or(SocketAddr::from(([0, 0, 0, 0], 50055)));
+
     let health_app = Router::new()
         .route("/health", get(health_check))
         .route("/ready", get(readiness_check));
@@ -24,7 +51,51 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr = SocketAddr::from(([0, 0, 0, 0], 8085));
     info!("Health check server listening on {}", addr);
 
-    axum::serve(tokio::net::TcpListener::bind(addr).await?, health_app).await?;
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(
+            tokio::net::TcpListener::bind(addr).await.unwrap(),
+            health_app,
+        )
+        .await
+        {
+            warn!("Health check server error: {}", e);
+        }
+    });
+
+    let (batch_tx, batch_rx) = mpsc::unbounded_channel();
+    let (frame_tx, _) = broadcast::channel(FRAME_QUEUE);
+    let service = PhysicsService::new(batch_tx, frame_tx.clone());
+    info!("gRPC server listening on {}", grpc_addr);
+    tokio::spawn(async move {
+        if let Err(e) = tonic::transport::Server::builder()
+            .add_service(PhysicsServer::new(service))
+            .serve(grpc_addr)
+            .await
+        {
+            warn!("gRPC server error: {}", e);
+        }
+    });
+
+    // Bevy's schedule runner loops until the app exits, so it gets a
+    // thread of its own rather than a tokio worker
+    tokio::task::spawn_blocking(move || {
+        App::new()
+            // Logging is already set up through tracing, so no LogPlugin.
+            // Avian keeps `Transform`s in step with bodies, so it needs
+            // the transform and hierarchy plugins `MinimalPlugins` lacks.
+            .add_plugins((
+                MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f32(
+                    PHYSICS_TIMESTEP,
+                ))),
+                TransformPlugin,
+                HierarchyPlugin,
+            ))
+            .insert_resource(Inbox(batch_rx))
+            .insert_resource(Outbox(frame_tx))
+            .add_plugins(SimulationPlugin)
+            .run();
+    })
+    .await?;
 
     Ok(())
 }
@@ -0,0 +1,144 @@
+//! Wire Types
+//!
+//! The gRPC types generated from `shared/proto/physics.proto`, and their
+//! conversions to and from `shared::physics` and glam.
+
+#![allow(clippy::result_large_err)]
+
+use bevy::math::{Quat as GlamQuat, Vec3 as GlamVec3};
+use shared::physics::{BodyDef, BodyKind as Kind, Shape as ShapeDef};
+use std::fmt;
+
+tonic::include_proto!("physics");
+
+/// Why a body from world-state can't be simulated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BodyError {
+    /// It has no shape
+    MissingShape(u64),
+    /// A size is zero, negative or not a number
+    BadSize(u64),
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingShape(id) => write!(f, "body #{} has no shape", id),
+            Self::BadSize(id) => write!(f, "body #{} has a bad size", id),
+        }
+    }
+}
+
+impl std::error::Error for BodyError {}
+
+impl Body {
+    /// How the body moves and what shape it is
+    pub fn def(&self) -> Result<BodyDef, BodyError> {
+        let shape = self
+            .shape
+            .and_then(|shape| shape.shape)
+            .ok_or(BodyError::MissingShape(self.id))?;
+        let shape = match shape {
+            shape::Shape::Ball(ball) => ShapeDef::Ball {
+                radius: ball.radius,
+            },
+            shape::Shape::Cuboid(cuboid) => ShapeDef::Cuboid {
+                half_extents: cuboid.half_extents.unwrap_or_default().into(),
+            },
+            shape::Shape::Capsule(capsule) => ShapeDef::Capsule {
+                radius: capsule.radius,
+                half_length: capsule.half_length,
+            },
+        };
+        let sizes = match shape {
+            ShapeDef::Ball { radius } => vec![radius],
+            ShapeDef::Cuboid { half_extents } => half_extents.to_array().to_vec(),
+            ShapeDef::Capsule {
+                radius,
+                half_length,
+            } => vec![radius, half_length],
+        };
+        if !sizes.iter().all(|size| size.is_finite() && *size > 0.0) {
+            return Err(BodyError::BadSize(self.id));
+        }
+        let kind = match self.kind() {
+            BodyKind::Static => Kind::Static,
+            BodyKind::Dynamic => Kind::Dynamic,
+            BodyKind::Kinematic => Kind::Kinematic,
+        };
+        Ok(BodyDef {
+            kind,
+            shape,
+            sensor: self.sensor,
+        })
+    }
+}
+
+impl From<GlamVec3> for Vec3 {
+    fn from(v: GlamVec3) -> Self {
+        Self {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+        }
+    }
+}
+
+impl From<Vec3> for GlamVec3 {
+    fn from(v: Vec3) -> Self {
+        GlamVec3::new(v.x, v.y, v.z)
+    }
+}
+
+impl From<GlamQuat> for Quat {
+    fn from(q: GlamQuat) -> Self {
+        Self {
+            x: q.x,
+            y: q.y,
+            z: q.z,
+            w: q.w,
+        }
+    }
+}
+
+impl From<Quat> for GlamQuat {
+    fn from(q: Quat) -> Self {
+        GlamQuat::from_xyzw(q.x, q.y, q.z, q.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body_defs() {
+        let mut body = Body {
+            id: 7,
+            kind: BodyKind::Dynamic.into(),
+            shape: Some(Shape {
+                shape: Some(shape::Shape::Cuboid(Cuboid {
+                    half_extents: Some(GlamVec3::new(1.0, 1.0, 0.5).into()),
+                })),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            body.def(),
+            Ok(BodyDef {
+                kind: Kind::Dynamic,
+                shape: ShapeDef::Cuboid {
+                    half_extents: GlamVec3::new(1.0, 1.0, 0.5)
+                },
+                sensor: false,
+            })
+        );
+
+        body.shape = Some(Shape {
+            shape: Some(shape::Shape::Ball(Ball { radius: 0.0 })),
+        });
+        assert_eq!(body.def(), Err(BodyError::BadSize(7)));
+        body.shape = None;
+        assert_eq!(body.def(), Err(BodyError::MissingShape(7)));
+    }
+}
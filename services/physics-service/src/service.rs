@@ -0,0 +1,128 @@
+//! gRPC Service
+//!
+//! The `Physics` service world-state talks to. It never touches the
+//! simulation itself: body batches go to the Bevy app over a channel,
+//! and frames the app publishes come back to every subscriber through a
+//! broadcast channel.
+//!
+//! A subscriber that falls more than [`FRAME_QUEUE`] frames behind has its
+//! stream ended with `DATA_LOSS`. Skipping frames would leave it with
+//! stale positions for bodies that have since come to rest, whereas
+//! reconnecting makes world-state send every body again.
+
+// tonic's API returns its large `Status` by value
+#![allow(clippy::result_large_err)]
+
+use std::pin::Pin;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+use tracing::warn;
+
+use crate::proto::physics_server::Physics;
+use crate::proto::{BodyBatch, Frame, SubscribeRequest, SyncReply};
+
+/// Frames buffered per subscriber, about four seconds of simulation
+pub const FRAME_QUEUE: usize = 256;
+
+/// Hands requests to the simulation
+pub struct PhysicsService {
+    batches: mpsc::UnboundedSender<BodyBatch>,
+    frames: broadcast::Sender<Frame>,
+}
+
+impl PhysicsService {
+    /// Serve with the simulation's ends of its channels
+    pub fn new(
+        batches: mpsc::UnboundedSender<BodyBatch>,
+        frames: broadcast::Sender<Frame>,
+    ) -> Self {
+        Self { batches, frames }
+    }
+}
+
+type FrameStream = Pin<Box<dyn Stream<Item = Result<Frame, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Physics for PhysicsService {
+    async fn sync_bodies(
+        &self,
+        request: Request<BodyBatch>,
+    ) -> Result<Response<SyncReply>, Status> {
+        self.batches
+            .send(request.into_inner())
+            .map_err(|_| Status::unavailable("simulation stopped"))?;
+        Ok(Response::new(SyncReply {}))
+    }
+
+    type SubscribeStream = FrameStream;
+
+    async fn subscribe(
+        &self,
+        _request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let mut failed = false;
+        let frames = BroadcastStream::new(self.frames.subscribe())
+            .map(|frame| {
+                frame.map_err(|BroadcastStreamRecvError::Lagged(skipped)| {
+                    warn!("Subscriber fell {} frames behind; dropping it", skipped);
+                    Status::data_loss("fell behind the simulation")
+                })
+            })
+            // Stop after the error rather than carry on with gaps
+            .take_while(move |frame| !std::mem::replace(&mut failed, frame.is_err()));
+        Ok(Response::new(Box::pin(frames)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_batches_in_frames_out() {
+        let (batch_tx, mut batch_rx) = mpsc::unbounded_channel();
+        let (frame_tx, _) = broadcast::channel(2);
+        let service = PhysicsService::new(batch_tx, frame_tx.clone());
+
+        let batch = BodyBatch {
+            reset: true,
+            ..Default::default()
+        };
+        service
+            .sync_bodies(Request::new(batch.clone()))
+            .await
+            .unwrap();
+        assert_eq!(batch_rx.try_recv(), Ok(batch));
+
+        let mut frames = service
+            .subscribe(Request::new(SubscribeRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        let frame = |step| Frame {
+            step,
+            ..Default::default()
+        };
+        frame_tx.send(frame(1)).unwrap();
+        assert_eq!(frames.next().await.unwrap().unwrap().step, 1);
+
+        // Falling behind ends the stream
+        for step in 2..=4 {
+            frame_tx.send(frame(step)).unwrap();
+        }
+        let status = frames.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), tonic::Code::DataLoss);
+        assert!(frames.next().await.is_none());
+
+        // Nobody left to simulate for
+        drop(batch_rx);
+        let status = service
+            .sync_bodies(Request::new(BodyBatch::default()))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+    }
+}
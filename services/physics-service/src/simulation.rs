@@ -0,0 +1,242 @@
+//! Simulation
+//!
+//! A headless Avian world holding a body for every object world-state
+//! told us about. Each fixed step (`PHYSICS_TIMESTEP`) it:
+//!
+//! 1. applies the [`BodyBatch`]es that arrived, spawning, changing or
+//!    despawning bodies
+//! 2. steps Avian, with contacts between bodies in different rooms thrown
+//!    away before the solver sees them
+//! 3. publishes a [`Frame`] with the dynamic bodies that moved and the
+//!    contacts that began or ended, if there were any
+//!
+//! World coordinates are z up, so gravity points down z and the floor
+//! every room stands on is the plane z = 0.
+//!
+//! # Learning Note
+//! Rooms overlap: every room's coordinates start at the origin. Rather
+//! than spacing them out (large coordinates lose `f32` precision) or
+//! using collision layers (there are only 32), bodies remember their
+//! room and a system in Avian's `PostProcessCollisions` schedule drops
+//! contacts between rooms. The broad phase still pairs them up, which is
+//! cheap next to solving contacts.
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use shared::components::ObjectId;
+use shared::physics::{self, BodyDef, BodyKind, GRAVITY, PHYSICS_TIMESTEP};
+use std::collections::HashMap;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::proto::{self, BodyBatch, ContactPhase, Frame};
+
+/// Steps world-state's bodies and publishes what happened
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(PhysicsPlugins::default())
+            .insert_resource(Time::<Fixed>::from_seconds(PHYSICS_TIMESTEP as f64))
+            .insert_resource(Gravity(Vec3::NEG_Z * GRAVITY))
+            .init_resource::<Bodies>()
+            .add_systems(Startup, spawn_floor)
+            .add_systems(FixedUpdate, apply_batches)
+            .add_systems(PostProcessCollisions, keep_rooms_apart)
+            .add_systems(FixedPostUpdate, publish_frame.after(PhysicsSet::Sync));
+    }
+}
+
+/// Body batches from the gRPC service
+#[derive(Resource)]
+pub struct Inbox(pub mpsc::UnboundedReceiver<BodyBatch>);
+
+/// Where frames go to reach subscribers
+#[derive(Resource)]
+pub struct Outbox(pub broadcast::Sender<Frame>);
+
+/// The world-state object a body stands for
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BodyId(pub ObjectId);
+
+/// The room a body is in; it only touches bodies in the same room
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Room(pub ObjectId);
+
+/// Every body's entity, and the definition its collider was built from
+#[derive(Resource, Default)]
+struct Bodies(HashMap<ObjectId, (Entity, BodyDef)>);
+
+fn spawn_floor(mut commands: Commands) {
+    commands.spawn((RigidBody::Static, Collider::half_space(Vec3::Z)));
+}
+
+fn rigid_body(kind: BodyKind) -> RigidBody {
+    match kind {
+        BodyKind::Static => RigidBody::Static,
+        BodyKind::Dynamic => RigidBody::Dynamic,
+        BodyKind::Kinematic => RigidBody::Kinematic,
+    }
+}
+
+fn collider(shape: physics::Shape) -> Collider {
+    match shape {
+        physics::Shape::Ball { radius } => Collider::sphere(radius),
+        physics::Shape::Cuboid { half_extents } => {
+            let size = half_extents * 2.0;
+            Collider::cuboid(size.x, size.y, size.z)
+        }
+        // Avian's capsules stand along y; ours stand along z
+        physics::Shape::Capsule {
+            radius,
+            half_length,
+        } => Collider::capsule_endpoints(radius, Vec3::NEG_Z * half_length, Vec3::Z * half_length),
+    }
+}
+
+/// Spawn, change and despawn bodies as world-state asked
+fn apply_batches(mut commands: Commands, mut inbox: ResMut<Inbox>, mut bodies: ResMut<Bodies>) {
+    while let Ok(batch) = inbox.0.try_recv() {
+        if batch.reset {
+            for (_, (entity, _)) in bodies.0.drain() {
+                commands.entity(entity).despawn();
+            }
+        }
+        for id in batch.removed {
+            if let Some((entity, _)) = bodies.0.remove(&ObjectId(id)) {
+                commands.entity(entity).despawn();
+            }
+        }
+        for body in batch.upserts {
+            let def = match body.def() {
+                Ok(def) => def,
+                Err(e) => {
+                    warn!("Ignoring body: {}", e);
+                    continue;
+                }
+            };
+            let id = ObjectId(body.id);
+            let position = body.position.map_or(Vec3::ZERO, Vec3::from);
+            let rotation = body.rotation.map_or(Quat::IDENTITY, Quat::from);
+
+            let (mut entity, rebuild) = match bodies.0.get(&id) {
+                Some(&(entity, old)) => (commands.entity(entity), old != def),
+                None => (commands.spawn(BodyId(id)), true),
+            };
+            // Moving a body teleports it; velocity carries on
+            entity.insert((
+                Room(ObjectId(body.room)),
+                Position(position),
+                Rotation(rotation),
+                Transform::from_translation(position).with_rotation(rotation),
+            ));
+            // Building a collider is the expensive part, and players move
+            // (and so are sent again) every step they walk
+            if rebuild {
+                entity.insert((rigid_body(def.kind), collider(def.shape)));
+                if def.sensor {
+                    entity.insert(Sensor);
+                } else {
+                    entity.remove::<Sensor>();
+                }
+            }
+            bodies.0.insert(id, (entity.id(), def));
+        }
+    }
+}
+
+fn keep_rooms_apart(mut collisions: ResMut<Collisions>, rooms: Query<&Room>) {
+    collisions.retain(|contacts| {
+        match (rooms.get(contacts.entity1), rooms.get(contacts.entity2)) {
+            (Ok(a), Ok(b)) => a == b,
+            // The floor is under every room
+            _ => true,
+        }
+    });
+}
+
+type Moved = Or<(Changed<Position>, Changed<Rotation>)>;
+
+/// Send this step's moves and contacts to subscribers
+fn publish_frame(
+    mut step: Local<u64>,
+    outbox: Res<Outbox>,
+    moved: Query<(&BodyId, &RigidBody, &Position, &Rotation), Moved>,
+    mut started: EventReader<CollisionStarted>,
+    mut ended: EventReader<CollisionEnded>,
+    collisions: Res<Collisions>,
+    bodies: Query<(&BodyId, &Position, &Rotation, Has<Sensor>)>,
+) {
+    *step += 1;
+    let mut frame = Frame {
+        step: *step,
+        ..default()
+    };
+    for (id, rigid_body, position, rotation) in &moved {
+        if rigid_body.is_dynamic() {
+            frame.transforms.push(proto::Transform {
+                id: id.0 .0,
+                position: Some(position.0.into()),
+                rotation: Some(rotation.0.into()),
+            });
+        }
+    }
+    for CollisionStarted(a, b) in started.read() {
+        frame
+            .contacts
+            .extend(contact(*a, *b, ContactPhase::Begin, &collisions, &bodies));
+    }
+    for CollisionEnded(a, b) in ended.read() {
+        frame
+            .contacts
+            .extend(contact(*a, *b, ContactPhase::End, &collisions, &bodies));
+    }
+    if frame.transforms.is_empty() && frame.contacts.is_empty() {
+        return;
+    }
+    // Nobody may be subscribed; the frame is simply not needed then
+    let _ = outbox.0.send(frame);
+}
+
+/// A contact between two bodies, or `None` if either isn't an object
+/// (the floor)
+fn contact(
+    a: Entity,
+    b: Entity,
+    phase: ContactPhase,
+    collisions: &Collisions,
+    bodies: &Query<(&BodyId, &Position, &Rotation, Has<Sensor>)>,
+) -> Option<proto::Contact> {
+    let (id_a, position_a, rotation_a, sensor_a) = bodies.get(a).ok()?;
+    let (id_b, _, _, sensor_b) = bodies.get(b).ok()?;
+    let (low, high) = if id_a.0 <= id_b.0 {
+        (id_a.0, id_b.0)
+    } else {
+        (id_b.0, id_a.0)
+    };
+    let mut contact = proto::Contact {
+        a: low.0,
+        b: high.0,
+        phase: phase.into(),
+        sensor: sensor_a || sensor_b,
+        point: None,
+        impulse: 0.0,
+    };
+    if phase == ContactPhase::Begin {
+        if let Some(contacts) = collisions.get(a, b) {
+            // Contact points are local to the first collider of the pair
+            let (position, rotation) = if contacts.entity1 == a {
+                (position_a.0, rotation_a.0)
+            } else {
+                let (_, position_b, rotation_b, _) = bodies.get(b).ok()?;
+                (position_b.0, rotation_b.0)
+            };
+            contact.point = contacts
+                .manifolds
+                .iter()
+                .find_map(|manifold| manifold.contacts.first())
+                .map(|data| (position + rotation * data.point1).into());
+            contact.impulse = contacts.total_normal_impulse;
+        }
+    }
+    Some(contact)
+}
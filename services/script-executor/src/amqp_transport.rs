@@ -0,0 +1,54 @@
+//! AMQP Job Transport (Placeholder)
+//!
+//! This module provides a placeholder for carrying script jobs over
+//! RabbitMQ, so several executor processes can share one queue.
+//! Uncomment the `lapin` dependency in Cargo.toml and enable the
+//! `amqp-transport` feature to work on it.
+//!
+//! Intended mapping onto [`JobTransport`]:
+//! - one durable queue declared with `x-max-priority`, so `Interactive`
+//!   jobs are delivered before `Tick` jobs
+//! - `push` publishes a JSON [`QueuedJob`]; a full queue (`x-max-length`
+//!   with `reject-publish`) surfaces as [`TransportError::Full`]
+//! - `pop` consumes with a prefetch of one per worker
+//! - `remove` cannot pull a message back out of a broker, so it returns
+//!   false and cancellation falls back to the pool's cancel flag
+
+#![allow(dead_code)] // Allow dead code in placeholder
+
+use tracing::info;
+
+use crate::transport::{JobId, JobTransport, QueuedJob, TransportError};
+
+/// RabbitMQ-backed job queue (placeholder)
+pub struct AmqpTransport {
+    url: String,
+}
+
+impl AmqpTransport {
+    /// Connect to the broker at `url`
+    pub fn connect(url: &str) -> Result<Self, String> {
+        info!("AMQP transport requested for {} (placeholder)", url);
+        Err("AMQP transport not implemented yet; use the in-process queue".to_string())
+    }
+}
+
+impl JobTransport for AmqpTransport {
+    fn push(&self, _job: QueuedJob) -> Result<(), TransportError> {
+        Err(TransportError::Closed)
+    }
+
+    fn pop(&self) -> Option<QueuedJob> {
+        None
+    }
+
+    fn remove(&self, _id: JobId) -> bool {
+        false
+    }
+
+    fn len(&self) -> usize {
+        0
+    }
+
+    fn close(&self) {}
+}
@@ -0,0 +1,154 @@
+//! Output Capture
+//!
+//! Collects what a run does besides returning a value: the lines it
+//! writes with `print` and `debug`, and, when its job asks for a trace,
+//! every call it makes to a host function. [`RhaiExecutor::run`](crate::rhai_executor::RhaiExecutor::run)
+//! turns the capture into the [`ExecutionTrace`] returned with the job's
+//! output.
+//!
+//! Output is bounded: lines past [`MAX_OUTPUT_LINES`] are counted but not
+//! kept, and long lines are cut at [`MAX_LINE_LEN`] bytes. A script can't
+//! grow a reply without limit by printing in a loop.
+//!
+//! Outside a capture (plain `execute` calls, validation), script output is
+//! only logged.
+//!
+//! # Learning Note
+//! Rhai sends `print` and `debug` to stdout unless the engine is given
+//! `on_print`/`on_debug` callbacks. Like the meter, the capture is
+//! thread-local: the callbacks have no handle on the run, but Rhai always
+//! calls them on the thread doing the evaluation.
+
+use rhai::Engine;
+use shared::scripting::{ExecutionTrace, HostCall, OutputLine, OutputStream};
+use std::cell::RefCell;
+use std::time::Duration;
+use tracing::debug;
+
+/// Most output lines kept per run
+pub const MAX_OUTPUT_LINES: usize = 200;
+
+/// Most host calls kept per traced run
+pub const MAX_HOST_CALLS: usize = 200;
+
+/// Longest output line kept, in bytes
+pub const MAX_LINE_LEN: usize = 1_000;
+
+/// What the run in progress on this thread has done so far
+#[derive(Debug, Default)]
+pub struct Capture {
+    output: Vec<OutputLine>,
+    dropped_lines: usize,
+    host_calls: Option<Vec<HostCall>>,
+}
+
+impl Capture {
+    /// Complete the trace with the run's usage
+    pub fn into_trace(self, ops: u64, duration: Duration) -> ExecutionTrace {
+        ExecutionTrace {
+            output: self.output,
+            dropped_lines: self.dropped_lines,
+            ops,
+            duration_us: duration.as_micros().min(u64::MAX as u128) as u64,
+            host_calls: self.host_calls,
+        }
+    }
+}
+
+thread_local! {
+    static CAPTURE: RefCell<Option<Capture>> = const { RefCell::new(None) };
+}
+
+/// Route `print` and `debug` on `engine` into the capture
+pub fn register(engine: &mut Engine) {
+    engine.on_print(|text| write(OutputStream::Print, text, 0));
+    engine.on_debug(|text, _source, pos| write(OutputStream::Debug, text, pos.line().unwrap_or(0)));
+}
+
+/// Start capturing on this thread, recording host calls if `trace` is set
+pub fn begin(trace: bool) {
+    let capture = Capture {
+        host_calls: trace.then(Vec::new),
+        ..Capture::default()
+    };
+    CAPTURE.with(|slot| *slot.borrow_mut() = Some(capture));
+}
+
+/// Stop capturing and return what was collected since [`begin`]
+pub fn take() -> Capture {
+    CAPTURE.with(|slot| slot.borrow_mut().take().unwrap_or_default())
+}
+
+/// Record a host function call, if the run is being traced
+pub fn record_call(function: &str, args: Vec<String>, result: Result<String, String>) {
+    CAPTURE.with(|slot| {
+        let mut slot = slot.borrow_mut();
+        let Some(calls) = slot.as_mut().and_then(|c| c.host_calls.as_mut()) else {
+            return;
+        };
+        if calls.len() < MAX_HOST_CALLS {
+            calls.push(HostCall {
+                function: function.to_string(),
+                args,
+                result,
+            });
+        }
+    });
+}
+
+fn write(stream: OutputStream, text: &str, line: usize) {
+    CAPTURE.with(|slot| match slot.borrow_mut().as_mut() {
+        Some(capture) if capture.output.len() < MAX_OUTPUT_LINES => {
+            capture.output.push(OutputLine {
+                stream,
+                text: truncate(text),
+                line,
+            });
+        }
+        Some(capture) => capture.dropped_lines += 1,
+        None => debug!("Script {:?} outside a run: {}", stream, text),
+    });
+}
+
+fn truncate(text: &str) -> String {
+    if text.len() <= MAX_LINE_LEN {
+        return text.to_string();
+    }
+    let mut end = MAX_LINE_LEN;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &text[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_is_bounded() {
+        let mut engine = Engine::new();
+        register(&mut engine);
+        begin(false);
+        engine
+            .run(&format!(
+                "print(\"{}\"); for i in 0..{} {{ debug(i); }}",
+                "x".repeat(MAX_LINE_LEN + 5),
+                MAX_OUTPUT_LINES + 9
+            ))
+            .unwrap();
+        let trace = take().into_trace(0, Duration::ZERO);
+        assert_eq!(trace.output.len(), MAX_OUTPUT_LINES);
+        assert_eq!(trace.dropped_lines, 10);
+        assert_eq!(trace.output[0].text.len(), MAX_LINE_LEN + 3);
+        assert_eq!(
+            trace.output[2],
+            OutputLine {
+                stream: OutputStream::Debug,
+                text: "1".to_string(),
+                line: 1,
+            }
+        );
+        assert_eq!(trace.host_calls, None);
+    }
+}
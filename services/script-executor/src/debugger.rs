@@ -0,0 +1,565 @@
+//! Script Debugger
+//!
+//! Runs a job under Rhai's debugging interface so a builder can pause it,
+//! step through it and look at the call stack and variables in scope.
+//!
+//! Each session runs its job on a thread of its own, never on a pool
+//! worker, because a paused script holds its thread until the next
+//! command. The engine's debugger callback finds the session through a
+//! thread-local [`Link`]: it reports a [`DebugState::Paused`] snapshot
+//! and blocks until [`DebugSessions::command`] sends the next
+//! [`DebugCommand`]. Runs on other threads have no link, so the callback
+//! lets them continue straight away.
+//!
+//! Line breakpoints are resolved against the compiled script to the
+//! position of the first statement on that line, so a line holding
+//! several expressions pauses once rather than once per expression.
+//! Function breakpoints pause at every call to the function.
+//!
+//! Debug runs are metered like any other run, except that time spent
+//! paused is not charged as wall time.
+//!
+//! # Learning Note
+//! Rhai calls the debugger callback synchronously in the middle of
+//! evaluation, with the scope and call stack still live. Blocking inside
+//! the callback is what "paused" means: the interpreter simply doesn't
+//! continue until the callback returns.
+
+use rhai::debugger::{BreakPoint, Debugger, DebuggerCommand, DebuggerEvent};
+use rhai::{ASTNode, Engine, EvalAltResult, EvalContext, Expr, Position, Stmt, AST};
+use shared::scripting::{
+    Breakpoint, DebugCommand, DebugFrame, DebugReply, DebugStart, DebugState, DebugVariable,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::rhai_executor::{self, RhaiExecutor};
+
+/// Most debug sessions open at once
+pub const MAX_SESSIONS: usize = 8;
+
+/// A paused session with no command for this long is aborted
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long a request waits for the run to pause or finish
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Longest rendering of a variable's value
+const MAX_VALUE_LEN: usize = 200;
+
+/// Why a debug request failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugError {
+    /// [`MAX_SESSIONS`] are already open
+    TooManySessions,
+    /// No open session has this id
+    NoSuchSession(u64),
+    /// The script doesn't compile
+    Compile(String),
+    /// A breakpoint doesn't match anything in the script
+    BadBreakpoint(Breakpoint),
+    /// The run neither paused nor finished within [`REPLY_TIMEOUT`]; the
+    /// session has been aborted
+    Timeout,
+}
+
+impl fmt::Display for DebugError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManySessions => write!(f, "too many debug sessions are open"),
+            Self::NoSuchSession(id) => write!(f, "no debug session {}", id),
+            Self::Compile(e) => write!(f, "script does not compile: {}", e),
+            Self::BadBreakpoint(Breakpoint::Line { line }) => {
+                write!(f, "there is no statement on line {}", line)
+            }
+            Self::BadBreakpoint(breakpoint) => write!(f, "bad breakpoint {}", breakpoint),
+            Self::Timeout => write!(f, "the script kept running without pausing"),
+        }
+    }
+}
+
+impl std::error::Error for DebugError {}
+
+/// Connects a session's run to the engine's debugger callback
+struct Link {
+    ast: AST,
+    breakpoints: Vec<Breakpoint>,
+    commands: Receiver<DebugCommand>,
+    states: Sender<DebugState>,
+}
+
+thread_local! {
+    // Set on a session's thread for the duration of its run
+    static LINK: RefCell<Option<Link>> = const { RefCell::new(None) };
+}
+
+/// Install the debugger hooks on `engine`
+///
+/// Runs without a session pay for one callback at the start and an empty
+/// breakpoint check per node.
+pub fn register(engine: &mut Engine) {
+    // Marked deprecated only because Rhai considers the API volatile
+    #[allow(deprecated)]
+    engine.register_debugger(
+        |_, mut debugger: Debugger| {
+            LINK.with(|link| {
+                if let Some(link) = link.borrow().as_ref() {
+                    for breakpoint in &link.breakpoints {
+                        if let Some(resolved) = resolve(&link.ast, breakpoint) {
+                            debugger.break_points_mut().push(resolved);
+                        }
+                    }
+                }
+            });
+            debugger
+        },
+        |context, event, node, _source, pos| {
+            LINK.with(|link| match link.borrow_mut().as_mut() {
+                Some(link) => pause(link, context, event, node, pos),
+                None => Ok(DebuggerCommand::Continue),
+            })
+        },
+    );
+}
+
+/// Report a stop and wait for the next command
+fn pause(
+    link: &mut Link,
+    mut context: EvalContext,
+    event: DebuggerEvent,
+    node: ASTNode,
+    pos: Position,
+) -> Result<DebuggerCommand, Box<EvalAltResult>> {
+    let reason = match event {
+        // Run to the first breakpoint, or to the first statement if none
+        // are set
+        DebuggerEvent::Start if !link.breakpoints.is_empty() => {
+            return Ok(DebuggerCommand::Continue)
+        }
+        DebuggerEvent::Start => return Ok(DebuggerCommand::StepInto),
+        DebuggerEvent::Step => "step".to_string(),
+        DebuggerEvent::BreakPoint(_) => "breakpoint".to_string(),
+        DebuggerEvent::FunctionExitWithValue(value) => format!("returned {}", render(value)),
+        DebuggerEvent::FunctionExitWithError(e) => format!("failed: {}", e),
+        _ => return Ok(DebuggerCommand::Continue),
+    };
+    let pos = if pos.is_none() { node.position() } else { pos };
+
+    loop {
+        let state = snapshot(&context, &reason, pos, &link.breakpoints);
+        if link.states.send(state).is_err() {
+            return Err("debug session closed".into());
+        }
+
+        let waiting = Instant::now();
+        let command = link.commands.recv_timeout(IDLE_TIMEOUT);
+        rhai_executor::add_paused(waiting.elapsed());
+        let command = match command {
+            Ok(command) => command,
+            Err(RecvTimeoutError::Timeout) => return Err("debug session timed out".into()),
+            Err(RecvTimeoutError::Disconnected) => return Err("debug session closed".into()),
+        };
+
+        return Ok(match command {
+            DebugCommand::Continue => DebuggerCommand::Continue,
+            DebugCommand::Step => DebuggerCommand::StepInto,
+            DebugCommand::Next => DebuggerCommand::Next,
+            DebugCommand::Finish => DebuggerCommand::FunctionExit,
+            DebugCommand::Abort => return Err("debug session aborted".into()),
+            DebugCommand::Break { breakpoint } => {
+                // Validated by `DebugSessions::command` before it is sent
+                if let Some(resolved) = resolve(&link.ast, &breakpoint) {
+                    let debugger = context.global_runtime_state_mut().debugger_mut();
+                    debugger.break_points_mut().push(resolved);
+                    link.breakpoints.push(breakpoint);
+                }
+                continue;
+            }
+            DebugCommand::Clear { breakpoint } => {
+                if let Some(resolved) = resolve(&link.ast, &breakpoint) {
+                    let debugger = context.global_runtime_state_mut().debugger_mut();
+                    debugger.break_points_mut().retain(|bp| *bp != resolved);
+                    link.breakpoints.retain(|bp| *bp != breakpoint);
+                }
+                continue;
+            }
+        });
+    }
+}
+
+/// Describe where a paused run stands
+fn snapshot(
+    context: &EvalContext,
+    reason: &str,
+    pos: Position,
+    breakpoints: &[Breakpoint],
+) -> DebugState {
+    let stack = context
+        .global_runtime_state()
+        .debugger()
+        .call_stack()
+        .iter()
+        .rev()
+        .map(|frame| DebugFrame {
+            function: frame.fn_name.to_string(),
+            args: frame.args.iter().map(render).collect(),
+            line: frame.pos.line().unwrap_or(0),
+        })
+        .collect();
+    let scope = context
+        .scope()
+        .iter()
+        .map(|(name, constant, value)| DebugVariable {
+            name: name.to_string(),
+            type_name: value.type_name().to_string(),
+            value: render(&value),
+            constant,
+        })
+        .collect();
+    DebugState::Paused {
+        reason: reason.to_string(),
+        line: pos.line().unwrap_or(0),
+        column: pos.position().unwrap_or(0),
+        stack,
+        scope,
+        breakpoints: breakpoints.to_vec(),
+    }
+}
+
+/// Render a value for display, cut short if it is long
+fn render(value: &rhai::Dynamic) -> String {
+    let text = if value.is_string() {
+        format!("{:?}", value.to_string())
+    } else {
+        value.to_string()
+    };
+    match text.char_indices().nth(MAX_VALUE_LEN) {
+        Some((cut, _)) => format!("{}...", &text[..cut]),
+        None => text,
+    }
+}
+
+/// Translate a breakpoint into Rhai's terms
+///
+/// Returns `None` for a line with no statement on it.
+fn resolve(ast: &AST, breakpoint: &Breakpoint) -> Option<BreakPoint> {
+    match breakpoint {
+        Breakpoint::Function { name } => Some(BreakPoint::AtFunctionName {
+            name: name.as_str().into(),
+            enabled: true,
+        }),
+        Breakpoint::Line { line } => {
+            let mut first: Option<Position> = None;
+            ast.walk(&mut |path: &[ASTNode]| {
+                let Some(node) = path.last() else {
+                    return true;
+                };
+                // The debugger never stops on these wrapper nodes
+                if matches!(
+                    node,
+                    ASTNode::Stmt(Stmt::Expr(..)) | ASTNode::Expr(Expr::Stmt(..))
+                ) {
+                    return true;
+                }
+                let pos = node.position();
+                if pos.line() == Some(*line)
+                    && first.is_none_or(|first| pos.position() < first.position())
+                {
+                    first = Some(pos);
+                }
+                true
+            });
+            first.map(|pos| BreakPoint::AtPosition {
+                source: None,
+                pos,
+                enabled: true,
+            })
+        }
+    }
+}
+
+/// One open session
+struct Session {
+    commands: Sender<DebugCommand>,
+    states: Receiver<DebugState>,
+    cancel: Arc<AtomicBool>,
+    ast: AST,
+}
+
+/// Every open debug session
+pub struct DebugSessions {
+    executor: Arc<RhaiExecutor>,
+    sessions: Mutex<HashMap<u64, Session>>,
+    next_id: AtomicU64,
+}
+
+impl DebugSessions {
+    /// Run sessions on `executor`
+    pub fn new(executor: Arc<RhaiExecutor>) -> Self {
+        Self {
+            executor,
+            sessions: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    fn sessions(&self) -> MutexGuard<'_, HashMap<u64, Session>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start a session, returning where it first pauses (or how it ended)
+    ///
+    /// Blocks until then, for at most [`REPLY_TIMEOUT`].
+    pub fn start(&self, start: DebugStart) -> Result<DebugReply, DebugError> {
+        if self.sessions().len() >= MAX_SESSIONS {
+            return Err(DebugError::TooManySessions);
+        }
+        let ast = self
+            .executor
+            .compile(&start.job.source)
+            .map_err(|e| DebugError::Compile(e.to_string()))?;
+        if let Some(bad) = start
+            .breakpoints
+            .iter()
+            .find(|bp| resolve(&ast, bp).is_none())
+        {
+            return Err(DebugError::BadBreakpoint(bad.clone()));
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (commands, command_rx) = mpsc::channel();
+        let (state_tx, states) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let link = Link {
+            ast: ast.clone(),
+            breakpoints: start.breakpoints,
+            commands: command_rx,
+            states: state_tx.clone(),
+        };
+        let executor = self.executor.clone();
+        let job = start.job;
+        let run_cancel = cancel.clone();
+        std::thread::Builder::new()
+            .name(format!("script-debug-{}", id))
+            .spawn(move || {
+                LINK.with(|slot| *slot.borrow_mut() = Some(link));
+                let state = match executor.run(&job, run_cancel) {
+                    Ok(output) => DebugState::Finished {
+                        value: output.value,
+                    },
+                    Err(e) => DebugState::Failed {
+                        error: e.to_string(),
+                    },
+                };
+                LINK.with(|slot| slot.borrow_mut().take());
+                // Nobody may be listening any more; that's fine
+                let _ = state_tx.send(state);
+            })
+            .map_err(|e| DebugError::Compile(e.to_string()))?;
+
+        debug!("Debug session {} started", id);
+        self.sessions().insert(
+            id,
+            Session {
+                commands,
+                states,
+                cancel,
+                ast,
+            },
+        );
+        self.reply(id)
+    }
+
+    /// Send a command to a paused session and wait for its next state
+    pub fn command(&self, id: u64, command: DebugCommand) -> Result<DebugReply, DebugError> {
+        {
+            let sessions = self.sessions();
+            let session = sessions.get(&id).ok_or(DebugError::NoSuchSession(id))?;
+            if let DebugCommand::Break { breakpoint } = &command {
+                if resolve(&session.ast, breakpoint).is_none() {
+                    return Err(DebugError::BadBreakpoint(breakpoint.clone()));
+                }
+            }
+            if session.commands.send(command).is_err() {
+                warn!("Debug session {} lost its run", id);
+            }
+        }
+        self.reply(id)
+    }
+
+    /// Stop a session, returning false if there is no such session
+    pub fn abort(&self, id: u64) -> bool {
+        let Some(session) = self.sessions().remove(&id) else {
+            return false;
+        };
+        session.cancel.store(true, Ordering::Relaxed);
+        let _ = session.commands.send(DebugCommand::Abort);
+        debug!("Debug session {} aborted", id);
+        true
+    }
+
+    /// Wait for the session's next state, closing it once the run is over
+    fn reply(&self, id: u64) -> Result<DebugReply, DebugError> {
+        let received = {
+            let sessions = self.sessions();
+            let session = sessions.get(&id).ok_or(DebugError::NoSuchSession(id))?;
+            session.states.recv_timeout(REPLY_TIMEOUT)
+        };
+        let state = match received {
+            Ok(state) => state,
+            Err(RecvTimeoutError::Timeout) => {
+                self.abort(id);
+                return Err(DebugError::Timeout);
+            }
+            Err(RecvTimeoutError::Disconnected) => DebugState::Failed {
+                error: "debug session ended unexpectedly".to_string(),
+            },
+        };
+        if state.is_over() {
+            self.sessions().remove(&id);
+            debug!("Debug session {} ended", id);
+        }
+        Ok(DebugReply { session: id, state })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::components::ObjectId;
+    use shared::scripting::{JobPriority, ScriptJob};
+
+    const SCRIPT: &str = r#"fn price(n) {
+    let each = 5;
+    n * each
+}
+fn on_use(actor) {
+    let count = 3;
+    let total = price(count);
+    total + 1
+}"#;
+
+    fn start(sessions: &DebugSessions, breakpoints: Vec<Breakpoint>) -> DebugReply {
+        sessions
+            .start(DebugStart {
+                job: ScriptJob {
+                    owner: ObjectId(2),
+                    priority: JobPriority::Interactive,
+                    source: SCRIPT.to_string(),
+                    function: Some("on_use".to_string()),
+                    args: vec!["#2".to_string()],
+                    trace: false,
+                    modules: Vec::new(),
+                    surroundings: None,
+                },
+                breakpoints,
+            })
+            .unwrap()
+    }
+
+    fn paused_line(state: &DebugState) -> usize {
+        match state {
+            DebugState::Paused { line, .. } => *line,
+            other => panic!("not paused: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_breakpoints_and_inspection() {
+        let sessions = DebugSessions::new(Arc::new(RhaiExecutor::new()));
+        let reply = start(&sessions, vec![Breakpoint::Line { line: 7 }]);
+        let id = reply.session;
+        let DebugState::Paused {
+            reason,
+            line,
+            stack,
+            scope,
+            ..
+        } = reply.state
+        else {
+            panic!("not paused: {:?}", reply.state);
+        };
+        assert_eq!((reason.as_str(), line), ("breakpoint", 7));
+        assert_eq!(stack[0].function, "on_use");
+        assert_eq!(stack[0].args, vec!["\"#2\""]);
+        let vars: Vec<_> = scope
+            .iter()
+            .map(|v| (v.name.as_str(), v.value.as_str()))
+            .collect();
+        assert_eq!(vars, vec![("actor", "\"#2\""), ("count", "3")]);
+
+        // Steps go expression by expression: the call, its argument, then
+        // into `price`
+        for expected in [7, 7, 1] {
+            let reply = sessions.command(id, DebugCommand::Step).unwrap();
+            assert_eq!(paused_line(&reply.state), expected);
+        }
+        let reply = sessions.command(id, DebugCommand::Next).unwrap();
+        let DebugState::Paused { stack, .. } = &reply.state else {
+            panic!("not paused: {:?}", reply.state);
+        };
+        let calls: Vec<_> = stack.iter().map(|frame| frame.function.as_str()).collect();
+        assert_eq!(calls, vec!["price", "on_use"]);
+        let reply = sessions.command(id, DebugCommand::Finish).unwrap();
+        let DebugState::Paused { reason, .. } = &reply.state else {
+            panic!("not paused: {:?}", reply.state);
+        };
+        assert_eq!(reason, "returned 15");
+
+        let reply = sessions.command(id, DebugCommand::Continue).unwrap();
+        assert_eq!(
+            reply.state,
+            DebugState::Finished {
+                value: "16".to_string()
+            }
+        );
+        assert_eq!(
+            sessions.command(id, DebugCommand::Continue),
+            Err(DebugError::NoSuchSession(id))
+        );
+    }
+
+    #[test]
+    fn test_function_breakpoints_and_abort() {
+        let sessions = DebugSessions::new(Arc::new(RhaiExecutor::new()));
+        assert!(matches!(
+            sessions.start(DebugStart {
+                job: ScriptJob {
+                    owner: ObjectId(2),
+                    priority: JobPriority::Interactive,
+                    source: SCRIPT.to_string(),
+                    function: None,
+                    args: Vec::new(),
+                    trace: false,
+                    modules: Vec::new(),
+                    surroundings: None,
+                },
+                breakpoints: vec![Breakpoint::Line { line: 99 }],
+            }),
+            Err(DebugError::BadBreakpoint(_))
+        ));
+
+        // No breakpoints: pause at the first statement
+        let reply = start(&sessions, Vec::new());
+        assert_eq!(paused_line(&reply.state), 6);
+        let id = reply.session;
+        let breakpoint = Breakpoint::Function {
+            name: "price".to_string(),
+        };
+        let reply = sessions
+            .command(id, DebugCommand::Break { breakpoint })
+            .unwrap();
+        assert_eq!(paused_line(&reply.state), 6);
+        let reply = sessions.command(id, DebugCommand::Continue).unwrap();
+        assert_eq!(paused_line(&reply.state), 7);
+
+        assert!(sessions.abort(id));
+        assert!(!sessions.abort(id));
+    }
+}
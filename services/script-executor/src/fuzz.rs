@@ -0,0 +1,483 @@
+//! Sandbox Fuzzing
+//!
+//! Property tests that throw generated scripts at the executor and check
+//! that the sandbox holds whatever the script does. For every run:
+//!
+//! - nothing panics
+//! - the operations charged never exceed `max_operations`, and the run
+//!   ends within `max_duration`
+//! - the value returned stays within the string, array and map limits,
+//!   and captured output within the capture limits
+//! - nothing read from the filesystem or produced by `eval` shows up in
+//!   the value, the output or the error
+//!
+//! Runs may fail (most generated scripts do); they may not fail any other
+//! way than with a script error or a timeout.
+//!
+//! Three generators feed the same [`check`]: well-formed programs built
+//! from loops, data growth, recursion and escape attempts, token soup
+//! that mostly exercises the parser, and arbitrary text.
+//!
+//! # Regression Corpus
+//! `tests/corpus/rhai/*.rhai` holds scripts that broke the sandbox once or
+//! probe an escape we never want to reopen. Each goes through [`check`],
+//! and a first line of `// expect: error` or `// expect: ok <value>` pins
+//! its outcome too. When a property fails, proptest prints the shrunk
+//! program (and records its seed under `proptest-regressions/`); add the
+//! program to the corpus along with the fix.
+//!
+//! `tests/corpus/lua/*.lua` and a smaller generator do the same for the
+//! Lua executor under the `lua-scripting` feature, checking its
+//! instruction, time and memory limits, with `-- expect:` lines.
+//!
+//! # Learning Note
+//! Proptest shrinks a failing input before reporting it, so a failure
+//! inside a deep `prop_recursive` program usually comes back as a
+//! one-line script. Keep generated fragments independent of each other
+//! for that to work well.
+
+use proptest::prelude::*;
+use proptest::sample::select;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use shared::components::ObjectId;
+use shared::scripting::{JobPriority, ScriptJob};
+
+use crate::capture::{MAX_LINE_LEN, MAX_OUTPUT_LINES};
+use crate::rhai_executor::{ExecError, ExecutorConfig, RhaiExecutor};
+
+/// Appears in the probe file, so seeing it means the filesystem was read
+const FS_MARKER: &str = "FS_REACHED";
+
+/// Produced only inside `eval` arguments
+const EVAL_MARKER: &str = "EVAL_REACHED";
+
+/// How far past `max_duration` a run may end, for scheduling noise
+const DURATION_SLACK: Duration = Duration::from_secs(1);
+
+fn config() -> ExecutorConfig {
+    ExecutorConfig {
+        max_operations: 20_000,
+        max_duration: Duration::from_secs(2),
+        max_string_len: 1_000,
+        max_array_size: 100,
+        max_map_size: 100,
+        ..ExecutorConfig::default()
+    }
+}
+
+/// Longest value a run within the limits can render to
+///
+/// Every element and property renders to a bounded amount besides its
+/// strings, whose total length the string limit already bounds.
+fn value_limit(config: &ExecutorConfig) -> usize {
+    (config.max_string_len + 32) * (config.max_array_size + config.max_map_size + 1)
+}
+
+fn executor() -> &'static RhaiExecutor {
+    static EXECUTOR: OnceLock<RhaiExecutor> = OnceLock::new();
+    EXECUTOR.get_or_init(|| RhaiExecutor::with_config(config()))
+}
+
+/// A Rhai module on disk that no script should be able to import
+fn probe() -> &'static Path {
+    static PROBE: OnceLock<PathBuf> = OnceLock::new();
+    PROBE.get_or_init(|| {
+        let path = std::env::temp_dir().join("script-executor-fuzz-probe.rhai");
+        fs::write(&path, format!("export const secret = \"{}\";", FS_MARKER)).unwrap();
+        path
+    })
+}
+
+/// Ways a script might try to leave the sandbox
+///
+/// Naming `eval` literally, as a call or in `Fn("eval")`, is a syntax
+/// error that would reject the whole program, so only the corpus covers
+/// those forms.
+fn escapes() -> Vec<String> {
+    let probe = probe().display().to_string();
+    let module = probe.trim_end_matches(".rhai");
+    let eval_arg = format!(
+        "\"\\\"{}\\\" + \\\"{}\\\"\"",
+        &EVAL_MARKER[..4],
+        &EVAL_MARKER[4..]
+    );
+    vec![
+        format!("let e = \"ev\" + \"al\"; s = call(Fn(e), {});", eval_arg),
+        format!("s = Fn(\"ev\" + \"al\").call({});", eval_arg),
+        format!("import \"{}\" as probe; s = probe::secret;", probe),
+        format!("import \"{}\" as probe; s = probe::secret;", module),
+        format!("import \"../{}\" as probe; s = probe::secret;", module),
+        format!("s = open_file(\"{}\").read_string();", probe),
+        format!("s = read_file(\"{}\");", probe),
+        "s = system(\"cat /etc/passwd\");".to_string(),
+    ]
+}
+
+/// Scripts get a fresh owner each run, so the quota report is the run's
+/// own usage
+fn next_owner() -> ObjectId {
+    static NEXT: AtomicU64 = AtomicU64::new(1_000);
+    ObjectId(NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Run `source` and check every sandbox invariant
+///
+/// Returns the run's value, or `None` if the script failed.
+fn check(source: &str) -> Result<Option<String>, TestCaseError> {
+    let executor = executor();
+    let config = config();
+    let owner = next_owner();
+    let job = ScriptJob {
+        owner,
+        priority: JobPriority::Interactive,
+        source: source.to_string(),
+        function: None,
+        args: Vec::new(),
+        trace: true,
+        modules: Vec::new(),
+        surroundings: None,
+    };
+
+    let started = Instant::now();
+    let result = executor.run(&job, Arc::default());
+    let elapsed = started.elapsed();
+
+    let used = executor.quota_report(owner).ops_used;
+    prop_assert!(
+        used <= config.max_operations + 1,
+        "charged {} ops, limit {}",
+        used,
+        config.max_operations
+    );
+    prop_assert!(
+        elapsed <= config.max_duration + DURATION_SLACK,
+        "ran for {:?}, limit {:?}",
+        elapsed,
+        config.max_duration
+    );
+
+    let leaked = |text: &str| text.contains(FS_MARKER) || text.contains(EVAL_MARKER);
+    match result {
+        Ok(output) => {
+            prop_assert!(
+                !leaked(&output.value),
+                "leaked into value: {}",
+                output.value
+            );
+            prop_assert!(
+                output.value.len() <= value_limit(&config),
+                "value of {} bytes",
+                output.value.len()
+            );
+            prop_assert!(output.trace.output.len() <= MAX_OUTPUT_LINES);
+            for line in &output.trace.output {
+                prop_assert!(!leaked(&line.text), "leaked into output: {}", line.text);
+                prop_assert!(line.text.len() <= MAX_LINE_LEN + 3);
+            }
+            Ok(Some(output.value))
+        }
+        Err(e @ (ExecError::Script(_) | ExecError::TimedOut(_))) => {
+            let message = e.to_string();
+            prop_assert!(!leaked(&message), "leaked into error: {}", message);
+            Ok(None)
+        }
+        Err(e) => Err(TestCaseError::fail(format!("unexpected failure: {}", e))),
+    }
+}
+
+/// Variables every generated program declares up front
+const PRELUDE: &str = "\
+fn f(x) { if x == () { 0 } else { [x, x] } }
+fn g(n) { if n <= 0 { 0 } else { g(n - 1) + 1 } }
+let a = 1; let b = 2.5; let s = \"s\"; let xs = []; let m = #{};
+";
+
+fn var() -> impl Strategy<Value = &'static str> {
+    select(vec!["a", "b", "s", "xs", "m"])
+}
+
+fn expr() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![
+        any::<i32>().prop_map(|n| n.to_string()),
+        (-1e6f64..1e6).prop_map(|x| format!("{:?}", x)),
+        "[a-z]{0,8}".prop_map(|s| format!("\"{}\"", s)),
+        var().prop_map(str::to_string),
+        select(vec!["()", "true", "'c'", "`t${a}`", "xs.len()", "s.len()"])
+            .prop_map(str::to_string),
+    ];
+    leaf.prop_recursive(4, 32, 4, |inner| {
+        prop_oneof![
+            (
+                inner.clone(),
+                select(vec![
+                    "+", "-", "*", "/", "%", "**", "==", "<", "|", "<<", "in"
+                ]),
+                inner.clone()
+            )
+                .prop_map(|(l, op, r)| format!("({} {} {})", l, op, r)),
+            prop::collection::vec(inner.clone(), 0..4)
+                .prop_map(|items| format!("[{}]", items.join(", "))),
+            (inner.clone(), inner.clone()).prop_map(|(k, v)| format!("#{{ k: {}, v: {} }}", k, v)),
+            (var(), select(vec!["0", "1", "-1", "a", "(a % 4)", "\"k\""]))
+                .prop_map(|(x, i)| format!("{}[{}]", x, i)),
+            (
+                inner.clone(),
+                select(vec![
+                    "len()",
+                    "to_string()",
+                    "pad(50, \"x\")",
+                    "keys()",
+                    "sub_string(1)"
+                ])
+            )
+                .prop_map(|(x, method)| format!("{}.{}", x, method)),
+            inner.clone().prop_map(|x| format!("f({})", x)),
+            inner.clone().prop_map(|x| format!("g({})", x)),
+            inner.prop_map(|x| format!("(|y| y + {}).call(a)", x)),
+        ]
+    })
+}
+
+/// A condition that parses whatever `expr` generates
+fn condition() -> impl Strategy<Value = String> {
+    prop_oneof![
+        (expr(), select(vec!["==", "!="]), expr())
+            .prop_map(|(l, op, r)| format!("({} {} {})", l, op, r)),
+        (
+            select(vec!["a", "xs.len()", "s.len()", "m.len()"]),
+            select(vec!["<", ">"]),
+            0..100i32
+        )
+            .prop_map(|(x, op, n)| format!("({} {} {})", x, op, n)),
+        select(vec!["true", "false"]).prop_map(str::to_string),
+    ]
+}
+
+fn statement() -> impl Strategy<Value = String> {
+    let simple = prop_oneof![
+        (var(), expr()).prop_map(|(v, e)| format!("{} = {};", v, e)),
+        (var(), expr()).prop_map(|(v, e)| format!("{} += {};", v, e)),
+        var().prop_map(|v| format!("{0} += {0};", v)),
+        expr().prop_map(|e| format!("xs.push({});", e)),
+        expr().prop_map(|e| format!("m[\"k\" + {}] = xs;", e)),
+        expr().prop_map(|e| format!("print({});", e)),
+        expr().prop_map(|e| format!("debug({});", e)),
+        expr().prop_map(|e| format!("wait(1, || {});", e)),
+        select(escapes()),
+    ];
+    simple.prop_recursive(3, 24, 4, |inner| {
+        let body = || prop::collection::vec(inner.clone(), 1..4).prop_map(|b| b.join(" "));
+        prop_oneof![
+            body().prop_map(|b| format!("loop {{ {} }}", b)),
+            (0..5_000i32, body()).prop_map(|(n, b)| format!("for i in 0..{} {{ {} }}", n, b)),
+            (condition(), body()).prop_map(|(c, b)| format!("while {} {{ {} }}", c, b)),
+            (condition(), body(), body())
+                .prop_map(|(c, t, e)| format!("if {} {{ {} }} else {{ {} }}", c, t, e)),
+            body().prop_map(|b| format!("try {{ {} }} catch {{ }}", b)),
+        ]
+    })
+}
+
+/// A prelude, statements and a final expression
+///
+/// Each statement is wrapped in `try` so a type error in one doesn't end
+/// the run; running out of operations or time can't be caught.
+fn program() -> impl Strategy<Value = String> {
+    (prop::collection::vec(statement(), 0..8), expr()).prop_map(|(body, tail)| {
+        let body: Vec<_> = body
+            .iter()
+            .map(|statement| format!("try {{ {} }} catch {{ }}", statement))
+            .collect();
+        format!("{}{}\n{}", PRELUDE, body.join("\n"), tail)
+    })
+}
+
+/// Token soup: mostly not valid Rhai, for the parser
+fn tokens() -> impl Strategy<Value = String> {
+    let token = select(vec![
+        "fn", "let", "const", "if", "else", "loop", "while", "for", "in", "do", "until", "break",
+        "continue", "return", "throw", "try", "catch", "import", "export", "as", "private",
+        "switch", "=>", "(", ")", "{", "}", "[", "]", "#{", ";", ",", ".", "?.", "::", "+", "*",
+        "=", "==", "|", "||", "!", "?", "\"x\"", "`t${", "'c'", "1", "1.5", "a", "f", "Fn", "eval",
+        "this", "global", "//", "/*", "*/",
+    ]);
+    prop::collection::vec(token, 0..64).prop_map(|tokens| tokens.join(" "))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn fuzz_programs(source in program()) {
+        check(&source)?;
+    }
+
+    #[test]
+    fn fuzz_tokens(source in tokens()) {
+        check(&source)?;
+    }
+
+    #[test]
+    fn fuzz_text(source in "\\PC{0,300}") {
+        check(&source)?;
+    }
+}
+
+/// Files in `tests/corpus/<language>` with the given extension
+fn corpus(language: &str, extension: &str) -> Vec<(PathBuf, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/corpus")
+        .join(language);
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("can't read {}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .map(|path| {
+            let source = fs::read_to_string(&path).unwrap();
+            (path, source)
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_rhai_corpus() {
+    let files = corpus("rhai", "rhai");
+    assert!(!files.is_empty());
+    for (path, source) in files {
+        // Corpus scripts name the probe file by placeholder
+        let source = source.replace("$PROBE", &probe().display().to_string());
+        let value = check(&source).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        check_expectation(&path, &source, "// expect: ", value);
+    }
+}
+
+/// Compare a corpus run with the `expect:` line it starts with, if any
+fn check_expectation(path: &Path, source: &str, prefix: &str, value: Option<String>) {
+    let expect = source
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix(prefix));
+    match expect {
+        Some("error") => assert_eq!(value, None, "{} should fail", path.display()),
+        Some(expected) => {
+            let expected = expected.strip_prefix("ok ").unwrap_or(expected);
+            assert_eq!(value.as_deref(), Some(expected), "{}", path.display());
+        }
+        None => {}
+    }
+}
+
+#[cfg(feature = "lua-scripting")]
+mod lua {
+    use super::*;
+    use crate::lua_executor::{self, LuaExecutor, HOOK_INTERVAL};
+
+    fn config() -> lua_executor::ExecutorConfig {
+        lua_executor::ExecutorConfig {
+            max_instructions: 200_000,
+            max_duration: Duration::from_secs(2),
+            memory_limit: 1024 * 1024,
+        }
+    }
+
+    /// Run `source` and check every sandbox invariant
+    ///
+    /// Returns the run's value, or `None` if the script failed.
+    fn check_lua(source: &str) -> Result<Option<String>, TestCaseError> {
+        let config = config();
+        let executor = LuaExecutor::with_config(config.clone());
+
+        let started = Instant::now();
+        let run = executor.run(source);
+        let elapsed = started.elapsed();
+
+        prop_assert!(
+            run.instructions <= config.max_instructions + HOOK_INTERVAL as u64,
+            "ran {} instructions, limit {}",
+            run.instructions,
+            config.max_instructions
+        );
+        prop_assert!(
+            elapsed <= config.max_duration + DURATION_SLACK,
+            "ran for {:?}, limit {:?}",
+            elapsed,
+            config.max_duration
+        );
+        prop_assert!(
+            run.memory <= config.memory_limit,
+            "held {} bytes, limit {}",
+            run.memory,
+            config.memory_limit
+        );
+
+        let leaked = |text: &str| text.contains(FS_MARKER) || text.contains(EVAL_MARKER);
+        match run.result {
+            Ok(value) => {
+                prop_assert!(!leaked(&value), "leaked into value: {}", value);
+                prop_assert!(value.len() <= config.memory_limit);
+                Ok(Some(value))
+            }
+            Err(e) => {
+                let message = e.to_string();
+                prop_assert!(!leaked(&message), "leaked into error: {}", message);
+                Ok(None)
+            }
+        }
+    }
+
+    fn lua_statement() -> impl Strategy<Value = String> {
+        let probe = probe().display().to_string();
+        prop_oneof![
+            "[a-z]{0,8}".prop_map(|s| format!("s = s .. \"{}\"", s)),
+            Just("s = s .. s".to_string()),
+            Just("t[#t + 1] = s".to_string()),
+            Just("for i = 1, 1e6 do t[i] = i end".to_string()),
+            Just("while true do s = s .. s end".to_string()),
+            Just("while true do end".to_string()),
+            Just("s = string.rep(s, 1e9)".to_string()),
+            Just("s = #string.rep(\"\", 1e12)".to_string()),
+            Just("local function f(n) return f(n + 1) + 1 end s = f(0)".to_string()),
+            Just(format!(
+                "s = load(\"return '{}' .. '{}'\")()",
+                &EVAL_MARKER[..4],
+                &EVAL_MARKER[4..]
+            )),
+            Just(format!("s = io.open(\"{}\"):read(\"a\")", probe)),
+            Just(format!("s = dofile(\"{}\")", probe)),
+            Just("s = require(\"os\").execute(\"true\")".to_string()),
+            Just("s = debug.getregistry()".to_string()),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn fuzz_lua(body in prop::collection::vec(lua_statement(), 0..8)) {
+            check_lua(&format!("local s = \"s\"\nlocal t = {{}}\n{}\nreturn s", body.join("\n")))?;
+        }
+
+        #[test]
+        fn fuzz_lua_text(source in "\\PC{0,300}") {
+            check_lua(&source)?;
+        }
+    }
+
+    #[test]
+    fn test_lua_corpus() {
+        let files = corpus("lua", "lua");
+        assert!(!files.is_empty());
+        for (path, source) in files {
+            let source = source.replace("$PROBE", &probe().display().to_string());
+            let value = check_lua(&source).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            check_expectation(&path, &source, "-- expect: ", value);
+        }
+    }
+}
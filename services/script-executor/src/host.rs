@@ -0,0 +1,122 @@
+//! Deferred Host Functions
+//!
+//! Host functions that ask for one of the script's own functions to be
+//! called later, in the spirit of MUSH `@wait`:
+//! - `wait(seconds, fn)` calls `fn` after `seconds` (an integer or float)
+//! - `schedule_at(unix_seconds, fn)` calls `fn` at a wall-clock time, or
+//!   as soon as possible if that time has passed
+//!
+//! `fn` is a function pointer: `Fn("name")` or a closure. A closure may not
+//! capture variables, because nothing from the run survives until the
+//! call. The called function receives no arguments.
+//!
+//! Neither function blocks. Each call records a [`TimerRequest`] for the
+//! run in progress, and [`RhaiExecutor::run`](crate::rhai_executor::RhaiExecutor::run)
+//! hands the requests back with the result. World-state stores them as
+//! timers on the object and fires them from its tick.
+//!
+//! Every call also goes into the run's host-call trace when one was asked
+//! for (see [`capture`](crate::capture)).
+//!
+//! # Learning Note
+//! Sleeping inside a script would pin a worker thread for as long as the
+//! wait lasts, so a handful of `wait(3600, ...)` calls could stall the
+//! whole pool. Turning the wait into data means a pending timer costs
+//! nothing but a row, and survives a restart like any other world state.
+
+use rhai::{Engine, EvalAltResult, FnPtr};
+use shared::scripting::TimerRequest;
+use std::cell::RefCell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::capture;
+
+/// Most timers one run may request
+pub const MAX_TIMERS_PER_RUN: usize = 16;
+
+thread_local! {
+    // Filled by host functions during a run on this thread
+    static TIMERS: RefCell<Vec<TimerRequest>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Register the deferred host functions on `engine`
+pub fn register(engine: &mut Engine) {
+    engine.register_fn("wait", |seconds: i64, function: FnPtr| {
+        let args = vec![seconds.to_string(), function.to_string()];
+        traced("wait", args, wait(seconds as f64, function))
+    });
+    engine.register_fn("wait", |seconds: f64, function: FnPtr| {
+        let args = vec![seconds.to_string(), function.to_string()];
+        traced("wait", args, wait(seconds, function))
+    });
+    engine.register_fn("schedule_at", |unix_seconds: i64, function: FnPtr| {
+        let args = vec![unix_seconds.to_string(), function.to_string()];
+        traced("schedule_at", args, schedule_at(unix_seconds, function))
+    });
+}
+
+/// Pass a call's result through, recording the call in the run's trace
+fn traced(
+    function: &str,
+    args: Vec<String>,
+    result: Result<(), Box<EvalAltResult>>,
+) -> Result<(), Box<EvalAltResult>> {
+    let recorded = match &result {
+        Ok(()) => Ok("()".to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    capture::record_call(function, args, recorded);
+    result
+}
+
+/// Forget requests left over from an earlier run on this thread
+pub fn begin() {
+    TIMERS.with(|timers| timers.borrow_mut().clear());
+}
+
+/// Take the requests made since [`begin`]
+pub fn take() -> Vec<TimerRequest> {
+    TIMERS.with(|timers| timers.take())
+}
+
+fn wait(seconds: f64, function: FnPtr) -> Result<(), Box<EvalAltResult>> {
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err("wait() needs a non-negative number of seconds".into());
+    }
+    request((seconds * 1000.0) as u64, function)
+}
+
+fn schedule_at(unix_seconds: i64, function: FnPtr) -> Result<(), Box<EvalAltResult>> {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+    let delay_ms = unix_seconds.saturating_mul(1000).saturating_sub(now_ms);
+    request(delay_ms.max(0) as u64, function)
+}
+
+fn request(delay_ms: u64, function: FnPtr) -> Result<(), Box<EvalAltResult>> {
+    if function.is_curried() {
+        return Err(format!(
+            "`{}` captures variables, which can't be kept until the timer fires; \
+             pass a named function instead",
+            function.fn_name()
+        )
+        .into());
+    }
+    TIMERS.with(|timers| {
+        let mut timers = timers.borrow_mut();
+        if timers.len() >= MAX_TIMERS_PER_RUN {
+            return Err(format!(
+                "a script may set at most {} timers per run",
+                MAX_TIMERS_PER_RUN
+            )
+            .into());
+        }
+        timers.push(TimerRequest {
+            function: function.fn_name().to_string(),
+            delay_ms,
+        });
+        Ok(())
+    })
+}
@@ -1,22 +1,69 @@
-//! Lua Script Executor (Placeholder)
+//! Lua Script Executor
 //!
-//! This module provides a placeholder for Lua script execution.
-//! Uncomment the mlua dependency in Cargo.toml and enable the
-//! lua-scripting feature to activate this module.
+//! Executes Lua 5.4 scripts (through `mlua`) in a sandboxed state, behind
+//! the `lua-scripting` feature. It is not wired into the job pool yet;
+//! the Rhai executor still serves every job.
 //!
-//! To switch from Rhai to Lua:
-//! 1. Uncomment `mlua` in Cargo.toml
-//! 2. Build with: `cargo build --features lua-scripting --no-default-features`
-//! 3. Update Dockerfile to use lua-scripting feature
-
-#![allow(dead_code)] // Allow dead code in placeholder
-
-use std::time::Duration;
+//! Each run gets a fresh state with only the `table`, `string`, `math`
+//! and `utf8` libraries. The base library functions that reach outside
+//! the state (`load`, `dofile`, `loadfile`, `require`, `collectgarbage`,
+//! `print`) are removed, and `string.rep` is wrapped so an empty string
+//! can't be repeated a trillion times inside a single call.
+//!
+//! The limits hold a run in:
+//! - an instruction hook every [`HOOK_INTERVAL`] instructions counts
+//!   against `max_instructions` and checks `max_duration`
+//! - `memory_limit` caps the state's allocator, so `string.rep` and
+//!   table growth fail with a memory error instead of growing the
+//!   process
+//!
+//! To serve jobs with Lua:
+//! 1. Build with: `cargo build --features lua-scripting --no-default-features`
+//! 2. Update Dockerfile to use lua-scripting feature
+//!
+//! # Learning Note
+//! A hook only runs between Lua instructions, so a single C function
+//! call is never interrupted by it. Library functions that can loop for
+//! long without allocating have to be guarded one by one, like
+//! `string.rep` here; the allocator limit covers the rest.
+
+#![allow(dead_code)] // Not used by the job pool yet
+
+use mlua::{HookTriggers, Lua, LuaOptions, StdLib, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::info;
 
+/// Instructions between two checks of the limits
+pub const HOOK_INTERVAL: u32 = 1_000;
+
+/// Base library functions that reach outside the state
+const REMOVED_GLOBALS: &[&str] = &[
+    "load",
+    "dofile",
+    "loadfile",
+    "require",
+    "collectgarbage",
+    "print",
+];
+
+/// Runs before every script, with the libraries already loaded
+const PRELUDE: &str = r#"
+local rep = string.rep
+string.rep = function(s, n, sep)
+    if type(s) == "string" and #s == 0 and (sep == nil or sep == "") then
+        return rep(s, math.min(n, 1))
+    end
+    return rep(s, n, sep)
+end
+"#;
+
 /// Configuration for Lua script execution
 #[derive(Debug, Clone)]
 pub struct ExecutorConfig {
+    /// Maximum number of instructions before the run is stopped
+    pub max_instructions: u64,
     /// Maximum script execution time
     pub max_duration: Duration,
     /// Memory limit in bytes
@@ -26,13 +73,24 @@ pub struct ExecutorConfig {
 impl Default for ExecutorConfig {
     fn default() -> Self {
         Self {
+            max_instructions: 1_000_000,
             max_duration: Duration::from_secs(5),
             memory_limit: 10 * 1024 * 1024, // 10 MB
         }
     }
 }
 
-/// Lua script executor (placeholder)
+/// What a run returned and what it used
+#[derive(Debug)]
+pub struct LuaRun {
+    pub result: Result<String, mlua::Error>,
+    /// Instructions executed, counted in steps of [`HOOK_INTERVAL`]
+    pub instructions: u64,
+    /// Memory the state held when the run ended
+    pub memory: usize,
+}
+
+/// Lua script executor
 pub struct LuaExecutor {
     config: ExecutorConfig,
 }
@@ -44,98 +102,95 @@ impl LuaExecutor {
     }
 
     /// Create with custom configuration
-    pub fn with_config(config: ExecutorConfig) -> Self {
-        info!(
-            "Lua executor initialized (placeholder) with limits: {:?}",
-            config
-        );
-        Self { config }
-    }
-
-    /// Execute a Lua script
-    pub fn execute(&self, _script: &str) -> Result<String, String> {
-        Err("Lua scripting not enabled. Build with --features lua-scripting".to_string())
-    }
-}
-
-impl Default for LuaExecutor {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-/* UNCOMMENT WHEN READY TO IMPLEMENT LUA SUPPORT
-
-use mlua::{Lua, LuaOptions, StdLib};
-
-impl LuaExecutor {
-    pub fn new() -> Self {
-        Self::with_config(ExecutorConfig::default())
-    }
-
     pub fn with_config(config: ExecutorConfig) -> Self {
         info!("Lua executor initialized with limits: {:?}", config);
         Self { config }
     }
 
-    /// Execute a Lua script in sandboxed environment
+    /// Execute a Lua script and render the value it returns
     pub fn execute(&self, script: &str) -> Result<String, mlua::Error> {
-        // Create Lua VM with restricted standard library
-        let lua = Lua::new_with(
-            StdLib::TABLE | StdLib::STRING | StdLib::MATH,
-            LuaOptions::default(),
-        )?;
+        self.run(script).result
+    }
 
-        // Set memory limit
-        lua.set_memory_limit(self.config.memory_limit)?;
+    /// Execute a Lua script in a fresh sandboxed state
+    pub fn run(&self, script: &str) -> LuaRun {
+        let lua = match self.sandbox() {
+            Ok(lua) => lua,
+            Err(e) => {
+                return LuaRun {
+                    result: Err(e),
+                    instructions: 0,
+                    memory: 0,
+                }
+            }
+        };
+
+        let instructions = Arc::new(AtomicU64::new(0));
+        let counted = instructions.clone();
+        let max_instructions = self.config.max_instructions;
+        let deadline = Instant::now() + self.config.max_duration;
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(HOOK_INTERVAL),
+            move |_, _| {
+                let total = counted.fetch_add(HOOK_INTERVAL as u64, Ordering::Relaxed)
+                    + HOOK_INTERVAL as u64;
+                if total > max_instructions {
+                    return Err(mlua::Error::RuntimeError(format!(
+                        "instruction limit of {} exceeded",
+                        max_instructions
+                    )));
+                }
+                if Instant::now() > deadline {
+                    return Err(mlua::Error::RuntimeError("script timed out".to_string()));
+                }
+                Ok(())
+            },
+        );
 
-        // Disable dangerous operations
-        lua.globals().set("dofile", mlua::Nil)?;
-        lua.globals().set("loadfile", mlua::Nil)?;
-        lua.globals().set("require", mlua::Nil)?;
-
-        // Execute script
-        let result: mlua::Value = lua.load(script).eval()?;
-
-        // Convert result to string
-        match result {
-            mlua::Value::String(s) => Ok(s.to_str()?.to_string()),
-            mlua::Value::Number(n) => Ok(n.to_string()),
-            mlua::Value::Boolean(b) => Ok(b.to_string()),
-            mlua::Value::Nil => Ok("nil".to_string()),
-            _ => Ok(format!("{:?}", result)),
+        let result = lua
+            .load(script)
+            .eval::<Value>()
+            .and_then(|value| render(&value));
+        LuaRun {
+            result,
+            instructions: instructions.load(Ordering::Relaxed),
+            memory: lua.used_memory(),
         }
     }
 
-    /// Call a Lua function
-    pub fn call_fn<'lua>(
-        &self,
-        script: &str,
-        fn_name: &str,
-        args: Vec<&str>,
-    ) -> Result<String, mlua::Error> {
+    /// A state with the safe libraries, the prelude and the memory limit
+    fn sandbox(&self) -> Result<Lua, mlua::Error> {
         let lua = Lua::new_with(
-            StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+            StdLib::TABLE | StdLib::STRING | StdLib::MATH | StdLib::UTF8,
             LuaOptions::default(),
         )?;
-
-        // Load script
-        lua.load(script).exec()?;
-
-        // Get function
-        let func: mlua::Function = lua.globals().get(fn_name)?;
-
-        // Call with args
-        let result: mlua::Value = func.call(args)?;
-
-        match result {
-            mlua::Value::String(s) => Ok(s.to_str()?.to_string()),
-            mlua::Value::Number(n) => Ok(n.to_string()),
-            _ => Ok(format!("{:?}", result)),
+        for name in REMOVED_GLOBALS {
+            lua.globals().set(*name, Value::Nil)?;
         }
+        lua.load(PRELUDE).exec()?;
+        lua.set_memory_limit(self.config.memory_limit)?;
+        Ok(lua)
     }
 }
 
+impl Default for LuaExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Scripts return plain values; anything else renders as its type name
+fn render(value: &Value) -> Result<String, mlua::Error> {
+    Ok(match value {
+        Value::String(s) => s.to_str()?.to_string(),
+        Value::Integer(n) => n.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Nil => "nil".to_string(),
+        other => other.type_name().to_string(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -143,29 +198,43 @@ mod tests {
     #[test]
     fn test_basic_execution() {
         let executor = LuaExecutor::new();
-        let result = executor.execute("return 40 + 2").unwrap();
-        assert_eq!(result, "42");
+        assert_eq!(executor.execute("return 40 + 2").unwrap(), "42");
+        assert_eq!(
+            executor.execute("return string.rep('ab', 2)").unwrap(),
+            "abab"
+        );
+        assert_eq!(
+            executor.execute("return #string.rep('', 1e12)").unwrap(),
+            "0"
+        );
     }
 
     #[test]
     fn test_sandboxing() {
         let executor = LuaExecutor::new();
-        // This should fail because io is disabled
-        let result = executor.execute("return io.open('/etc/passwd')");
-        assert!(result.is_err());
+        for script in [
+            "return io.open('/etc/passwd')",
+            "return os.execute('true')",
+            "return load('return 1')()",
+            "return require('io')",
+            "return dofile('/etc/passwd')",
+        ] {
+            assert!(executor.execute(script).is_err(), "{}", script);
+        }
     }
 
     #[test]
-    fn test_function_call() {
-        let executor = LuaExecutor::new();
-        let script = r#"
-            function greet(name)
-                return "Hello, " .. name .. "!"
-            end
-        "#;
-        let result = executor.call_fn(script, "greet", vec!["World"]).unwrap();
-        assert_eq!(result, "Hello, World!");
+    fn test_limits() {
+        let executor = LuaExecutor::with_config(ExecutorConfig {
+            max_instructions: 10_000,
+            memory_limit: 1024 * 1024,
+            ..ExecutorConfig::default()
+        });
+        let run = executor.run("while true do end");
+        assert!(run.result.is_err());
+        assert!(run.instructions <= 10_000 + HOOK_INTERVAL as u64);
+
+        let run = executor.run("return string.rep('x', 1e9)");
+        assert!(matches!(run.result, Err(mlua::Error::MemoryError(_))));
     }
 }
-
-*/
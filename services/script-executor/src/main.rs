@@ -2,14 +2,73 @@
 //!
 //! This service executes user scripts in a safe, sandboxed environment.
 //! It supports both Rhai (default) and Lua (placeholder for future).
+//!
+//! `POST /validate` takes script source as the body and returns the
+//! problems found as a JSON list of `ScriptDiagnostic`s (empty when the
+//! script is fine). World-state calls it before accepting a new script.
+//! `GET /quota/:owner` returns the owner's script usage over the last
+//! minute as a `QuotaReport`.
+//!
+//! Scripts run on a bounded worker pool:
+//!
+//! | Method | Path        | Body / Response                                |
+//! |--------|-------------|------------------------------------------------|
+//! | POST   | `/jobs`     | `ScriptJob` -> `JobOutcome` (503 when full)    |
+//! | GET    | `/jobs`     | `[JobInfo]`, queued and running jobs           |
+//! | DELETE | `/jobs/:id` | cancel a job; 404 if it already finished       |
+//! | GET    | `/metrics`  | `PoolMetrics`                                  |
+//!
+//! Debug sessions run a job outside the pool, pausing it for inspection
+//! (see [`debugger`]). Every reply is a `DebugReply`:
+//!
+//! | Method | Path         | Body / Response                                 |
+//! |--------|--------------|-------------------------------------------------|
+//! | POST   | `/debug`     | `DebugStart`; runs to the first pause           |
+//! | POST   | `/debug/:id` | `DebugCommand`; runs to the next pause          |
+//! | DELETE | `/debug/:id` | abort the session; 404 if it already ended      |
 
-use axum::{routing::get, Router};
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use shared::components::ObjectId;
+use shared::scripting::{
+    DebugCommand, DebugReply, DebugStart, JobOutcome, QuotaReport, ScriptDiagnostic, ScriptJob,
+};
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tracing::{info, warn};
 
+#[cfg(feature = "amqp-transport")]
+mod amqp_transport;
+mod capture;
+mod debugger;
+#[cfg(test)]
+mod fuzz;
+mod host;
 #[cfg(feature = "lua-scripting")]
 mod lua_executor;
+mod modules;
+mod pool;
+mod quota;
 mod rhai_executor;
+mod spatial;
+mod transport;
+mod validate;
+
+use debugger::{DebugError, DebugSessions};
+use pool::{JobError, JobInfo, PoolConfig, PoolMetrics, WorkerPool};
+use rhai_executor::RhaiExecutor;
+use transport::{InProcessQueue, JobId};
+
+/// Shared handles for the HTTP handlers
+#[derive(Clone)]
+struct AppState {
+    executor: Arc<RhaiExecutor>,
+    pool: Arc<WorkerPool>,
+    debug: Arc<DebugSessions>,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -33,13 +92,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     #[cfg(feature = "lua-scripting")]
     info!("Using Lua scripting engine");
 
-    // Health check server
+    let executor = Arc::new(RhaiExecutor::new());
+    let defaults = PoolConfig::default();
+    let pool_config = PoolConfig {
+        workers: env_or("SCRIPT_WORKERS", defaults.workers),
+        queue_capacity: env_or("SCRIPT_QUEUE_CAPACITY", defaults.queue_capacity),
+    };
+    info!(
+        "Starting {} script workers (queue capacity {})",
+        pool_config.workers, pool_config.queue_capacity
+    );
+    let transport = Arc::new(InProcessQueue::new(pool_config.queue_capacity));
+    let pool = Arc::new(WorkerPool::new(executor.clone(), transport, &pool_config));
+    let debug = Arc::new(DebugSessions::new(executor.clone()));
+
+    // Health check, validation and job server
     let health_app = Router::new()
         .route("/health", get(health_check))
-        .route("/ready", get(readiness_check));
+        .route("/ready", get(readiness_check))
+        .route("/validate", post(validate_script))
+        .route("/quota/:owner", get(quota_report))
+        .route("/jobs", get(list_jobs).post(submit_job))
+        .route("/jobs/:id", delete(cancel_job))
+        .route("/metrics", get(metrics))
+        .route("/debug", post(start_debug))
+        .route("/debug/:id", post(debug_command).delete(abort_debug))
+        .with_state(AppState {
+            executor,
+            pool,
+            debug,
+        });
 
     let health_addr = SocketAddr::from(([0, 0, 0, 0], 8081));
-    info!("Health check server listening on {}", health_addr);
+    info!("HTTP server listening on {}", health_addr);
 
     tokio::spawn(async move {
         if let Err(e) = axum::serve(
@@ -66,6 +151,115 @@ async fn health_check() -> &'static str {
     "OK"
 }
 
+/// Read a numeric setting from the environment
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Check a script without running it
+async fn validate_script(
+    State(state): State<AppState>,
+    source: String,
+) -> Json<Vec<ScriptDiagnostic>> {
+    Json(state.executor.validate(&source).err().unwrap_or_default())
+}
+
+/// Script usage charged to one owner
+async fn quota_report(State(state): State<AppState>, Path(owner): Path<u64>) -> Json<QuotaReport> {
+    Json(state.executor.quota_report(ObjectId(owner)))
+}
+
+/// Run a job on the pool and wait for its result
+async fn submit_job(State(state): State<AppState>, Json(job): Json<ScriptJob>) -> Response {
+    let handle = match state.pool.submit(job) {
+        Ok(handle) => handle,
+        Err(e @ (JobError::QueueFull | JobError::ShuttingDown)) => {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(header::RETRY_AFTER, "1")],
+                e.to_string(),
+            )
+                .into_response();
+        }
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let id = handle.id().0;
+    let (output, error) = match handle.wait().await {
+        Ok(output) => (Some(output), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    Json(JobOutcome { id, output, error }).into_response()
+}
+
+/// Jobs that are queued or running
+async fn list_jobs(State(state): State<AppState>) -> Json<Vec<JobInfo>> {
+    Json(state.pool.jobs())
+}
+
+/// Cancel a queued or running job
+async fn cancel_job(State(state): State<AppState>, Path(id): Path<u64>) -> StatusCode {
+    if state.pool.cancel(JobId(id)) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Worker pool metrics
+async fn metrics(State(state): State<AppState>) -> Json<PoolMetrics> {
+    Json(state.pool.metrics())
+}
+
+/// Start a debug session
+async fn start_debug(State(state): State<AppState>, Json(start): Json<DebugStart>) -> Response {
+    let result = tokio::task::spawn_blocking(move || state.debug.start(start)).await;
+    debug_response(result)
+}
+
+/// Send a command to a paused debug session
+async fn debug_command(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(command): Json<DebugCommand>,
+) -> Response {
+    let result = tokio::task::spawn_blocking(move || state.debug.command(id, command)).await;
+    debug_response(result)
+}
+
+/// Abort a debug session
+async fn abort_debug(State(state): State<AppState>, Path(id): Path<u64>) -> StatusCode {
+    if state.debug.abort(id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Turn the outcome of a debug request into a response
+fn debug_response(
+    result: Result<Result<DebugReply, DebugError>, tokio::task::JoinError>,
+) -> Response {
+    let (status, message) = match result {
+        Ok(Ok(reply)) => return Json(reply).into_response(),
+        Ok(Err(e)) => {
+            let status = match e {
+                DebugError::TooManySessions => StatusCode::SERVICE_UNAVAILABLE,
+                DebugError::NoSuchSession(_) => StatusCode::NOT_FOUND,
+                DebugError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+                DebugError::Compile(_) | DebugError::BadBreakpoint(_) => {
+                    StatusCode::UNPROCESSABLE_ENTITY
+                }
+            };
+            (status, e.to_string())
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    (status, message).into_response()
+}
+
 /// Readiness check endpoint
 async fn readiness_check() -> &'static str {
     "READY"
@@ -0,0 +1,152 @@
+//! Library Modules
+//!
+//! Resolves `import "lib/combat" as combat;` in scripts. World-state
+//! finds the library objects a script imports, checks that the script's
+//! owner may read them and sends their source with the job as
+//! [`ModuleSource`]s. The [`LibraryResolver`] installed on the engine
+//! serves imports from that list only.
+//!
+//! Compiled modules are cached by library object and script revision.
+//! Changing a library's script creates a new revision, so the next job
+//! that imports it misses the cache and compiles the new source; an
+//! import pinned to an old revision (`lib/combat@3`) keeps getting the
+//! old module. Entries are evicted oldest first past
+//! [`MAX_CACHED_MODULES`].
+//!
+//! A module's top-level statements run once, when it is compiled, so
+//! host calls made there (such as `wait`) only take effect for the job
+//! that happened to load it. Libraries should stick to function
+//! definitions and constants.
+//!
+//! # Learning Note
+//! `Engine::new()` comes with a resolver that loads `.rhai` files from
+//! disk. Replacing it is what keeps `import` from reading the executor's
+//! filesystem.
+
+use rhai::module_resolvers::ModuleResolver;
+use rhai::{Engine, EvalAltResult, Module, Position, Scope, Shared};
+use shared::components::ObjectId;
+use shared::scripting::ModuleSource;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::debug;
+
+/// Most compiled modules kept
+pub const MAX_CACHED_MODULES: usize = 128;
+
+thread_local! {
+    // Modules the job running on this thread may import
+    static PROVIDED: RefCell<Vec<ModuleSource>> = const { RefCell::new(Vec::new()) };
+    // Paths being compiled right now, to catch circular imports
+    static LOADING: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Make `modules` importable by the run about to start on this thread
+pub fn begin(modules: &[ModuleSource]) {
+    PROVIDED.with(|provided| *provided.borrow_mut() = modules.to_vec());
+    LOADING.with(|loading| loading.borrow_mut().clear());
+}
+
+/// Forget the modules provided since [`begin`]
+pub fn end() {
+    PROVIDED.with(|provided| provided.borrow_mut().clear());
+}
+
+/// Compiled modules by library object and revision
+#[derive(Default)]
+struct ModuleCache {
+    modules: HashMap<(ObjectId, u32), Shared<Module>>,
+    order: VecDeque<(ObjectId, u32)>,
+}
+
+/// Serves `import` from the modules provided with the job
+///
+/// Clones share one cache.
+#[derive(Clone, Default)]
+pub struct LibraryResolver {
+    cache: Arc<Mutex<ModuleCache>>,
+}
+
+impl LibraryResolver {
+    /// An empty resolver
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn cache(&self) -> MutexGuard<'_, ModuleCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Number of compiled modules held
+    pub fn cached(&self) -> usize {
+        self.cache().modules.len()
+    }
+
+    fn compile(
+        &self,
+        engine: &Engine,
+        module: &ModuleSource,
+        pos: Position,
+    ) -> Result<Shared<Module>, Box<EvalAltResult>> {
+        let in_module = |e: Box<EvalAltResult>| {
+            Box::new(EvalAltResult::ErrorInModule(module.path.clone(), e, pos))
+        };
+        let circular = LOADING.with(|loading| loading.borrow().contains(&module.path));
+        if circular {
+            return Err(in_module(
+                format!("`{}` imports itself", module.path).into(),
+            ));
+        }
+
+        LOADING.with(|loading| loading.borrow_mut().push(module.path.clone()));
+        let compiled = engine
+            .compile(&module.source)
+            .map_err(|e| in_module(e.into()))
+            .and_then(|ast| Module::eval_ast_as_new(Scope::new(), &ast, engine).map_err(in_module));
+        LOADING.with(|loading| loading.borrow_mut().pop());
+        let compiled = compiled?;
+        debug!(
+            "Compiled module {} ({} r{})",
+            module.path, module.object, module.revision
+        );
+        Ok(compiled.into())
+    }
+}
+
+impl ModuleResolver for LibraryResolver {
+    fn resolve(
+        &self,
+        engine: &Engine,
+        _source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> Result<Shared<Module>, Box<EvalAltResult>> {
+        let Some(module) = PROVIDED.with(|provided| {
+            provided
+                .borrow()
+                .iter()
+                .find(|module| module.path == path)
+                .cloned()
+        }) else {
+            return Err(EvalAltResult::ErrorModuleNotFound(path.to_string(), pos).into());
+        };
+
+        let key = (module.object, module.revision);
+        if let Some(compiled) = self.cache().modules.get(&key) {
+            return Ok(compiled.clone());
+        }
+        let compiled = self.compile(engine, &module, pos)?;
+
+        let mut cache = self.cache();
+        if cache.modules.insert(key, compiled.clone()).is_none() {
+            cache.order.push_back(key);
+        }
+        while cache.order.len() > MAX_CACHED_MODULES {
+            if let Some(oldest) = cache.order.pop_front() {
+                cache.modules.remove(&oldest);
+            }
+        }
+        Ok(compiled)
+    }
+}
@@ -0,0 +1,467 @@
+//! Worker Pool
+//!
+//! A fixed number of worker threads take jobs from a [`JobTransport`] and
+//! run them on a shared [`RhaiExecutor`]. Submitting returns a
+//! [`JobHandle`] to await the result; a full queue fails the submission
+//! with [`JobError::QueueFull`] instead of waiting.
+//!
+//! Jobs can be cancelled while queued (they are dropped from the queue) or
+//! while running (Rhai's progress callback stops them at the next
+//! operation).
+//!
+//! # Learning Note
+//! Scripts are CPU-bound and Rhai evaluates synchronously, so workers are
+//! plain OS threads rather than tokio tasks. Async callers bridge over with
+//! a oneshot channel, which never blocks the runtime.
+
+use serde::Serialize;
+use shared::components::ObjectId;
+use shared::scripting::{JobOutput, JobPriority, ScriptJob};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Instant;
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+
+use crate::quota::QuotaError;
+use crate::rhai_executor::{ExecError, RhaiExecutor};
+use crate::transport::{JobId, JobTransport, QueuedJob, TransportError};
+
+/// Pool size settings
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Worker threads, each running one job at a time
+    pub workers: usize,
+    /// Jobs allowed to wait before submissions are refused
+    pub queue_capacity: usize,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            workers: std::thread::available_parallelism().map_or(4, |n| n.get()),
+            queue_capacity: 1_024,
+        }
+    }
+}
+
+/// Why a job produced no result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobError {
+    /// The queue is full; back off and retry
+    QueueFull,
+    /// The pool is shutting down
+    ShuttingDown,
+    /// The job was cancelled
+    Cancelled,
+    /// The owner is over quota
+    Quota(QuotaError),
+    /// The script failed; the message comes from the engine
+    Failed(String),
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::QueueFull => write!(f, "script queue is full, try again shortly"),
+            Self::ShuttingDown => write!(f, "script executor is shutting down"),
+            Self::Cancelled => write!(f, "script cancelled"),
+            Self::Quota(e) => write!(f, "{}", e),
+            Self::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+impl From<TransportError> for JobError {
+    fn from(e: TransportError) -> Self {
+        match e {
+            TransportError::Full => Self::QueueFull,
+            TransportError::Closed => Self::ShuttingDown,
+        }
+    }
+}
+
+impl From<ExecError> for JobError {
+    fn from(e: ExecError) -> Self {
+        match e {
+            ExecError::Quota(e) => Self::Quota(e),
+            ExecError::Cancelled => Self::Cancelled,
+            e => Self::Failed(e.to_string()),
+        }
+    }
+}
+
+/// What a job produced
+pub type JobResult = Result<JobOutput, JobError>;
+
+/// Await the result of a submitted job
+pub struct JobHandle {
+    id: JobId,
+    result: oneshot::Receiver<JobResult>,
+}
+
+impl JobHandle {
+    /// The job's id, for cancelling it
+    pub fn id(&self) -> JobId {
+        self.id
+    }
+
+    /// Wait for the job to finish
+    pub async fn wait(self) -> JobResult {
+        self.result.await.unwrap_or(Err(JobError::ShuttingDown))
+    }
+}
+
+/// A queued or running job, as listed by [`WorkerPool::jobs`]
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    /// Pool-assigned id
+    pub id: JobId,
+    /// Player the job is charged to
+    pub owner: ObjectId,
+    /// Scheduling class
+    pub priority: JobPriority,
+    /// Whether a worker has started it
+    pub running: bool,
+    /// Milliseconds since it was submitted
+    pub age_ms: u64,
+}
+
+/// Counters describing pool activity since startup
+#[derive(Debug, Default)]
+struct Counters {
+    submitted: AtomicU64,
+    rejected: AtomicU64,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    cancelled: AtomicU64,
+    wait_ms: AtomicU64,
+    run_ms: AtomicU64,
+}
+
+/// Point-in-time pool metrics
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PoolMetrics {
+    /// Worker threads
+    pub workers: usize,
+    /// Jobs waiting in the queue
+    pub queued: usize,
+    /// Jobs being run right now
+    pub running: usize,
+    /// Jobs accepted since startup
+    pub submitted: u64,
+    /// Jobs refused because the queue was full
+    pub rejected: u64,
+    /// Jobs that returned a value
+    pub completed: u64,
+    /// Jobs that ended in an error other than cancellation
+    pub failed: u64,
+    /// Jobs cancelled while queued or running
+    pub cancelled: u64,
+    /// Total milliseconds jobs spent queued
+    pub wait_ms: u64,
+    /// Total milliseconds jobs spent running
+    pub run_ms: u64,
+}
+
+/// Bookkeeping for a job submitted through this pool
+struct Pending {
+    owner: ObjectId,
+    priority: JobPriority,
+    submitted: Instant,
+    started: Option<Instant>,
+    cancel: Arc<AtomicBool>,
+    reply: oneshot::Sender<JobResult>,
+}
+
+/// State shared by the pool and its workers
+struct Shared {
+    executor: Arc<RhaiExecutor>,
+    transport: Arc<dyn JobTransport>,
+    pending: Mutex<HashMap<JobId, Pending>>,
+    counters: Counters,
+}
+
+impl Shared {
+    fn pending(&self) -> MutexGuard<'_, HashMap<JobId, Pending>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Deliver a result and update the counters
+    fn finish(&self, id: JobId, result: JobResult) {
+        let Some(pending) = self.pending().remove(&id) else {
+            return;
+        };
+        let counter = match &result {
+            Ok(_) => &self.counters.completed,
+            Err(JobError::Cancelled) => &self.counters.cancelled,
+            Err(_) => &self.counters.failed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if let Some(started) = pending.started {
+            let ms = started.elapsed().as_millis() as u64;
+            self.counters.run_ms.fetch_add(ms, Ordering::Relaxed);
+        }
+        // The submitter may have stopped waiting; that's fine
+        let _ = pending.reply.send(result);
+    }
+
+    /// Worker loop: run jobs until the transport closes
+    fn work(&self, worker: usize) {
+        while let Some(queued) = self.transport.pop() {
+            let cancel = match self.pending().get_mut(&queued.id) {
+                Some(entry) => {
+                    let waited = entry.submitted.elapsed().as_millis() as u64;
+                    self.counters.wait_ms.fetch_add(waited, Ordering::Relaxed);
+                    entry.started = Some(Instant::now());
+                    entry.cancel.clone()
+                }
+                // Submitted by another process sharing the transport;
+                // nobody here is waiting for the result
+                None => Arc::new(AtomicBool::new(false)),
+            };
+
+            debug!("Worker {} running {}", worker, queued.id);
+            let result = if cancel.load(Ordering::Relaxed) {
+                Err(JobError::Cancelled)
+            } else {
+                self.executor
+                    .run(&queued.job, cancel)
+                    .map_err(JobError::from)
+            };
+            self.finish(queued.id, result);
+        }
+    }
+}
+
+/// Bounded pool of script workers
+pub struct WorkerPool {
+    shared: Arc<Shared>,
+    next_id: AtomicU64,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    /// Start `config.workers` threads taking jobs from `transport`
+    pub fn new(
+        executor: Arc<RhaiExecutor>,
+        transport: Arc<dyn JobTransport>,
+        config: &PoolConfig,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            executor,
+            transport,
+            pending: Mutex::new(HashMap::new()),
+            counters: Counters::default(),
+        });
+        let workers = (0..config.workers)
+            .map(|worker| {
+                let shared = shared.clone();
+                std::thread::Builder::new()
+                    .name(format!("script-worker-{}", worker))
+                    .spawn(move || shared.work(worker))
+                    .expect("spawn script worker")
+            })
+            .collect();
+        Self {
+            shared,
+            next_id: AtomicU64::new(1),
+            workers,
+        }
+    }
+
+    /// Queue a job, failing immediately if the queue is full
+    pub fn submit(&self, job: ScriptJob) -> Result<JobHandle, JobError> {
+        let id = JobId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let (reply, result) = oneshot::channel();
+        self.shared.pending().insert(
+            id,
+            Pending {
+                owner: job.owner,
+                priority: job.priority,
+                submitted: Instant::now(),
+                started: None,
+                cancel: Arc::new(AtomicBool::new(false)),
+                reply,
+            },
+        );
+
+        if let Err(e) = self.shared.transport.push(QueuedJob { id, job }) {
+            self.shared.pending().remove(&id);
+            self.shared
+                .counters
+                .rejected
+                .fetch_add(1, Ordering::Relaxed);
+            return Err(e.into());
+        }
+        self.shared
+            .counters
+            .submitted
+            .fetch_add(1, Ordering::Relaxed);
+        Ok(JobHandle { id, result })
+    }
+
+    /// Cancel a queued or running job
+    ///
+    /// Returns false if the job is unknown or already finished.
+    pub fn cancel(&self, id: JobId) -> bool {
+        let Some(cancel) = self.shared.pending().get(&id).map(|p| p.cancel.clone()) else {
+            return false;
+        };
+        cancel.store(true, Ordering::Relaxed);
+        if self.shared.transport.remove(id) {
+            self.shared.finish(id, Err(JobError::Cancelled));
+        }
+        true
+    }
+
+    /// Every job that is queued or running, oldest first
+    pub fn jobs(&self) -> Vec<JobInfo> {
+        let mut jobs: Vec<JobInfo> = self
+            .shared
+            .pending()
+            .iter()
+            .map(|(id, pending)| JobInfo {
+                id: *id,
+                owner: pending.owner,
+                priority: pending.priority,
+                running: pending.started.is_some(),
+                age_ms: pending.submitted.elapsed().as_millis() as u64,
+            })
+            .collect();
+        jobs.sort_by_key(|job| job.id);
+        jobs
+    }
+
+    /// Current metrics
+    pub fn metrics(&self) -> PoolMetrics {
+        let counters = &self.shared.counters;
+        let running = self
+            .shared
+            .pending()
+            .values()
+            .filter(|pending| pending.started.is_some())
+            .count();
+        PoolMetrics {
+            workers: self.workers.len(),
+            queued: self.shared.transport.len(),
+            running,
+            submitted: counters.submitted.load(Ordering::Relaxed),
+            rejected: counters.rejected.load(Ordering::Relaxed),
+            completed: counters.completed.load(Ordering::Relaxed),
+            failed: counters.failed.load(Ordering::Relaxed),
+            cancelled: counters.cancelled.load(Ordering::Relaxed),
+            wait_ms: counters.wait_ms.load(Ordering::Relaxed),
+            run_ms: counters.run_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    /// Close the queue and wait for workers to finish what is left
+    fn drop(&mut self) {
+        self.shared.transport.close();
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                warn!("A script worker panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rhai_executor::ExecutorConfig;
+    use crate::transport::InProcessQueue;
+    use std::time::Duration;
+
+    fn job(source: &str, priority: JobPriority) -> ScriptJob {
+        ScriptJob {
+            owner: ObjectId(2),
+            priority,
+            source: source.to_string(),
+            function: None,
+            args: Vec::new(),
+            trace: false,
+            modules: Vec::new(),
+            surroundings: None,
+        }
+    }
+
+    fn pool(workers: usize, queue_capacity: usize) -> WorkerPool {
+        let executor = RhaiExecutor::with_config(ExecutorConfig {
+            max_operations: 0, // unlimited, so only cancellation stops `loop {}`
+            ..Default::default()
+        });
+        WorkerPool::new(
+            Arc::new(executor),
+            Arc::new(InProcessQueue::new(queue_capacity)),
+            &PoolConfig {
+                workers,
+                queue_capacity,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_jobs_run_on_workers() {
+        let pool = pool(2, 8);
+        let call = ScriptJob {
+            function: Some("double".to_string()),
+            args: vec!["21".to_string()],
+            ..job("fn double(x) { parse_int(x) * 2 }", JobPriority::Tick)
+        };
+        let handles = vec![
+            pool.submit(job("40 + 2", JobPriority::Interactive))
+                .unwrap(),
+            pool.submit(call).unwrap(),
+            pool.submit(job("undefined_fn()", JobPriority::Tick))
+                .unwrap(),
+        ];
+        let mut results = Vec::new();
+        for handle in handles {
+            results.push(handle.wait().await.map(|output| output.value));
+        }
+        assert_eq!(results[0], Ok("42".to_string()));
+        assert_eq!(results[1], Ok("42".to_string()));
+        assert!(matches!(results[2], Err(JobError::Failed(_))));
+
+        let metrics = pool.metrics();
+        assert_eq!(
+            (metrics.submitted, metrics.completed, metrics.failed),
+            (3, 2, 1)
+        );
+        assert_eq!((metrics.queued, metrics.running), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_backpressure_and_cancellation() {
+        // One worker, kept busy by an endless job, and room for one more
+        let pool = pool(1, 1);
+        let endless = pool.submit(job("loop {}", JobPriority::Tick)).unwrap();
+        while !pool.jobs().first().is_some_and(|job| job.running) {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let queued = pool.submit(job("1", JobPriority::Interactive)).unwrap();
+        assert_eq!(
+            pool.submit(job("2", JobPriority::Interactive)).err(),
+            Some(JobError::QueueFull)
+        );
+        assert_eq!(pool.metrics().rejected, 1);
+
+        assert!(pool.cancel(queued.id()));
+        assert_eq!(queued.wait().await, Err(JobError::Cancelled));
+        assert!(pool.cancel(endless.id()));
+        assert_eq!(endless.wait().await, Err(JobError::Cancelled));
+        assert!(!pool.cancel(JobId(999)));
+        assert_eq!(pool.metrics().cancelled, 2);
+        assert!(pool.jobs().is_empty());
+    }
+}
@@ -0,0 +1,286 @@
+//! Script Quotas
+//!
+//! `ExecutorConfig::max_operations` only bounds a single call. Quotas bound
+//! what one owner can use across all calls: every run's operation count and
+//! wall time are charged to the player who owns the scripted object, over a
+//! rolling one-minute window.
+//!
+//! An owner over either limit is *throttled*: new runs are refused until
+//! enough usage ages out of the window. An owner throttled
+//! `strikes_to_suspend` times within a window is *suspended* for
+//! `suspend_for`, so a runaway tick script can't keep knocking on the door.
+//!
+//! # Learning Note
+//! A rolling window (a queue of timestamped charges) avoids the burst a
+//! fixed "reset every minute" counter allows at the boundary, where a
+//! script could use a full quota at 0:59 and another at 1:00.
+
+use shared::components::ObjectId;
+use shared::scripting::{QuotaReport, QuotaStatus};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Length of the rolling quota window
+pub const WINDOW: Duration = Duration::from_secs(60);
+
+/// Per-owner limits
+#[derive(Debug, Clone)]
+pub struct QuotaConfig {
+    /// Rhai operations per owner per minute
+    pub ops_per_minute: u64,
+    /// Wall-clock script time per owner per minute
+    pub wall_per_minute: Duration,
+    /// Throttled runs within one window before the owner is suspended
+    pub strikes_to_suspend: usize,
+    /// How long a suspension lasts
+    pub suspend_for: Duration,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            ops_per_minute: 5_000_000,
+            wall_per_minute: Duration::from_secs(10),
+            strikes_to_suspend: 20,
+            suspend_for: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Why a run was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaError {
+    /// The owner is over quota for the current window
+    Throttled {
+        /// When runs are likely to be admitted again
+        retry_after: Duration,
+    },
+    /// The owner was throttled too often and is suspended
+    Suspended {
+        /// Time left on the suspension
+        remaining: Duration,
+    },
+}
+
+impl fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Throttled { retry_after } => write!(
+                f,
+                "script quota exceeded; try again in {}s",
+                retry_after.as_secs().max(1)
+            ),
+            Self::Suspended { remaining } => write!(
+                f,
+                "scripts suspended for exceeding quota; {}s remaining",
+                remaining.as_secs().max(1)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QuotaError {}
+
+/// One finished run
+struct Charge {
+    at: Instant,
+    ops: u64,
+    wall: Duration,
+}
+
+/// Usage history of one owner
+#[derive(Default)]
+struct Account {
+    charges: VecDeque<Charge>,
+    strikes: VecDeque<Instant>,
+    suspended_until: Option<Instant>,
+}
+
+impl Account {
+    /// Forget charges and strikes older than the window
+    fn prune(&mut self, now: Instant) {
+        while let Some(charge) = self.charges.front() {
+            if now.duration_since(charge.at) < WINDOW {
+                break;
+            }
+            self.charges.pop_front();
+        }
+        while let Some(strike) = self.strikes.front() {
+            if now.duration_since(*strike) < WINDOW {
+                break;
+            }
+            self.strikes.pop_front();
+        }
+        if self.suspended_until.is_some_and(|until| until <= now) {
+            self.suspended_until = None;
+        }
+    }
+
+    fn used(&self) -> (u64, Duration) {
+        self.charges
+            .iter()
+            .fold((0, Duration::ZERO), |(ops, wall), charge| {
+                (ops + charge.ops, wall + charge.wall)
+            })
+    }
+
+    /// Time until the oldest charge leaves the window
+    fn retry_after(&self, now: Instant) -> Duration {
+        self.charges.front().map_or(Duration::ZERO, |charge| {
+            WINDOW.saturating_sub(now.duration_since(charge.at))
+        })
+    }
+}
+
+/// Usage of every owner, shared by all executor threads
+pub struct QuotaLedger {
+    config: QuotaConfig,
+    accounts: Mutex<HashMap<ObjectId, Account>>,
+}
+
+impl QuotaLedger {
+    /// Create an empty ledger
+    pub fn new(config: QuotaConfig) -> Self {
+        Self {
+            config,
+            accounts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Decide whether `owner` may start a run
+    ///
+    /// Returns the operations left in the owner's quota, which the run
+    /// must not exceed. A refusal counts as a strike.
+    pub fn admit(&self, owner: ObjectId, now: Instant) -> Result<u64, QuotaError> {
+        let mut accounts = self.accounts.lock().unwrap_or_else(|e| e.into_inner());
+        let account = accounts.entry(owner).or_default();
+        account.prune(now);
+
+        if let Some(until) = account.suspended_until {
+            return Err(QuotaError::Suspended {
+                remaining: until - now,
+            });
+        }
+        let (ops, wall) = account.used();
+        if ops < self.config.ops_per_minute && wall < self.config.wall_per_minute {
+            return Ok(self.config.ops_per_minute - ops);
+        }
+
+        account.strikes.push_back(now);
+        if account.strikes.len() >= self.config.strikes_to_suspend {
+            account.strikes.clear();
+            account.suspended_until = Some(now + self.config.suspend_for);
+            return Err(QuotaError::Suspended {
+                remaining: self.config.suspend_for,
+            });
+        }
+        Err(QuotaError::Throttled {
+            retry_after: account.retry_after(now),
+        })
+    }
+
+    /// Charge a finished run to `owner`
+    pub fn charge(&self, owner: ObjectId, ops: u64, wall: Duration, now: Instant) {
+        let mut accounts = self.accounts.lock().unwrap_or_else(|e| e.into_inner());
+        let account = accounts.entry(owner).or_default();
+        account.prune(now);
+        account.charges.push_back(Charge { at: now, ops, wall });
+    }
+
+    /// Current usage of `owner`
+    pub fn report(&self, owner: ObjectId, now: Instant) -> QuotaReport {
+        let mut accounts = self.accounts.lock().unwrap_or_else(|e| e.into_inner());
+        let account = accounts.entry(owner).or_default();
+        account.prune(now);
+
+        let (ops, wall) = account.used();
+        let status = match account.suspended_until {
+            Some(until) => QuotaStatus::Suspended {
+                remaining_ms: (until - now).as_millis() as u64,
+            },
+            None if ops >= self.config.ops_per_minute || wall >= self.config.wall_per_minute => {
+                QuotaStatus::Throttled {
+                    retry_after_ms: account.retry_after(now).as_millis() as u64,
+                }
+            }
+            None => QuotaStatus::Ok,
+        };
+        QuotaReport {
+            owner,
+            ops_used: ops,
+            ops_limit: self.config.ops_per_minute,
+            wall_ms_used: wall.as_millis() as u64,
+            wall_ms_limit: self.config.wall_per_minute.as_millis() as u64,
+            status,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: ObjectId = ObjectId(2);
+
+    fn ledger() -> QuotaLedger {
+        QuotaLedger::new(QuotaConfig {
+            ops_per_minute: 1_000,
+            wall_per_minute: Duration::from_secs(1),
+            strikes_to_suspend: 3,
+            suspend_for: Duration::from_secs(300),
+        })
+    }
+
+    #[test]
+    fn test_rolling_window() {
+        let ledger = ledger();
+        let start = Instant::now();
+        assert_eq!(ledger.admit(ALICE, start), Ok(1_000));
+
+        ledger.charge(ALICE, 600, Duration::from_millis(10), start);
+        ledger.charge(ALICE, 500, Duration::from_millis(10), start + WINDOW / 2);
+        assert_eq!(
+            ledger.admit(ALICE, start + WINDOW / 2),
+            Err(QuotaError::Throttled {
+                retry_after: WINDOW / 2
+            })
+        );
+        // The first charge has aged out, the second hasn't
+        assert_eq!(ledger.admit(ALICE, start + WINDOW), Ok(500));
+        assert_eq!(ledger.admit(ObjectId(3), start), Ok(1_000));
+    }
+
+    #[test]
+    fn test_wall_time_and_suspension() {
+        let ledger = ledger();
+        let start = Instant::now();
+        ledger.charge(ALICE, 1, Duration::from_secs(2), start);
+
+        assert!(matches!(
+            ledger.admit(ALICE, start),
+            Err(QuotaError::Throttled { .. })
+        ));
+        assert!(matches!(
+            ledger.admit(ALICE, start),
+            Err(QuotaError::Throttled { .. })
+        ));
+        assert!(matches!(
+            ledger.admit(ALICE, start),
+            Err(QuotaError::Suspended { .. })
+        ));
+
+        // Still suspended after the charge ages out
+        let later = start + WINDOW * 2;
+        let report = ledger.report(ALICE, later);
+        assert_eq!(report.ops_used, 0);
+        assert_eq!(
+            report.status,
+            QuotaStatus::Suspended {
+                remaining_ms: 180_000
+            }
+        );
+        assert_eq!(ledger.admit(ALICE, start + WINDOW * 5), Ok(1_000));
+    }
+}
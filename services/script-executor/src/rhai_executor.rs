@@ -2,13 +2,106 @@
 //!
 //! Executes Rhai scripts in a sandboxed environment with operation limits
 //! and timeout protection.
+//!
+//! Runs made on behalf of an owner (`execute_as`, `call_fn_as`, `run`) are
+//! also metered: Rhai's progress callback counts operations into a
+//! per-thread [`Meter`], and the total and wall time are charged to the
+//! owner's [`QuotaLedger`] entry when the run ends. The same callback
+//! stops a run whose cancel flag has been raised or which has run longer
+//! than `max_duration`.
+//!
+//! Every engine also has the deferred host functions from [`host`]
+//! registered; timers they request are returned with a job's output,
+//! along with the `print`/`debug` lines and host calls collected by
+//! [`capture`] and the run's operation count and duration. The
+//! [`debugger`] hooks are installed too, and stay idle unless the run is
+//! part of a debug session. `import` is served by the [`modules`]
+//! resolver, from the library sources sent with the job.
 
 #![allow(dead_code)] // Allow dead code in template - remove when implementing
 
-use rhai::{Engine, EvalAltResult, Scope};
-use std::time::Duration;
+use rhai::{Dynamic, Engine, EvalAltResult, ParseError, Scope, AST};
+use shared::components::ObjectId;
+use shared::scripting::{JobOutput, QuotaReport, ScriptDiagnostic, ScriptJob};
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::{debug, info};
 
+use crate::capture;
+use crate::debugger;
+use crate::host;
+use crate::modules::{self, LibraryResolver};
+use crate::quota::{QuotaConfig, QuotaError, QuotaLedger};
+use crate::spatial;
+use crate::validate;
+
+/// Operation count of the run in progress on this thread
+#[derive(Debug, Clone)]
+struct Meter {
+    /// Operations performed so far
+    ops: u64,
+    /// Operations the owner has left
+    budget: u64,
+    /// Raised to stop the run early
+    cancel: Option<Arc<AtomicBool>>,
+    /// Time spent paused in the debugger, which isn't charged
+    paused: Duration,
+    /// When the run started and how long it may take
+    deadline: Option<(Instant, Duration)>,
+}
+
+impl Meter {
+    fn cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    fn overdue(&self) -> bool {
+        self.deadline
+            .is_some_and(|(started, limit)| started.elapsed().saturating_sub(self.paused) > limit)
+    }
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self {
+            ops: 0,
+            budget: u64::MAX,
+            cancel: None,
+            paused: Duration::ZERO,
+            deadline: None,
+        }
+    }
+}
+
+/// Operations between wall-clock checks, since reading the clock on
+/// every operation would cost more than the operation
+const DEADLINE_CHECK_OPS: u64 = 256;
+
+thread_local! {
+    // Rhai evaluates synchronously, so the engine's progress callback
+    // always runs on the thread that started the run
+    static METER: RefCell<Meter> = RefCell::new(Meter::default());
+}
+
+/// What a metered run used
+#[derive(Debug, Clone, Copy)]
+struct Usage {
+    /// Operations performed
+    ops: u64,
+    /// Wall-clock time charged
+    wall: Duration,
+}
+
+/// Exclude `paused` from the wall time of the run in progress on this thread
+pub(crate) fn add_paused(paused: Duration) {
+    METER.with(|meter| meter.borrow_mut().paused += paused);
+}
+
 /// Configuration for script execution
 #[derive(Debug, Clone)]
 pub struct ExecutorConfig {
@@ -20,6 +113,10 @@ pub struct ExecutorConfig {
     pub max_string_len: usize,
     /// Maximum array size
     pub max_array_size: usize,
+    /// Maximum number of properties in an object map
+    pub max_map_size: usize,
+    /// Per-owner limits across all calls
+    pub quota: QuotaConfig,
 }
 
 impl Default for ExecutorConfig {
@@ -29,14 +126,53 @@ impl Default for ExecutorConfig {
             max_duration: Duration::from_secs(5),
             max_string_len: 10_000,
             max_array_size: 1_000,
+            max_map_size: 1_000,
+            quota: QuotaConfig::default(),
+        }
+    }
+}
+
+/// Why a metered run failed
+#[derive(Debug)]
+pub enum ExecError {
+    /// The owner may not run scripts right now
+    Quota(QuotaError),
+    /// The run was stopped because it used up its owner's quota
+    QuotaExhausted,
+    /// The run was cancelled before it finished
+    Cancelled,
+    /// The run took longer than the configured maximum duration
+    TimedOut(Duration),
+    /// The script failed or ran out of operations
+    Script(Box<EvalAltResult>),
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Quota(e) => write!(f, "{}", e),
+            Self::QuotaExhausted => write!(f, "script stopped: its owner's quota ran out"),
+            Self::Cancelled => write!(f, "script cancelled"),
+            Self::TimedOut(limit) => write!(f, "script stopped: ran longer than {:?}", limit),
+            Self::Script(e) => write!(f, "{}", e),
         }
     }
 }
 
+impl std::error::Error for ExecError {}
+
+impl From<Box<EvalAltResult>> for ExecError {
+    fn from(e: Box<EvalAltResult>) -> Self {
+        Self::Script(e)
+    }
+}
+
 /// Rhai script executor with sandboxing
 pub struct RhaiExecutor {
     engine: Engine,
     config: ExecutorConfig,
+    quotas: QuotaLedger,
+    modules: LibraryResolver,
 }
 
 impl RhaiExecutor {
@@ -52,15 +188,47 @@ impl RhaiExecutor {
         // Set operation limits for sandboxing
         engine.set_max_operations(config.max_operations);
 
-        // Note: String and array limits are set differently in newer Rhai versions
-        // These can be configured via the Engine's limits if needed
+        // Bound the data a script can build; Rhai counts nested contents,
+        // so an array of strings is limited by both sizes
+        engine.set_max_string_size(config.max_string_len);
+        engine.set_max_array_size(config.max_array_size);
+        engine.set_max_map_size(config.max_map_size);
+
+        // Count operations for quota accounting, stopping the run once
+        // the owner's remaining budget is spent, its time is up or the job
+        // is cancelled
+        engine.on_progress(|ops| {
+            METER.with(|meter| {
+                let mut meter = meter.borrow_mut();
+                meter.ops = ops;
+                let stop = ops > meter.budget
+                    || meter.cancelled()
+                    || (ops % DEADLINE_CHECK_OPS == 0 && meter.overdue());
+                stop.then_some(Dynamic::UNIT)
+            })
+        });
 
         // Disable dangerous operations
         engine.disable_symbol("eval"); // Prevent eval injection
 
+        host::register(&mut engine);
+        spatial::register(&mut engine);
+        capture::register(&mut engine);
+        debugger::register(&mut engine);
+
+        // Imports come only from the job's modules, never from disk
+        let modules = LibraryResolver::new();
+        engine.set_module_resolver(modules.clone());
+
         info!("Rhai executor initialized with limits: {:?}", config);
 
-        Self { engine, config }
+        let quotas = QuotaLedger::new(config.quota.clone());
+        Self {
+            engine,
+            config,
+            quotas,
+            modules,
+        }
     }
 
     /// Get mutable access to the engine for registering custom functions
@@ -76,6 +244,23 @@ impl RhaiExecutor {
         &mut self.engine
     }
 
+    /// Check a script without running it
+    ///
+    /// See [`validate`] for what is checked.
+    pub fn validate(&self, script: &str) -> Result<(), Vec<ScriptDiagnostic>> {
+        let diagnostics = validate::validate(&self.engine, script);
+        if diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    /// Compile a script without running it
+    pub fn compile(&self, script: &str) -> Result<AST, ParseError> {
+        self.engine.compile(script)
+    }
+
     /// Execute a script and return the result
     pub fn execute(&self, script: &str) -> Result<String, Box<EvalAltResult>> {
         debug!("Executing Rhai script ({} chars)", script.len());
@@ -118,6 +303,103 @@ impl RhaiExecutor {
     }
 }
 
+impl RhaiExecutor {
+    /// Execute a script on behalf of `owner`, charging it to their quota
+    pub fn execute_as(&self, owner: ObjectId, script: &str) -> Result<String, ExecError> {
+        self.metered(owner, None, || self.execute(script))
+            .map(|(value, _)| value)
+    }
+
+    /// Call a script function on behalf of `owner`, charging it to their quota
+    pub fn call_fn_as(
+        &self,
+        owner: ObjectId,
+        script: &str,
+        fn_name: &str,
+        args: Vec<String>,
+    ) -> Result<String, ExecError> {
+        self.metered(owner, None, || self.call_fn(script, fn_name, args))
+            .map(|(value, _)| value)
+    }
+
+    /// Run a queued job, stopping early if `cancel` is raised
+    ///
+    /// Timers requested and output written by a run that fails are
+    /// discarded.
+    pub fn run(&self, job: &ScriptJob, cancel: Arc<AtomicBool>) -> Result<JobOutput, ExecError> {
+        host::begin();
+        capture::begin(job.trace);
+        modules::begin(&job.modules);
+        spatial::begin(job.surroundings.as_ref());
+        let result = self.metered(job.owner, Some(cancel), || match &job.function {
+            Some(function) => self.call_fn(&job.source, function, job.args.clone()),
+            None => self.execute(&job.source),
+        });
+        modules::end();
+        spatial::end();
+        let captured = capture::take();
+        let (value, usage) = result?;
+        Ok(JobOutput {
+            value,
+            timers: host::take(),
+            trace: captured.into_trace(usage.ops, usage.wall),
+        })
+    }
+
+    /// Number of compiled library modules cached
+    pub fn cached_modules(&self) -> usize {
+        self.modules.cached()
+    }
+
+    /// Current quota usage of `owner`
+    pub fn quota_report(&self, owner: ObjectId) -> QuotaReport {
+        self.quotas.report(owner, Instant::now())
+    }
+
+    /// Run `f` with the meter armed, then charge what it used
+    fn metered(
+        &self,
+        owner: ObjectId,
+        cancel: Option<Arc<AtomicBool>>,
+        f: impl FnOnce() -> Result<String, Box<EvalAltResult>>,
+    ) -> Result<(String, Usage), ExecError> {
+        let budget = self
+            .quotas
+            .admit(owner, Instant::now())
+            .map_err(ExecError::Quota)?;
+        let started = Instant::now();
+        METER.with(|meter| {
+            *meter.borrow_mut() = Meter {
+                ops: 0,
+                budget,
+                cancel,
+                paused: Duration::ZERO,
+                deadline: Some((started, self.config.max_duration)),
+            }
+        });
+
+        let result = f();
+        let elapsed = started.elapsed();
+
+        let meter = METER.with(|meter| meter.take());
+        let wall = elapsed.saturating_sub(meter.paused);
+        self.quotas.charge(owner, meter.ops, wall, Instant::now());
+        debug!("{} used {} ops in {:?}", owner, meter.ops, wall);
+        match result {
+            Err(_) if meter.cancelled() => Err(ExecError::Cancelled),
+            Err(_) if meter.ops > meter.budget => Err(ExecError::QuotaExhausted),
+            Err(_) if meter.overdue() => Err(ExecError::TimedOut(self.config.max_duration)),
+            result => Ok((
+                result?,
+                Usage {
+                    ops: meter.ops,
+                    wall,
+                },
+            )),
+        }
+    }
+}
+
 impl Default for RhaiExecutor {
     fn default() -> Self {
         Self::new()
@@ -127,6 +409,8 @@ impl Default for RhaiExecutor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use shared::scripting::{HostCall, ModuleSource, OutputStream, Surroundings};
+    use shared::spatial::{Placed, Vec3};
 
     #[test]
     fn test_basic_execution() {
@@ -158,6 +442,45 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_wall_time_limit() {
+        let executor = RhaiExecutor::with_config(ExecutorConfig {
+            max_operations: 10_000_000,
+            max_duration: Duration::ZERO,
+            ..Default::default()
+        });
+        let result = executor.execute_as(ObjectId(2), "let x = 0; loop { x += 1; }");
+        assert!(
+            matches!(result, Err(ExecError::TimedOut(_))),
+            "{:?}",
+            result
+        );
+
+        // Unmetered runs only have the operation limit
+        assert!(matches!(
+            executor
+                .execute("let x = 0; for i in 0..1000 { x += i; } x")
+                .as_deref(),
+            Ok("499500")
+        ));
+    }
+
+    #[test]
+    fn test_data_limits() {
+        let executor = RhaiExecutor::with_config(ExecutorConfig {
+            max_string_len: 100,
+            max_array_size: 10,
+            ..Default::default()
+        });
+        assert!(executor
+            .execute("let s = \"ab\"; loop { s += s; }")
+            .is_err());
+        assert!(executor
+            .execute("let a = []; loop { a.push([]); }")
+            .is_err());
+        assert!(executor.execute("\"x\".pad(100, \"y\")").is_ok());
+    }
+
     #[test]
     fn test_function_call() {
         let executor = RhaiExecutor::new();
@@ -174,4 +497,214 @@ mod tests {
 
         assert_eq!(result, "Hello, World!");
     }
+
+    #[test]
+    fn test_runs_are_charged_to_owner() {
+        let config = ExecutorConfig {
+            quota: QuotaConfig {
+                ops_per_minute: 50,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let executor = RhaiExecutor::with_config(config);
+        let owner = ObjectId(2);
+
+        executor.execute_as(owner, "let x = 1; x + 1").unwrap();
+        let used = executor.quota_report(owner).ops_used;
+        assert!(used > 0);
+
+        // The run is stopped once the remaining budget is spent
+        assert!(matches!(
+            executor.execute_as(owner, "let x = 0; for i in 0..100 { x += i; } x"),
+            Err(ExecError::QuotaExhausted)
+        ));
+        assert!(matches!(
+            executor.execute_as(owner, "1"),
+            Err(ExecError::Quota(QuotaError::Throttled { .. }))
+        ));
+        assert_eq!(executor.quota_report(ObjectId(3)).ops_used, 0);
+    }
+
+    #[test]
+    fn test_run_returns_requested_timers() {
+        let executor = RhaiExecutor::new();
+        let job = ScriptJob {
+            owner: ObjectId(2),
+            priority: shared::scripting::JobPriority::Tick,
+            source: r#"
+                fn chime() { "dong" }
+                fn on_use(actor) {
+                    wait(10, Fn("chime"));
+                    wait(0.25, || "soon");
+                    schedule_at(0, Fn("chime"));
+                    "ding"
+                }
+            "#
+            .to_string(),
+            function: Some("on_use".to_string()),
+            args: vec!["#3".to_string()],
+            trace: false,
+            modules: Vec::new(),
+            surroundings: None,
+        };
+        let output = executor.run(&job, Arc::default()).unwrap();
+        assert_eq!(output.value, "ding");
+        let timers: Vec<_> = output
+            .timers
+            .iter()
+            .map(|timer| (timer.function.starts_with("anon$"), timer.delay_ms))
+            .collect();
+        assert_eq!(timers, vec![(false, 10_000), (true, 250), (false, 0)]);
+
+        // The closure's generated name can be called on a later run
+        let later = ScriptJob {
+            function: Some(output.timers[1].function.clone()),
+            args: Vec::new(),
+            ..job.clone()
+        };
+        assert_eq!(executor.run(&later, Arc::default()).unwrap().value, "soon");
+
+        let capturing = ScriptJob {
+            source: "let n = 1; wait(1, || n + 1);".to_string(),
+            function: None,
+            ..job
+        };
+        let err = executor.run(&capturing, Arc::default()).unwrap_err();
+        assert!(err.to_string().contains("captures variables"), "{}", err);
+    }
+
+    #[test]
+    fn test_run_captures_output_and_trace() {
+        let executor = RhaiExecutor::new();
+        let mut job = ScriptJob {
+            owner: ObjectId(2),
+            priority: shared::scripting::JobPriority::Interactive,
+            source: "fn chime() {}\nprint(\"hello\");\ndebug(\"x\");\nwait(2, Fn(\"chime\"));\n7"
+                .to_string(),
+            function: None,
+            args: Vec::new(),
+            trace: false,
+            modules: Vec::new(),
+            surroundings: None,
+        };
+        let output = executor.run(&job, Arc::default()).unwrap();
+        assert_eq!(output.value, "7");
+        let lines: Vec<_> = output
+            .trace
+            .output
+            .iter()
+            .map(|line| (line.stream, line.text.as_str(), line.line))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (OutputStream::Print, "hello", 0),
+                (OutputStream::Debug, "\"x\"", 3)
+            ]
+        );
+        assert!(output.trace.ops > 0);
+        assert_eq!(output.trace.host_calls, None);
+
+        job.trace = true;
+        let output = executor.run(&job, Arc::default()).unwrap();
+        assert_eq!(
+            output.trace.host_calls,
+            Some(vec![HostCall {
+                function: "wait".to_string(),
+                args: vec!["2".to_string(), "Fn(chime)".to_string()],
+                result: Ok("()".to_string()),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_imports_come_from_job_modules() {
+        let executor = RhaiExecutor::new();
+        let dice = |revision: u32, sides: i64| ModuleSource {
+            path: "lib/dice".to_string(),
+            object: ObjectId(4),
+            revision,
+            source: format!("export const SIDES = {0};\nfn roll(n) {{ n * {0} }}", sides),
+        };
+        let mut job = ScriptJob {
+            owner: ObjectId(2),
+            priority: shared::scripting::JobPriority::Interactive,
+            source:
+                "import \"lib/dice\" as dice;\nfn on_use(actor) { dice::roll(2) + dice::SIDES }"
+                    .to_string(),
+            function: Some("on_use".to_string()),
+            args: vec!["#3".to_string()],
+            trace: false,
+            modules: vec![dice(1, 6)],
+            surroundings: None,
+        };
+        assert_eq!(executor.run(&job, Arc::default()).unwrap().value, "18");
+        assert_eq!(executor.run(&job, Arc::default()).unwrap().value, "18");
+        assert_eq!(executor.cached_modules(), 1);
+
+        // A new revision of the library is compiled afresh
+        job.modules = vec![dice(2, 20)];
+        assert_eq!(executor.run(&job, Arc::default()).unwrap().value, "60");
+        assert_eq!(executor.cached_modules(), 2);
+
+        // Nothing is imported that the job didn't bring, not even files
+        job.modules.clear();
+        let err = executor.run(&job, Arc::default()).unwrap_err();
+        assert!(err.to_string().contains("lib/dice"), "{}", err);
+        job.source = "import \"/etc/hostname\" as h; 1".to_string();
+        job.function = None;
+        assert!(executor.run(&job, Arc::default()).is_err());
+
+        job.modules = vec![ModuleSource {
+            source: "import \"lib/dice\" as again; fn roll(n) { n }".to_string(),
+            ..dice(3, 6)
+        }];
+        job.source = "import \"lib/dice\" as dice; dice::roll(1)".to_string();
+        let err = executor.run(&job, Arc::default()).unwrap_err();
+        assert!(err.to_string().contains("imports itself"), "{}", err);
+    }
+
+    #[test]
+    fn test_spatial_queries_use_surroundings() {
+        let executor = RhaiExecutor::new();
+        let placed = |id, position: [f32; 3], radius| Placed {
+            id: ObjectId(id),
+            position: position.into(),
+            radius,
+        };
+        let mut job = ScriptJob {
+            owner: ObjectId(2),
+            priority: shared::scripting::JobPriority::Interactive,
+            source: r#"
+                let ahead = raycast(position(), facing(), 20);
+                [ahead.object, ahead.distance, nearest([0, 0, 0], 2),
+                 overlap_sphere([0, 0, 0], 5.0), overlap_box([4, 0, 0], [2, 2, 2])]
+            "#
+            .to_string(),
+            function: None,
+            args: Vec::new(),
+            trace: false,
+            modules: Vec::new(),
+            surroundings: Some(Surroundings {
+                position: Vec3::new(0.0, 0.0, 1.0),
+                facing: Vec3::Y,
+                objects: vec![
+                    placed(5, [0.0, 10.0, 1.0], 1.0),
+                    placed(6, [4.0, 0.0, 0.0], 0.5),
+                    placed(7, [0.0, -30.0, 0.0], 0.5),
+                ],
+            }),
+        };
+        let output = executor.run(&job, Arc::default()).unwrap();
+        assert_eq!(
+            output.value,
+            r##"["#5", 9.0, ["#6", "#5"], ["#6"], ["#6"]]"##
+        );
+
+        // A script out of every room can't ask
+        job.surroundings = None;
+        let err = executor.run(&job, Arc::default()).unwrap_err();
+        assert!(err.to_string().contains("in a room"), "{}", err);
+    }
 }
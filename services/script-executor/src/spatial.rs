@@ -0,0 +1,227 @@
+//! Spatial Query Host Functions
+//!
+//! Host functions that ask about the space around the object running:
+//! - `position()` and `facing()`: where it is and which way it faces, as
+//!   `[x, y, z]`
+//! - `raycast(origin, direction, distance)`: the first object along a ray,
+//!   as `#{ object, distance, point }`, or `()` if there is none
+//! - `overlap_sphere(center, radius)` and `overlap_box(center, size)`: the
+//!   objects touching a volume, lowest dbref first
+//! - `nearest(center, count)`: up to `count` objects, nearest first
+//!
+//! Points and directions are `[x, y, z]` arrays of numbers, `size` is a
+//! box's full size as in `COLLIDER`, and objects are dbrefs such as `"#12"`.
+//! Coordinates come back rounded to [`DECIMALS`] places.
+//!
+//! Scripts can't reach the world from here, so world-state sends what the
+//! object can see with the job (see `shared::scripting::Surroundings`):
+//! objects in its room within `spatial::MAX_RANGE`, leaving out any its
+//! owner may not see. Queries are answered from that, which is also why
+//! they can't find anything further away. A job with no surroundings (the
+//! object isn't in a room) fails any query.
+//!
+//! Every call also goes into the run's host-call trace when one was asked
+//! for (see [`capture`](crate::capture)).
+//!
+//! # Learning Note
+//! Asking world-state mid-run would make every query a round trip and
+//! leave a worker waiting on the main loop, the same trade-off `host`
+//! makes for timers. A snapshot taken when the job is built costs one
+//! index build per run, and also means a script sees one consistent
+//! moment rather than objects moving between two of its queries.
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, FLOAT};
+use shared::components::ObjectId;
+use shared::scripting::Surroundings;
+use shared::spatial::{SpatialIndex, Vec3};
+use std::cell::RefCell;
+
+use crate::capture;
+
+/// Decimal places coordinates and distances are rounded to
+pub const DECIMALS: i32 = 3;
+
+/// The one room a job's surroundings are in
+const HERE: ObjectId = ObjectId(0);
+
+/// A job's surroundings, indexed for queries
+struct Around {
+    position: Vec3,
+    facing: Vec3,
+    index: SpatialIndex,
+}
+
+thread_local! {
+    // Surroundings of the run on this thread
+    static AROUND: RefCell<Option<Around>> = const { RefCell::new(None) };
+}
+
+type QueryResult = Result<Dynamic, Box<EvalAltResult>>;
+
+/// Register the spatial query host functions on `engine`
+pub fn register(engine: &mut Engine) {
+    engine.register_fn("position", || {
+        traced(
+            "position",
+            Vec::new(),
+            here(|around| Ok(vector(around.position))),
+        )
+    });
+    engine.register_fn("facing", || {
+        traced(
+            "facing",
+            Vec::new(),
+            here(|around| Ok(vector(around.facing))),
+        )
+    });
+    engine.register_fn(
+        "raycast",
+        |origin: Array, direction: Array, distance: Dynamic| {
+            let args = vec![render(&origin), render(&direction), distance.to_string()];
+            traced("raycast", args, raycast(&origin, &direction, &distance))
+        },
+    );
+    engine.register_fn("overlap_sphere", |center: Array, radius: Dynamic| {
+        let args = vec![render(&center), radius.to_string()];
+        let result = point(&center, "center").and_then(|center| {
+            let radius = number(&radius, "radius")?;
+            here(|around| {
+                Ok(dbrefs(around.index.overlap_sphere(
+                    HERE,
+                    center,
+                    radius,
+                    |_| true,
+                )))
+            })
+        });
+        traced("overlap_sphere", args, result)
+    });
+    engine.register_fn("overlap_box", |center: Array, size: Array| {
+        let args = vec![render(&center), render(&size)];
+        let result = point(&center, "center").and_then(|center| {
+            let half = point(&size, "size")?.abs() / 2.0;
+            here(|around| {
+                let ids = around
+                    .index
+                    .overlap_box(HERE, center - half, center + half, |_| true);
+                Ok(dbrefs(ids))
+            })
+        });
+        traced("overlap_box", args, result)
+    });
+    engine.register_fn("nearest", |center: Array, count: i64| {
+        let args = vec![render(&center), count.to_string()];
+        let result = point(&center, "center").and_then(|center| {
+            let count =
+                usize::try_from(count).map_err(|_| "nearest() needs a count of 0 or more")?;
+            here(|around| {
+                let nearest = around
+                    .index
+                    .nearest(HERE, center, count, f32::INFINITY, |_| true);
+                Ok(dbrefs(nearest.into_iter().map(|(id, _)| id).collect()))
+            })
+        });
+        traced("nearest", args, result)
+    });
+}
+
+/// Make `surroundings` what the run about to start on this thread sees
+pub fn begin(surroundings: Option<&Surroundings>) {
+    let around = surroundings.map(|surroundings| {
+        let mut index = SpatialIndex::default();
+        for placed in &surroundings.objects {
+            index.upsert(HERE, *placed);
+        }
+        Around {
+            position: surroundings.position,
+            facing: surroundings.facing,
+            index,
+        }
+    });
+    AROUND.with(|slot| *slot.borrow_mut() = around);
+}
+
+/// Forget the surroundings set since [`begin`]
+pub fn end() {
+    AROUND.with(|slot| slot.borrow_mut().take());
+}
+
+fn raycast(origin: &Array, direction: &Array, distance: &Dynamic) -> QueryResult {
+    let origin = point(origin, "origin")?;
+    let direction = point(direction, "direction")?;
+    let distance = number(distance, "distance")?;
+    if direction == Vec3::ZERO {
+        return Err("raycast() needs a direction other than [0, 0, 0]".into());
+    }
+    here(|around| {
+        let Some(hit) = around
+            .index
+            .raycast(HERE, origin, direction, distance, |_| true)
+        else {
+            return Ok(Dynamic::UNIT);
+        };
+        let mut map = Map::new();
+        map.insert("object".into(), hit.id.to_string().into());
+        map.insert("distance".into(), round(hit.distance).into());
+        map.insert("point".into(), vector(hit.point));
+        Ok(map.into())
+    })
+}
+
+/// Run `query` against the current run's surroundings
+fn here(query: impl FnOnce(&Around) -> QueryResult) -> QueryResult {
+    AROUND.with(|slot| match slot.borrow().as_ref() {
+        Some(around) => query(around),
+        None => Err("spatial queries only work for objects in a room".into()),
+    })
+}
+
+/// Pass a call's result through, recording the call in the run's trace
+fn traced(function: &str, args: Vec<String>, result: QueryResult) -> QueryResult {
+    let recorded = match &result {
+        Ok(value) => Ok(value.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    capture::record_call(function, args, recorded);
+    result
+}
+
+fn render(array: &Array) -> String {
+    Dynamic::from_array(array.clone()).to_string()
+}
+
+/// A number argument, integer or float
+fn number(value: &Dynamic, name: &str) -> Result<f32, Box<EvalAltResult>> {
+    let number = value
+        .as_float()
+        .or_else(|_| value.as_int().map(|n| n as FLOAT))
+        .map_err(|_| format!("`{}` should be a number, not {}", name, value.type_name()))?;
+    Ok(number as f32)
+}
+
+/// An `[x, y, z]` argument
+fn point(array: &Array, name: &str) -> Result<Vec3, Box<EvalAltResult>> {
+    let [x, y, z] = array.as_slice() else {
+        return Err(format!("`{}` should be [x, y, z]", name).into());
+    };
+    let point = Vec3::new(number(x, name)?, number(y, name)?, number(z, name)?);
+    if !point.is_finite() {
+        return Err(format!("`{}` should be finite", name).into());
+    }
+    Ok(point)
+}
+
+fn round(value: f32) -> FLOAT {
+    let scale = 10f64.powi(DECIMALS);
+    (value as FLOAT * scale).round() / scale
+}
+
+fn vector(v: Vec3) -> Dynamic {
+    let array: Array = v.to_array().into_iter().map(|c| round(c).into()).collect();
+    array.into()
+}
+
+fn dbrefs(ids: Vec<ObjectId>) -> Dynamic {
+    let array: Array = ids.into_iter().map(|id| id.to_string().into()).collect();
+    array.into()
+}
@@ -0,0 +1,235 @@
+//! Job Transport
+//!
+//! Where script jobs wait between being submitted and a worker picking
+//! them up. The worker pool only sees [`JobTransport`], so the default
+//! in-process queue can be swapped for a message broker (see
+//! `amqp_transport`) without touching the workers.
+//!
+//! # Learning Note
+//! A bounded queue is what makes backpressure possible: when it is full
+//! the submitter gets an error straight away, instead of the executor
+//! quietly building an ever-growing backlog it can never catch up on.
+
+use serde::{Deserialize, Serialize};
+use shared::scripting::{JobPriority, ScriptJob};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::sync::{Condvar, Mutex};
+
+/// Identifies a job for its whole life
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct JobId(pub u64);
+
+impl fmt::Display for JobId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "job {}", self.0)
+    }
+}
+
+/// A job as carried by a transport
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QueuedJob {
+    /// Pool-assigned id
+    pub id: JobId,
+    /// What to run
+    pub job: ScriptJob,
+}
+
+/// Why a transport refused a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportError {
+    /// The queue is at capacity; try again later
+    Full,
+    /// The transport has been shut down
+    Closed,
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => write!(f, "job queue is full"),
+            Self::Closed => write!(f, "job queue is closed"),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+/// Queue of jobs waiting for a worker
+pub trait JobTransport: Send + Sync {
+    /// Add a job, failing immediately if there is no room
+    fn push(&self, job: QueuedJob) -> Result<(), TransportError>;
+
+    /// Wait for the next job, highest priority first
+    ///
+    /// Returns `None` once the transport is closed and drained.
+    fn pop(&self) -> Option<QueuedJob>;
+
+    /// Take a job out of the queue before any worker starts it
+    ///
+    /// Returns false if the job is not (or no longer) queued.
+    fn remove(&self, id: JobId) -> bool;
+
+    /// Jobs currently waiting
+    fn len(&self) -> usize;
+
+    /// Stop accepting jobs and wake every waiting worker
+    fn close(&self);
+}
+
+/// Heap entry: higher priority first, then first in, first out
+struct Entry {
+    priority: JobPriority,
+    sequence: u64,
+    job: QueuedJob,
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+#[derive(Default)]
+struct QueueState {
+    heap: BinaryHeap<Entry>,
+    next_sequence: u64,
+    closed: bool,
+}
+
+/// Bounded priority queue shared by threads of this process
+pub struct InProcessQueue {
+    capacity: usize,
+    state: Mutex<QueueState>,
+    ready: Condvar,
+}
+
+impl InProcessQueue {
+    /// Create a queue holding at most `capacity` jobs
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(QueueState::default()),
+            ready: Condvar::new(),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl JobTransport for InProcessQueue {
+    fn push(&self, job: QueuedJob) -> Result<(), TransportError> {
+        let mut state = self.state();
+        if state.closed {
+            return Err(TransportError::Closed);
+        }
+        if state.heap.len() >= self.capacity {
+            return Err(TransportError::Full);
+        }
+        let sequence = state.next_sequence;
+        state.next_sequence += 1;
+        state.heap.push(Entry {
+            priority: job.job.priority,
+            sequence,
+            job,
+        });
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<QueuedJob> {
+        let mut state = self.state();
+        loop {
+            if let Some(entry) = state.heap.pop() {
+                return Some(entry.job);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.ready.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn remove(&self, id: JobId) -> bool {
+        let mut state = self.state();
+        let before = state.heap.len();
+        state.heap.retain(|entry| entry.job.id != id);
+        state.heap.len() != before
+    }
+
+    fn len(&self) -> usize {
+        self.state().heap.len()
+    }
+
+    fn close(&self) {
+        self.state().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::components::ObjectId;
+
+    fn job(id: u64, priority: JobPriority) -> QueuedJob {
+        QueuedJob {
+            id: JobId(id),
+            job: ScriptJob {
+                owner: ObjectId(1),
+                priority,
+                source: String::new(),
+                function: None,
+                args: Vec::new(),
+                trace: false,
+                modules: Vec::new(),
+                surroundings: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_priority_order_and_capacity() {
+        let queue = InProcessQueue::new(3);
+        queue.push(job(1, JobPriority::Tick)).unwrap();
+        queue.push(job(2, JobPriority::Interactive)).unwrap();
+        queue.push(job(3, JobPriority::Tick)).unwrap();
+        assert_eq!(
+            queue.push(job(4, JobPriority::Interactive)),
+            Err(TransportError::Full)
+        );
+
+        assert!(queue.remove(JobId(3)));
+        assert!(!queue.remove(JobId(3)));
+        queue.push(job(5, JobPriority::Interactive)).unwrap();
+
+        let order: Vec<u64> = (0..3).map(|_| queue.pop().unwrap().id.0).collect();
+        assert_eq!(order, vec![2, 5, 1]);
+
+        queue.close();
+        assert_eq!(queue.pop(), None);
+        assert_eq!(
+            queue.push(job(6, JobPriority::Tick)),
+            Err(TransportError::Closed)
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Script Validation
+//!
+//! Checks a script before world-state accepts it, so mistakes are
+//! reported when the builder types `@set obj/script = ...` instead of the
+//! first time a player triggers the object.
+//!
+//! Three checks run, in order:
+//! 1. The script must compile with the configured engine
+//! 2. Every `on_*` function must be a known event handler taking the
+//!    parameters world-state passes it
+//! 3. Every plain function call must name a host function, a built-in or
+//!    a function defined in the script, with a matching argument count
+//!
+//! # Learning Note
+//! Rhai resolves function calls at run time, so a typo like `emti("hi")`
+//! compiles fine and only fails when that line runs. Walking the compiled
+//! AST lets us catch those early without running anything.
+
+use rhai::{ASTNode, Engine, Expr, FnCallExpr, Position, Stmt, AST};
+use shared::scripting::ScriptDiagnostic;
+use std::collections::{BTreeSet, HashMap};
+
+/// Event handlers world-state calls and the parameters each receives
+pub const HANDLERS: &[(&str, &[&str])] = &[
+    ("on_look", &["actor"]),
+    ("on_examine", &["actor"]),
+    ("on_use", &["actor"]),
+    ("on_get", &["actor"]),
+    ("on_drop", &["actor"]),
+    ("on_enter", &["actor"]),
+    ("on_leave", &["actor"]),
+    ("on_step", &["actor"]),
+    ("on_equip", &["actor"]),
+    ("on_collide", &["other", "phase", "point", "impulse"]),
+    ("on_trigger", &["other", "phase", "point", "impulse"]),
+];
+
+/// Functions the Rhai evaluator handles itself rather than registering
+const KEYWORD_FUNCTIONS: &[&str] = &[
+    "print",
+    "debug",
+    "type_of",
+    "Fn",
+    "call",
+    "curry",
+    "is_shared",
+    "is_def_var",
+    "is_def_fn",
+];
+
+/// Check `source` against `engine`, returning every problem found
+///
+/// An empty list means the script is valid.
+pub fn validate(engine: &Engine, source: &str) -> Vec<ScriptDiagnostic> {
+    let ast = match engine.compile(source) {
+        Ok(ast) => ast,
+        Err(e) => return vec![diagnostic(e.position(), e.err_type().to_string())],
+    };
+
+    let mut diagnostics = check_handlers(&ast, source);
+    diagnostics.extend(check_calls(engine, &ast));
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+/// Check that `on_*` functions are known handlers with the right arity
+fn check_handlers(ast: &AST, source: &str) -> Vec<ScriptDiagnostic> {
+    let mut diagnostics = Vec::new();
+    for function in ast.iter_functions() {
+        if !function.name.starts_with("on_") {
+            continue;
+        }
+        let (line, column) = definition_position(source, function.name);
+        let message = match HANDLERS.iter().find(|(name, _)| *name == function.name) {
+            Some((_, params)) if params.len() == function.params.len() => continue,
+            Some((name, params)) => format!(
+                "handler `{}` must take {} parameter{} ({}), not {}",
+                name,
+                params.len(),
+                if params.len() == 1 { "" } else { "s" },
+                params.join(", "),
+                function.params.len()
+            ),
+            None => format!(
+                "unknown event handler `{}`; handlers are {}",
+                function.name,
+                HANDLERS
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        diagnostics.push(ScriptDiagnostic {
+            line,
+            column,
+            message,
+        });
+    }
+    diagnostics
+}
+
+/// Check that every plain function call can be resolved
+fn check_calls(engine: &Engine, ast: &AST) -> Vec<ScriptDiagnostic> {
+    // Name -> accepted argument counts, from the engine and the script
+    let mut known: HashMap<String, BTreeSet<usize>> = HashMap::new();
+    for (name, params) in engine.collect_fn_metadata(
+        None,
+        |info| Some((info.metadata.name.to_string(), info.metadata.num_params)),
+        true,
+    ) {
+        known.entry(name).or_default().insert(params);
+    }
+    for function in ast.iter_functions() {
+        known
+            .entry(function.name.to_string())
+            .or_default()
+            .insert(function.params.len());
+    }
+
+    let mut diagnostics = Vec::new();
+    ast.walk(&mut |path: &[ASTNode]| {
+        let call = match path.last() {
+            Some(ASTNode::Expr(Expr::FnCall(call, pos))) => Some((call, *pos)),
+            Some(ASTNode::Stmt(Stmt::FnCall(call, pos))) => Some((call, *pos)),
+            _ => None,
+        };
+        if let Some((call, pos)) = call {
+            if let Some(message) = unresolved(&known, call) {
+                diagnostics.push(diagnostic(pos, message));
+            }
+        }
+        true
+    });
+    diagnostics
+}
+
+/// Describe why `call` cannot be resolved, if it can't
+fn unresolved(known: &HashMap<String, BTreeSet<usize>>, call: &FnCallExpr) -> Option<String> {
+    // Operators and module-qualified calls are resolved elsewhere
+    if call.is_operator_call() || call.is_qualified() {
+        return None;
+    }
+    let name = call.name.as_str();
+    if KEYWORD_FUNCTIONS.contains(&name) {
+        return None;
+    }
+    let arity = call.args.len();
+    match known.get(name) {
+        None => Some(format!("unknown function `{}`", name)),
+        Some(arities) if arities.contains(&arity) => None,
+        Some(arities) => Some(format!(
+            "`{}` takes {} argument(s), not {}",
+            name,
+            arities
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(" or "),
+            arity
+        )),
+    }
+}
+
+/// Locate `fn <name>` in the source (Rhai does not record where functions
+/// are defined)
+fn definition_position(source: &str, name: &str) -> (usize, usize) {
+    for (index, line) in source.lines().enumerate() {
+        let mut rest = line;
+        let mut offset = 0;
+        while let Some(at) = rest.find("fn ") {
+            let after = rest[at + 3..].trim_start();
+            if after.starts_with(name)
+                && !after[name.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '_')
+            {
+                return (index + 1, offset + at + 1);
+            }
+            offset += at + 3;
+            rest = &rest[at + 3..];
+        }
+    }
+    (0, 0)
+}
+
+/// Build a diagnostic from a Rhai position
+fn diagnostic(pos: Position, message: String) -> ScriptDiagnostic {
+    ScriptDiagnostic {
+        line: pos.line().unwrap_or(0),
+        column: pos.position().unwrap_or(0),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rhai_executor::RhaiExecutor;
+
+    fn check(source: &str) -> Vec<String> {
+        let mut executor = RhaiExecutor::new();
+        executor.engine_mut().register_fn("emit", |_: &str| ());
+        executor
+            .validate(source)
+            .err()
+            .unwrap_or_default()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_script() {
+        let source = r#"
+            fn shout(text) { text.to_upper() }
+            fn on_use(actor) {
+                emit(shout("click"));
+                print(len([1, 2]));
+            }
+        "#;
+        assert_eq!(check(source), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_syntax_error_has_position() {
+        let errors = check("let x = 1;\nlet y = ;");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("line 2, column 9: "), "{}", errors[0]);
+    }
+
+    #[test]
+    fn test_handler_arity() {
+        let errors = check("fn on_use() { 1 }\n\nfn on_lok(actor) { 2 }");
+        assert_eq!(
+            errors[0],
+            "line 1, column 1: handler `on_use` must take 1 parameter (actor), not 0"
+        );
+        assert!(errors[1].starts_with("line 3, column 1: unknown event handler `on_lok`"));
+    }
+
+    #[test]
+    fn test_unknown_functions() {
+        let errors = check("fn on_use(actor) {\n    emti(\"hi\");\n    emit(1, 2)\n}");
+        assert_eq!(
+            errors,
+            vec![
+                "line 2, column 5: unknown function `emti`",
+                "line 3, column 5: `emit` takes 1 argument(s), not 2",
+            ]
+        );
+    }
+}
@@ -0,0 +1,3 @@
+-- expect: error
+local function f(n) return f(n + 1) + 1 end
+return f(0)
@@ -0,0 +1,2 @@
+-- expect: error
+while true do end
@@ -0,0 +1,2 @@
+-- expect: error
+return io.open("$PROBE"):read("a")
@@ -0,0 +1,2 @@
+-- expect: error
+return load("return 40 + 2")()
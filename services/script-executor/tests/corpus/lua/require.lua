@@ -0,0 +1,2 @@
+-- expect: error
+return require("io").open("$PROBE"):read("a")
@@ -0,0 +1,3 @@
+-- expect: error
+local s = "ab"
+while true do s = s .. s end
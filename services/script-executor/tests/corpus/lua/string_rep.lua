@@ -0,0 +1,2 @@
+-- expect: error
+return string.rep("x", 1e12)
@@ -0,0 +1,2 @@
+-- expect: ok 0
+return #string.rep("", 1e12)
@@ -1,15 +1,42 @@
 //! Text Gateway - TCP/Telnet clients
+//!
+//! Accepts line-based TCP (telnet) connections. Clients log in with a
+//! session token, and after that every line they type is run by
+//! world-state as a console command, its reply written back as text (see
+//! [`session`]).
+//!
+//! Configuration:
+//! - `TEXT_ADDR`: TCP address for clients (default `0.0.0.0:4201`)
+//! - `WORLD_STATE_URL`: world-state's HTTP API (default `http://localhost:8080`)
+//! - `SESSION_SECRET`: secret session tokens are signed with; the
+//!   gateway refuses to start without it
+//! - `ALLOW_DEV_SESSIONS`: set to `1` to accept tokens signed with the
+//!   development secret when `SESSION_SECRET` is unset, for local runs
 
 use axum::{routing::get, Router};
+use shared::auth::DEV_SESSION_SECRET;
+use shared::gateway::CommandReply;
 use std::net::SocketAddr;
-use tracing::info;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+mod session;
+mod world;
+
+use session::{decode_line, Session, Step, MAX_LINE_LEN, WELCOME};
+use world::WorldClient;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "text-gateway=debug,info".into()),
+                .unwrap_or_else(|_| "text_gateway=debug,info".into()),
         )
         .init();
 
@@ -17,6 +44,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     dotenvy::dotenv().ok();
 
+    let text_addr = std::env::var("TEXT_ADDR")
+        .ok()
+        .and_then(|addr| addr.parse().ok())
+        .unwrap_or(SocketAddr::from(([0, 0, 0, 0], 4201)));
+    let world_url =
+        std::env::var("WORLD_STATE_URL").unwrap_or_else(|_| "http://localhost:8080".to_string());
+    let allow_dev_sessions = std::env::var("ALLOW_DEV_SESSIONS").is_ok_and(|flag| flag == "1");
+    let secret: Arc<[u8]> = match std::env::var("SESSION_SECRET") {
+        Ok(secret) if !secret.is_empty() => secret.into_bytes().into(),
+        _ if allow_dev_sessions => {
+            warn!("SESSION_SECRET is not set; accepting development tokens");
+            DEV_SESSION_SECRET.into()
+        }
+        _ => {
+            return Err(
+                "SESSION_SECRET is not set; set ALLOW_DEV_SESSIONS=1 for development".into(),
+            )
+        }
+    };
+
     let health_app = Router::new()
         .route("/health", get(health_check))
         .route("/ready", get(readiness_check));
@@ -24,9 +71,107 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr = SocketAddr::from(([0, 0, 0, 0], 8083));
     info!("Health check server listening on {}", addr);
 
-    axum::serve(tokio::net::TcpListener::bind(addr).await?, health_app).await?;
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(
+            tokio::net::TcpListener::bind(addr).await.unwrap(),
+            health_app,
+        )
+        .await
+        {
+            warn!("Health check server error: {}", e);
+        }
+    });
+
+    let world = WorldClient::new(&world_url);
+    let listener = tokio::net::TcpListener::bind(text_addr).await?;
+    info!(
+        "Relaying text clients on {} to world-state at {}",
+        text_addr, world_url
+    );
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let world = world.clone();
+        let secret = secret.clone();
+        tokio::spawn(async move {
+            info!("Text client {} connected", peer);
+            if let Err(e) = serve(stream, &world, &secret).await {
+                warn!("Text client {}: {}", peer, e);
+            }
+            info!("Text client {} disconnected", peer);
+        });
+    }
+}
+
+/// Talk to one client until it quits or hangs up
+async fn serve(stream: TcpStream, world: &WorldClient, secret: &[u8]) -> std::io::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut read = BufReader::new(read);
+    let mut session = Session::default();
+    let mut bytes = Vec::new();
+    write_line(&mut write, WELCOME).await?;
+
+    loop {
+        if read_line(&mut read, &mut bytes).await? == 0 {
+            return Ok(());
+        }
+        if !bytes.ends_with(b"\n") && bytes.len() > MAX_LINE_LEN {
+            // Skip the rest of it
+            loop {
+                if read_line(&mut read, &mut bytes).await? == 0 {
+                    return Ok(());
+                }
+                if bytes.ends_with(b"\n") {
+                    break;
+                }
+            }
+            write_line(&mut write, "Line too long.").await?;
+            continue;
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        match session.handle(&decode_line(&bytes), secret, now) {
+            Step::Ignore => {}
+            Step::Reply(text) => write_line(&mut write, &text).await?,
+            Step::Forward(command) => match world.command(command).await {
+                Ok(CommandReply::Output(text) | CommandReply::Failed(text)) => {
+                    write_line(&mut write, &text).await?
+                }
+                Ok(CommandReply::Done) => {}
+                Err(e) => {
+                    warn!("Could not reach world-state: {}", e);
+                    write_line(&mut write, "The world is not answering; try again.").await?
+                }
+            },
+            Step::Quit => {
+                write_line(&mut write, "Goodbye.").await?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Read up to the next newline into `bytes`, but no more than one byte
+/// past [`MAX_LINE_LEN`], which tells a long line from one that fits
+async fn read_line(
+    read: &mut (impl AsyncBufRead + Unpin),
+    bytes: &mut Vec<u8>,
+) -> std::io::Result<usize> {
+    bytes.clear();
+    read.take(MAX_LINE_LEN as u64 + 1)
+        .read_until(b'\n', bytes)
+        .await
+}
 
-    Ok(())
+/// Write `text` with telnet's CRLF line endings
+async fn write_line(write: &mut (impl AsyncWrite + Unpin), text: &str) -> std::io::Result<()> {
+    for line in text.lines() {
+        write.write_all(line.as_bytes()).await?;
+        write.write_all(b"\r\n").await?;
+    }
+    write.flush().await
 }
 
 async fn health_check() -> &'static str {
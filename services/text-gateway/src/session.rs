@@ -0,0 +1,178 @@
+//! Text Sessions
+//!
+//! What the gateway does with each line a text client sends. A client
+//! starts out anonymous and may only log in, with the same session token
+//! graphical clients use:
+//!
+//! ```text
+//! connect 42.1767225600.3f0c...e9
+//! ```
+//!
+//! Once logged in, every line goes to world-state as a `Console` command
+//! on the player's behalf, so builder commands like `@debug` work here as
+//! they do from the graphical console. `QUIT` closes the connection
+//! either way.
+//!
+//! # Learning Note
+//! Telnet clients mix option negotiation into the byte stream: `IAC`
+//! (255) followed by a command byte, an option byte for `WILL`/`WONT`/
+//! `DO`/`DONT`, or a whole `SB ... IAC SE` block. The gateway never
+//! negotiates anything, so it just drops those sequences from each line.
+
+use shared::auth;
+use shared::components::ObjectId;
+use shared::gateway::WorldCommand;
+
+/// Longest line a client may send, in bytes
+pub const MAX_LINE_LEN: usize = 4096;
+
+/// Shown until the client logs in
+pub const WELCOME: &str = "Welcome. Log in with: connect <session token>";
+
+const IAC: u8 = 255;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const WILL: u8 = 251;
+const DONT: u8 = 254;
+
+/// What to do about one line
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    /// Nothing to do
+    Ignore,
+    /// Answer the client directly
+    Reply(String),
+    /// Send the command to world-state and show its reply
+    Forward(WorldCommand),
+    /// Say goodbye and close the connection
+    Quit,
+}
+
+/// One connected text client
+#[derive(Debug, Default)]
+pub struct Session {
+    /// The player it logged in as; `None` until a valid token arrives
+    pub player: Option<ObjectId>,
+}
+
+impl Session {
+    /// Decide what to do about `line`, checking tokens at `now` (Unix
+    /// seconds) against `secret`
+    pub fn handle(&mut self, line: &str, secret: &[u8], now: u64) -> Step {
+        let line = line.trim();
+        if line == "QUIT" {
+            return Step::Quit;
+        }
+        match self.player {
+            Some(_) if line.is_empty() => Step::Ignore,
+            Some(actor) => Step::Forward(WorldCommand::Console {
+                actor,
+                line: line.to_string(),
+            }),
+            None => match line.strip_prefix("connect ") {
+                Some(token) => match auth::verify(secret, token.trim(), now) {
+                    Ok(player) => {
+                        self.player = Some(player);
+                        Step::Reply(format!("Logged in as {}.", player))
+                    }
+                    Err(e) => Step::Reply(format!("Login failed: {}.", e)),
+                },
+                None if line.is_empty() => Step::Ignore,
+                None => Step::Reply(WELCOME.to_string()),
+            },
+        }
+    }
+}
+
+/// Turn the raw bytes of a line into text, without telnet negotiation,
+/// the line ending or other control characters
+pub fn decode_line(bytes: &[u8]) -> String {
+    let mut text = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != IAC {
+            text.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        match bytes.get(i + 1) {
+            // An escaped 255 is data
+            Some(&IAC) => {
+                text.push(IAC);
+                i += 2;
+            }
+            Some(&SB) => {
+                let end = bytes[i..]
+                    .windows(2)
+                    .position(|pair| pair == [IAC, SE])
+                    .map_or(bytes.len(), |at| i + at + 2);
+                i = end;
+            }
+            Some(&(WILL..=DONT)) => i += 3,
+            _ => i += 2,
+        }
+    }
+    String::from_utf8_lossy(&text)
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"test secret";
+
+    #[test]
+    fn test_login_then_forward() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.handle("look", SECRET, 100),
+            Step::Reply(WELCOME.to_string())
+        );
+        assert!(matches!(
+            session.handle("connect 7.200.00", SECRET, 100),
+            Step::Reply(reply) if reply.starts_with("Login failed")
+        ));
+        let expired = auth::issue(SECRET, ObjectId(7), 50);
+        assert!(matches!(
+            session.handle(&format!("connect {}", expired), SECRET, 100),
+            Step::Reply(reply) if reply.starts_with("Login failed")
+        ));
+        assert_eq!(session.player, None);
+
+        let token = auth::issue(SECRET, ObjectId(7), 200);
+        assert_eq!(
+            session.handle(&format!("connect {}", token), SECRET, 100),
+            Step::Reply("Logged in as #7.".to_string())
+        );
+        assert_eq!(
+            session.handle("@debug/step ", SECRET, 100),
+            Step::Forward(WorldCommand::Console {
+                actor: ObjectId(7),
+                line: "@debug/step".to_string(),
+            })
+        );
+        assert_eq!(session.handle("", SECRET, 100), Step::Ignore);
+        assert_eq!(session.handle("QUIT", SECRET, 100), Step::Quit);
+    }
+
+    #[test]
+    fn test_decode_line_drops_telnet_negotiation() {
+        assert_eq!(decode_line(b"look\r\n"), "look");
+        // IAC DO ECHO, IAC SB NAWS ... IAC SE, IAC NOP
+        let line = [
+            &[IAC, 253, 1][..],
+            b"lo",
+            &[IAC, SB, 31, 0, 80, 0, 24, IAC, SE],
+            b"ok",
+            &[IAC, 241],
+            b"\r\n",
+        ]
+        .concat();
+        assert_eq!(decode_line(&line), "look");
+        assert_eq!(decode_line(&[b'a', IAC, IAC, b'b']), "a\u{fffd}b");
+        assert_eq!(decode_line(&[b'a', IAC, SB, 1, 2]), "a");
+    }
+}
@@ -0,0 +1,50 @@
+//! World-State Link
+//!
+//! Text clients type one command at a time and wait for its answer, so
+//! unlike graphics-gateway there is no batching: each line is posted to
+//! world-state's `/commands` on its own (see `shared::gateway`).
+
+use shared::gateway::{CommandReplies, CommandReply, WorldCommand};
+use std::time::Duration;
+
+/// How long to wait for world-state before giving up on a command
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// HTTP client for world-state's gateway API
+#[derive(Clone)]
+pub struct WorldClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl WorldClient {
+    /// Talk to world-state at `base_url`
+    pub fn new(base_url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Run one command, returning its reply
+    pub async fn command(&self, command: WorldCommand) -> Result<CommandReply, reqwest::Error> {
+        let replies: CommandReplies = self
+            .client
+            .post(format!("{}/commands", self.base_url))
+            .json(&[command])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(replies
+            .replies
+            .into_iter()
+            .next()
+            .unwrap_or(CommandReply::Done))
+    }
+}
@@ -0,0 +1,11 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Use the protoc bundled with the build rather than needing one installed
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    tonic_build::configure()
+        .build_server(false)
+        .compile_protos(
+            &["../../shared/proto/physics.proto"],
+            &["../../shared/proto"],
+        )?;
+    Ok(())
+}
@@ -0,0 +1,383 @@
+//! Gateway API
+//!
+//! HTTP endpoints the gateways use to act on the world and follow its
+//! changes (see `shared::gateway` for the protocol). The handlers don't
+//! touch the world themselves: the ECS world lives in the main loop, so
+//! each request is passed there as an [`ApiRequest`] and answered over a
+//! oneshot channel once [`handle`] has run it.
+//!
+//! A batch of commands runs in order. When one has to wait on
+//! script-executor (see `commands::Reply`), the rest of the batch is
+//! parked in [`Waiting`] and the main loop goes on with other requests;
+//! the batch carries on once the answer is back.
+//!
+//! World-state trusts the gateways: they authenticate players and only
+//! send commands for the player a connection logged in as.
+//!
+//! # Learning Note
+//! Bevy's `World` is not `Sync`, so it can't be shared with axum's
+//! handlers behind an `Arc`. Sending requests to the one task that owns
+//! the world keeps every change on that task, in order, without locks.
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use bevy::prelude::*;
+use serde::Deserialize;
+use shared::components::{ObjectId, Position, Velocity};
+use shared::gateway::{
+    ChangeBatch, CommandReplies, CommandReply, Subscription, TickStats, WorldCommand,
+};
+use shared::protocol::MoveInput;
+use shared::systems::apply_input;
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::changes;
+use crate::commands::{self, CommandError, Pending, Reply, Resume};
+use crate::objects::ObjectRegistry;
+use crate::stats;
+
+/// Requests waiting for the main loop
+pub const REQUEST_QUEUE: usize = 256;
+
+/// A gateway request for the main loop to run
+pub enum ApiRequest {
+    /// Apply commands in order
+    Commands(Vec<WorldCommand>, oneshot::Sender<CommandReplies>),
+    /// Changes after a sequence number, for a subscription or everything
+    Changes(u64, Option<Subscription>, oneshot::Sender<ChangeBatch>),
+    /// Tick statistics
+    Stats(oneshot::Sender<TickStats>),
+}
+
+/// Routes for the gateways, forwarding to the main loop through `requests`
+pub fn router(requests: mpsc::Sender<ApiRequest>) -> Router {
+    Router::new()
+        .route("/commands", post(post_commands))
+        .route("/changes", get(get_changes))
+        .route("/stats", get(get_stats))
+        .with_state(requests)
+}
+
+/// Command batches waiting on script-executor
+pub struct Waiting {
+    batches: HashMap<u64, Batch>,
+    next_id: u64,
+    done: mpsc::UnboundedSender<Answered>,
+    answered: mpsc::UnboundedReceiver<Answered>,
+}
+
+/// The commands of one request still to run, and the replies so far
+struct Batch {
+    commands: VecDeque<WorldCommand>,
+    replies: Vec<CommandReply>,
+    reply: oneshot::Sender<CommandReplies>,
+}
+
+/// A parked batch's command, ready to finish
+pub struct Answered(u64, Resume);
+
+impl Default for Waiting {
+    fn default() -> Self {
+        let (done, answered) = mpsc::unbounded_channel();
+        Self {
+            batches: HashMap::new(),
+            next_id: 0,
+            done,
+            answered,
+        }
+    }
+}
+
+impl Waiting {
+    /// The next parked command whose answer is back
+    ///
+    /// Never `None`, as the sending side lives here too.
+    pub async fn answered(&mut self) -> Option<Answered> {
+        self.answered.recv().await
+    }
+
+    /// Wait for `pending` off the main loop, then carry on with `batch`
+    fn park(&mut self, pending: Pending, batch: Batch) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.batches.insert(id, batch);
+        let done = self.done.clone();
+        tokio::spawn(async move {
+            let resume = pending.wait().await;
+            // Only fails while the main loop is shutting down
+            let _ = done.send(Answered(id, resume));
+        });
+    }
+}
+
+/// Run one request against the world and send the answer back
+pub fn handle(world: &mut World, waiting: &mut Waiting, request: ApiRequest) {
+    // A gateway that gave up waiting has dropped the receiver; fine
+    match request {
+        ApiRequest::Commands(commands, reply) => {
+            let batch = Batch {
+                commands: commands.into(),
+                replies: Vec::new(),
+                reply,
+            };
+            advance(world, waiting, batch);
+        }
+        ApiRequest::Changes(after, subscription, reply) => {
+            let _ = reply.send(changes::since(world, after, subscription.as_ref()));
+        }
+        ApiRequest::Stats(reply) => {
+            let _ = reply.send(stats::report(world));
+        }
+    }
+}
+
+/// Finish a parked command and carry on with the rest of its batch
+pub fn resume(world: &mut World, waiting: &mut Waiting, Answered(id, resume): Answered) {
+    let Some(mut batch) = waiting.batches.remove(&id) else {
+        return;
+    };
+    match record(&mut batch, resume.apply(world)) {
+        Some(pending) => waiting.park(pending, batch),
+        None => advance(world, waiting, batch),
+    }
+}
+
+/// Apply `batch`'s commands in order, until one has to wait
+fn advance(world: &mut World, waiting: &mut Waiting, mut batch: Batch) {
+    while let Some(command) = batch.commands.pop_front() {
+        let reply = match command {
+            WorldCommand::Console { actor, line } => commands::start(world, actor, &line),
+            WorldCommand::Move { actor, input } => {
+                batch.replies.push(move_object(world, actor, input));
+                continue;
+            }
+        };
+        if let Some(pending) = record(&mut batch, reply) {
+            waiting.park(pending, batch);
+            return;
+        }
+    }
+    let _ = batch.reply.send(CommandReplies {
+        seq: changes::publish(world),
+        replies: batch.replies,
+    });
+}
+
+/// Add a console command's reply to `batch`, or hand back what it is
+/// waiting on
+fn record(batch: &mut Batch, reply: Result<Reply, CommandError>) -> Option<Pending> {
+    match reply {
+        Ok(Reply::Text(output)) => batch.replies.push(CommandReply::Output(output)),
+        Ok(Reply::Pending(pending)) => return Some(pending),
+        Err(e) => batch.replies.push(CommandReply::Failed(e.to_string())),
+    }
+    None
+}
+
+/// One tick of movement, with the same code clients predict with
+///
+/// Objects get a position the first time they move.
+fn move_object(world: &mut World, actor: ObjectId, input: MoveInput) -> CommandReply {
+    let Some(entity) = world.resource::<ObjectRegistry>().entity(actor) else {
+        return CommandReply::Failed(format!("No such object {}.", actor));
+    };
+    let mut entity = world.entity_mut(entity);
+    let mut position = entity.get::<Position>().copied().unwrap_or_default();
+    let mut velocity = entity.get::<Velocity>().copied().unwrap_or_default();
+    apply_input(&mut position, &mut velocity, input);
+    // Standing still doesn't count as a change
+    if entity.get::<Position>() != Some(&position) {
+        entity.insert(position);
+    }
+    if entity.get::<Velocity>() != Some(&velocity) {
+        entity.insert(velocity);
+    }
+    CommandReply::Done
+}
+
+async fn post_commands(
+    State(requests): State<mpsc::Sender<ApiRequest>>,
+    Json(commands): Json<Vec<WorldCommand>>,
+) -> Result<Json<CommandReplies>, StatusCode> {
+    let (reply, answer) = oneshot::channel();
+    forward(&requests, ApiRequest::Commands(commands, reply), answer).await
+}
+
+#[derive(Deserialize)]
+struct ChangesQuery {
+    #[serde(default)]
+    after: u64,
+    rooms: Option<String>,
+    objects: Option<String>,
+}
+
+async fn get_changes(
+    State(requests): State<mpsc::Sender<ApiRequest>>,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<ChangeBatch>, StatusCode> {
+    // Without either list the gateway follows the whole world
+    let subscription = match (&query.rooms, &query.objects) {
+        (None, None) => None,
+        (rooms, objects) => Some(
+            Subscription::from_query(
+                rooms.as_deref().unwrap_or(""),
+                objects.as_deref().unwrap_or(""),
+            )
+            .ok_or(StatusCode::BAD_REQUEST)?,
+        ),
+    };
+    let (reply, answer) = oneshot::channel();
+    let request = ApiRequest::Changes(query.after, subscription, reply);
+    forward(&requests, request, answer).await
+}
+
+async fn get_stats(
+    State(requests): State<mpsc::Sender<ApiRequest>>,
+) -> Result<Json<TickStats>, StatusCode> {
+    let (reply, answer) = oneshot::channel();
+    forward(&requests, ApiRequest::Stats(reply), answer).await
+}
+
+async fn forward<T>(
+    requests: &mpsc::Sender<ApiRequest>,
+    request: ApiRequest,
+    answer: oneshot::Receiver<T>,
+) -> Result<Json<T>, StatusCode> {
+    // Both fail only while the main loop is shutting down
+    requests
+        .send(request)
+        .await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+    answer
+        .await
+        .map(Json)
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{bootstrap, component, GOD};
+    use crate::scripts::{Answer, ScriptValidator, ScriptValidatorHook};
+    use shared::gateway::ObjectChange;
+    use std::sync::Mutex;
+
+    fn apply(world: &mut World, commands: Vec<WorldCommand>) -> CommandReplies {
+        let (reply, mut answer) = oneshot::channel();
+        let request = ApiRequest::Commands(commands, reply);
+        handle(world, &mut Waiting::default(), request);
+        answer.try_recv().unwrap()
+    }
+
+    #[test]
+    fn test_commands_from_gateways() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        let start = changes::publish(&mut world);
+
+        let right = MoveInput { x: 1, y: 0 };
+        let replies = apply(
+            &mut world,
+            vec![
+                WorldCommand::Move {
+                    actor: GOD,
+                    input: right,
+                },
+                WorldCommand::Move {
+                    actor: GOD,
+                    input: right,
+                },
+                WorldCommand::Console {
+                    actor: GOD,
+                    line: "@create Ball".to_string(),
+                },
+                WorldCommand::Console {
+                    actor: GOD,
+                    line: "@frobnicate".to_string(),
+                },
+                WorldCommand::Move {
+                    actor: ObjectId(99),
+                    input: right,
+                },
+            ],
+        );
+        assert_eq!(replies.replies[0], CommandReply::Done);
+        assert!(matches!(&replies.replies[2], CommandReply::Output(_)));
+        assert!(matches!(&replies.replies[3], CommandReply::Failed(e) if e.starts_with("Huh?")));
+        assert!(matches!(&replies.replies[4], CommandReply::Failed(_)));
+
+        // Two ticks of the shared movement code
+        let mut expected = (Position::default(), Velocity::default());
+        apply_input(&mut expected.0, &mut expected.1, right);
+        apply_input(&mut expected.0, &mut expected.1, right);
+        assert_eq!(component::<Position>(&world, GOD), Some(&expected.0));
+
+        // Everything the commands did is in the stream up to `seq`
+        let batch = changes::since(&mut world, start, None);
+        assert_eq!(batch.seq, replies.seq);
+        let names: Vec<_> = batch
+            .changes
+            .iter()
+            .map(|change| match change {
+                ObjectChange::Upsert(state) => state.name.as_str(),
+                ObjectChange::Removed(_) => "",
+            })
+            .collect();
+        assert_eq!(names, ["Wizard", "Ball"]);
+    }
+
+    /// Accepts every script, once `release` fires
+    struct SlowValidator(Mutex<Option<oneshot::Receiver<()>>>);
+
+    impl ScriptValidator for SlowValidator {
+        fn validate(&self, _source: &str) -> Answer<()> {
+            let release = self.0.lock().unwrap().take().unwrap();
+            Box::pin(async move { release.await.map_err(|e| e.to_string()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_wait_for_the_executor() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        let (release, released) = oneshot::channel();
+        let validator = SlowValidator(Mutex::new(Some(released)));
+        world.insert_resource(ScriptValidatorHook(Box::new(validator)));
+
+        let mut waiting = Waiting::default();
+        let (reply, mut answer) = oneshot::channel();
+        let console = |line: &str| WorldCommand::Console {
+            actor: GOD,
+            line: line.to_string(),
+        };
+        let commands = vec![
+            console("@script me = fn on_look(actor) { 1 }"),
+            console("@create Ball"),
+        ];
+        handle(
+            &mut world,
+            &mut waiting,
+            ApiRequest::Commands(commands, reply),
+        );
+
+        // Other requests are answered while the script is validated
+        let (stats, mut report) = oneshot::channel();
+        handle(&mut world, &mut waiting, ApiRequest::Stats(stats));
+        assert!(report.try_recv().is_ok());
+        assert!(answer.try_recv().is_err());
+        assert!(commands::match_object(&world, GOD, "Ball").is_err());
+
+        release.send(()).unwrap();
+        let answered = waiting.answered().await.unwrap();
+        resume(&mut world, &mut waiting, answered);
+        let replies = answer.try_recv().unwrap().replies;
+        assert_eq!(
+            replies[0],
+            CommandReply::Output("Script saved as revision 1.".to_string())
+        );
+        assert!(matches!(&replies[1], CommandReply::Output(o) if o.starts_with("Created: Ball")));
+    }
+}
@@ -0,0 +1,335 @@
+//! Change Stream
+//!
+//! The gateways mirror the parts of the world their players can see. They
+//! follow it through a numbered log of [`ObjectChange`]s: [`publish`]
+//! appends whatever changed since the last call, and [`since`] answers a
+//! gateway's poll with everything after the sequence number it last saw.
+//!
+//! The log keeps the last [`LOG_LEN`] changes. A gateway asking from 0,
+//! one further behind than that, or one that saw sequence numbers from
+//! before a restart gets a full snapshot instead.
+//!
+//! A gateway only follows the rooms its players are interested in (its
+//! [`Subscription`]). Each entry remembers where the object was before,
+//! so an object leaving a followed room still reaches the gateways that
+//! followed it there.
+//!
+//! # Learning Note
+//! Like the persistence [`ChangeTracker`](crate::persistence::ChangeTracker)
+//! this keeps a `SystemState` to remember what it has already seen. The
+//! two track changes independently, so a flush doesn't hide changes from
+//! the gateways or the other way round.
+
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+use shared::components::{Attributes, Location, ObjectId, ObjectKind, ObjectName, Position};
+use shared::gateway::{ChangeBatch, ObjectChange, ObjectState, Subscription, MODEL_ATTR};
+use std::collections::{HashMap, VecDeque};
+
+/// Changes kept for gateways that are catching up
+pub const LOG_LEN: usize = 4096;
+
+/// Objects whose published state may be out of date
+type Published = Or<(
+    Added<ObjectId>,
+    Changed<ObjectName>,
+    Changed<Location>,
+    Changed<Position>,
+    Changed<Attributes>,
+)>;
+
+/// Numbered log of recent changes
+#[derive(Resource)]
+pub struct ChangeFeed {
+    changed: SystemState<(
+        Query<'static, 'static, Entity, Published>,
+        RemovedComponents<'static, 'static, ObjectId>,
+    )>,
+    /// Object id and last published location by entity, to name objects
+    /// once they are despawned and to tell where they came from
+    published: HashMap<Entity, (ObjectId, Option<ObjectId>)>,
+    seq: u64,
+    log: VecDeque<Entry>,
+}
+
+struct Entry {
+    seq: u64,
+    change: ObjectChange,
+    /// Where the object was before this change
+    from: Option<ObjectId>,
+}
+
+impl Entry {
+    fn visible_to(&self, subscription: &Subscription) -> bool {
+        let (id, location) = match &self.change {
+            ObjectChange::Upsert(state) => (state.id, state.location),
+            ObjectChange::Removed(id) => (*id, None),
+        };
+        subscription.covers(id, location) || subscription.covers(id, self.from)
+    }
+}
+
+impl FromWorld for ChangeFeed {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            changed: SystemState::new(world),
+            published: HashMap::new(),
+            seq: 0,
+            log: VecDeque::with_capacity(LOG_LEN),
+        }
+    }
+}
+
+impl ChangeFeed {
+    fn push(&mut self, change: ObjectChange, from: Option<ObjectId>) {
+        if self.log.len() == LOG_LEN {
+            self.log.pop_front();
+        }
+        self.seq += 1;
+        self.log.push_back(Entry {
+            seq: self.seq,
+            change,
+            from,
+        });
+    }
+}
+
+/// Log everything that changed since the last call, returning the
+/// sequence number of the newest change
+///
+/// The first call publishes every object in the world.
+pub fn publish(world: &mut World) -> u64 {
+    world.init_resource::<ChangeFeed>();
+    world.resource_scope(|world, mut feed: Mut<ChangeFeed>| {
+        let (changed, mut removed) = feed.changed.get_mut(world);
+        let mut changed: Vec<Entity> = changed.iter().collect();
+        let removed: Vec<Entity> = removed.read().collect();
+        changed.sort_by_key(|entity| world.get::<ObjectId>(*entity).copied());
+
+        for entity in removed {
+            if let Some((id, from)) = feed.published.remove(&entity) {
+                feed.push(ObjectChange::Removed(id), from);
+            }
+        }
+        for entity in changed {
+            if let Some(state) = state(world, entity) {
+                let from = feed
+                    .published
+                    .insert(entity, (state.id, state.location))
+                    .and_then(|(_, from)| from);
+                feed.push(ObjectChange::Upsert(state), from);
+            }
+        }
+        feed.seq
+    })
+}
+
+/// Changes numbered after `after` under `subscription` (everything if
+/// `None`), or a snapshot if those are no longer in the log
+pub fn since(world: &mut World, after: u64, subscription: Option<&Subscription>) -> ChangeBatch {
+    let seq = publish(world);
+    let feed = world.resource::<ChangeFeed>();
+    let oldest = feed.log.front().map_or(seq + 1, |entry| entry.seq);
+    let followed = |entry: &&Entry| subscription.is_none_or(|s| entry.visible_to(s));
+    if after > 0 && after <= seq && after + 1 >= oldest {
+        return ChangeBatch {
+            seq,
+            reset: false,
+            changes: feed
+                .log
+                .iter()
+                .filter(|entry| entry.seq > after)
+                .filter(followed)
+                .map(|entry| entry.change.clone())
+                .collect(),
+        };
+    }
+
+    let mut states: Vec<ObjectState> = world
+        .query_filtered::<Entity, With<ObjectId>>()
+        .iter(world)
+        .filter_map(|entity| state(world, entity))
+        .filter(|state| subscription.is_none_or(|s| s.covers(state.id, state.location)))
+        .collect();
+    states.sort_by_key(|state| state.id);
+    ChangeBatch {
+        seq,
+        reset: true,
+        changes: states.into_iter().map(ObjectChange::Upsert).collect(),
+    }
+}
+
+/// What the gateways see of one object
+fn state(world: &World, entity: Entity) -> Option<ObjectState> {
+    let entity = world.get_entity(entity).ok()?;
+    Some(ObjectState {
+        id: *entity.get::<ObjectId>()?,
+        kind: *entity.get::<ObjectKind>()?,
+        name: entity.get::<ObjectName>()?.0.clone(),
+        location: entity.get::<Location>().map(|location| location.0),
+        position: entity.get::<Position>().map(|position| position.0),
+        model: entity
+            .get::<Attributes>()
+            .and_then(|attributes| attributes.get(MODEL_ATTR))
+            .map(|model| model.value.clone()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutations::{self, Mutation};
+    use crate::objects::{bootstrap, component, ObjectRegistry, GOD};
+    use shared::components::ObjectKind;
+
+    #[test]
+    fn test_changes_are_numbered_and_resumable() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+
+        // A new gateway starts from nothing and gets the whole world
+        let first = since(&mut world, 0, None);
+        assert_eq!(first.seq, 2);
+        assert!(first.reset);
+        assert_eq!(first.changes.len(), 2);
+        assert!(since(&mut world, first.seq, None).changes.is_empty());
+
+        let thing = mutations::apply(
+            &mut world,
+            GOD,
+            Mutation::Create {
+                kind: ObjectKind::Thing,
+                name: "Ball".to_string(),
+            },
+        )
+        .unwrap();
+        let batch = since(&mut world, first.seq, None);
+        let [ObjectChange::Upsert(state)] = batch.changes.as_slice() else {
+            panic!("{:?}", batch.changes);
+        };
+        assert_eq!(state.name, "Ball");
+        assert_eq!(state.location, Some(GOD));
+        assert_eq!(state.position, None);
+        assert_eq!(state.model, None);
+
+        // Giving it a model is a change like any other
+        mutations::apply(
+            &mut world,
+            GOD,
+            Mutation::SetAttr {
+                target: thing,
+                name: "model".to_string(),
+                value: "models/ball.glb".to_string(),
+            },
+        )
+        .unwrap();
+        let batch = since(&mut world, batch.seq, None);
+        let [ObjectChange::Upsert(state)] = batch.changes.as_slice() else {
+            panic!("{:?}", batch.changes);
+        };
+        assert_eq!(state.model.as_deref(), Some("models/ball.glb"));
+
+        mutations::apply(&mut world, GOD, Mutation::Destroy { target: thing }).unwrap();
+        let removed = since(&mut world, batch.seq, None);
+        assert_eq!(removed.changes, vec![ObjectChange::Removed(thing)]);
+        assert_eq!(component::<ObjectName>(&world, thing), None);
+
+        // Asking again from an older point repeats the same changes
+        assert_eq!(since(&mut world, first.seq, None).changes.len(), 3);
+    }
+
+    #[test]
+    fn test_stale_gateways_get_a_snapshot() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        let seq = publish(&mut world);
+
+        // Sequence numbers from before a restart
+        let batch = since(&mut world, seq + 10, None);
+        assert!(batch.reset);
+        assert_eq!(batch.seq, seq);
+        assert_eq!(batch.changes.len(), 2);
+
+        // Far enough behind that the log has moved on
+        let entity = world.resource::<ObjectRegistry>().entity(GOD).unwrap();
+        for step in 0..=LOG_LEN {
+            world
+                .entity_mut(entity)
+                .insert(Position(Vec3::X * step as f32));
+            publish(&mut world);
+        }
+        let batch = since(&mut world, seq, None);
+        assert!(batch.reset);
+        assert_eq!(batch.changes.len(), 2);
+        assert_eq!(since(&mut world, batch.seq - 1, None).changes.len(), 1);
+    }
+
+    #[test]
+    fn test_subscriptions_follow_rooms() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        let dig = |world: &mut World, name: &str| {
+            let room = Mutation::Create {
+                kind: ObjectKind::Room,
+                name: name.to_string(),
+            };
+            mutations::apply(world, GOD, room).unwrap()
+        };
+        let hall = dig(&mut world, "Hall");
+        let yard = dig(&mut world, "Yard");
+        let ball = mutations::apply(
+            &mut world,
+            GOD,
+            Mutation::Create {
+                kind: ObjectKind::Thing,
+                name: "Ball".to_string(),
+            },
+        )
+        .unwrap();
+        let place = |world: &mut World, id: ObjectId, room: ObjectId| {
+            let entity = world.resource::<ObjectRegistry>().entity(id).unwrap();
+            world.entity_mut(entity).insert(Location(room));
+        };
+        place(&mut world, ball, hall);
+
+        // Following the hall: the hall and what is in it
+        let hall_only = Subscription {
+            rooms: [hall].into(),
+            objects: Default::default(),
+        };
+        let snapshot = since(&mut world, 0, Some(&hall_only));
+        assert!(snapshot.reset);
+        let ids: Vec<ObjectId> = snapshot
+            .changes
+            .iter()
+            .map(|change| match change {
+                ObjectChange::Upsert(state) => state.id,
+                ObjectChange::Removed(id) => *id,
+            })
+            .collect();
+        assert_eq!(ids, [hall, ball]);
+
+        // The ball leaving for the yard is still news to the hall...
+        place(&mut world, ball, yard);
+        let left = since(&mut world, snapshot.seq, Some(&hall_only));
+        assert!(matches!(&left.changes[..],
+            [ObjectChange::Upsert(state)] if state.location == Some(yard)));
+        // ...but moving it about the yard isn't
+        let entity = world.resource::<ObjectRegistry>().entity(ball).unwrap();
+        world.entity_mut(entity).insert(Position(Vec3::X));
+        assert!(since(&mut world, left.seq, Some(&hall_only))
+            .changes
+            .is_empty());
+
+        // Followed objects are reported wherever they are
+        let following = Subscription {
+            rooms: [hall].into(),
+            objects: [ball].into(),
+        };
+        world.entity_mut(entity).insert(Position(Vec3::Y));
+        assert_eq!(
+            since(&mut world, left.seq, Some(&following)).changes.len(),
+            2
+        );
+    }
+}
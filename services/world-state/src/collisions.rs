@@ -0,0 +1,245 @@
+//! Collision Handlers
+//!
+//! Contacts physics-service reports are passed on to the scripts of both
+//! objects involved, so builders can make traps, pressure plates and
+//! projectiles in softcode:
+//!
+//! - two solid bodies touching call `on_collide(other, phase, point,
+//!   impulse)` with phase `begin` or `end`
+//! - a body entering or leaving a trigger volume (`BODY` = `trigger`)
+//!   calls `on_trigger(other, phase, point, impulse)` with phase `enter`
+//!   or `exit`, on the volume and on whatever crossed it
+//!
+//! `other` is the other object's dbref. `point` is where they touched, as
+//! `x,y,z` in room coordinates, and `impulse` how hard they were pushed
+//! apart; both only mean something when contact begins (`point` is empty
+//! and `impulse` 0 otherwise).
+//!
+//! Handlers run as timer jobs do: in the background, as the object's
+//! owner, and not at all while the object is halted. Objects whose script
+//! doesn't define the handler are skipped.
+//!
+//! # Learning Note
+//! A box resting on a seesaw, or a player jittering on a plate, can touch
+//! and let go every few steps. [`PairLimiter`] lets a pair of objects
+//! begin touching at most once per [`PAIR_COOLDOWN`] and only reports an
+//! end for a begin it let through, so handlers always see begin and end
+//! in pairs.
+
+use bevy::prelude::*;
+use shared::components::{Attributes, ObjectId};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::locks::SCRIPT_ATTR;
+use crate::objects::component;
+use crate::physics::proto::{Contact, ContactPhase};
+use crate::timers::{self, Dispatcher};
+
+/// Shortest time between two contacts of the same pair reaching scripts
+pub const PAIR_COOLDOWN: Duration = Duration::from_millis(500);
+
+/// Decides which contacts reach scripts
+#[derive(Resource, Debug, Default)]
+pub struct PairLimiter {
+    /// By pair, lower id first: when they last began touching, and
+    /// whether that begin was let through without an end yet
+    pairs: HashMap<(ObjectId, ObjectId), (i64, bool)>,
+}
+
+impl PairLimiter {
+    /// Whether a contact between `a` and `b` at `now` (Unix ms) should be
+    /// passed on
+    pub fn admit(&mut self, a: ObjectId, b: ObjectId, begin: bool, now: i64) -> bool {
+        let pair = (a.min(b), a.max(b));
+        let cooldown = PAIR_COOLDOWN.as_millis() as i64;
+        match (begin, self.pairs.get_mut(&pair)) {
+            (true, Some((_, true))) => false,
+            (true, Some((last, false))) if now - *last < cooldown => false,
+            (true, _) => {
+                self.pairs.insert(pair, (now, true));
+                true
+            }
+            (false, Some((_, touching))) => std::mem::replace(touching, false),
+            (false, None) => false,
+        }
+    }
+
+    /// Forget pairs that are apart and past their cooldown
+    pub fn prune(&mut self, now: i64) {
+        let cooldown = PAIR_COOLDOWN.as_millis() as i64;
+        self.pairs
+            .retain(|_, (last, touching)| *touching || now - *last < cooldown);
+    }
+}
+
+/// Call the handlers for a frame's contacts at `now` (Unix ms)
+pub fn dispatch(world: &mut World, dispatcher: &Dispatcher, contacts: Vec<Contact>, now: i64) {
+    world.init_resource::<PairLimiter>();
+    for contact in contacts {
+        let (a, b) = (ObjectId(contact.a), ObjectId(contact.b));
+        let begin = contact.phase() == ContactPhase::Begin;
+        if !world.resource_mut::<PairLimiter>().admit(a, b, begin, now) {
+            continue;
+        }
+        let (function, phase) = match (contact.sensor, begin) {
+            (false, true) => ("on_collide", "begin"),
+            (false, false) => ("on_collide", "end"),
+            (true, true) => ("on_trigger", "enter"),
+            (true, false) => ("on_trigger", "exit"),
+        };
+        let point = contact
+            .point
+            .map(|p| format!("{:.2},{:.2},{:.2}", p.x, p.y, p.z))
+            .unwrap_or_default();
+        let impulse = format!("{:.2}", contact.impulse);
+        for (object, other) in [(a, b), (b, a)] {
+            if !handles(world, object, function) {
+                continue;
+            }
+            let args = vec![
+                other.to_string(),
+                phase.to_string(),
+                point.clone(),
+                impulse.clone(),
+            ];
+            if let Some(job) = timers::background_job(world, object, function, args) {
+                dispatcher.send(object, job);
+            }
+        }
+    }
+    world.resource_mut::<PairLimiter>().prune(now);
+}
+
+/// Whether `object`'s script defines `function`
+///
+/// A textual check, so contacts don't queue a job for every object with
+/// a script; a false positive only costs a failed run.
+fn handles(world: &World, object: ObjectId, function: &str) -> bool {
+    let Some(script) = component::<Attributes>(world, object).and_then(|a| a.get(SCRIPT_ATTR))
+    else {
+        return false;
+    };
+    script.value.split("fn ").skip(1).any(|rest| {
+        rest.trim_start()
+            .strip_prefix(function)
+            .is_some_and(|after| after.trim_start().starts_with('('))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands;
+    use crate::objects::{bootstrap, GOD};
+    use crate::physics::proto;
+    use crate::timers::{spawn_dispatcher, ScriptRunner};
+    use shared::scripting::{JobOutput, ScriptJob};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_pairs_are_rate_limited() {
+        let mut limiter = PairLimiter::default();
+        let (a, b) = (ObjectId(3), ObjectId(4));
+
+        assert!(limiter.admit(a, b, true, 0));
+        // Either order is the same pair; no second begin while touching
+        assert!(!limiter.admit(b, a, true, 10));
+        assert!(limiter.admit(a, b, false, 20));
+        assert!(!limiter.admit(a, b, false, 30));
+
+        // Bouncing within the cooldown is dropped, begin and end alike
+        assert!(!limiter.admit(a, b, true, 100));
+        assert!(!limiter.admit(a, b, false, 120));
+        assert!(limiter.admit(a, b, true, 600));
+
+        // Other pairs are counted separately
+        assert!(limiter.admit(a, ObjectId(5), true, 600));
+
+        limiter.admit(a, b, false, 700);
+        limiter.admit(a, ObjectId(5), false, 700);
+        limiter.prune(1_200);
+        assert!(limiter.pairs.is_empty());
+    }
+
+    struct FakeRunner(Arc<Mutex<Vec<ScriptJob>>>);
+
+    impl ScriptRunner for FakeRunner {
+        async fn run(&self, job: &ScriptJob) -> Result<JobOutput, String> {
+            self.0.lock().unwrap().push(job.clone());
+            Ok(JobOutput::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handlers_get_contacts() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        commands::run(&mut world, GOD, "@create Plate").unwrap();
+        commands::run(&mut world, GOD, "@create Crate").unwrap();
+        commands::run(
+            &mut world,
+            GOD,
+            "&script Plate = fn on_trigger(other, phase, point, impulse) { 1 }",
+        )
+        .unwrap();
+        let plate = commands::match_object(&world, GOD, "Plate").unwrap();
+        let crate_ = commands::match_object(&world, GOD, "Crate").unwrap();
+
+        let jobs = Arc::new(Mutex::new(Vec::new()));
+        let (dispatcher, _task) = spawn_dispatcher(FakeRunner(jobs.clone()));
+        let contact = |phase: ContactPhase, sensor| Contact {
+            a: plate.0,
+            b: crate_.0,
+            phase: phase.into(),
+            sensor,
+            point: Some(proto::Vec3 {
+                x: 1.0,
+                y: 2.5,
+                z: 0.0,
+            }),
+            impulse: 0.0,
+        };
+        // Only the plate has a script, and it has no on_collide
+        dispatch(
+            &mut world,
+            &dispatcher,
+            vec![contact(ContactPhase::Begin, true)],
+            0,
+        );
+        dispatch(
+            &mut world,
+            &dispatcher,
+            vec![
+                contact(ContactPhase::End, true),
+                contact(ContactPhase::Begin, false),
+            ],
+            1_000,
+        );
+        while jobs.lock().unwrap().len() < 2 {
+            tokio::task::yield_now().await;
+        }
+        let mut calls: Vec<(Option<String>, Vec<String>)> = jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|job| (job.function.clone(), job.args.clone()))
+            .collect();
+        calls.sort();
+        let args = |phase: &str| {
+            vec![
+                crate_.to_string(),
+                phase.to_string(),
+                "1.00,2.50,0.00".to_string(),
+                "0.00".to_string(),
+            ]
+        };
+        assert_eq!(
+            calls,
+            vec![
+                (Some("on_trigger".to_string()), args("enter")),
+                (Some("on_trigger".to_string()), args("exit")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,1862 @@
+//! Builder Commands
+//!
+//! Parses MUSH-style `@` commands into [`Mutation`]s and applies them on
+//! behalf of the player who typed them. Parsing never touches the world;
+//! all permission checks happen in [`mutations::apply`].
+//!
+//! Supported commands:
+//! - `@create <name>` / `@dig <name>`
+//! - `@pcreate <name>` creates a player in Room Zero (wizards only)
+//! - `@destroy <object>`
+//! - `@set <object> = [!]<FLAG>`
+//! - `@set <object>/<attr> = <value>` and the shorthand `&<attr> <object> = <value>`
+//!   (an empty value clears the attribute)
+//! - `@attrflag <object>/<attr> = [!]<ATTRFLAG>`
+//! - `@lock[/<kind>] <object> = <key>` / `@unlock[/<kind>] <object>`
+//! - `@chown <object> = <player>`
+//! - `@open <exit> = <room>` / `@link <exit> = <room>`
+//! - `@script <object>[/<message>] = <source>` saves a new script revision
+//! - `@script/history <object>`, `@script/diff <object>[ = <from>[,<to>]]`
+//!   and `@script/revert <object> = <revision>`
+//! - `@quota [<player>]` shows script usage charged to a player this minute
+//! - `@ps [<object>]` / `@ps/all` lists pending script timers
+//! - `@halt <object>` cancels an object's timers; `@halt/timer <id>` cancels one
+//! - `@debug <object>/<function>[(<args>)][ = <breakpoint>, ...]` runs a
+//!   script function under the debugger; `@debug/step`, `/next`, `/finish`,
+//!   `/continue`, `/break <bp>`, `/clear <bp>`, `/stack`, `/vars` and
+//!   `/abort` drive the session, and `@debug` alone shows where it stands
+//! - `@trace <object>/<function>[(<args>)]` runs a script function and shows
+//!   its value, output, effects, usage and host calls
+//!
+//! Player verbs check the target's locks before moving anything:
+//! - `get <thing>` / `drop <thing>`
+//! - `enter <object>`, `use <object>`, `go <exit>`
+//!
+//! Objects are matched by `me`, `#<dbref>`, or exact (case-insensitive) name.
+//! Player verbs only look nearby: `get`, `enter`, `use` and `go` match
+//! objects in the same place as the player, and `drop` what it carries.
+//!
+//! Commands that need script-executor (saving a script, `@quota`, `@trace`,
+//! `@debug`, and verbs behind a `$function` lock) don't wait for it:
+//! [`start`] hands back a [`Reply::Pending`] and the main loop finishes the
+//! command once the answer arrives, against the world as it is by then.
+//! A verb waiting on its lock is started again with the lock's answers.
+//!
+//! # Learning Note
+//! A [`Pending`] splits a command in two: a future that only talks to the
+//! executor, and a closure that gets the world back afterwards. Only the
+//! closure touches the ECS, so the world never has to be held across an
+//! `.await`.
+
+use bevy::prelude::*;
+use shared::components::{
+    AttrFlags, Attributes, Destination, Flags, Location, LockKind, ObjectId, ObjectKind,
+    ObjectName, Owner,
+};
+use shared::locks::{LockExpr, LockParseError};
+use shared::scripting::{
+    DebugCommand, DebugStart, JobOutput, JobPriority, OutputStream, QuotaReport, QuotaStatus,
+    ScriptJob,
+};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::debug::{self, ActiveSession, DebugSessions, ScriptDebuggerHook};
+use crate::libraries::{self, ImportError};
+use crate::locks::{self, LockAction, ScriptAnswers, SCRIPT_ATTR};
+use crate::mutations::{self, Mutation, MutationError};
+use crate::objects::{component, location};
+use crate::permissions::{self, PermissionError, Subject};
+use crate::scripts::{self, ScriptCallerHook, ScriptHistory};
+use crate::spatial;
+use crate::timers::{self, ScriptTimers};
+
+/// Why a command could not be run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    /// The command is not recognized
+    Unknown(String),
+    /// The command is malformed; the message shows correct usage
+    Usage(&'static str),
+    /// No object matched the given name
+    NoMatch(String),
+    /// A lock key could not be parsed
+    BadLock(LockParseError),
+    /// A lock stopped the action; the message is shown to the player
+    Locked(&'static str),
+    /// The mutation was rejected
+    Mutation(MutationError),
+    /// A script's imports could not be resolved
+    Import(ImportError),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(cmd) => write!(f, "Huh? Unknown command '{}'.", cmd),
+            Self::Usage(usage) => write!(f, "Usage: {}", usage),
+            Self::NoMatch(name) => write!(f, "I don't see '{}' here.", name),
+            Self::BadLock(err) => write!(f, "Bad lock key: {}", err),
+            Self::Locked(message) => f.write_str(message),
+            Self::Mutation(err) => write!(f, "{}", err),
+            Self::Import(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<MutationError> for CommandError {
+    fn from(err: MutationError) -> Self {
+        Self::Mutation(err)
+    }
+}
+
+/// What a command answers with
+pub enum Reply {
+    /// Text to show the player
+    Text(String),
+    /// The command is waiting on script-executor
+    Pending(Pending),
+}
+
+impl Reply {
+    /// Wait for `answer`, then finish the command with `finish`
+    fn later<T: Send + 'static>(
+        answer: impl Future<Output = T> + Send + 'static,
+        finish: impl FnOnce(&mut World, T) -> Result<Reply, CommandError> + Send + 'static,
+    ) -> Self {
+        Self::Pending(Pending(Box::pin(async move {
+            let answer = answer.await;
+            Resume(Box::new(move |world: &mut World| finish(world, answer)))
+        })))
+    }
+}
+
+/// A command waiting on script-executor
+pub struct Pending(Pin<Box<dyn Future<Output = Resume> + Send>>);
+
+impl Pending {
+    /// Wait for the answer; this never touches the world
+    pub async fn wait(self) -> Resume {
+        self.0.await
+    }
+}
+
+/// What is left of a command once script-executor has answered
+type Finish = Box<dyn FnOnce(&mut World) -> Result<Reply, CommandError> + Send>;
+
+/// The rest of a command, once script-executor has answered
+pub struct Resume(Finish);
+
+impl Resume {
+    /// Finish the command against the world
+    pub fn apply(self, world: &mut World) -> Result<Reply, CommandError> {
+        (self.0)(world)
+    }
+}
+
+#[cfg(test)]
+impl Pending {
+    /// The answer, which hooks installed by tests give straight away
+    fn now(mut self) -> Resume {
+        use std::task::{Context, Poll, Waker};
+        match self
+            .0
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(resume) => resume,
+            Poll::Pending => panic!("test hooks should answer straight away"),
+        }
+    }
+}
+
+/// Run one command line to the end, as the main loop would
+#[cfg(test)]
+pub fn run(world: &mut World, actor: ObjectId, input: &str) -> Result<String, CommandError> {
+    let mut reply = start(world, actor, input)?;
+    loop {
+        match reply {
+            Reply::Text(text) => return Ok(text),
+            Reply::Pending(pending) => reply = pending.now().apply(world)?,
+        }
+    }
+}
+
+/// Parse and start one command line typed by `actor`
+///
+/// Returns the text to show the player on success, or the rest of the
+/// command if it has to wait on script-executor.
+pub fn start(world: &mut World, actor: ObjectId, input: &str) -> Result<Reply, CommandError> {
+    start_with(world, actor, input, None)
+}
+
+/// [`start`], given the answers to `$function` locks once they are known
+fn start_with(
+    world: &mut World,
+    actor: ObjectId,
+    input: &str,
+    answers: Option<&ScriptAnswers>,
+) -> Result<Reply, CommandError> {
+    let input = input.trim();
+    let (verb, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let rest = rest.trim();
+    let (verb, switch) = match verb.split_once('/') {
+        Some((verb, switch)) => (verb, Some(switch)),
+        None => (verb, None),
+    };
+
+    let mutation = match verb.to_ascii_lowercase().as_str() {
+        "@create" | "@dig" | "@pcreate" => {
+            if rest.is_empty() {
+                return Err(CommandError::Usage("@create <name>"));
+            }
+            let kind = match verb.to_ascii_lowercase().as_str() {
+                "@dig" => ObjectKind::Room,
+                "@pcreate" => ObjectKind::Player,
+                _ => ObjectKind::Thing,
+            };
+            Mutation::Create {
+                kind,
+                name: rest.to_string(),
+            }
+        }
+        "@destroy" => Mutation::Destroy {
+            target: match_object(world, actor, rest)?,
+        },
+        "@set" => parse_set(world, actor, rest)?,
+        "@attrflag" => {
+            const USAGE: &str = "@attrflag <object>/<attr> = [!]<flag>";
+            let (lhs, flag) = split_assignment(rest).ok_or(CommandError::Usage(USAGE))?;
+            let (object, name) = lhs.split_once('/').ok_or(CommandError::Usage(USAGE))?;
+            let (flag, on) = parse_negation(flag);
+            Mutation::SetAttrFlags {
+                target: match_object(world, actor, object)?,
+                name: name.trim().to_string(),
+                flags: AttrFlags::from_name(flag).ok_or(CommandError::Usage(USAGE))?,
+                on,
+            }
+        }
+        "@lock" => {
+            const USAGE: &str = "@lock[/<basic|use|enter>] <object> = <key>";
+            let (object, key) = split_assignment(rest).ok_or(CommandError::Usage(USAGE))?;
+            let key = LockExpr::parse(key, |name| match_object(world, actor, name).ok())
+                .map_err(CommandError::BadLock)?;
+            Mutation::SetLock {
+                target: match_object(world, actor, object)?,
+                kind: parse_lock_kind(switch).ok_or(CommandError::Usage(USAGE))?,
+                key: Some(key),
+            }
+        }
+        "@unlock" => Mutation::SetLock {
+            target: match_object(world, actor, rest)?,
+            kind: parse_lock_kind(switch)
+                .ok_or(CommandError::Usage("@unlock[/<basic|use|enter>] <object>"))?,
+            key: None,
+        },
+        "@chown" => {
+            const USAGE: &str = "@chown <object> = <player>";
+            let (object, owner) = split_assignment(rest).ok_or(CommandError::Usage(USAGE))?;
+            Mutation::Chown {
+                target: match_object(world, actor, object)?,
+                owner: match_object(world, actor, owner)?,
+            }
+        }
+        "@open" => {
+            const USAGE: &str = "@open <exit> = <room>";
+            let (name, room) = split_assignment(rest).ok_or(CommandError::Usage(USAGE))?;
+            let room = match_object(world, actor, room)?;
+            // Check the destination first, so a bad one leaves no exit behind
+            match component::<ObjectKind>(world, room) {
+                Some(ObjectKind::Room) => {}
+                Some(kind) => return Err(MutationError::WrongKind(room, *kind).into()),
+                None => return Err(MutationError::NoSuchObject(room).into()),
+            }
+            let exit = mutations::apply(
+                world,
+                actor,
+                Mutation::Create {
+                    kind: ObjectKind::Exit,
+                    name: name.to_string(),
+                },
+            )?;
+            Mutation::Link {
+                target: exit,
+                destination: room,
+            }
+        }
+        "@link" => {
+            const USAGE: &str = "@link <exit> = <room>";
+            let (exit, room) = split_assignment(rest).ok_or(CommandError::Usage(USAGE))?;
+            Mutation::Link {
+                target: match_object(world, actor, exit)?,
+                destination: match_object(world, actor, room)?,
+            }
+        }
+        "@script" => match switch.map(str::to_ascii_lowercase).as_deref() {
+            None => {
+                const USAGE: &str = "@script <object>[/<message>] = <source>";
+                let (lhs, source) = split_assignment(rest).ok_or(CommandError::Usage(USAGE))?;
+                let (object, message) = lhs.split_once('/').unwrap_or((lhs, ""));
+                Mutation::SetScript {
+                    target: match_object(world, actor, object)?,
+                    source: source.to_string(),
+                    message: message.trim().to_string(),
+                }
+            }
+            Some("history") => return script_history(world, actor, rest).map(Reply::Text),
+            Some("diff") => return script_diff(world, actor, rest).map(Reply::Text),
+            Some("revert") => {
+                const USAGE: &str = "@script/revert <object> = <revision>";
+                let (object, revision) =
+                    split_assignment(rest).ok_or(CommandError::Usage(USAGE))?;
+                Mutation::RevertScript {
+                    target: match_object(world, actor, object)?,
+                    revision: revision.parse().map_err(|_| CommandError::Usage(USAGE))?,
+                }
+            }
+            _ => return Err(CommandError::Usage("@script[/history|/diff|/revert]")),
+        },
+        "@quota" => return quota(world, actor, rest),
+        "@ps" => return ps(world, actor, switch, rest).map(Reply::Text),
+        "@halt" => return halt(world, actor, switch, rest).map(Reply::Text),
+        "@debug" => return debug(world, actor, switch, rest),
+        "@trace" => return trace(world, actor, rest),
+        "get" | "take" => {
+            let thing = match_here(world, actor, rest)?;
+            let lock = (LockAction::Pickup, "You can't pick that up.");
+            if let Some(wait) = require_lock(world, actor, thing, lock, input, answers)? {
+                return Ok(wait);
+            }
+            Mutation::Move {
+                target: thing,
+                destination: actor,
+            }
+        }
+        "drop" => Mutation::Move {
+            target: match_held(world, actor, rest)?,
+            destination: location(world, actor).ok_or(CommandError::Usage("drop <thing>"))?,
+        },
+        "enter" => {
+            let object = match_here(world, actor, rest)?;
+            let kind = component::<ObjectKind>(world, object).copied();
+            if kind == Some(ObjectKind::Exit) {
+                return Err(MutationError::WrongKind(object, ObjectKind::Exit).into());
+            }
+            let lock = (LockAction::Enter, "You can't enter that.");
+            if let Some(wait) = require_lock(world, actor, object, lock, input, answers)? {
+                return Ok(wait);
+            }
+            Mutation::Move {
+                target: actor,
+                destination: object,
+            }
+        }
+        "use" => {
+            let object = match_here(world, actor, rest)?;
+            let lock = (LockAction::Use, "You can't use that.");
+            if let Some(wait) = require_lock(world, actor, object, lock, input, answers)? {
+                return Ok(wait);
+            }
+            let name = component::<ObjectName>(world, object)
+                .map(|name| name.0.clone())
+                .unwrap_or_default();
+            return Ok(Reply::Text(format!("You use {}.", name)));
+        }
+        "go" => {
+            let exit = match_here(world, actor, rest)?;
+            let destination = component::<Destination>(world, exit)
+                .ok_or(CommandError::Locked("That exit doesn't lead anywhere."))?
+                .0;
+            let lock = (LockAction::Pass, "You can't go that way.");
+            if let Some(wait) = require_lock(world, actor, exit, lock, input, answers)? {
+                return Ok(wait);
+            }
+            Mutation::Move {
+                target: actor,
+                destination,
+            }
+        }
+        _ if verb.starts_with('&') && verb.len() > 1 => {
+            let (object, value) =
+                split_assignment(rest).ok_or(CommandError::Usage("&<attr> <object> = <value>"))?;
+            attr_mutation(match_object(world, actor, object)?, &verb[1..], value)
+        }
+        _ => return Err(CommandError::Unknown(verb.to_string())),
+    };
+
+    // A new script is only saved once the validator accepts it
+    if let Some(check) = script_to_check(world, actor, &mutation)
+        .and_then(|source| scripts::validate(world, &source))
+    {
+        return Ok(Reply::later(check, move |world, valid| {
+            valid.map_err(MutationError::InvalidScript)?;
+            apply(world, actor, mutation).map(Reply::Text)
+        }));
+    }
+    apply(world, actor, mutation).map(Reply::Text)
+}
+
+/// The source `mutation` would make an object's script, if it needs
+/// validating first
+///
+/// Cleared scripts don't, and neither does a change the actor may not
+/// make: applying it reports that instead.
+fn script_to_check(world: &World, actor: ObjectId, mutation: &Mutation) -> Option<String> {
+    let (target, source) = match mutation {
+        Mutation::SetScript { target, source, .. } => (*target, source.clone()),
+        Mutation::SetAttr {
+            target,
+            name,
+            value,
+        } if Attributes::normalize(name) == SCRIPT_ATTR => (*target, value.clone()),
+        Mutation::RevertScript { target, revision } => {
+            let history = component::<ScriptHistory>(world, *target)?;
+            (*target, history.get(*revision)?.source.clone())
+        }
+        _ => return None,
+    };
+    let subject = Subject::load(world, actor)?;
+    let object = Subject::load(world, target)?;
+    let existing = component::<Attributes>(world, target)?.get(SCRIPT_ATTR);
+    let writable = permissions::check_write_attr(&subject, &object, existing).is_ok();
+    (writable && !source.trim().is_empty()).then_some(source)
+}
+
+/// Apply a command's mutation and describe what happened
+fn apply(world: &mut World, actor: ObjectId, mutation: Mutation) -> Result<String, CommandError> {
+    let created = match &mutation {
+        Mutation::Create { name, .. } => Some(name.clone()),
+        _ => None,
+    };
+    let scripted = matches!(
+        mutation,
+        Mutation::SetScript { .. } | Mutation::RevertScript { .. }
+    );
+    let reply = match &mutation {
+        Mutation::Destroy { .. } => "Destroyed.",
+        Mutation::SetLock { key: Some(_), .. } => "Locked.",
+        Mutation::SetLock { key: None, .. } => "Unlocked.",
+        Mutation::Chown { .. } => "Owner changed.",
+        Mutation::Link { .. } => "Linked.",
+        Mutation::Move { .. } => "Moved.",
+        Mutation::ClearAttr { .. } => "Cleared.",
+        _ => "Set.",
+    };
+
+    let id = mutations::apply(world, actor, mutation)?;
+    if scripted {
+        let number = component::<ScriptHistory>(world, id)
+            .and_then(ScriptHistory::latest)
+            .map_or(0, |revision| revision.number);
+        return Ok(format!("Script saved as revision {}.", number));
+    }
+    Ok(match created {
+        Some(name) => format!("Created: {}({}).", name, id),
+        None => reply.to_string(),
+    })
+}
+
+/// Load `object`'s script history if `actor` may read its script
+fn readable_history(
+    world: &World,
+    actor: ObjectId,
+    object: &str,
+) -> Result<ScriptHistory, CommandError> {
+    let target = match_object(world, actor, object)?;
+    let subject = Subject::load(world, actor).ok_or(MutationError::NoSuchObject(actor))?;
+    let owner = Subject::load(world, target).ok_or(MutationError::NoSuchObject(target))?;
+    let readable = match component::<Attributes>(world, target).and_then(|a| a.get(SCRIPT_ATTR)) {
+        Some(script) => permissions::can_read_attr(&subject, &owner, script),
+        None => permissions::can_examine(&subject, &owner),
+    };
+    if !readable {
+        return Err(MutationError::Denied(PermissionError::NotController).into());
+    }
+    Ok(component::<ScriptHistory>(world, target)
+        .cloned()
+        .unwrap_or_default())
+}
+
+/// `@quota [<player>]`: script usage charged to a player (default: yourself)
+///
+/// Only wizards and royalty may look at someone else's quota.
+fn quota(world: &World, actor: ObjectId, rest: &str) -> Result<Reply, CommandError> {
+    let target = if rest.is_empty() {
+        actor
+    } else {
+        match_object(world, actor, rest)?
+    };
+    let owner = component::<Owner>(world, target)
+        .ok_or(MutationError::NoSuchObject(target))?
+        .0;
+    let subject = Subject::load(world, actor).ok_or(MutationError::NoSuchObject(actor))?;
+    if owner != subject.owner && !subject.sees_all() {
+        return Err(MutationError::Denied(PermissionError::NotController).into());
+    }
+
+    let Some(report) = scripts::quota_report(world, owner) else {
+        return Ok(Reply::Text("Script quotas are not enabled.".to_string()));
+    };
+    Ok(Reply::later(report, move |world, report| {
+        Ok(Reply::Text(match report {
+            Ok(report) => describe_quota(world, owner, &report),
+            Err(e) => format!("Quota unavailable: {}", e),
+        }))
+    }))
+}
+
+/// Render `owner`'s quota report for `@quota`
+fn describe_quota(world: &World, owner: ObjectId, report: &QuotaReport) -> String {
+    let name = component::<ObjectName>(world, owner)
+        .map(|name| name.0.as_str())
+        .unwrap_or("?");
+    let status = match report.status {
+        QuotaStatus::Ok => "ok".to_string(),
+        QuotaStatus::Throttled { retry_after_ms } => {
+            format!("throttled, retry in {}s", retry_after_ms.div_ceil(1000))
+        }
+        QuotaStatus::Suspended { remaining_ms } => {
+            format!("suspended for {}s", remaining_ms.div_ceil(1000))
+        }
+    };
+    format!(
+        "Script quota for {}({}), last minute:\n  Operations: {} / {}\n  Wall time: {:.2}s / {:.2}s\n  Status: {}",
+        name,
+        owner,
+        report.ops_used,
+        report.ops_limit,
+        report.wall_ms_used as f64 / 1000.0,
+        report.wall_ms_limit as f64 / 1000.0,
+        status
+    )
+}
+
+/// `@ps [<object>]` / `@ps/all`: pending script timers, soonest first
+///
+/// Without an argument, lists timers on everything the actor's owner owns.
+/// Listing one object needs permission to examine it, and `/all` is for
+/// wizards and royalty.
+fn ps(
+    world: &mut World,
+    actor: ObjectId,
+    switch: Option<&str>,
+    rest: &str,
+) -> Result<String, CommandError> {
+    let subject = Subject::load(world, actor).ok_or(MutationError::NoSuchObject(actor))?;
+    let all = match switch.map(str::to_ascii_lowercase).as_deref() {
+        None => false,
+        Some("all") if subject.sees_all() => true,
+        Some("all") => return Err(MutationError::Denied(PermissionError::NotController).into()),
+        _ => return Err(CommandError::Usage("@ps[/all] [<object>]")),
+    };
+    let only = if rest.is_empty() {
+        None
+    } else {
+        let target = match_object(world, actor, rest)?;
+        let object = Subject::load(world, target).ok_or(MutationError::NoSuchObject(target))?;
+        if !permissions::can_examine(&subject, &object) {
+            return Err(MutationError::Denied(PermissionError::NotController).into());
+        }
+        Some(target)
+    };
+
+    let now = timers::now_ms();
+    let mut pending = Vec::new();
+    let mut query = world.query::<(&ObjectId, &ObjectName, &Owner, &ScriptTimers)>();
+    for (id, name, owner, object_timers) in query.iter(world) {
+        let listed = match only {
+            Some(target) => *id == target,
+            None => all || owner.0 == subject.owner,
+        };
+        if !listed {
+            continue;
+        }
+        for timer in object_timers.timers() {
+            pending.push((
+                timer.due,
+                timer.id,
+                *id,
+                name.0.clone(),
+                timer.function.clone(),
+            ));
+        }
+    }
+    if pending.is_empty() {
+        return Ok("No timers pending.".to_string());
+    }
+    pending.sort();
+
+    let mut lines: Vec<String> = pending
+        .iter()
+        .map(|(due, timer, id, name, function)| {
+            format!(
+                "  {:>4}  in {:<7}  {}({})/{}",
+                timer,
+                timers::format_remaining(due - now),
+                name,
+                id,
+                function
+            )
+        })
+        .collect();
+    lines.push(format!(
+        "{} timer{} pending.",
+        pending.len(),
+        if pending.len() == 1 { "" } else { "s" }
+    ));
+    Ok(lines.join("\n"))
+}
+
+/// `@halt <object>` cancels every timer on an object; `@halt/timer <id>`
+/// cancels one
+fn halt(
+    world: &mut World,
+    actor: ObjectId,
+    switch: Option<&str>,
+    rest: &str,
+) -> Result<String, CommandError> {
+    match switch.map(str::to_ascii_lowercase).as_deref() {
+        None if !rest.is_empty() => {
+            let target = match_object(world, actor, rest)?;
+            let count = component::<ScriptTimers>(world, target).map_or(0, ScriptTimers::len);
+            mutations::apply(
+                world,
+                actor,
+                Mutation::CancelTimers {
+                    target,
+                    timer: None,
+                },
+            )?;
+            Ok(format!(
+                "Halted: {} timer{} cancelled.",
+                count,
+                if count == 1 { "" } else { "s" }
+            ))
+        }
+        Some("timer") => {
+            let id: u64 = rest
+                .parse()
+                .map_err(|_| CommandError::Usage("@halt/timer <id>"))?;
+            let target = timers::owner_of(world, id).ok_or(MutationError::NoSuchTimer(id))?;
+            mutations::apply(
+                world,
+                actor,
+                Mutation::CancelTimers {
+                    target,
+                    timer: Some(id),
+                },
+            )?;
+            Ok(format!("Timer {} cancelled.", id))
+        }
+        _ => Err(CommandError::Usage("@halt <object> or @halt/timer <id>")),
+    }
+}
+
+const DEBUG_USAGE: &str = "@debug <object>/<function>[(<args>)][ = <breakpoint>, ...] or \
+     @debug/step|next|finish|continue|break|clear|stack|vars|abort";
+
+/// `@debug`: run a script function under the debugger and step through it
+///
+/// Starting a session needs control of the object, and replaces any
+/// session the actor already has open. The other switches act on the
+/// actor's open session.
+fn debug(
+    world: &mut World,
+    actor: ObjectId,
+    switch: Option<&str>,
+    rest: &str,
+) -> Result<Reply, CommandError> {
+    if !world.contains_resource::<ScriptDebuggerHook>() {
+        return Ok(Reply::Text("Script debugging is not enabled.".to_string()));
+    }
+    world.init_resource::<DebugSessions>();
+    let switch = switch.map(str::to_ascii_lowercase);
+    if switch.is_none() && !rest.is_empty() {
+        return debug_start(world, actor, rest);
+    }
+    let Some(session) = world.resource::<DebugSessions>().get(actor).cloned() else {
+        return Ok(Reply::Text("No debug session.".to_string()));
+    };
+
+    let breakpoint = || {
+        debug::parse_breakpoint(rest).ok_or(CommandError::Usage(
+            "@debug/break <line>|<function> or @debug/clear <line>|<function>",
+        ))
+    };
+    let command = match switch.as_deref() {
+        None => {
+            let text = debug::describe(&session.state, &session.source);
+            return Ok(Reply::Text(text));
+        }
+        Some("stack") => return Ok(Reply::Text(debug::describe_stack(&session.state))),
+        Some("vars") => return Ok(Reply::Text(debug::describe_scope(&session.state))),
+        Some("abort") => {
+            world.resource_mut::<DebugSessions>().remove(actor);
+            let aborted = world
+                .resource::<ScriptDebuggerHook>()
+                .0
+                .abort(session.session);
+            return Ok(Reply::later(aborted, |_, aborted| {
+                Ok(Reply::Text(match aborted {
+                    Ok(()) => "Debug session aborted.".to_string(),
+                    Err(e) => format!("Debug session dropped: {}", e),
+                }))
+            }));
+        }
+        Some("step") => DebugCommand::Step,
+        Some("next") => DebugCommand::Next,
+        Some("finish") => DebugCommand::Finish,
+        Some("continue") => DebugCommand::Continue,
+        Some("break") => DebugCommand::Break {
+            breakpoint: breakpoint()?,
+        },
+        Some("clear") => DebugCommand::Clear {
+            breakpoint: breakpoint()?,
+        },
+        _ => return Err(CommandError::Usage(DEBUG_USAGE)),
+    };
+
+    let reply = world
+        .resource::<ScriptDebuggerHook>()
+        .0
+        .command(session.session, &command);
+    Ok(Reply::later(reply, move |world, reply| {
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(e) => return Ok(Reply::Text(format!("Debugger error: {}", e))),
+        };
+        let text = match &command {
+            DebugCommand::Break { breakpoint } => format!("Breakpoint set at {}.", breakpoint),
+            DebugCommand::Clear { breakpoint } => {
+                format!("Breakpoint at {} cleared.", breakpoint)
+            }
+            _ => debug::describe(&reply.state, &session.source),
+        };
+        world.resource_mut::<DebugSessions>().update(actor, reply);
+        Ok(Reply::Text(text))
+    }))
+}
+
+/// `@debug <object>/<function>[(<args>)][ = <breakpoint>, ...]`
+fn debug_start(world: &mut World, actor: ObjectId, rest: &str) -> Result<Reply, CommandError> {
+    let usage = CommandError::Usage(DEBUG_USAGE);
+    let (call, breakpoints) = split_assignment(rest).unwrap_or((rest, ""));
+    let (object, function, args) = parse_call(call).ok_or(usage.clone())?;
+    let breakpoints = breakpoints
+        .split(',')
+        .map(str::trim)
+        .filter(|bp| !bp.is_empty())
+        .map(|bp| debug::parse_breakpoint(bp).ok_or(usage.clone()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some((target, job)) = call_job(world, actor, object, function, args, false)? else {
+        return Ok(Reply::Text(format!("{} has no script.", object)));
+    };
+    let source = job.source.clone();
+    let function = function.to_string();
+    let start = DebugStart { job, breakpoints };
+
+    let previous = world.resource_mut::<DebugSessions>().remove(actor);
+    let hook = world.resource::<ScriptDebuggerHook>();
+    let abort = previous.map(|previous| hook.0.abort(previous.session));
+    let started = hook.0.start(&start);
+    let reply = async move {
+        if let Some(abort) = abort {
+            // It may already have ended on the executor's side
+            let _ = abort.await;
+        }
+        started.await
+    };
+    Ok(Reply::later(reply, move |world, reply| {
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(e) => return Ok(Reply::Text(format!("Debugger error: {}", e))),
+        };
+        let text = debug::describe(&reply.state, &source);
+        world.resource_mut::<DebugSessions>().insert(
+            actor,
+            ActiveSession {
+                session: reply.session,
+                object: target,
+                function,
+                source,
+                state: reply.state,
+            },
+        );
+        Ok(Reply::Text(text))
+    }))
+}
+
+/// `@trace <object>/<function>[(<args>)]`: run a script function and show
+/// everything it did
+///
+/// This is a real run: timers it sets are scheduled as usual.
+fn trace(world: &mut World, actor: ObjectId, rest: &str) -> Result<Reply, CommandError> {
+    let (object, function, args) =
+        parse_call(rest).ok_or(CommandError::Usage("@trace <object>/<function>[(<args>)]"))?;
+    if !world.contains_resource::<ScriptCallerHook>() {
+        return Ok(Reply::Text("Script tracing is not enabled.".to_string()));
+    }
+    let Some((target, job)) = call_job(world, actor, object, function, args, true)? else {
+        return Ok(Reply::Text(format!("{} has no script.", object)));
+    };
+    let name = component::<ObjectName>(world, target)
+        .map(|name| name.0.clone())
+        .unwrap_or_default();
+    let call = format!("{}({})/{}", name, target, function);
+    let output = world.resource::<ScriptCallerHook>().0.call(&job);
+    Ok(Reply::later(output, move |world, output| {
+        Ok(Reply::Text(match output {
+            Ok(output) => describe_trace(world, target, &call, output),
+            Err(e) => format!("{} failed: {}", call, e),
+        }))
+    }))
+}
+
+/// Render a traced run of `call` on `target` for `@trace`, scheduling the
+/// timers it set
+fn describe_trace(world: &mut World, target: ObjectId, call: &str, output: JobOutput) -> String {
+    let trace = &output.trace;
+    let mut lines = vec![format!(
+        "{} returned {} ({} ops, {:.2}ms).",
+        call,
+        output.value,
+        trace.ops,
+        trace.duration_us as f64 / 1000.0
+    )];
+    if !trace.output.is_empty() {
+        lines.push("Output:".to_string());
+        for line in &trace.output {
+            lines.push(match (line.stream, line.line) {
+                (OutputStream::Print, _) => format!("  {}", line.text),
+                (OutputStream::Debug, 0) => format!("  [debug] {}", line.text),
+                (OutputStream::Debug, n) => format!("  [debug, line {}] {}", n, line.text),
+            });
+        }
+        if trace.dropped_lines > 0 {
+            lines.push(format!("  ... {} more lines", trace.dropped_lines));
+        }
+    }
+    if !output.timers.is_empty() {
+        lines.push("Effects:".to_string());
+        for timer in &output.timers {
+            lines.push(format!(
+                "  timer: {}() in {}",
+                timer.function,
+                timers::format_remaining(timer.delay_ms.min(i64::MAX as u64) as i64)
+            ));
+        }
+    }
+    if let Some(calls) = trace.host_calls.as_ref().filter(|calls| !calls.is_empty()) {
+        lines.push("Host calls:".to_string());
+        for host_call in calls {
+            let result = match &host_call.result {
+                Ok(value) => value.clone(),
+                Err(e) => format!("error: {}", e),
+            };
+            lines.push(format!(
+                "  {}({}) -> {}",
+                host_call.function,
+                host_call.args.join(", "),
+                result
+            ));
+        }
+    }
+    timers::schedule(world, target, output.timers, timers::now_ms());
+    lines.join("\n")
+}
+
+/// Split `<object>/<function>[(<args>)]`
+///
+/// Arguments are comma-separated and passed as strings; `None` means no
+/// parentheses were given.
+fn parse_call(input: &str) -> Option<(&str, &str, Option<Vec<String>>)> {
+    let (object, call) = input.split_once('/')?;
+    let (function, args) = match call.split_once('(') {
+        Some((function, args)) => {
+            let args = args.trim().strip_suffix(')')?;
+            let args = args
+                .split(',')
+                .map(str::trim)
+                .filter(|arg| !arg.is_empty())
+                .map(str::to_string)
+                .collect();
+            (function.trim(), Some(args))
+        }
+        None => (call.trim(), None),
+    };
+    let object = object.trim();
+    (!object.is_empty() && !function.is_empty()).then_some((object, function, args))
+}
+
+/// Build an interactive job calling `function` in an object's script
+///
+/// Needs control of the object. Handlers (`on_*`) called without
+/// arguments get the actor's dbref, as when they are triggered. Returns
+/// `None` if the object has no script.
+fn call_job(
+    world: &World,
+    actor: ObjectId,
+    object: &str,
+    function: &str,
+    args: Option<Vec<String>>,
+    trace: bool,
+) -> Result<Option<(ObjectId, ScriptJob)>, CommandError> {
+    let target = match_object(world, actor, object)?;
+    let subject = Subject::load(world, actor).ok_or(MutationError::NoSuchObject(actor))?;
+    let object = Subject::load(world, target).ok_or(MutationError::NoSuchObject(target))?;
+    permissions::check_control(&subject, &object).map_err(MutationError::Denied)?;
+    let Some(source) = component::<Attributes>(world, target)
+        .and_then(|attrs| attrs.get(SCRIPT_ATTR))
+        .map(|script| script.value.clone())
+    else {
+        return Ok(None);
+    };
+    let modules = libraries::resolve(world, object.owner, &source).map_err(CommandError::Import)?;
+    let args = args.unwrap_or_else(|| match function.starts_with("on_") {
+        true => vec![actor.to_string()],
+        false => Vec::new(),
+    });
+    let surroundings = spatial::surroundings(world, target, object.owner, &source, &modules);
+    let job = ScriptJob {
+        owner: object.owner,
+        priority: JobPriority::Interactive,
+        source,
+        function: Some(function.to_string()),
+        args,
+        trace,
+        modules,
+        surroundings,
+    };
+    Ok(Some((target, job)))
+}
+
+/// `@script/history <object>`: one line per revision, newest first
+fn script_history(world: &World, actor: ObjectId, rest: &str) -> Result<String, CommandError> {
+    if rest.is_empty() {
+        return Err(CommandError::Usage("@script/history <object>"));
+    }
+    let history = readable_history(world, actor, rest)?;
+    if history.revisions().is_empty() {
+        return Ok("No script revisions.".to_string());
+    }
+    let lines: Vec<String> = history
+        .revisions()
+        .iter()
+        .rev()
+        .map(|revision| {
+            let author = component::<ObjectName>(world, revision.author)
+                .map(|name| name.0.as_str())
+                .unwrap_or("?");
+            let mut line = format!(
+                "r{} {} by {}({})",
+                revision.number,
+                scripts::format_timestamp(revision.timestamp),
+                author,
+                revision.author
+            );
+            if revision.source.is_empty() {
+                line.push_str(" [cleared]");
+            }
+            if !revision.message.is_empty() {
+                line.push_str(": ");
+                line.push_str(&revision.message);
+            }
+            line
+        })
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+/// `@script/diff <object>[ = <from>[,<to>]]`, defaulting to the last change
+fn script_diff(world: &World, actor: ObjectId, rest: &str) -> Result<String, CommandError> {
+    const USAGE: &str = "@script/diff <object>[ = <from>[,<to>]]";
+    let (object, range) = split_assignment(rest).unwrap_or((rest, ""));
+    if object.is_empty() {
+        return Err(CommandError::Usage(USAGE));
+    }
+    let history = readable_history(world, actor, object)?;
+    let latest = history.latest().map_or(0, |revision| revision.number);
+
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<u32>()
+            .map_err(|_| CommandError::Usage(USAGE))
+    };
+    let (from, to) = match range.split_once(',') {
+        Some((from, to)) => (parse(from)?, parse(to)?),
+        None if range.is_empty() => (latest.saturating_sub(1), latest),
+        None => (parse(range)?, latest),
+    };
+    let source = |number: u32| -> Result<&str, CommandError> {
+        if number == 0 {
+            return Ok("");
+        }
+        history
+            .get(number)
+            .map(|revision| revision.source.as_str())
+            .ok_or_else(|| MutationError::NoSuchRevision(number).into())
+    };
+    let diff = scripts::diff(source(from)?, source(to)?);
+    Ok(format!("r{} -> r{}\n{}", from, to, diff))
+}
+
+/// Fail with the message unless `actor` passes the lock guarding the
+/// action
+///
+/// If the lock runs `$function`s that `answers` doesn't have yet, they are
+/// asked for and `line` is started again once they're in; the reply to
+/// hand back meanwhile is `Some`.
+fn require_lock(
+    world: &World,
+    actor: ObjectId,
+    target: ObjectId,
+    (action, message): (LockAction, &'static str),
+    line: &str,
+    answers: Option<&ScriptAnswers>,
+) -> Result<Option<Reply>, CommandError> {
+    let unanswered = ScriptAnswers::default();
+    let answers = match answers {
+        Some(answers) => answers,
+        None => {
+            let calls = locks::script_calls(world, actor, target, action);
+            let asked = match calls.is_empty() {
+                true => None,
+                false => locks::ask(world, actor, &calls),
+            };
+            if let Some(asked) = asked {
+                let line = line.to_string();
+                return Ok(Some(Reply::later(asked, move |world, answers| {
+                    start_with(world, actor, &line, Some(&answers))
+                })));
+            }
+            &unanswered
+        }
+    };
+    if locks::passes(world, actor, target, action, answers) {
+        Ok(None)
+    } else {
+        Err(CommandError::Locked(message))
+    }
+}
+
+/// Parse the two forms of `@set`: object flags and attribute values
+fn parse_set(world: &World, actor: ObjectId, rest: &str) -> Result<Mutation, CommandError> {
+    const USAGE: &str = "@set <object> = [!]<flag> or @set <object>/<attr> = <value>";
+    let (lhs, rhs) = split_assignment(rest).ok_or(CommandError::Usage(USAGE))?;
+
+    if let Some((object, name)) = lhs.split_once('/') {
+        return Ok(attr_mutation(
+            match_object(world, actor, object)?,
+            name,
+            rhs,
+        ));
+    }
+
+    let (flag, on) = parse_negation(rhs);
+    Ok(Mutation::SetFlags {
+        target: match_object(world, actor, lhs)?,
+        flags: Flags::from_name(flag).ok_or(CommandError::Usage(USAGE))?,
+        on,
+    })
+}
+
+/// Build the mutation for setting (or, with an empty value, clearing) an attribute
+fn attr_mutation(target: ObjectId, name: &str, value: &str) -> Mutation {
+    let name = name.trim().to_string();
+    if value.is_empty() {
+        Mutation::ClearAttr { target, name }
+    } else {
+        Mutation::SetAttr {
+            target,
+            name,
+            value: value.to_string(),
+        }
+    }
+}
+
+/// Split `lhs = rhs`, trimming both sides
+fn split_assignment(input: &str) -> Option<(&str, &str)> {
+    let (lhs, rhs) = input.split_once('=')?;
+    let lhs = lhs.trim();
+    (!lhs.is_empty()).then_some((lhs, rhs.trim()))
+}
+
+/// Strip a leading `!`, returning the name and whether to set (`true`) or clear
+fn parse_negation(input: &str) -> (&str, bool) {
+    match input.strip_prefix('!') {
+        Some(name) => (name.trim(), false),
+        None => (input, true),
+    }
+}
+
+/// Map an optional `/switch` to a lock kind (no switch means the basic lock)
+fn parse_lock_kind(switch: Option<&str>) -> Option<LockKind> {
+    switch.map_or(Some(LockKind::Basic), LockKind::from_name)
+}
+
+/// Resolve an object reference typed by `actor`
+pub fn match_object(world: &World, actor: ObjectId, name: &str) -> Result<ObjectId, CommandError> {
+    let name = name.trim();
+    if name.eq_ignore_ascii_case("me") {
+        return Ok(actor);
+    }
+    if let Some(id) = name.strip_prefix('#').and_then(|n| n.parse().ok()) {
+        return Ok(ObjectId(id));
+    }
+
+    world
+        .iter_entities()
+        .filter_map(|entity| Some((entity.get::<ObjectId>()?, entity.get::<ObjectName>()?)))
+        .filter(|(_, object_name)| object_name.0.eq_ignore_ascii_case(name))
+        .map(|(id, _)| *id)
+        .min()
+        .ok_or_else(|| CommandError::NoMatch(name.to_string()))
+}
+
+/// Match an object standing in the same place as `actor`, other than
+/// `actor` itself
+fn match_here(world: &World, actor: ObjectId, name: &str) -> Result<ObjectId, CommandError> {
+    match_in(world, actor, location(world, actor), name)
+}
+
+/// Match an object `actor` carries
+fn match_held(world: &World, actor: ObjectId, name: &str) -> Result<ObjectId, CommandError> {
+    match_in(world, actor, Some(actor), name)
+}
+
+/// Match an object inside `place`, other than `actor`, by `#<dbref>` or
+/// name
+///
+/// Only what is in `place` is looked at, so an object with the same name
+/// somewhere else never gets in the way.
+fn match_in(
+    world: &World,
+    actor: ObjectId,
+    place: Option<ObjectId>,
+    name: &str,
+) -> Result<ObjectId, CommandError> {
+    let name = name.trim();
+    let dbref = name
+        .strip_prefix('#')
+        .and_then(|n| n.parse().ok())
+        .map(ObjectId);
+    world
+        .iter_entities()
+        .filter_map(|entity| {
+            let location = entity.get::<Location>()?;
+            let id = *entity.get::<ObjectId>()?;
+            let object_name = entity.get::<ObjectName>()?;
+            let named = match dbref {
+                Some(dbref) => id == dbref,
+                None => object_name.0.eq_ignore_ascii_case(name),
+            };
+            (Some(location.0) == place && id != actor && named).then_some(id)
+        })
+        .min()
+        .ok_or_else(|| CommandError::NoMatch(name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug::ScriptDebugger;
+    use crate::locks::{LockScriptHook, LockScripts};
+    use crate::objects::{bootstrap, location, GOD, ROOM_ZERO};
+    use crate::scripts::{
+        Answer, QuotaSource, QuotaSourceHook, ScriptCaller, ScriptValidator, ScriptValidatorHook,
+    };
+    use shared::components::Locks;
+    use shared::scripting::{
+        DebugReply, DebugState, DebugVariable, ExecutionTrace, HostCall, OutputLine, TimerRequest,
+    };
+    use std::sync::{Arc, Mutex};
+
+    fn setup() -> (World, ObjectId, ObjectId) {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        let mut player = |name: &str| {
+            let create = Mutation::Create {
+                kind: ObjectKind::Player,
+                name: name.to_string(),
+            };
+            mutations::apply(&mut world, GOD, create).unwrap()
+        };
+        let alice = player("Alice");
+        let bob = player("Bob");
+        (world, alice, bob)
+    }
+
+    fn attr_value(world: &World, id: ObjectId, name: &str) -> Option<String> {
+        let entity = world
+            .resource::<crate::objects::ObjectRegistry>()
+            .entity(id)?;
+        let attrs = world.get::<Attributes>(entity)?;
+        attrs.get(name).map(|attr| attr.value.clone())
+    }
+
+    #[test]
+    fn test_builder_flow() {
+        let (mut world, alice, _) = setup();
+
+        // Alice is not a builder yet
+        let err = run(&mut world, alice, "@create Lamp").unwrap_err();
+        assert_eq!(
+            err,
+            CommandError::Mutation(MutationError::Denied(PermissionError::NotBuilder))
+        );
+
+        run(&mut world, GOD, "@set Alice = BUILDER").unwrap();
+        let reply = run(&mut world, alice, "@create Lamp").unwrap();
+        assert!(reply.starts_with("Created: Lamp(#"));
+
+        // Only wizards make players
+        let err = run(&mut world, alice, "@pcreate Carol").unwrap_err();
+        assert_eq!(
+            err,
+            CommandError::Mutation(MutationError::Denied(PermissionError::WizardOnly))
+        );
+        let reply = run(&mut world, GOD, "@pcreate Carol").unwrap();
+        assert!(reply.starts_with("Created: Carol(#"));
+
+        run(&mut world, alice, "&desc Lamp = A brass lamp.").unwrap();
+        assert_eq!(
+            attr_value(&world, match_object(&world, alice, "lamp").unwrap(), "DESC"),
+            Some("A brass lamp.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_mutations_are_checked() {
+        let (mut world, alice, bob) = setup();
+        run(&mut world, GOD, "@set Alice = BUILDER").unwrap();
+        run(&mut world, alice, "@create Door").unwrap();
+
+        // Bob neither owns the door nor is a wizard
+        assert!(run(&mut world, bob, "@set Door/desc = Graffiti").is_err());
+        assert!(run(&mut world, bob, "@lock Door = me").is_err());
+        assert!(run(&mut world, bob, "@destroy Door").is_err());
+
+        run(&mut world, alice, "@lock/use Door = =Alice | +Door").unwrap();
+        let door = match_object(&world, alice, "Door").unwrap();
+        let locks = component::<Locks>(&world, door).unwrap();
+        assert_eq!(
+            locks.0.get(&LockKind::Use).map(ToString::to_string),
+            Some(format!("={} | +{}", alice, door))
+        );
+
+        run(&mut world, alice, "@set Door = SAFE").unwrap();
+        assert!(run(&mut world, alice, "@destroy Door").is_err());
+    }
+
+    #[test]
+    fn test_verbs_match_nearby_objects() {
+        let (mut world, alice, _) = setup();
+        run(&mut world, GOD, "@dig Attic").unwrap();
+        let attic = match_object(&world, GOD, "Attic").unwrap();
+        // The first sword and lever made are in the attic, out of reach
+        let mut make = |name: &str, place| {
+            run(&mut world, GOD, &format!("@create {}", name)).unwrap();
+            let id = match_held(&world, GOD, name).unwrap();
+            let put = Mutation::Move {
+                target: id,
+                destination: place,
+            };
+            mutations::apply(&mut world, GOD, put).unwrap();
+            id
+        };
+        let far_sword = make("Sword", attic);
+        make("Lever", attic);
+        let sword = make("Sword", ROOM_ZERO);
+        assert!(far_sword < sword);
+
+        assert_eq!(
+            run(&mut world, alice, "use Lever"),
+            Err(CommandError::NoMatch("Lever".to_string()))
+        );
+        run(&mut world, alice, "get Sword").unwrap();
+        assert_eq!(location(&world, sword), Some(alice));
+        assert_eq!(location(&world, far_sword), Some(attic));
+
+        // Dropping only looks in the inventory
+        let put = Mutation::Move {
+            target: far_sword,
+            destination: ROOM_ZERO,
+        };
+        mutations::apply(&mut world, GOD, put).unwrap();
+        run(&mut world, alice, "drop Sword").unwrap();
+        assert_eq!(location(&world, sword), Some(ROOM_ZERO));
+        assert_eq!(
+            run(&mut world, alice, "drop Sword"),
+            Err(CommandError::NoMatch("Sword".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_destroy_rehomes_contents() {
+        let (mut world, alice, _) = setup();
+        run(&mut world, GOD, "@dig Attic").unwrap();
+        let attic = match_object(&world, GOD, "Attic").unwrap();
+        run(&mut world, GOD, "@open Ladder = Attic").unwrap();
+        let ladder = match_object(&world, GOD, "Ladder").unwrap();
+        run(&mut world, GOD, "@create Crate").unwrap();
+        let crate_ = match_object(&world, GOD, "Crate").unwrap();
+        run(&mut world, GOD, "@create Lamp").unwrap();
+        let lamp = match_object(&world, GOD, "Lamp").unwrap();
+        let mut put = |target, destination| {
+            let put = Mutation::Move {
+                target,
+                destination,
+            };
+            mutations::apply(&mut world, GOD, put).unwrap();
+        };
+        put(lamp, crate_);
+        put(ladder, attic);
+        put(alice, attic);
+
+        // A thing's contents drop to where it was
+        run(&mut world, GOD, "@destroy Crate").unwrap();
+        assert_eq!(location(&world, lamp), Some(GOD));
+
+        // A room has no location: players fall back to Room Zero, and its
+        // exits go with it
+        run(&mut world, GOD, "@destroy Attic").unwrap();
+        assert_eq!(location(&world, alice), Some(ROOM_ZERO));
+        assert_eq!(component::<ObjectKind>(&world, ladder), None);
+
+        let destroy = Mutation::Destroy { target: ROOM_ZERO };
+        assert_eq!(
+            mutations::apply(&mut world, GOD, destroy),
+            Err(MutationError::RoomZero)
+        );
+    }
+
+    #[test]
+    fn test_chown_needs_a_player() {
+        let (mut world, alice, _) = setup();
+        run(&mut world, GOD, "@create Lamp").unwrap();
+        run(&mut world, GOD, "@create Crate").unwrap();
+        let lamp = match_object(&world, GOD, "Lamp").unwrap();
+        let crate_ = match_object(&world, GOD, "Crate").unwrap();
+        let owner = |world: &World| component::<Owner>(world, lamp).map(|owner| owner.0);
+
+        assert_eq!(
+            run(&mut world, GOD, "@chown Lamp = Crate"),
+            Err(CommandError::Mutation(MutationError::WrongKind(
+                crate_,
+                ObjectKind::Thing
+            )))
+        );
+        assert_eq!(owner(&world), Some(GOD));
+
+        run(&mut world, GOD, "@chown Lamp = Alice").unwrap();
+        assert_eq!(owner(&world), Some(alice));
+    }
+
+    #[test]
+    fn test_wizard_attribute_flag() {
+        let (mut world, alice, _) = setup();
+        run(&mut world, GOD, "&title Alice = Novice").unwrap();
+        run(&mut world, GOD, "@attrflag Alice/title = WIZARD").unwrap();
+
+        // Alice controls herself but cannot change a WIZARD attribute
+        assert!(run(&mut world, alice, "&title me = Archmage").is_err());
+        assert_eq!(
+            attr_value(&world, alice, "title"),
+            Some("Novice".to_string())
+        );
+    }
+
+    #[test]
+    fn test_locked_exit() {
+        let (mut world, alice, bob) = setup();
+        run(&mut world, GOD, "@dig Vault").unwrap();
+        run(&mut world, GOD, "@open Vault Door = Vault").unwrap();
+        run(&mut world, GOD, "@create Brass Key").unwrap();
+        run(&mut world, GOD, "drop Brass Key").unwrap();
+        run(&mut world, GOD, "@lock Vault Door = +Brass Key").unwrap();
+        let vault = match_object(&world, GOD, "Vault").unwrap();
+
+        assert_eq!(
+            run(&mut world, alice, "go Vault Door"),
+            Err(CommandError::Locked("You can't go that way."))
+        );
+
+        // Anyone may pick up the key from the room they stand in
+        run(&mut world, alice, "get Brass Key").unwrap();
+        run(&mut world, alice, "go Vault Door").unwrap();
+        assert_eq!(location(&world, alice), Some(vault));
+        assert!(run(&mut world, bob, "go Vault Door").is_err());
+
+        assert!(matches!(
+            run(&mut world, GOD, "@lock Vault Door = +Nothing"),
+            Err(CommandError::BadLock(_))
+        ));
+
+        // An exit to nowhere is never created
+        assert!(matches!(
+            run(&mut world, GOD, "@open Hole = Brass Key"),
+            Err(CommandError::Mutation(MutationError::WrongKind(
+                _,
+                ObjectKind::Thing
+            )))
+        ));
+        assert!(matches!(
+            run(&mut world, GOD, "@open Hole = #99"),
+            Err(CommandError::Mutation(MutationError::NoSuchObject(_)))
+        ));
+        assert!(match_object(&world, GOD, "Hole").is_err());
+    }
+
+    /// Lets only Alice (#2) through
+    struct AliceOnly;
+
+    impl LockScripts for AliceOnly {
+        fn call(&self, job: &ScriptJob) -> Answer<bool> {
+            Box::pin(std::future::ready(Ok(job.args == ["#2"])))
+        }
+    }
+
+    #[test]
+    fn test_script_lock() {
+        let (mut world, alice, bob) = setup();
+        run(&mut world, GOD, "@create Lever").unwrap();
+        run(&mut world, GOD, "drop Lever").unwrap();
+        run(
+            &mut world,
+            GOD,
+            "@script Lever = fn may_pull(actor) { true }",
+        )
+        .unwrap();
+        run(&mut world, GOD, "@lock/use Lever = $may_pull").unwrap();
+
+        // Fails closed without the executor
+        let locked = Err(CommandError::Locked("You can't use that."));
+        assert_eq!(run(&mut world, alice, "use Lever"), locked);
+
+        world.insert_resource(LockScriptHook(Box::new(AliceOnly)));
+        assert_eq!(
+            run(&mut world, alice, "use Lever").unwrap(),
+            "You use Lever."
+        );
+        assert_eq!(run(&mut world, bob, "use Lever"), locked);
+    }
+
+    #[test]
+    fn test_movement_stays_local() {
+        let (mut world, alice, bob) = setup();
+        run(&mut world, GOD, "@dig Attic").unwrap();
+        run(&mut world, GOD, "@dig Cellar").unwrap();
+        let attic = match_object(&world, GOD, "Attic").unwrap();
+        run(&mut world, GOD, "@open Trapdoor = Cellar").unwrap();
+        let trapdoor = match_object(&world, GOD, "Trapdoor").unwrap();
+        run(&mut world, GOD, "@create Wardrobe").unwrap();
+        let wardrobe = match_object(&world, GOD, "Wardrobe").unwrap();
+        run(&mut world, GOD, "drop Wardrobe").unwrap();
+
+        // Exits and objects elsewhere can't be reached by dbref
+        run(&mut world, GOD, "@open Ladder = Attic").unwrap();
+        let ladder = match_object(&world, GOD, "Ladder").unwrap();
+        mutations::apply(
+            &mut world,
+            GOD,
+            Mutation::Move {
+                target: ladder,
+                destination: attic,
+            },
+        )
+        .unwrap();
+        assert!(matches!(
+            run(&mut world, alice, &format!("go {}", ladder)),
+            Err(CommandError::NoMatch(_))
+        ));
+        run(&mut world, bob, "go Trapdoor").unwrap();
+        assert!(matches!(
+            run(&mut world, alice, &format!("enter {}", bob)),
+            Err(CommandError::NoMatch(_))
+        ));
+        assert!(matches!(
+            run(&mut world, alice, "enter me"),
+            Err(CommandError::NoMatch(_))
+        ));
+        assert!(matches!(
+            run(&mut world, alice, &format!("enter {}", trapdoor)),
+            Err(CommandError::Mutation(MutationError::WrongKind(
+                _,
+                ObjectKind::Exit
+            )))
+        ));
+        let start = location(&world, alice);
+        run(&mut world, alice, "enter Wardrobe").unwrap();
+        assert_eq!(location(&world, alice), Some(wardrobe));
+        assert_ne!(location(&world, alice), start);
+
+        // Nothing ends up inside itself, however deep
+        let into = |target, destination| Mutation::Move {
+            target,
+            destination,
+        };
+        assert_eq!(
+            mutations::apply(&mut world, GOD, into(wardrobe, alice)),
+            Err(MutationError::ContainmentLoop(wardrobe))
+        );
+        assert_eq!(
+            mutations::apply(&mut world, GOD, into(wardrobe, wardrobe)),
+            Err(MutationError::ContainmentLoop(wardrobe))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let (mut world, alice, _) = setup();
+        assert!(matches!(
+            run(&mut world, alice, "@frobnicate"),
+            Err(CommandError::Unknown(_))
+        ));
+        assert!(matches!(
+            run(&mut world, alice, "@set me"),
+            Err(CommandError::Usage(_))
+        ));
+        assert!(matches!(
+            run(&mut world, alice, "@destroy Nothing"),
+            Err(CommandError::NoMatch(_))
+        ));
+    }
+
+    struct NoLoops;
+
+    impl ScriptValidator for NoLoops {
+        fn validate(&self, source: &str) -> Answer<()> {
+            let valid = match source.contains("loop") {
+                true => Err("loops are not allowed".to_string()),
+                false => Ok(()),
+            };
+            Box::pin(std::future::ready(valid))
+        }
+    }
+
+    #[test]
+    fn test_script_revisions() {
+        let (mut world, alice, bob) = setup();
+        world.insert_resource(ScriptValidatorHook(Box::new(NoLoops)));
+
+        let reply = run(
+            &mut world,
+            alice,
+            "@script me/greeting = fn on_look(actor) { 1 }",
+        )
+        .unwrap();
+        assert_eq!(reply, "Script saved as revision 1.");
+        run(
+            &mut world,
+            alice,
+            "@set me/script = fn on_look(actor) { 2 }",
+        )
+        .unwrap();
+        let err = run(&mut world, alice, "@script me = loop {}").unwrap_err();
+        assert!(matches!(
+            err,
+            CommandError::Mutation(MutationError::InvalidScript(_))
+        ));
+        assert_eq!(
+            attr_value(&world, alice, "script").as_deref(),
+            Some("fn on_look(actor) { 2 }")
+        );
+
+        let diff = run(&mut world, alice, "@script/diff me").unwrap();
+        assert_eq!(
+            diff,
+            "r1 -> r2\n- fn on_look(actor) { 1 }\n+ fn on_look(actor) { 2 }"
+        );
+
+        let reply = run(&mut world, alice, "@script/revert me = 1").unwrap();
+        assert_eq!(reply, "Script saved as revision 3.");
+        assert_eq!(
+            attr_value(&world, alice, "script").as_deref(),
+            Some("fn on_look(actor) { 1 }")
+        );
+        let history = run(&mut world, alice, "@script/history me").unwrap();
+        let lines: Vec<&str> = history.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(
+            lines[0].starts_with("r3 ") && lines[0].ends_with("by Alice(#2): Revert to revision 1")
+        );
+        assert!(lines[2].ends_with(": greeting"));
+
+        assert!(run(&mut world, bob, "@script/history Alice").is_err());
+        assert!(run(&mut world, bob, "@script/revert Alice = 2").is_err());
+        assert!(matches!(
+            run(&mut world, alice, "@script/revert me = 9"),
+            Err(CommandError::Mutation(MutationError::NoSuchRevision(9)))
+        ));
+    }
+
+    struct FixedQuota;
+
+    impl QuotaSource for FixedQuota {
+        fn report(&self, owner: ObjectId) -> Answer<QuotaReport> {
+            Box::pin(std::future::ready(Ok(QuotaReport {
+                owner,
+                ops_used: 1_200,
+                ops_limit: 5_000,
+                wall_ms_used: 250,
+                wall_ms_limit: 10_000,
+                status: QuotaStatus::Throttled {
+                    retry_after_ms: 1_500,
+                },
+            })))
+        }
+    }
+
+    #[test]
+    fn test_quota() {
+        let (mut world, alice, bob) = setup();
+        assert_eq!(
+            run(&mut world, alice, "@quota").unwrap(),
+            "Script quotas are not enabled."
+        );
+
+        world.insert_resource(QuotaSourceHook(Box::new(FixedQuota)));
+        assert_eq!(
+            run(&mut world, alice, "@quota me").unwrap(),
+            "Script quota for Alice(#2), last minute:\n  Operations: 1200 / 5000\n  \
+             Wall time: 0.25s / 10.00s\n  Status: throttled, retry in 2s"
+        );
+        assert!(run(&mut world, bob, "@quota Alice").is_err());
+        assert!(run(&mut world, GOD, "@quota Alice").is_ok());
+    }
+
+    #[test]
+    fn test_timers() {
+        let (mut world, alice, bob) = setup();
+        run(&mut world, GOD, "@set Alice = BUILDER").unwrap();
+        run(&mut world, alice, "@create Clock").unwrap();
+        let clock = match_object(&world, alice, "Clock").unwrap();
+        let now = timers::now_ms();
+        for (function, delay) in [("chime", 90_000), ("tick", 5_000)] {
+            let schedule = Mutation::ScheduleTimer {
+                target: clock,
+                function: function.to_string(),
+                due: now + delay,
+            };
+            mutations::apply(&mut world, clock, schedule).unwrap();
+        }
+        // A script can't set timers on objects it doesn't control
+        let foreign = Mutation::ScheduleTimer {
+            target: alice,
+            function: "tick".to_string(),
+            due: now,
+        };
+        assert!(mutations::apply(&mut world, clock, foreign).is_err());
+
+        assert_eq!(
+            run(&mut world, alice, "@ps").unwrap(),
+            "     2  in 5s       Clock(#4)/tick\n     1  in 1m 30s   Clock(#4)/chime\n2 timers pending."
+        );
+        assert_eq!(run(&mut world, bob, "@ps").unwrap(), "No timers pending.");
+        assert!(run(&mut world, bob, "@ps Clock").is_err());
+        assert!(run(&mut world, bob, "@ps/all").is_err());
+        assert!(run(&mut world, GOD, "@ps/all")
+            .unwrap()
+            .ends_with("2 timers pending."));
+
+        assert!(run(&mut world, bob, "@halt/timer 2").is_err());
+        assert_eq!(
+            run(&mut world, alice, "@halt/timer 2").unwrap(),
+            "Timer 2 cancelled."
+        );
+        assert_eq!(
+            run(&mut world, alice, "@halt/timer 2")
+                .unwrap_err()
+                .to_string(),
+            "No such timer 2."
+        );
+        assert!(run(&mut world, bob, "@halt Clock").is_err());
+        assert_eq!(
+            run(&mut world, alice, "@halt Clock").unwrap(),
+            "Halted: 1 timer cancelled."
+        );
+        assert_eq!(
+            run(&mut world, alice, "@ps Clock").unwrap(),
+            "No timers pending."
+        );
+    }
+
+    /// Pauses once at line 2, then finishes on `continue`
+    struct FakeDebugger(Arc<Mutex<Vec<String>>>);
+
+    impl FakeDebugger {
+        fn paused() -> DebugState {
+            DebugState::Paused {
+                reason: "breakpoint".to_string(),
+                line: 2,
+                column: 5,
+                stack: Vec::new(),
+                scope: vec![DebugVariable {
+                    name: "x".to_string(),
+                    type_name: "i64".to_string(),
+                    value: "1".to_string(),
+                    constant: false,
+                }],
+                breakpoints: Vec::new(),
+            }
+        }
+    }
+
+    impl ScriptDebugger for FakeDebugger {
+        fn start(&self, start: &DebugStart) -> Answer<DebugReply> {
+            let log = format!("start {:?} {:?}", start.job.args, start.breakpoints);
+            self.0.lock().unwrap().push(log);
+            Box::pin(std::future::ready(Ok(DebugReply {
+                session: 7,
+                state: Self::paused(),
+            })))
+        }
+
+        fn command(&self, session: u64, command: &DebugCommand) -> Answer<DebugReply> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{} {:?}", session, command));
+            let state = match command {
+                DebugCommand::Continue => DebugState::Finished {
+                    value: "3".to_string(),
+                },
+                _ => Self::paused(),
+            };
+            Box::pin(std::future::ready(Ok(DebugReply { session, state })))
+        }
+
+        fn abort(&self, session: u64) -> Answer<()> {
+            self.0.lock().unwrap().push(format!("abort {}", session));
+            Box::pin(std::future::ready(Ok(())))
+        }
+    }
+
+    #[test]
+    fn test_debug() {
+        let (mut world, alice, bob) = setup();
+        assert_eq!(
+            run(&mut world, alice, "@debug/step").unwrap(),
+            "Script debugging is not enabled."
+        );
+        let log = Arc::new(Mutex::new(Vec::new()));
+        world.insert_resource(ScriptDebuggerHook(Box::new(FakeDebugger(log.clone()))));
+        run(&mut world, GOD, "@set Alice = BUILDER").unwrap();
+        run(&mut world, alice, "@create Widget").unwrap();
+        run(
+            &mut world,
+            alice,
+            "@script Widget = fn on_use(actor) {\n    let x = 1;\n    x + 2\n}",
+        )
+        .unwrap();
+
+        assert!(run(&mut world, bob, "@debug Widget/on_use").is_err());
+        assert!(run(&mut world, alice, "@debug Widget/on_use = 2, 3x").is_err());
+        assert_eq!(
+            run(&mut world, alice, "@debug Widget/on_use = 2").unwrap(),
+            "Paused at line 2, column 5 (breakpoint).\n    2 |     let x = 1;"
+        );
+        assert_eq!(
+            run(&mut world, alice, "@debug/vars").unwrap(),
+            "  x: i64 = 1"
+        );
+        assert_eq!(
+            run(&mut world, alice, "@debug/break helper").unwrap(),
+            "Breakpoint set at helper()."
+        );
+        assert_eq!(
+            run(&mut world, bob, "@debug/continue").unwrap(),
+            "No debug session."
+        );
+        assert_eq!(
+            run(&mut world, alice, "@debug/continue").unwrap(),
+            "Finished: 3"
+        );
+        assert_eq!(
+            run(&mut world, alice, "@debug/step").unwrap(),
+            "No debug session."
+        );
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                format!("start [\"{}\"] [Line {{ line: 2 }}]", alice),
+                "7 Break { breakpoint: Function { name: \"helper\" } }".to_string(),
+                "7 Continue".to_string(),
+            ]
+        );
+    }
+
+    /// Echoes the job's arguments and sets a timer
+    struct EchoCaller;
+
+    impl ScriptCaller for EchoCaller {
+        fn call(&self, job: &ScriptJob) -> Answer<JobOutput> {
+            Box::pin(std::future::ready(Ok(JobOutput {
+                value: job.args.join("+"),
+                timers: vec![TimerRequest {
+                    function: "later".to_string(),
+                    delay_ms: 2_000,
+                }],
+                trace: ExecutionTrace {
+                    output: vec![
+                        OutputLine {
+                            stream: OutputStream::Print,
+                            text: "hello".to_string(),
+                            line: 0,
+                        },
+                        OutputLine {
+                            stream: OutputStream::Debug,
+                            text: "\"x\"".to_string(),
+                            line: 3,
+                        },
+                    ],
+                    dropped_lines: 0,
+                    ops: 12,
+                    duration_us: 310,
+                    host_calls: job.trace.then(|| {
+                        vec![HostCall {
+                            function: "wait".to_string(),
+                            args: vec!["2".to_string(), "Fn(later)".to_string()],
+                            result: Ok("()".to_string()),
+                        }]
+                    }),
+                },
+            })))
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let (mut world, alice, bob) = setup();
+        world.insert_resource(ScriptCallerHook(Box::new(EchoCaller)));
+        run(&mut world, GOD, "@set Alice = BUILDER").unwrap();
+        run(&mut world, alice, "@create Widget").unwrap();
+        assert_eq!(
+            run(&mut world, alice, "@trace Widget/on_use").unwrap(),
+            "Widget has no script."
+        );
+        run(&mut world, alice, "@script Widget = fn on_use(actor) { 1 }").unwrap();
+
+        assert!(run(&mut world, alice, "@trace Widget").is_err());
+        assert!(run(&mut world, bob, "@trace Widget/on_use").is_err());
+        assert_eq!(
+            run(&mut world, alice, "@trace Widget/on_use").unwrap(),
+            format!(
+                "Widget(#4)/on_use returned {} (12 ops, 0.31ms).\n\
+                 Output:\n  hello\n  [debug, line 3] \"x\"\n\
+                 Effects:\n  timer: later() in 2s\n\
+                 Host calls:\n  wait(2, Fn(later)) -> ()",
+                alice
+            )
+        );
+        assert!(run(&mut world, alice, "@trace Widget/total(1, 2)")
+            .unwrap()
+            .starts_with("Widget(#4)/total returned 1+2 "));
+        assert!(run(&mut world, alice, "@ps Widget")
+            .unwrap()
+            .ends_with("2 timers pending."));
+    }
+}
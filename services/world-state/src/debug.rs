@@ -0,0 +1,238 @@
+//! Script Debug Sessions
+//!
+//! Builders debug an object's script with `@debug`: the run happens on
+//! script-executor, which pauses it at breakpoints and waits for the next
+//! command. World-state only remembers which session belongs to which
+//! player, forwards their commands through the [`ScriptDebuggerHook`] and
+//! renders each [`DebugState`] as text.
+//!
+//! A player has at most one session. Starting another aborts the first.
+//!
+//! # Learning Note
+//! The paused run keeps its thread on script-executor, not in this
+//! service, so a forgotten session never blocks the world tick. The
+//! executor aborts sessions that sit idle for too long.
+
+use bevy::prelude::*;
+use shared::components::ObjectId;
+use shared::scripting::{Breakpoint, DebugCommand, DebugReply, DebugStart, DebugState};
+use std::collections::HashMap;
+
+use crate::scripts::Answer;
+
+/// Runs scripts under the debugger
+pub trait ScriptDebugger: Send + Sync {
+    /// Start a session, answering with its first pause (or how it ended)
+    fn start(&self, start: &DebugStart) -> Answer<DebugReply>;
+
+    /// Send a command to a paused session
+    fn command(&self, session: u64, command: &DebugCommand) -> Answer<DebugReply>;
+
+    /// Abort a session
+    fn abort(&self, session: u64) -> Answer<()>;
+}
+
+/// Where `@debug` sessions run
+#[derive(Resource)]
+pub struct ScriptDebuggerHook(pub Box<dyn ScriptDebugger>);
+
+/// A player's open debug session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveSession {
+    /// Session id on script-executor
+    pub session: u64,
+    /// Object whose script is being debugged
+    pub object: ObjectId,
+    /// Function the session called
+    pub function: String,
+    /// Source being run, for showing the paused line
+    pub source: String,
+    /// Latest state reported
+    pub state: DebugState,
+}
+
+/// Open debug sessions by player
+#[derive(Resource, Debug, Default)]
+pub struct DebugSessions(HashMap<ObjectId, ActiveSession>);
+
+impl DebugSessions {
+    /// The session `player` has open
+    pub fn get(&self, player: ObjectId) -> Option<&ActiveSession> {
+        self.0.get(&player)
+    }
+
+    /// Record a reply for `player`, closing the session once it is over
+    ///
+    /// A reply for a session the player has since replaced is dropped.
+    pub fn update(&mut self, player: ObjectId, reply: DebugReply) {
+        let current = self.0.get(&player).map(|session| session.session);
+        if current != Some(reply.session) {
+            return;
+        }
+        if reply.state.is_over() {
+            self.0.remove(&player);
+        } else if let Some(session) = self.0.get_mut(&player) {
+            session.state = reply.state;
+        }
+    }
+
+    /// Remember a newly started session
+    pub fn insert(&mut self, player: ObjectId, session: ActiveSession) {
+        if !session.state.is_over() {
+            self.0.insert(player, session);
+        }
+    }
+
+    /// Forget `player`'s session
+    pub fn remove(&mut self, player: ObjectId) -> Option<ActiveSession> {
+        self.0.remove(&player)
+    }
+}
+
+/// Parse `12` as a line breakpoint and `name` or `name()` as a function one
+pub fn parse_breakpoint(input: &str) -> Option<Breakpoint> {
+    let input = input.trim();
+    if let Ok(line) = input.parse() {
+        return Some(Breakpoint::Line { line });
+    }
+    let name = input.strip_suffix("()").unwrap_or(input);
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then(|| Breakpoint::Function {
+        name: name.to_string(),
+    })
+}
+
+/// Describe where a session stands, quoting the paused line of `source`
+pub fn describe(state: &DebugState, source: &str) -> String {
+    match state {
+        DebugState::Paused {
+            reason,
+            line,
+            column,
+            ..
+        } => {
+            let mut text = format!("Paused at line {}, column {} ({}).", line, column, reason);
+            if let Some(code) = line.checked_sub(1).and_then(|i| source.lines().nth(i)) {
+                text.push_str(&format!("\n{:>5} | {}", line, code));
+            }
+            text
+        }
+        DebugState::Finished { value } => format!("Finished: {}", value),
+        DebugState::Failed { error } => format!("Failed: {}", error),
+    }
+}
+
+/// The call stack of a paused session, innermost first
+pub fn describe_stack(state: &DebugState) -> String {
+    let DebugState::Paused { stack, .. } = state else {
+        return "Not paused.".to_string();
+    };
+    if stack.is_empty() {
+        return "At top level.".to_string();
+    }
+    let lines: Vec<String> = stack
+        .iter()
+        .enumerate()
+        .map(|(depth, frame)| {
+            let call = format!("  {}: {}({})", depth, frame.function, frame.args.join(", "));
+            match frame.line {
+                0 => call,
+                line => format!("{}, called from line {}", call, line),
+            }
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// The variables in scope of a paused session, plus its breakpoints
+pub fn describe_scope(state: &DebugState) -> String {
+    let DebugState::Paused {
+        scope, breakpoints, ..
+    } = state
+    else {
+        return "Not paused.".to_string();
+    };
+    let mut lines: Vec<String> = scope
+        .iter()
+        .map(|var| {
+            format!(
+                "  {}{}: {} = {}",
+                if var.constant { "const " } else { "" },
+                var.name,
+                var.type_name,
+                var.value
+            )
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push("No variables in scope.".to_string());
+    }
+    if !breakpoints.is_empty() {
+        let set: Vec<String> = breakpoints.iter().map(ToString::to_string).collect();
+        lines.push(format!("Breakpoints: {}", set.join(", ")));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::scripting::{DebugFrame, DebugVariable};
+
+    #[test]
+    fn test_parse_breakpoint() {
+        assert_eq!(parse_breakpoint("12"), Some(Breakpoint::Line { line: 12 }));
+        assert_eq!(
+            parse_breakpoint(" price() "),
+            Some(Breakpoint::Function {
+                name: "price".to_string()
+            })
+        );
+        assert_eq!(parse_breakpoint("2x"), None);
+        assert_eq!(parse_breakpoint(""), None);
+    }
+
+    #[test]
+    fn test_describe() {
+        let state = DebugState::Paused {
+            reason: "breakpoint".to_string(),
+            line: 2,
+            column: 5,
+            stack: vec![
+                DebugFrame {
+                    function: "price".to_string(),
+                    args: vec!["3".to_string()],
+                    line: 2,
+                },
+                DebugFrame {
+                    function: "on_use".to_string(),
+                    args: vec!["\"#2\"".to_string()],
+                    line: 0,
+                },
+            ],
+            scope: vec![DebugVariable {
+                name: "count".to_string(),
+                type_name: "i64".to_string(),
+                value: "3".to_string(),
+                constant: false,
+            }],
+            breakpoints: vec![Breakpoint::Line { line: 2 }],
+        };
+        assert_eq!(
+            describe(&state, "fn on_use(actor) {\n    price(3)\n}"),
+            "Paused at line 2, column 5 (breakpoint).\n    2 |     price(3)"
+        );
+        assert_eq!(
+            describe_stack(&state),
+            "  0: price(3), called from line 2\n  1: on_use(\"#2\")"
+        );
+        assert_eq!(
+            describe_scope(&state),
+            "  count: i64 = 3\nBreakpoints: line 2"
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! Script Libraries
+//!
+//! Any object can serve its script as a library module: setting its
+//! `LIBRARY` attribute to a name such as `lib/combat` lets other scripts
+//! write
+//!
+//! ```text
+//! import "lib/combat" as combat;
+//! combat::attack(actor, 5)
+//! ```
+//!
+//! Library names are unique across the world; setting a name another
+//! object already uses is refused.
+//!
+//! World-state never compiles scripts, so before a job is sent it scans
+//! the source for `import "..."` statements and [`resolve`]s each path to
+//! the library's source, following imports inside libraries too. The
+//! paths go to script-executor with the job as [`ModuleSource`]s.
+//!
+//! - `lib/combat` imports the library's current script
+//! - `lib/combat@3` pins revision 3 from the library's script history, so
+//!   later edits to the library don't change the importing script
+//!
+//! Every library, including ones imported by other libraries, must be
+//! readable by the owner of the script being run: the library's `SCRIPT`
+//! attribute must be VISUAL, or the owner must be able to examine the
+//! library object.
+//!
+//! # Learning Note
+//! The scan only looks for the literal `import "path"` form. An import
+//! whose path is computed at run time can't be found ahead of time, so the
+//! executor reports it as a missing module.
+
+use bevy::prelude::*;
+use shared::components::{Attributes, ObjectId};
+use shared::scripting::ModuleSource;
+use std::fmt;
+
+use crate::locks::SCRIPT_ATTR;
+use crate::objects::component;
+use crate::permissions::{self, Subject};
+use crate::scripts::ScriptHistory;
+
+/// Attribute holding the name an object's script is imported by
+pub const LIBRARY_ATTR: &str = "LIBRARY";
+
+/// Most modules one job may import, counting nested imports
+pub const MAX_MODULES: usize = 16;
+
+/// Longest library name
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Why a script's imports could not be resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    /// The path is not a library name with an optional `@revision`
+    BadPath(String),
+    /// No object serves a library by this name
+    NoSuchLibrary(String),
+    /// The library has no script revision with this number
+    NoSuchRevision(String, u32),
+    /// The library object has no script
+    Empty(String),
+    /// The script's owner may not read the library
+    Denied(String),
+    /// More than [`MAX_MODULES`] modules would be imported
+    TooMany,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadPath(path) => write!(f, "Bad import path \"{}\".", path),
+            Self::NoSuchLibrary(name) => write!(f, "No library named \"{}\".", name),
+            Self::NoSuchRevision(name, revision) => {
+                write!(f, "Library \"{}\" has no revision {}.", name, revision)
+            }
+            Self::Empty(name) => write!(f, "Library \"{}\" has no script.", name),
+            Self::Denied(name) => {
+                write!(f, "Permission denied: can't import library \"{}\".", name)
+            }
+            Self::TooMany => write!(f, "Too many imports (at most {}).", MAX_MODULES),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// True if `name` may be used as a library name
+///
+/// Names are lowercase letters, digits, `_` and `-`, in `/`-separated
+/// segments: `combat`, `lib/combat`, `alice/dice-v2`.
+pub fn valid_name(name: &str) -> bool {
+    name.len() <= MAX_NAME_LEN
+        && name.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+        })
+}
+
+/// The object serving the library `name`, if any
+pub fn find(world: &World, name: &str) -> Option<ObjectId> {
+    world
+        .iter_entities()
+        .filter(|entity| {
+            entity
+                .get::<Attributes>()
+                .and_then(|attrs| attrs.get(LIBRARY_ATTR))
+                .is_some_and(|attr| attr.value == name)
+        })
+        .filter_map(|entity| entity.get::<ObjectId>().copied())
+        .min()
+}
+
+/// Resolve every module a script owned by `owner` imports
+///
+/// Returns an empty list for a script without imports.
+pub fn resolve(
+    world: &World,
+    owner: ObjectId,
+    source: &str,
+) -> Result<Vec<ModuleSource>, ImportError> {
+    let mut pending = imports(source);
+    if pending.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(reader) = Subject::load(world, owner) else {
+        return Err(ImportError::Denied(pending.remove(0)));
+    };
+
+    let mut modules: Vec<ModuleSource> = Vec::new();
+    pending.reverse();
+    while let Some(path) = pending.pop() {
+        if modules.iter().any(|module| module.path == path) {
+            continue;
+        }
+        if modules.len() >= MAX_MODULES {
+            return Err(ImportError::TooMany);
+        }
+        let module = load(world, &reader, &path)?;
+        pending.extend(imports(&module.source).into_iter().rev());
+        modules.push(module);
+    }
+    Ok(modules)
+}
+
+/// Look up one import path on behalf of `reader`
+fn load(world: &World, reader: &Subject, path: &str) -> Result<ModuleSource, ImportError> {
+    let (name, pin) = match path.split_once('@') {
+        Some((name, revision)) => {
+            let revision = revision
+                .parse()
+                .map_err(|_| ImportError::BadPath(path.to_string()))?;
+            (name, Some(revision))
+        }
+        None => (path, None),
+    };
+    if !valid_name(name) {
+        return Err(ImportError::BadPath(path.to_string()));
+    }
+    let object = find(world, name).ok_or_else(|| ImportError::NoSuchLibrary(name.to_string()))?;
+    let library =
+        Subject::load(world, object).ok_or_else(|| ImportError::NoSuchLibrary(name.to_string()))?;
+    let script = component::<Attributes>(world, object).and_then(|attrs| attrs.get(SCRIPT_ATTR));
+    let readable = match script {
+        Some(script) => permissions::can_read_attr(reader, &library, script),
+        None => permissions::can_examine(reader, &library),
+    };
+    if !readable {
+        return Err(ImportError::Denied(name.to_string()));
+    }
+
+    let history = component::<ScriptHistory>(world, object);
+    let (revision, source) = match pin {
+        Some(number) => history
+            .and_then(|history| history.get(number))
+            .map(|revision| (number, revision.source.clone()))
+            .ok_or_else(|| ImportError::NoSuchRevision(name.to_string(), number))?,
+        None => {
+            let script = script.ok_or_else(|| ImportError::Empty(name.to_string()))?;
+            let number = history
+                .and_then(ScriptHistory::latest)
+                .map_or(0, |revision| revision.number);
+            (number, script.value.clone())
+        }
+    };
+    Ok(ModuleSource {
+        path: path.to_string(),
+        object,
+        revision,
+        source,
+    })
+}
+
+/// Paths of the `import "..."` statements in `source`, in order
+///
+/// Skips comments and the contents of other string literals, so a
+/// commented-out import is not resolved.
+pub fn imports(source: &str) -> Vec<String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut paths = Vec::new();
+    let mut after_import = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+        } else if matches!(c, '"' | '`' | '\'') {
+            let mut text = String::new();
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                if let Some(&ch) = chars.get(i) {
+                    text.push(ch);
+                }
+                i += 1;
+            }
+            i += 1;
+            if after_import && c == '"' {
+                paths.push(text);
+            }
+            after_import = false;
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            after_import = chars[start..i].iter().collect::<String>() == "import";
+        } else {
+            if !c.is_whitespace() {
+                after_import = false;
+            }
+            i += 1;
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands;
+    use crate::mutations::{self, Mutation, MutationError};
+    use crate::objects::{bootstrap, GOD};
+    use shared::components::ObjectKind;
+
+    #[test]
+    fn test_imports() {
+        let source = r#"
+            import "lib/combat" as combat;
+            // import "lib/old" as old;
+            /* import "lib/older" */
+            let s = "import \"not/this\"";
+            import   "alice/dice@2" as dice;
+        "#;
+        assert_eq!(imports(source), vec!["lib/combat", "alice/dice@2"]);
+        assert!(valid_name("lib/dice-v2"));
+        assert!(!valid_name("Lib/combat"));
+        assert!(!valid_name("lib//combat"));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        let mut player = |name: &str| {
+            let create = Mutation::Create {
+                kind: ObjectKind::Player,
+                name: name.to_string(),
+            };
+            mutations::apply(&mut world, GOD, create).unwrap()
+        };
+        let alice = player("Alice");
+        let bob = player("Bob");
+        commands::run(&mut world, GOD, "@set Alice = BUILDER").unwrap();
+        commands::run(&mut world, GOD, "@set Bob = BUILDER").unwrap();
+        for command in [
+            "@create Dice",
+            "@script Dice = fn roll() { 4 }",
+            "@script Dice = fn roll() { 6 }",
+            "@set Dice/library = lib/dice",
+            "@create Combat",
+            "@script Combat = import \"lib/dice@1\" as dice; fn attack() { dice::roll() }",
+            "@set Combat/library = lib/combat",
+        ] {
+            commands::run(&mut world, alice, command).unwrap();
+        }
+        commands::run(&mut world, bob, "@create Fake").unwrap();
+        assert_eq!(
+            commands::run(&mut world, bob, "@set Fake/library = lib/dice"),
+            Err(MutationError::LibraryTaken("lib/dice".to_string(), ObjectId(4)).into())
+        );
+        assert_eq!(
+            commands::run(&mut world, bob, "@set Fake/library = Lib Dice"),
+            Err(MutationError::BadLibraryName("Lib Dice".to_string()).into())
+        );
+
+        let script = "import \"lib/combat\" as c; import \"lib/dice\" as d;";
+        let modules: Vec<_> = resolve(&world, alice, script)
+            .unwrap()
+            .into_iter()
+            .map(|module| (module.path, module.object, module.revision))
+            .collect();
+        assert_eq!(
+            modules,
+            vec![
+                ("lib/combat".to_string(), ObjectId(5), 1),
+                ("lib/dice@1".to_string(), ObjectId(4), 1),
+                ("lib/dice".to_string(), ObjectId(4), 2),
+            ]
+        );
+        assert_eq!(
+            resolve(&world, bob, script),
+            Err(ImportError::Denied("lib/combat".to_string()))
+        );
+        commands::run(&mut world, alice, "@set Combat = VISUAL").unwrap();
+        commands::run(&mut world, alice, "@set Dice = VISUAL").unwrap();
+        assert_eq!(resolve(&world, bob, script).unwrap().len(), 3);
+        assert_eq!(
+            resolve(&world, alice, "import \"lib/dice@9\" as d;"),
+            Err(ImportError::NoSuchRevision("lib/dice".to_string(), 9))
+        );
+        assert_eq!(
+            resolve(&world, alice, "import \"lib/nope\" as n;"),
+            Err(ImportError::NoSuchLibrary("lib/nope".to_string()))
+        );
+    }
+}
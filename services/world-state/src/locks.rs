@@ -0,0 +1,384 @@
+//! Lock Evaluation
+//!
+//! Evaluates the [`LockExpr`]s stored on objects against the object trying
+//! to use, enter, pass through or pick them up. Parsing lives in
+//! `shared::locks`; this module only needs read access to the world.
+//!
+//! `$function` keys run a function from the locked object's `SCRIPT`
+//! attribute. World-state never runs scripts itself, so those calls go
+//! through the [`LockScriptHook`] resource, which is backed by the
+//! script executor. The function gets the actor's dbref and the lock
+//! passes if it returns `true`. Without a hook, script locks fail closed.
+//!
+//! Evaluation never waits on the executor. [`script_calls`] lists the
+//! functions a check needs, [`ask`] gets their answers off the main loop
+//! and [`passes`] is then given them as [`ScriptAnswers`].
+
+use bevy::prelude::*;
+use shared::components::{Attributes, Flags, LockKind, Locks, ObjectId};
+use shared::locks::{glob_match, LockExpr};
+use shared::scripting::{JobPriority, ScriptJob};
+use std::collections::HashMap;
+use std::future::Future;
+use tracing::warn;
+
+use crate::objects::{component, location};
+use crate::scripts::Answer;
+use crate::timers;
+
+/// Maximum nesting of `@indirect` locks before evaluation gives up
+pub const MAX_LOCK_DEPTH: u32 = 10;
+
+/// Attribute holding an object's script source
+pub const SCRIPT_ATTR: &str = "SCRIPT";
+
+/// An action guarded by a lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockAction {
+    /// Using an object
+    Use,
+    /// Entering an object
+    Enter,
+    /// Passing through an exit
+    Pass,
+    /// Picking up a thing
+    Pickup,
+}
+
+impl LockAction {
+    /// The lock that guards this action
+    pub fn lock_kind(self) -> LockKind {
+        match self {
+            Self::Use => LockKind::Use,
+            Self::Enter => LockKind::Enter,
+            Self::Pass | Self::Pickup => LockKind::Basic,
+        }
+    }
+}
+
+/// Runs `$function` lock keys in the script executor
+pub trait LockScripts: Send + Sync {
+    /// Run `job`, a call to a lock function, answering whether it passes
+    fn call(&self, job: &ScriptJob) -> Answer<bool>;
+}
+
+/// The installed script bridge for `$function` locks
+#[derive(Resource)]
+pub struct LockScriptHook(pub Box<dyn LockScripts>);
+
+/// What each `$function` lock call returned, by locked object and
+/// function
+///
+/// A call with no answer fails.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptAnswers(HashMap<(ObjectId, String), bool>);
+
+impl ScriptAnswers {
+    /// Record whether `function` on `locked` passed
+    pub fn insert(&mut self, locked: ObjectId, function: &str, passed: bool) {
+        self.0.insert((locked, function.to_string()), passed);
+    }
+
+    fn get(&self, locked: ObjectId, function: &str) -> bool {
+        self.0
+            .get(&(locked, function.to_string()))
+            .copied()
+            .unwrap_or(false)
+    }
+}
+
+/// True if `actor` may perform `action` on `target`
+///
+/// Missing locks always pass, and wizards pass every lock. `$function`
+/// keys are looked up in `answers`.
+pub fn passes(
+    world: &World,
+    actor: ObjectId,
+    target: ObjectId,
+    action: LockAction,
+    answers: &ScriptAnswers,
+) -> bool {
+    if object_flags(world, actor).contains(Flags::WIZARD) {
+        return true;
+    }
+    check_lock(world, actor, target, action.lock_kind(), 0, answers)
+}
+
+/// The `$function` calls [`passes`] needs answered, as locked object and
+/// function
+pub fn script_calls(
+    world: &World,
+    actor: ObjectId,
+    target: ObjectId,
+    action: LockAction,
+) -> Vec<(ObjectId, String)> {
+    let mut calls = Vec::new();
+    if !object_flags(world, actor).contains(Flags::WIZARD) {
+        collect_calls(world, target, action.lock_kind(), 0, &mut calls);
+    }
+    calls
+}
+
+/// Add the `$function` keys of one lock of `target` to `calls`, following
+/// `@indirect` keys as [`eval`] does
+fn collect_calls(
+    world: &World,
+    target: ObjectId,
+    kind: LockKind,
+    depth: u32,
+    calls: &mut Vec<(ObjectId, String)>,
+) {
+    let Some(expr) = component::<Locks>(world, target).and_then(|locks| locks.0.get(&kind)) else {
+        return;
+    };
+    let mut pending = vec![expr];
+    while let Some(expr) = pending.pop() {
+        match expr {
+            LockExpr::Eval(function) => {
+                let call = (target, function.clone());
+                if !calls.contains(&call) {
+                    calls.push(call);
+                }
+            }
+            LockExpr::Indirect(id) if depth < MAX_LOCK_DEPTH => {
+                collect_calls(world, *id, LockKind::Basic, depth + 1, calls);
+            }
+            LockExpr::Not(inner) => pending.push(inner),
+            LockExpr::And(a, b) | LockExpr::Or(a, b) => pending.extend([&**b, &**a]),
+            _ => {}
+        }
+    }
+}
+
+/// Ask the [`LockScriptHook`] for the answers to `calls` made by `actor`
+///
+/// The future owns everything it needs, so it can be awaited off the main
+/// loop. `None` without a hook: every call then fails.
+pub fn ask(
+    world: &World,
+    actor: ObjectId,
+    calls: &[(ObjectId, String)],
+) -> Option<impl Future<Output = ScriptAnswers> + Send + 'static> {
+    let Some(hook) = world.get_resource::<LockScriptHook>() else {
+        warn!("No script hook installed; $function locks fail");
+        return None;
+    };
+    let asked: Vec<_> = calls
+        .iter()
+        .map(|(locked, function)| {
+            let job = timers::background_job(world, *locked, function, vec![actor.to_string()]);
+            let answer = job.map(|job| {
+                hook.0.call(&ScriptJob {
+                    priority: JobPriority::Interactive,
+                    ..job
+                })
+            });
+            (*locked, function.clone(), answer)
+        })
+        .collect();
+    Some(async move {
+        let mut answers = ScriptAnswers::default();
+        for (locked, function, answer) in asked {
+            // No job means no script to run, or one that can't be
+            let passed = match answer {
+                Some(answer) => answer.await.unwrap_or_else(|e| {
+                    warn!("${} lock on {} failed: {}", function, locked, e);
+                    false
+                }),
+                None => false,
+            };
+            answers.insert(locked, &function, passed);
+        }
+        answers
+    })
+}
+
+/// Evaluate one lock of `target`, treating a missing lock as unlocked
+fn check_lock(
+    world: &World,
+    actor: ObjectId,
+    target: ObjectId,
+    kind: LockKind,
+    depth: u32,
+    answers: &ScriptAnswers,
+) -> bool {
+    let Some(locks) = component::<Locks>(world, target) else {
+        return true;
+    };
+    match locks.0.get(&kind) {
+        Some(expr) => eval(world, expr, actor, target, depth, answers),
+        None => true,
+    }
+}
+
+/// Evaluate a lock expression for `actor` on the object `locked`
+pub fn eval(
+    world: &World,
+    expr: &LockExpr,
+    actor: ObjectId,
+    locked: ObjectId,
+    depth: u32,
+    answers: &ScriptAnswers,
+) -> bool {
+    match expr {
+        LockExpr::Is(id) => actor == *id,
+        LockExpr::Carries(id) => carries(world, actor, *id),
+        LockExpr::IsOrCarries(id) => actor == *id || carries(world, actor, *id),
+        LockExpr::Indirect(id) => {
+            if depth >= MAX_LOCK_DEPTH {
+                warn!("Lock on {} exceeded indirection depth", locked);
+                return false;
+            }
+            check_lock(world, actor, *id, LockKind::Basic, depth + 1, answers)
+        }
+        LockExpr::Eval(function) => answers.get(locked, function),
+        LockExpr::Attr { name, pattern } => component::<Attributes>(world, actor)
+            .and_then(|attrs| attrs.get(name))
+            .is_some_and(|attr| glob_match(pattern, &attr.value)),
+        LockExpr::Not(inner) => !eval(world, inner, actor, locked, depth, answers),
+        LockExpr::And(a, b) => {
+            eval(world, a, actor, locked, depth, answers)
+                && eval(world, b, actor, locked, depth, answers)
+        }
+        LockExpr::Or(a, b) => {
+            eval(world, a, actor, locked, depth, answers)
+                || eval(world, b, actor, locked, depth, answers)
+        }
+    }
+}
+
+/// True if `item` is directly inside `holder`
+fn carries(world: &World, holder: ObjectId, item: ObjectId) -> bool {
+    location(world, item) == Some(holder)
+}
+
+/// Flags of an object, or none if it does not exist
+fn object_flags(world: &World, id: ObjectId) -> Flags {
+    component::<Flags>(world, id).copied().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{bootstrap, spawn_object, ObjectRegistry, GOD};
+    use shared::components::{Attribute, Location, ObjectKind};
+
+    struct FakeScripts;
+
+    impl LockScripts for FakeScripts {
+        fn call(&self, job: &ScriptJob) -> Answer<bool> {
+            assert_eq!(job.source, "fn is_member(who) { true }");
+            let passed = job.function.as_deref() == Some("is_member")
+                && job.args == [ObjectId(2).to_string()];
+            Box::pin(std::future::ready(Ok(passed)))
+        }
+    }
+
+    /// Check a lock with no `$function` answers
+    fn check(world: &World, actor: ObjectId, target: ObjectId, action: LockAction) -> bool {
+        passes(world, actor, target, action, &ScriptAnswers::default())
+    }
+
+    fn lock(world: &mut World, target: ObjectId, kind: LockKind, key: &str) {
+        let entity = world.resource::<ObjectRegistry>().entity(target).unwrap();
+        let expr = LockExpr::parse_canonical(key).unwrap();
+        world.get_mut::<Locks>(entity).unwrap().0.insert(kind, expr);
+    }
+
+    fn set_attr(world: &mut World, target: ObjectId, name: &str, value: &str) {
+        let entity = world.resource::<ObjectRegistry>().entity(target).unwrap();
+        let attr = Attribute {
+            value: value.to_string(),
+            owner: GOD,
+            flags: Default::default(),
+        };
+        world.get_mut::<Attributes>(entity).unwrap().set(name, attr);
+    }
+
+    fn setup() -> (World, ObjectId, ObjectId, ObjectId) {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        let alice = spawn_object(&mut world, ObjectKind::Player, "Alice", None);
+        let bob = spawn_object(&mut world, ObjectKind::Player, "Bob", None);
+        let door = spawn_object(&mut world, ObjectKind::Exit, "Door", Some(GOD));
+        (world, alice, bob, door)
+    }
+
+    #[test]
+    fn test_key_holder_passes() {
+        let (mut world, alice, bob, door) = setup();
+        let key = spawn_object(&mut world, ObjectKind::Thing, "Key", Some(alice));
+        lock(&mut world, door, LockKind::Basic, &format!("+{}", key));
+
+        assert!(!check(&world, alice, door, LockAction::Pass));
+
+        let key_entity = world.resource::<ObjectRegistry>().entity(key).unwrap();
+        world.entity_mut(key_entity).insert(Location(alice));
+        assert!(check(&world, alice, door, LockAction::Pass));
+        assert!(!check(&world, bob, door, LockAction::Pass));
+        // Other lock kinds are unaffected
+        assert!(check(&world, bob, door, LockAction::Use));
+        // Wizards pass every lock
+        assert!(check(&world, GOD, door, LockAction::Pass));
+    }
+
+    #[test]
+    fn test_attribute_and_indirect_locks() {
+        let (mut world, alice, bob, door) = setup();
+        let guild_hall = spawn_object(&mut world, ObjectKind::Room, "Hall", Some(GOD));
+        lock(
+            &mut world,
+            guild_hall,
+            LockKind::Basic,
+            "guild:thie* & !rank:novice",
+        );
+        lock(
+            &mut world,
+            door,
+            LockKind::Enter,
+            &format!("@{}", guild_hall),
+        );
+
+        set_attr(&mut world, alice, "guild", "Thieves");
+        set_attr(&mut world, bob, "guild", "Thieves");
+        set_attr(&mut world, bob, "rank", "Novice");
+
+        assert!(check(&world, alice, door, LockAction::Enter));
+        assert!(!check(&world, bob, door, LockAction::Enter));
+    }
+
+    #[test]
+    fn test_indirect_cycle_fails() {
+        let (mut world, alice, _, door) = setup();
+        lock(&mut world, door, LockKind::Basic, &format!("@{}", door));
+        assert!(!check(&world, alice, door, LockAction::Pass));
+    }
+
+    #[tokio::test]
+    async fn test_script_locks() {
+        let (mut world, alice, bob, door) = setup();
+        lock(&mut world, door, LockKind::Use, "$is_member & !$is_banned");
+        set_attr(&mut world, door, SCRIPT_ATTR, "fn is_member(who) { true }");
+
+        let calls = script_calls(&world, alice, door, LockAction::Use);
+        let named = |function: &str| (door, function.to_string());
+        assert_eq!(calls, [named("is_member"), named("is_banned")]);
+        assert!(script_calls(&world, GOD, door, LockAction::Use).is_empty());
+
+        // Unanswered calls fail closed, as does asking without a hook
+        assert!(!check(&world, alice, door, LockAction::Use));
+        assert!(ask(&world, alice, &calls).is_none());
+        let mut answers = ScriptAnswers::default();
+        answers.insert(door, "is_member", true);
+        assert!(passes(&world, alice, door, LockAction::Use, &answers));
+        answers.insert(door, "is_banned", true);
+        assert!(!passes(&world, alice, door, LockAction::Use, &answers));
+
+        world.insert_resource(LockScriptHook(Box::new(FakeScripts)));
+        let answers = ask(&world, alice, &calls).unwrap().await;
+        assert!(passes(&world, alice, door, LockAction::Use, &answers));
+        let calls = script_calls(&world, bob, door, LockAction::Use);
+        let answers = ask(&world, bob, &calls).unwrap().await;
+        assert!(!passes(&world, bob, door, LockAction::Use, &answers));
+    }
+}
@@ -5,9 +5,28 @@
 //! gRPC and distributes events to subscribers.
 
 use axum::{routing::get, Router};
+use bevy::prelude::*;
 use std::net::SocketAddr;
+use tokio::sync::mpsc;
 use tracing::{info, warn};
 
+mod api;
+mod changes;
+mod collisions;
+mod commands;
+mod debug;
+mod libraries;
+mod locks;
+mod mutations;
+mod objects;
+mod permissions;
+mod persistence;
+mod physics;
+mod scripts;
+mod spatial;
+mod stats;
+mod timers;
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize tracing
@@ -23,13 +42,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Load environment variables
     dotenvy::dotenv().ok();
 
-    // Health check server
+    // Authoritative ECS world, restored from persistence-service when it
+    // has one stored. A store that can't be reached stops the service:
+    // bootstrapping instead would flush a fresh world over the stored one.
+    let persistence_url =
+        std::env::var("PERSISTENCE_URL").unwrap_or_else(|_| "http://localhost:8087".to_string());
+    let persistence = persistence::HttpSink::new(&persistence_url);
+    let mut world = World::new();
+    let records = persistence
+        .load_world_retrying(persistence::RETRY_BACKOFF)
+        .await
+        .map_err(|e| format!("could not load the stored world: {}", e))?;
+    if records.is_empty() {
+        objects::bootstrap(&mut world);
+    } else {
+        persistence::restore(&mut world, &records)?;
+    }
+    let executor_url = std::env::var("SCRIPT_EXECUTOR_URL")
+        .unwrap_or_else(|_| "http://localhost:8081".to_string());
+    let executor = scripts::ExecutorClient::new(&executor_url);
+    world.insert_resource(scripts::ScriptValidatorHook(Box::new(executor.clone())));
+    world.insert_resource(scripts::QuotaSourceHook(Box::new(executor.clone())));
+    world.insert_resource(scripts::ScriptCallerHook(Box::new(executor.clone())));
+    world.insert_resource(debug::ScriptDebuggerHook(Box::new(executor.clone())));
+    world.insert_resource(locks::LockScriptHook(Box::new(executor.clone())));
+    info!(
+        "World loaded with {} objects",
+        world.resource::<objects::ObjectRegistry>().len()
+    );
+
+    // Health checks, plus the API the gateways send player commands to
+    let (api_tx, mut api_rx) = mpsc::channel(api::REQUEST_QUEUE);
     let health_app = Router::new()
         .route("/health", get(health_check))
-        .route("/ready", get(readiness_check));
+        .route("/ready", get(readiness_check))
+        .merge(api::router(api_tx));
 
     let health_addr = SocketAddr::from(([0, 0, 0, 0], 8080));
-    info!("Health check server listening on {}", health_addr);
+    info!("HTTP server listening on {}", health_addr);
 
     tokio::spawn(async move {
         if let Err(e) = axum::serve(
@@ -42,12 +92,64 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
+    // Changed objects are written behind to persistence-service
+    let (writer, writer_task) = persistence::spawn_writer(persistence, persistence::RETRY_BACKOFF);
+    let mut flush_timer = tokio::time::interval(persistence::FLUSH_INTERVAL);
+
+    // Script timers fire on the world tick and run on script-executor
+    let (mut dispatcher, _dispatcher_task) = timers::spawn_dispatcher(executor);
+    let mut tick_timer = tokio::time::interval(timers::TICK_INTERVAL);
+
+    // Commands waiting on script-executor finish when its answers arrive
+    let mut waiting = api::Waiting::default();
+
+    // Bodies are simulated by physics-service, which streams back where
+    // they went
+    let physics_url =
+        std::env::var("PHYSICS_URL").unwrap_or_else(|_| "http://localhost:50055".to_string());
+    let (physics_link, mut physics_rx, _physics_task) = physics::spawn_link(physics_url);
+    tick_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
     info!("✅ World State Service ready");
 
     // Keep the service running
-    tokio::signal::ctrl_c().await?;
+    let shutdown = tokio::signal::ctrl_c();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            due = tick_timer.tick() => {
+                stats::record(&mut world, due.elapsed());
+                spatial::refresh(&mut world);
+                timers::tick(&mut world, &mut dispatcher, timers::now_ms());
+                if let Some(batch) = physics::sync(&mut world) {
+                    physics_link.send(batch);
+                }
+            }
+            Some(event) = physics_rx.recv() => {
+                let contacts = physics::receive(&mut world, event);
+                collisions::dispatch(&mut world, &dispatcher, contacts, timers::now_ms());
+            }
+            _ = flush_timer.tick() => persistence::flush(&mut world, &writer),
+            Some(request) = api_rx.recv() => api::handle(&mut world, &mut waiting, request),
+            Some(answered) = waiting.answered() => api::resume(&mut world, &mut waiting, answered),
+            result = &mut shutdown => {
+                result?;
+                break;
+            }
+        }
+    }
     info!("Shutting down World State Service");
 
+    // Final flush; dropping the sender lets the writer exit once drained
+    persistence::flush(&mut world, &writer);
+    drop(writer);
+    if tokio::time::timeout(persistence::SHUTDOWN_TIMEOUT, writer_task)
+        .await
+        .is_err()
+    {
+        warn!("Persistence writer did not drain before shutdown; recent changes may be lost");
+    }
+
     Ok(())
 }
 
@@ -0,0 +1,544 @@
+//! World Mutations
+//!
+//! Every change to world objects goes through [`apply`], whether it comes
+//! from a builder command or from an effect emitted by a script. `apply`
+//! loads the acting object's privileges, runs the matching permission
+//! check and only then touches the ECS world.
+//!
+//! Scripts act with the privileges of the object they are attached to, so
+//! a script effect is applied with that object's id as the actor.
+
+use bevy::prelude::*;
+use shared::components::{
+    AttrFlags, Attribute, Attributes, Destination, Flags, Location, LockKind, Locks, ObjectId,
+    ObjectKind, Owner,
+};
+use shared::locks::LockExpr;
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::libraries::{self, LIBRARY_ATTR};
+use crate::locks::SCRIPT_ATTR;
+use crate::objects::{component, location, spawn_object, ObjectRegistry, ROOM_ZERO};
+use crate::permissions::{self, PermissionError, Subject};
+use crate::scripts::ScriptHistory;
+use crate::timers::{self, ScriptTimer, ScriptTimers, TimerIds};
+
+/// A requested change to the world
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mutation {
+    /// Set an attribute value, creating the attribute if needed
+    SetAttr {
+        /// Object to change
+        target: ObjectId,
+        /// Attribute name
+        name: String,
+        /// New value
+        value: String,
+    },
+    /// Remove an attribute
+    ClearAttr {
+        /// Object to change
+        target: ObjectId,
+        /// Attribute name
+        name: String,
+    },
+    /// Set or clear flags on an attribute
+    SetAttrFlags {
+        /// Object to change
+        target: ObjectId,
+        /// Attribute name
+        name: String,
+        /// Flags to change
+        flags: AttrFlags,
+        /// `true` to set, `false` to clear
+        on: bool,
+    },
+    /// Set or clear object flags
+    SetFlags {
+        /// Object to change
+        target: ObjectId,
+        /// Flags to change
+        flags: Flags,
+        /// `true` to set, `false` to clear
+        on: bool,
+    },
+    /// Set (`Some`) or remove (`None`) a lock
+    SetLock {
+        /// Object to change
+        target: ObjectId,
+        /// Which lock
+        kind: LockKind,
+        /// Parsed lock key
+        key: Option<LockExpr>,
+    },
+    /// Give an object to a new owner
+    Chown {
+        /// Object to change
+        target: ObjectId,
+        /// New owner, which must be a player
+        owner: ObjectId,
+    },
+    /// Move an object into another (a room, player or container)
+    Move {
+        /// Object to move
+        target: ObjectId,
+        /// New location
+        destination: ObjectId,
+    },
+    /// Point an exit at a room
+    Link {
+        /// Exit to change
+        target: ObjectId,
+        /// Room the exit leads to
+        destination: ObjectId,
+    },
+    /// Create a new object owned by the actor's owner
+    ///
+    /// Things start in the actor's inventory, exits in the actor's location
+    /// and players in Room Zero. Rooms have no location.
+    Create {
+        /// Kind of object
+        kind: ObjectKind,
+        /// Display name
+        name: String,
+    },
+    /// Destroy an object
+    ///
+    /// What it held drops out to where it was, or to Room Zero if it had
+    /// no location (a room). A room's exits are destroyed with it.
+    Destroy {
+        /// Object to remove
+        target: ObjectId,
+    },
+    /// Replace an object's script, recording a new revision
+    ///
+    /// Setting or clearing the `SCRIPT` attribute directly does the same
+    /// with an empty message. The source is not validated here: that
+    /// means asking script-executor, which commands do before applying.
+    SetScript {
+        /// Object to change
+        target: ObjectId,
+        /// New source; empty clears the script
+        source: String,
+        /// Description of the change
+        message: String,
+    },
+    /// Make an earlier script revision current again, as a new revision
+    RevertScript {
+        /// Object to change
+        target: ObjectId,
+        /// Revision to copy
+        revision: u32,
+    },
+    /// Call a function in the object's script at a later time
+    ScheduleTimer {
+        /// Object whose script to call
+        target: ObjectId,
+        /// Function to call
+        function: String,
+        /// When to call it, in Unix milliseconds
+        due: i64,
+    },
+    /// Cancel one pending timer (`Some`) or all of them (`None`)
+    CancelTimers {
+        /// Object the timers are pending on
+        target: ObjectId,
+        /// Timer to cancel
+        timer: Option<u64>,
+    },
+}
+
+/// Why a mutation was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MutationError {
+    /// The actor or target does not exist
+    NoSuchObject(ObjectId),
+    /// The named attribute does not exist on the target
+    NoSuchAttribute(String),
+    /// The object is the wrong kind for this change
+    WrongKind(ObjectId, ObjectKind),
+    /// The move would put the object inside itself
+    ContainmentLoop(ObjectId),
+    /// The actor lacks the required privileges
+    Denied(PermissionError),
+    /// The object has no script revision with this number
+    NoSuchRevision(u32),
+    /// The script validator rejected new source; the old script stays live
+    InvalidScript(String),
+    /// The object has no pending timer with this id
+    NoSuchTimer(u64),
+    /// The object already has as many timers as it may
+    TooManyTimers(ObjectId),
+    /// The object is HALT and may not start scripts
+    Halted(ObjectId),
+    /// The value is not a valid library name
+    BadLibraryName(String),
+    /// Another object already serves a library by this name
+    LibraryTaken(String, ObjectId),
+    /// Room Zero is where destroyed rooms' contents go, so it stays
+    RoomZero,
+}
+
+impl fmt::Display for MutationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSuchObject(id) => write!(f, "No such object {}.", id),
+            Self::NoSuchAttribute(name) => write!(f, "No such attribute {}.", name),
+            Self::WrongKind(id, kind) => write!(f, "{} is a {:?}; that won't work.", id, kind),
+            Self::ContainmentLoop(id) => write!(f, "{} can't be put inside itself.", id),
+            Self::Denied(reason) => write!(f, "{}", reason),
+            Self::NoSuchRevision(number) => write!(f, "No such script revision {}.", number),
+            Self::InvalidScript(error) => {
+                write!(
+                    f,
+                    "Script rejected, keeping the previous revision: {}",
+                    error
+                )
+            }
+            Self::NoSuchTimer(id) => write!(f, "No such timer {}.", id),
+            Self::TooManyTimers(id) => write!(
+                f,
+                "{} already has {} timers pending.",
+                id,
+                timers::MAX_TIMERS_PER_OBJECT
+            ),
+            Self::Halted(id) => write!(f, "{} is halted.", id),
+            Self::BadLibraryName(name) => write!(
+                f,
+                "\"{}\" is not a valid library name; use lowercase words separated by /.",
+                name
+            ),
+            Self::LibraryTaken(name, id) => {
+                write!(f, "Library name \"{}\" is already used by {}.", name, id)
+            }
+            Self::RoomZero => write!(f, "Room Zero can't be destroyed."),
+        }
+    }
+}
+
+impl std::error::Error for MutationError {}
+
+impl From<PermissionError> for MutationError {
+    fn from(err: PermissionError) -> Self {
+        Self::Denied(err)
+    }
+}
+
+/// Check and apply a mutation on behalf of `actor`
+///
+/// Returns the id of the object that changed (the new object for
+/// [`Mutation::Create`]).
+pub fn apply(
+    world: &mut World,
+    actor: ObjectId,
+    mutation: Mutation,
+) -> Result<ObjectId, MutationError> {
+    let actor = load(world, actor)?;
+
+    match mutation {
+        Mutation::SetAttr {
+            target,
+            name,
+            value,
+        } if Attributes::normalize(&name) == SCRIPT_ATTR => {
+            set_script(world, &actor, target, value, String::new())
+        }
+        Mutation::SetAttr {
+            target,
+            name,
+            value,
+        } => {
+            let subject = load(world, target)?;
+            if Attributes::normalize(&name) == LIBRARY_ATTR {
+                check_library_name(world, target, &value)?;
+            }
+            let mut attrs = component_mut::<Attributes>(world, target)?;
+            permissions::check_write_attr(&actor, &subject, attrs.get(&name))?;
+            let flags = attrs.get(&name).map(|attr| attr.flags).unwrap_or_default();
+            attrs.set(
+                &name,
+                Attribute {
+                    value,
+                    owner: actor.owner,
+                    flags,
+                },
+            );
+            Ok(target)
+        }
+        Mutation::ClearAttr { target, name } => {
+            let subject = load(world, target)?;
+            let mut attrs = component_mut::<Attributes>(world, target)?;
+            let existing = attrs
+                .get(&name)
+                .ok_or_else(|| MutationError::NoSuchAttribute(Attributes::normalize(&name)))?;
+            permissions::check_write_attr(&actor, &subject, Some(existing))?;
+            if Attributes::normalize(&name) == SCRIPT_ATTR {
+                return set_script(world, &actor, target, String::new(), String::new());
+            }
+            attrs.remove(&name);
+            Ok(target)
+        }
+        Mutation::SetAttrFlags {
+            target,
+            name,
+            flags,
+            on,
+        } => {
+            let subject = load(world, target)?;
+            let mut attrs = component_mut::<Attributes>(world, target)?;
+            let existing = attrs
+                .get(&name)
+                .ok_or_else(|| MutationError::NoSuchAttribute(Attributes::normalize(&name)))?;
+            permissions::check_set_attr_flags(&actor, &subject, existing, flags)?;
+            let attr = attrs.get_mut(&name).expect("attribute checked above");
+            if on {
+                attr.flags.insert(flags);
+            } else {
+                attr.flags.remove(flags);
+            }
+            Ok(target)
+        }
+        Mutation::SetFlags { target, flags, on } => {
+            let subject = load(world, target)?;
+            permissions::check_set_flag(&actor, &subject, flags)?;
+            let mut current = component_mut::<Flags>(world, target)?;
+            if on {
+                current.insert(flags);
+            } else {
+                current.remove(flags);
+            }
+            Ok(target)
+        }
+        Mutation::SetLock { target, kind, key } => {
+            let subject = load(world, target)?;
+            permissions::check_lock(&actor, &subject)?;
+            let mut locks = component_mut::<Locks>(world, target)?;
+            match key {
+                Some(key) => locks.0.insert(kind, key),
+                None => locks.0.remove(&kind),
+            };
+            Ok(target)
+        }
+        Mutation::Move {
+            target,
+            destination,
+        } => {
+            let subject = load(world, target)?;
+            load(world, destination)?;
+            let from = location(world, target);
+            permissions::check_move(
+                &actor,
+                location(world, actor.id),
+                &subject,
+                from,
+                destination,
+            )?;
+            // Walk out from the destination; meeting the target means it
+            // would end up inside itself
+            let mut outer = Some(destination);
+            let mut seen = HashSet::new();
+            while let Some(id) = outer.filter(|id| seen.insert(*id)) {
+                if id == target {
+                    return Err(MutationError::ContainmentLoop(target));
+                }
+                outer = location(world, id);
+            }
+            let entity = entity(world, target)?;
+            world.entity_mut(entity).insert(Location(destination));
+            Ok(target)
+        }
+        Mutation::Link {
+            target,
+            destination,
+        } => {
+            let subject = load(world, target)?;
+            if subject.kind != ObjectKind::Exit {
+                return Err(MutationError::WrongKind(target, subject.kind));
+            }
+            let room = load(world, destination)?;
+            if room.kind != ObjectKind::Room {
+                return Err(MutationError::WrongKind(destination, room.kind));
+            }
+            permissions::check_control(&actor, &subject)?;
+            let entity = entity(world, target)?;
+            world.entity_mut(entity).insert(Destination(destination));
+            Ok(target)
+        }
+        Mutation::Chown { target, owner } => {
+            let subject = load(world, target)?;
+            permissions::check_chown(&actor, &subject)?;
+            let new_owner = load(world, owner)?;
+            if new_owner.kind != ObjectKind::Player {
+                return Err(MutationError::WrongKind(owner, new_owner.kind));
+            }
+            component_mut::<Owner>(world, target)?.0 = owner;
+            Ok(target)
+        }
+        Mutation::Create { kind, name } => {
+            permissions::check_create(&actor, kind)?;
+            let owner = (kind != ObjectKind::Player).then_some(actor.owner);
+            let start = match kind {
+                ObjectKind::Room => None,
+                ObjectKind::Thing => Some(actor.id),
+                ObjectKind::Exit => location(world, actor.id),
+                ObjectKind::Player => Some(ROOM_ZERO),
+            };
+            let id = spawn_object(world, kind, &name, owner);
+            if let Some(start) = start {
+                let entity = entity(world, id)?;
+                world.entity_mut(entity).insert(Location(start));
+            }
+            Ok(id)
+        }
+        Mutation::Destroy { target } => {
+            let subject = load(world, target)?;
+            permissions::check_destroy(&actor, &subject)?;
+            if target == ROOM_ZERO {
+                return Err(MutationError::RoomZero);
+            }
+            let refuge = location(world, target).unwrap_or(ROOM_ZERO);
+            let contents: Vec<(Entity, ObjectId, ObjectKind)> = world
+                .query::<(Entity, &ObjectId, &ObjectKind, &Location)>()
+                .iter(world)
+                .filter(|(_, _, _, location)| location.0 == target)
+                .map(|(entity, id, kind, _)| (entity, *id, *kind))
+                .collect();
+            for (entity, id, kind) in contents {
+                if kind == ObjectKind::Exit {
+                    despawn(world, id);
+                } else {
+                    world.entity_mut(entity).insert(Location(refuge));
+                }
+            }
+            despawn(world, target);
+            Ok(target)
+        }
+        Mutation::SetScript {
+            target,
+            source,
+            message,
+        } => set_script(world, &actor, target, source, message),
+        Mutation::RevertScript { target, revision } => {
+            let source = component::<ScriptHistory>(world, target)
+                .and_then(|history| history.get(revision))
+                .ok_or(MutationError::NoSuchRevision(revision))?
+                .source
+                .clone();
+            let message = format!("Revert to revision {}", revision);
+            set_script(world, &actor, target, source, message)
+        }
+        Mutation::ScheduleTimer {
+            target,
+            function,
+            due,
+        } => {
+            let subject = load(world, target)?;
+            permissions::check_control(&actor, &subject)?;
+            if subject.flags.contains(Flags::HALT) {
+                return Err(MutationError::Halted(target));
+            }
+            if component::<ScriptTimers>(world, target).map_or(0, ScriptTimers::len)
+                >= timers::MAX_TIMERS_PER_OBJECT
+            {
+                return Err(MutationError::TooManyTimers(target));
+            }
+            world.init_resource::<TimerIds>();
+            let id = world.resource_mut::<TimerIds>().allocate();
+            component_mut::<ScriptTimers>(world, target)?.insert(ScriptTimer { id, function, due });
+            Ok(target)
+        }
+        Mutation::CancelTimers { target, timer } => {
+            let subject = load(world, target)?;
+            permissions::check_control(&actor, &subject)?;
+            let mut timers = component_mut::<ScriptTimers>(world, target)?;
+            match timer {
+                Some(id) if !timers.remove(id) => return Err(MutationError::NoSuchTimer(id)),
+                Some(_) => {}
+                None => {
+                    timers.clear();
+                }
+            }
+            Ok(target)
+        }
+    }
+}
+
+/// Record a new script revision and make it live
+fn set_script(
+    world: &mut World,
+    actor: &Subject,
+    target: ObjectId,
+    source: String,
+    message: String,
+) -> Result<ObjectId, MutationError> {
+    let subject = load(world, target)?;
+    let existing = component::<Attributes>(world, target)
+        .ok_or(MutationError::NoSuchObject(target))?
+        .get(SCRIPT_ATTR);
+    permissions::check_write_attr(actor, &subject, existing)?;
+
+    component_mut::<ScriptHistory>(world, target)?.push(source.clone(), actor.id, message);
+    let mut attrs = component_mut::<Attributes>(world, target)?;
+    if source.is_empty() {
+        attrs.remove(SCRIPT_ATTR);
+    } else {
+        let flags = attrs
+            .get(SCRIPT_ATTR)
+            .map(|attr| attr.flags)
+            .unwrap_or_default();
+        attrs.set(
+            SCRIPT_ATTR,
+            Attribute {
+                value: source,
+                owner: actor.owner,
+                flags,
+            },
+        );
+    }
+    Ok(target)
+}
+
+/// Check that `target` may serve its script as the library `name`
+fn check_library_name(world: &World, target: ObjectId, name: &str) -> Result<(), MutationError> {
+    if !libraries::valid_name(name) {
+        return Err(MutationError::BadLibraryName(name.to_string()));
+    }
+    match libraries::find(world, name) {
+        Some(holder) if holder != target => {
+            Err(MutationError::LibraryTaken(name.to_string(), holder))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Remove an object from the world
+fn despawn(world: &mut World, id: ObjectId) {
+    if let Some(entity) = world.resource_mut::<ObjectRegistry>().remove(id) {
+        world.despawn(entity);
+    }
+}
+
+/// Load an object's privileges or fail with `NoSuchObject`
+fn load(world: &World, id: ObjectId) -> Result<Subject, MutationError> {
+    Subject::load(world, id).ok_or(MutationError::NoSuchObject(id))
+}
+
+/// Resolve an object id to its entity or fail with `NoSuchObject`
+fn entity(world: &World, id: ObjectId) -> Result<Entity, MutationError> {
+    world
+        .resource::<ObjectRegistry>()
+        .entity(id)
+        .ok_or(MutationError::NoSuchObject(id))
+}
+
+/// Get mutable access to one component of an object
+fn component_mut<T: Component>(
+    world: &mut World,
+    id: ObjectId,
+) -> Result<Mut<'_, T>, MutationError> {
+    let entity = entity(world, id)?;
+    world
+        .get_mut::<T>(entity)
+        .ok_or(MutationError::NoSuchObject(id))
+}
@@ -0,0 +1,131 @@
+//! World Object Registry
+//!
+//! Maps stable `ObjectId`s to Bevy entities and spawns world objects with
+//! the standard set of components.
+//!
+//! # Learning Note
+//! Bevy `Entity` values are only meaningful inside one `World`. Anything that
+//! crosses a process boundary (commands, scripts, the database) refers to
+//! objects by `ObjectId` and resolves it here.
+
+use bevy::prelude::*;
+use shared::components::{
+    Attributes, Flags, Location, Locks, ObjectId, ObjectKind, ObjectName, Owner,
+};
+use std::collections::HashMap;
+
+use crate::scripts::ScriptHistory;
+use crate::timers::ScriptTimers;
+
+/// Object id of the starting room
+pub const ROOM_ZERO: ObjectId = ObjectId(0);
+
+/// Object id of the first wizard (the MUSH "God" character)
+pub const GOD: ObjectId = ObjectId(1);
+
+/// Lookup table from `ObjectId` to the entity that holds the object
+#[derive(Resource, Debug, Default)]
+pub struct ObjectRegistry {
+    entities: HashMap<ObjectId, Entity>,
+    next_id: u64,
+    removed: Vec<ObjectId>,
+}
+
+impl ObjectRegistry {
+    /// Resolve an object id to its entity
+    pub fn entity(&self, id: ObjectId) -> Option<Entity> {
+        self.entities.get(&id).copied()
+    }
+
+    /// Reserve the next unused object id
+    pub fn allocate(&mut self) -> ObjectId {
+        let id = ObjectId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// Record the entity for an object id
+    pub fn insert(&mut self, id: ObjectId, entity: Entity) {
+        self.next_id = self.next_id.max(id.0 + 1);
+        self.entities.insert(id, entity);
+    }
+
+    /// Forget an object id, returning its entity
+    pub fn remove(&mut self, id: ObjectId) -> Option<Entity> {
+        let entity = self.entities.remove(&id)?;
+        self.removed.push(id);
+        Some(entity)
+    }
+
+    /// Ids removed since the last call, for persistence to delete
+    pub fn take_removed(&mut self) -> Vec<ObjectId> {
+        std::mem::take(&mut self.removed)
+    }
+
+    /// Number of live objects
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+}
+
+/// Spawn a new world object and register it
+///
+/// When `owner` is `None` the object owns itself, which is how players are
+/// created.
+pub fn spawn_object(
+    world: &mut World,
+    kind: ObjectKind,
+    name: &str,
+    owner: Option<ObjectId>,
+) -> ObjectId {
+    let id = world.resource_mut::<ObjectRegistry>().allocate();
+    spawn_with_id(world, id, kind, name, owner.unwrap_or(id));
+    id
+}
+
+/// Spawn a world object with a known id (used when loading saved objects)
+pub fn spawn_with_id(
+    world: &mut World,
+    id: ObjectId,
+    kind: ObjectKind,
+    name: &str,
+    owner: ObjectId,
+) -> Entity {
+    let entity = world
+        .spawn((
+            id,
+            kind,
+            ObjectName(name.to_string()),
+            Owner(owner),
+            Flags::empty(),
+            Attributes::default(),
+            Locks::default(),
+            ScriptHistory::default(),
+            ScriptTimers::default(),
+        ))
+        .id();
+    world.resource_mut::<ObjectRegistry>().insert(id, entity);
+    entity
+}
+
+/// Create the minimal world every server starts from: Room Zero and God
+pub fn bootstrap(world: &mut World) {
+    world.init_resource::<ObjectRegistry>();
+
+    spawn_with_id(world, ROOM_ZERO, ObjectKind::Room, "Room Zero", GOD);
+    let god = spawn_with_id(world, GOD, ObjectKind::Player, "Wizard", GOD);
+    world
+        .entity_mut(god)
+        .insert((Flags::WIZARD, Location(ROOM_ZERO)));
+}
+
+/// Read a component of an object by id
+pub fn component<T: Component>(world: &World, id: ObjectId) -> Option<&T> {
+    let entity = world.resource::<ObjectRegistry>().entity(id)?;
+    world.get::<T>(entity)
+}
+
+/// Current location of an object, if it has one
+pub fn location(world: &World, id: ObjectId) -> Option<ObjectId> {
+    component::<Location>(world, id).map(|location| location.0)
+}
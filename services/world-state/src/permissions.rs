@@ -0,0 +1,355 @@
+//! Permission Checks
+//!
+//! MUSH-style access control for world objects. Every check takes the
+//! privileges of the *acting* object (a player typing a command, or the
+//! object whose script is running) and the object being acted on.
+//!
+//! The rules, roughly in order of precedence:
+//! - WIZARD objects may do anything except destroy SAFE objects
+//! - Nobody but a wizard controls a WIZARD object
+//! - Players control everything they own; other objects control things
+//!   sharing their owner, but never the owning player itself
+//! - ROYALTY may read everything but changes nothing it does not control
+//! - Attribute flags (`HIDDEN`, `VISUAL`, `WIZARD`, `LOCKED`) refine reads
+//!   and writes of individual attributes
+
+use bevy::prelude::*;
+use shared::components::{AttrFlags, Attribute, Flags, ObjectId, ObjectKind, Owner};
+use std::fmt;
+
+use crate::objects::ObjectRegistry;
+
+/// Why a permission check failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionError {
+    /// The actor does not control the target object
+    NotController,
+    /// Only wizards may perform this action
+    WizardOnly,
+    /// The attribute is locked to another owner
+    AttributeLocked,
+    /// The target has the SAFE flag
+    Safe,
+    /// Creating objects requires the BUILDER flag
+    NotBuilder,
+}
+
+impl fmt::Display for PermissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Self::NotController => "Permission denied.",
+            Self::WizardOnly => "Permission denied: wizards only.",
+            Self::AttributeLocked => "Permission denied: attribute is locked.",
+            Self::Safe => "Permission denied: object is SAFE.",
+            Self::NotBuilder => "Permission denied: you are not a builder.",
+        };
+        f.write_str(reason)
+    }
+}
+
+impl std::error::Error for PermissionError {}
+
+/// Privilege snapshot of one object taking part in a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subject {
+    /// The object itself
+    pub id: ObjectId,
+    /// The object's owner
+    pub owner: ObjectId,
+    /// The object's flags at the time of the check
+    pub flags: Flags,
+    /// The object's type
+    pub kind: ObjectKind,
+}
+
+impl Subject {
+    /// Read the privilege-relevant components of an object
+    pub fn load(world: &World, id: ObjectId) -> Option<Self> {
+        let entity = world.resource::<ObjectRegistry>().entity(id)?;
+        let entity = world.get_entity(entity).ok()?;
+        Some(Self {
+            id,
+            owner: entity.get::<Owner>()?.0,
+            flags: entity.get::<Flags>().copied().unwrap_or_default(),
+            kind: *entity.get::<ObjectKind>()?,
+        })
+    }
+
+    /// True if the object has the WIZARD flag
+    pub fn is_wizard(&self) -> bool {
+        self.flags.contains(Flags::WIZARD)
+    }
+
+    /// True if the object may read anything (WIZARD or ROYALTY)
+    pub fn sees_all(&self) -> bool {
+        self.flags.intersects(Flags::WIZARD | Flags::ROYALTY)
+    }
+}
+
+/// True if `actor` may modify `target`
+pub fn controls(actor: &Subject, target: &Subject) -> bool {
+    if actor.is_wizard() {
+        return true;
+    }
+    if target.is_wizard() {
+        return false;
+    }
+    if actor.id == target.id {
+        return true;
+    }
+    actor.owner == target.owner
+        && (actor.kind == ObjectKind::Player || target.kind != ObjectKind::Player)
+}
+
+/// Check that `actor` controls `target`
+pub fn check_control(actor: &Subject, target: &Subject) -> Result<(), PermissionError> {
+    if controls(actor, target) {
+        Ok(())
+    } else {
+        Err(PermissionError::NotController)
+    }
+}
+
+/// True if `actor` may examine `target` (see its owner, flags and attributes)
+pub fn can_examine(actor: &Subject, target: &Subject) -> bool {
+    actor.sees_all() || target.flags.contains(Flags::VISUAL) || controls(actor, target)
+}
+
+/// True if `actor` may notice `target` nearby (DARK objects are hidden
+/// from all but those who control them)
+pub fn can_see(actor: &Subject, target: &Subject) -> bool {
+    actor.sees_all() || !target.flags.contains(Flags::DARK) || controls(actor, target)
+}
+
+/// True if `actor` may read one attribute of `target`
+pub fn can_read_attr(actor: &Subject, target: &Subject, attr: &Attribute) -> bool {
+    if attr.flags.contains(AttrFlags::HIDDEN) {
+        return actor.sees_all();
+    }
+    attr.flags.contains(AttrFlags::VISUAL) || can_examine(actor, target)
+}
+
+/// Check that `actor` may set, change or clear an attribute on `target`
+///
+/// `existing` is the attribute's current value, if it has one.
+pub fn check_write_attr(
+    actor: &Subject,
+    target: &Subject,
+    existing: Option<&Attribute>,
+) -> Result<(), PermissionError> {
+    check_control(actor, target)?;
+    let Some(attr) = existing else {
+        return Ok(());
+    };
+    if attr.flags.contains(AttrFlags::WIZARD) && !actor.is_wizard() {
+        return Err(PermissionError::WizardOnly);
+    }
+    if attr.flags.contains(AttrFlags::LOCKED) && attr.owner != actor.owner && !actor.is_wizard() {
+        return Err(PermissionError::AttributeLocked);
+    }
+    Ok(())
+}
+
+/// Check that `actor` may change `flags` on an attribute of `target`
+pub fn check_set_attr_flags(
+    actor: &Subject,
+    target: &Subject,
+    attr: &Attribute,
+    flags: AttrFlags,
+) -> Result<(), PermissionError> {
+    if flags.contains(AttrFlags::WIZARD) && !actor.is_wizard() {
+        return Err(PermissionError::WizardOnly);
+    }
+    check_write_attr(actor, target, Some(attr))
+}
+
+/// Check that `actor` may set or clear `flag` on `target`
+///
+/// Privilege-granting flags (WIZARD, ROYALTY, BUILDER) are wizard-only.
+pub fn check_set_flag(
+    actor: &Subject,
+    target: &Subject,
+    flag: Flags,
+) -> Result<(), PermissionError> {
+    if flag.intersects(Flags::WIZARD | Flags::ROYALTY | Flags::BUILDER) && !actor.is_wizard() {
+        return Err(PermissionError::WizardOnly);
+    }
+    check_control(actor, target)
+}
+
+/// Check that `actor` may create an object of the given kind
+pub fn check_create(actor: &Subject, kind: ObjectKind) -> Result<(), PermissionError> {
+    match kind {
+        ObjectKind::Player if !actor.is_wizard() => Err(PermissionError::WizardOnly),
+        _ if actor.flags.intersects(Flags::WIZARD | Flags::BUILDER) => Ok(()),
+        _ => Err(PermissionError::NotBuilder),
+    }
+}
+
+/// Check that `actor` may destroy `target`
+pub fn check_destroy(actor: &Subject, target: &Subject) -> Result<(), PermissionError> {
+    if target.flags.contains(Flags::SAFE) {
+        return Err(PermissionError::Safe);
+    }
+    if target.kind == ObjectKind::Player && !actor.is_wizard() {
+        return Err(PermissionError::WizardOnly);
+    }
+    check_control(actor, target)
+}
+
+/// Check that `actor` may set or clear a lock on `target`
+pub fn check_lock(actor: &Subject, target: &Subject) -> Result<(), PermissionError> {
+    check_control(actor, target)
+}
+
+/// Check that `actor` may move `target` from `from` into `to`
+///
+/// Besides moving what it controls, anyone may pick up a thing from the
+/// place they stand or drop a thing they carry. Locks are checked
+/// separately by the caller.
+pub fn check_move(
+    actor: &Subject,
+    actor_location: Option<ObjectId>,
+    target: &Subject,
+    from: Option<ObjectId>,
+    to: ObjectId,
+) -> Result<(), PermissionError> {
+    if target.kind == ObjectKind::Thing {
+        let picking_up = from.is_some() && from == actor_location && to == actor.id;
+        let dropping = from == Some(actor.id) && Some(to) == actor_location;
+        if picking_up || dropping {
+            return Ok(());
+        }
+    }
+    check_control(actor, target)
+}
+
+/// Check that `actor` may give `target` to a new owner
+pub fn check_chown(actor: &Subject, _target: &Subject) -> Result<(), PermissionError> {
+    if actor.is_wizard() {
+        Ok(())
+    } else {
+        Err(PermissionError::WizardOnly)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subject(id: u64, owner: u64, flags: Flags) -> Subject {
+        Subject {
+            id: ObjectId(id),
+            owner: ObjectId(owner),
+            flags,
+            kind: if id == owner {
+                ObjectKind::Player
+            } else {
+                ObjectKind::Thing
+            },
+        }
+    }
+
+    fn attr(owner: u64, flags: AttrFlags) -> Attribute {
+        Attribute {
+            value: "value".to_string(),
+            owner: ObjectId(owner),
+            flags,
+        }
+    }
+
+    #[test]
+    fn test_ownership_control() {
+        let alice = subject(2, 2, Flags::empty());
+        let alices_box = subject(10, 2, Flags::empty());
+        let bob = subject(3, 3, Flags::empty());
+
+        assert!(controls(&alice, &alices_box));
+        // A scripted object cannot act on the player who owns it
+        assert!(!controls(&alices_box, &alice));
+        assert!(!controls(&bob, &alices_box));
+    }
+
+    #[test]
+    fn test_wizard_rules() {
+        let wizard = subject(1, 1, Flags::WIZARD);
+        let mortal = subject(2, 2, Flags::empty());
+        let wizard_item = subject(11, 2, Flags::WIZARD);
+
+        assert!(controls(&wizard, &mortal));
+        // Mortals never control a WIZARD object, even one they own
+        assert!(!controls(&mortal, &wizard_item));
+        assert_eq!(
+            check_set_flag(&mortal, &mortal, Flags::WIZARD),
+            Err(PermissionError::WizardOnly)
+        );
+        assert!(check_set_flag(&mortal, &mortal, Flags::DARK).is_ok());
+    }
+
+    #[test]
+    fn test_attribute_visibility() {
+        let owner = subject(2, 2, Flags::empty());
+        let stranger = subject(3, 3, Flags::empty());
+        let royal = subject(4, 4, Flags::ROYALTY);
+        let object = subject(10, 2, Flags::empty());
+
+        let plain = attr(2, AttrFlags::empty());
+        let visual = attr(2, AttrFlags::VISUAL);
+        let hidden = attr(2, AttrFlags::HIDDEN);
+
+        assert!(can_read_attr(&owner, &object, &plain));
+        assert!(!can_read_attr(&stranger, &object, &plain));
+        assert!(can_read_attr(&stranger, &object, &visual));
+        assert!(!can_read_attr(&owner, &object, &hidden));
+        assert!(can_read_attr(&royal, &object, &hidden));
+        // Royalty can look but not touch
+        assert!(check_write_attr(&royal, &object, Some(&plain)).is_err());
+
+        let dark = subject(11, 2, Flags::DARK);
+        assert!(can_see(&stranger, &object));
+        assert!(!can_see(&stranger, &dark));
+        assert!(can_see(&owner, &dark));
+        assert!(can_see(&royal, &dark));
+    }
+
+    #[test]
+    fn test_attribute_write_flags() {
+        let wizard = subject(1, 1, Flags::WIZARD);
+        let owner = subject(2, 2, Flags::empty());
+        let object = subject(10, 2, Flags::empty());
+
+        let wizard_only = attr(1, AttrFlags::WIZARD);
+        let locked_by_wizard = attr(1, AttrFlags::LOCKED);
+
+        assert_eq!(
+            check_write_attr(&owner, &object, Some(&wizard_only)),
+            Err(PermissionError::WizardOnly)
+        );
+        assert_eq!(
+            check_write_attr(&owner, &object, Some(&locked_by_wizard)),
+            Err(PermissionError::AttributeLocked)
+        );
+        assert!(check_write_attr(&wizard, &object, Some(&locked_by_wizard)).is_ok());
+    }
+
+    #[test]
+    fn test_create_and_destroy() {
+        let builder = subject(2, 2, Flags::BUILDER);
+        let mortal = subject(3, 3, Flags::empty());
+        let safe_box = subject(10, 2, Flags::SAFE);
+
+        assert!(check_create(&builder, ObjectKind::Room).is_ok());
+        assert_eq!(
+            check_create(&builder, ObjectKind::Player),
+            Err(PermissionError::WizardOnly)
+        );
+        assert_eq!(
+            check_create(&mortal, ObjectKind::Thing),
+            Err(PermissionError::NotBuilder)
+        );
+        assert_eq!(
+            check_destroy(&builder, &safe_box),
+            Err(PermissionError::Safe)
+        );
+    }
+}
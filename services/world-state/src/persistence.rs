@@ -0,0 +1,566 @@
+//! Write-Behind Persistence
+//!
+//! World-state never waits for the database. Every [`FLUSH_INTERVAL`] the
+//! main loop asks [`collect`] which objects changed since the last flush,
+//! snapshots them into a [`WriteBatch`] and hands it to a background
+//! writer task that posts it to persistence-service.
+//!
+//! Delivery is at-least-once: the writer retries a batch until the store
+//! acknowledges it, folding any newer batches into it while it waits.
+//! Resending is safe because each snapshot carries a per-object
+//! [`Version`] and the store ignores versions it has already seen.
+//!
+//! # Learning Note
+//! Bevy's change detection (`Changed<T>`, `Added<T>`) compares component
+//! change ticks against the last time a system ran. Keeping a
+//! `SystemState` in a resource gives us a "system" we can run by hand
+//! outside of a schedule, with its own memory of the last run.
+
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+use shared::components::{
+    AttrFlags, Attribute, Attributes, Destination, Flags, Location, Locks, ObjectId, ObjectKind,
+    ObjectName, Owner,
+};
+use shared::dump::read_dump;
+use shared::locks::{LockExpr, LockParseError};
+use shared::records::{AttributeRecord, BatchResult, ObjectRecord, WriteBatch};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::locks::SCRIPT_ATTR;
+use crate::objects::{spawn_with_id, ObjectRegistry};
+use crate::scripts::ScriptHistory;
+use crate::timers::{ScriptTimers, TimerIds};
+
+/// How often dirty objects are collected into a batch
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// First delay before retrying a failed batch
+pub const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Longest delay between retries
+pub const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Tries at loading the stored world before giving up on starting
+pub const LOAD_ATTEMPTS: u32 = 8;
+
+/// How long a batch write may take before it counts as failed and is
+/// retried
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long downloading the stored world may take; a dump is the whole
+/// world, so it gets longer than a batch
+pub const LOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How long shutdown waits for outstanding batches to be acknowledged
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of times an object has been written out
+///
+/// Bumped each time the object is collected, so the store can tell a
+/// retried write from a newer one.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Version(pub u64);
+
+/// Objects whose persisted state may be out of date
+type Dirty = Or<(
+    Added<ObjectId>,
+    Changed<ObjectName>,
+    Changed<Owner>,
+    Changed<Location>,
+    Changed<Destination>,
+    Changed<Flags>,
+    Changed<Attributes>,
+    Changed<Locks>,
+    Changed<ScriptHistory>,
+    Changed<ScriptTimers>,
+)>;
+
+/// Remembers when changes were last collected
+#[derive(Resource)]
+pub struct ChangeTracker {
+    dirty: SystemState<Query<'static, 'static, Entity, Dirty>>,
+}
+
+impl FromWorld for ChangeTracker {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            dirty: SystemState::new(world),
+        }
+    }
+}
+
+/// Snapshot every object changed since the last call
+///
+/// The first call reports every object in the world.
+pub fn collect(world: &mut World) -> WriteBatch {
+    world.init_resource::<ChangeTracker>();
+    let dirty: Vec<Entity> = world.resource_scope(|world, mut tracker: Mut<ChangeTracker>| {
+        tracker.dirty.get(world).iter().collect()
+    });
+
+    let mut batch = WriteBatch::default();
+    for entity in dirty {
+        let version = world.get::<Version>(entity).copied().unwrap_or_default().0 + 1;
+        // Version is not part of `Dirty`, so bumping it doesn't re-dirty
+        world.entity_mut(entity).insert(Version(version));
+        batch.upserts.extend(snapshot(world, entity));
+    }
+    batch.upserts.sort_by_key(|record| record.id);
+    batch.deletes = world.resource_mut::<ObjectRegistry>().take_removed();
+    batch
+}
+
+/// Forget pending changes, e.g. after restoring objects that are already stored
+pub fn mark_clean(world: &mut World) {
+    world.init_resource::<ChangeTracker>();
+    world.resource_scope(|world, mut tracker: Mut<ChangeTracker>| {
+        tracker.dirty.get(world);
+    });
+    world.resource_mut::<ObjectRegistry>().take_removed();
+}
+
+/// Why saved objects could not be restored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestoreError {
+    /// A stored lock key no longer parses
+    BadLock(ObjectId, LockParseError),
+}
+
+impl fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadLock(id, e) => write!(f, "bad lock on {}: {}", id, e),
+        }
+    }
+}
+
+impl std::error::Error for RestoreError {}
+
+/// Spawn objects from stored records into a world with no objects yet
+///
+/// This is the inverse of [`snapshot`]. Nothing is spawned unless every
+/// record is valid, and the restored objects are not reported as changed.
+pub fn restore(world: &mut World, records: &[ObjectRecord]) -> Result<(), RestoreError> {
+    // Parse every lock first so a bad record leaves the world untouched
+    let mut parsed = Vec::with_capacity(records.len());
+    for record in records {
+        let mut locks = Locks::default();
+        for (kind, key) in &record.locks {
+            let key =
+                LockExpr::parse_canonical(key).map_err(|e| RestoreError::BadLock(record.id, e))?;
+            locks.0.insert(*kind, key);
+        }
+        parsed.push(locks);
+    }
+
+    world.init_resource::<ObjectRegistry>();
+    world.init_resource::<TimerIds>();
+    for (record, locks) in records.iter().zip(parsed) {
+        let mut attributes = Attributes::default();
+        for attr in &record.attributes {
+            attributes.set(
+                &attr.name,
+                Attribute {
+                    value: attr.value.clone(),
+                    owner: attr.owner,
+                    flags: AttrFlags::from_bits_truncate(attr.flags),
+                },
+            );
+        }
+        if let Some(source) = &record.script {
+            attributes.set(
+                SCRIPT_ATTR,
+                Attribute {
+                    value: source.clone(),
+                    owner: record.script_owner.unwrap_or(record.owner),
+                    flags: AttrFlags::from_bits_truncate(record.script_flags),
+                },
+            );
+        }
+
+        let entity = spawn_with_id(world, record.id, record.kind, &record.name, record.owner);
+        let mut object = world.entity_mut(entity);
+        object.insert((
+            Flags::from_bits_truncate(record.flags),
+            attributes,
+            locks,
+            ScriptHistory::from_revisions(record.script_revisions.iter().map(Into::into).collect()),
+            ScriptTimers::from_timers(record.timers.iter().map(Into::into).collect()),
+            Version(record.version),
+        ));
+        if let Some(location) = record.location {
+            object.insert(Location(location));
+        }
+        if let Some(destination) = record.destination {
+            object.insert(Destination(destination));
+        }
+        let mut ids = world.resource_mut::<TimerIds>();
+        for timer in &record.timers {
+            ids.observe(timer.id);
+        }
+    }
+
+    mark_clean(world);
+    Ok(())
+}
+
+/// Build the storage record for one object
+pub fn snapshot(world: &World, entity: Entity) -> Option<ObjectRecord> {
+    let attributes = world.get::<Attributes>(entity)?;
+    let mut script = None;
+    let mut attribute_records = Vec::new();
+    for (name, attr) in attributes.iter() {
+        if name == SCRIPT_ATTR {
+            script = Some(attr);
+            continue;
+        }
+        attribute_records.push(AttributeRecord {
+            name: name.to_string(),
+            value: attr.value.clone(),
+            owner: attr.owner,
+            flags: attr.flags.bits(),
+        });
+    }
+
+    Some(ObjectRecord {
+        id: *world.get::<ObjectId>(entity)?,
+        kind: *world.get::<ObjectKind>(entity)?,
+        name: world.get::<ObjectName>(entity)?.0.clone(),
+        owner: world.get::<Owner>(entity)?.0,
+        location: world.get::<Location>(entity).map(|l| l.0),
+        destination: world.get::<Destination>(entity).map(|d| d.0),
+        flags: world.get::<Flags>(entity)?.bits(),
+        locks: world
+            .get::<Locks>(entity)?
+            .0
+            .iter()
+            .map(|(kind, key)| (*kind, key.to_string()))
+            .collect(),
+        attributes: attribute_records,
+        script: script.map(|attr| attr.value.clone()),
+        script_owner: script.map(|attr| attr.owner),
+        script_flags: script.map_or(0, |attr| attr.flags.bits()),
+        script_revisions: world
+            .get::<ScriptHistory>(entity)
+            .map(|history| history.revisions().iter().map(Into::into).collect())
+            .unwrap_or_default(),
+        timers: world
+            .get::<ScriptTimers>(entity)
+            .map(|timers| timers.timers().iter().map(Into::into).collect())
+            .unwrap_or_default(),
+        version: world.get::<Version>(entity).copied().unwrap_or_default().0,
+    })
+}
+
+/// Collect changes and queue them for the writer, if there are any
+pub fn flush(world: &mut World, writer: &mpsc::UnboundedSender<WriteBatch>) {
+    let batch = collect(world);
+    if batch.is_empty() {
+        return;
+    }
+    debug!(
+        "Queueing {} upserts and {} deletes",
+        batch.upserts.len(),
+        batch.deletes.len()
+    );
+    if writer.send(batch).is_err() {
+        warn!("Persistence writer has stopped; changes were not queued");
+    }
+}
+
+/// Somewhere batches can be written
+pub trait BatchSink: Send + Sync + 'static {
+    /// Write one batch, returning what the store did with it
+    fn send(&self, batch: &WriteBatch) -> impl Future<Output = Result<BatchResult, String>> + Send;
+}
+
+/// Talks to persistence-service over HTTP
+pub struct HttpSink {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl HttpSink {
+    /// Target the persistence-service at `base_url` (e.g. `http://localhost:8087`)
+    pub fn new(base_url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Download and verify the stored world
+    pub async fn load_world(&self) -> Result<Vec<ObjectRecord>, String> {
+        let response = self
+            .client
+            .get(format!("{}/dump", self.base_url))
+            .timeout(LOAD_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "persistence-service returned {}",
+                response.status()
+            ));
+        }
+        let dump = response.text().await.map_err(|e| e.to_string())?;
+        read_dump(&dump).map_err(|e| e.to_string())
+    }
+
+    /// [`load_world`](Self::load_world), retrying with backoff up to
+    /// [`LOAD_ATTEMPTS`] times
+    ///
+    /// An unreachable store is not an empty one: starting fresh would
+    /// bootstrap a new world and the first flush would write it over the
+    /// stored one, so the caller should give up on an error.
+    pub async fn load_world_retrying(
+        &self,
+        backoff: Duration,
+    ) -> Result<Vec<ObjectRecord>, String> {
+        let mut delay = backoff;
+        for attempt in 1.. {
+            match self.load_world().await {
+                Ok(records) => return Ok(records),
+                Err(e) if attempt >= LOAD_ATTEMPTS => return Err(e),
+                Err(e) => {
+                    warn!(
+                        "Could not load the stored world (try {} of {}), retrying in {:?}: {}",
+                        attempt, LOAD_ATTEMPTS, delay, e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RETRY_BACKOFF);
+                }
+            }
+        }
+        unreachable!("the retry loop only ends by returning")
+    }
+}
+
+impl BatchSink for HttpSink {
+    async fn send(&self, batch: &WriteBatch) -> Result<BatchResult, String> {
+        let response = self
+            .client
+            .post(format!("{}/objects/batch", self.base_url))
+            .json(batch)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "persistence-service returned {}",
+                response.status()
+            ));
+        }
+        response.json().await.map_err(|e| e.to_string())
+    }
+}
+
+/// Start the background writer
+///
+/// The task exits once every sender is dropped and everything queued
+/// has been acknowledged.
+pub fn spawn_writer<S: BatchSink>(
+    sink: S,
+    backoff: Duration,
+) -> (mpsc::UnboundedSender<WriteBatch>, JoinHandle<()>) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    (sender, tokio::spawn(run_writer(sink, receiver, backoff)))
+}
+
+async fn run_writer<S: BatchSink>(
+    sink: S,
+    mut batches: mpsc::UnboundedReceiver<WriteBatch>,
+    backoff: Duration,
+) {
+    while let Some(mut pending) = batches.recv().await {
+        let mut delay = backoff;
+        loop {
+            // Anything queued behind the pending batch rides along with it
+            while let Ok(newer) = batches.try_recv() {
+                pending.merge(newer);
+            }
+            match sink.send(&pending).await {
+                Ok(result) => {
+                    debug!(
+                        "Batch written: {} applied, {} skipped, {} deleted",
+                        result.applied, result.skipped, result.deleted
+                    );
+                    break;
+                }
+                Err(e) => {
+                    warn!("Batch write failed, retrying in {:?}: {}", delay, e);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RETRY_BACKOFF);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands;
+    use crate::mutations::{self, Mutation};
+    use crate::objects::{bootstrap, component, GOD, ROOM_ZERO};
+    use shared::dump::write_dump;
+    use std::sync::{Arc, Mutex};
+
+    /// Every object in the world, in id order
+    fn snapshot_all(world: &mut World) -> Vec<ObjectRecord> {
+        let entities: Vec<Entity> = world.query::<Entity>().iter(world).collect();
+        let mut records: Vec<_> = entities
+            .into_iter()
+            .filter_map(|entity| snapshot(world, entity))
+            .collect();
+        records.sort_by_key(|record| record.id);
+        records
+    }
+
+    fn ids(batch: &WriteBatch) -> Vec<(u64, u64)> {
+        batch.upserts.iter().map(|r| (r.id.0, r.version)).collect()
+    }
+
+    #[test]
+    fn test_collect_only_changed_objects() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        assert_eq!(ids(&collect(&mut world)), vec![(0, 1), (1, 1)]);
+        assert!(collect(&mut world).is_empty());
+
+        let lamp = mutations::apply(
+            &mut world,
+            GOD,
+            Mutation::Create {
+                kind: ObjectKind::Thing,
+                name: "Lamp".to_string(),
+            },
+        )
+        .unwrap();
+        mutations::apply(
+            &mut world,
+            GOD,
+            Mutation::SetAttr {
+                target: ROOM_ZERO,
+                name: "script".to_string(),
+                value: "fn on_enter(actor) {}".to_string(),
+            },
+        )
+        .unwrap();
+
+        let batch = collect(&mut world);
+        assert_eq!(ids(&batch), vec![(0, 2), (lamp.0, 1)]);
+        let room = &batch.upserts[0];
+        assert_eq!(room.script.as_deref(), Some("fn on_enter(actor) {}"));
+        assert!(room.attributes.is_empty());
+
+        mutations::apply(&mut world, GOD, Mutation::Destroy { target: lamp }).unwrap();
+        let batch = collect(&mut world);
+        assert!(batch.upserts.is_empty());
+        assert_eq!(batch.deletes, vec![lamp]);
+    }
+
+    #[test]
+    fn test_dump_restore_round_trip() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        let run = |world: &mut World, input: &str| commands::run(world, GOD, input).unwrap();
+        run(&mut world, "@dig Hall");
+        run(&mut world, "@create Brass Key");
+        run(&mut world, "@open North=Hall");
+        run(&mut world, "@lock North=Brass Key | =me");
+        run(&mut world, "@set Brass Key=DARK");
+        run(&mut world, "&desc Brass Key=Small and shiny.");
+        run(&mut world, "@attrflag Brass Key/desc=hidden");
+        run(&mut world, "@pcreate Alice");
+        run(&mut world, "@chown Brass Key=Alice");
+        run(&mut world, "&script Brass Key=fn on_use(actor) { 1 }");
+        run(&mut world, "@attrflag Brass Key/script=wizard");
+        run(&mut world, "@attrflag Brass Key/script=hidden");
+        collect(&mut world);
+
+        let dump = write_dump(&snapshot_all(&mut world));
+        let mut restored = World::new();
+        restore(&mut restored, &read_dump(&dump).unwrap()).unwrap();
+
+        assert_eq!(write_dump(&snapshot_all(&mut restored)), dump);
+        assert!(collect(&mut restored).is_empty());
+        // The script keeps its flags and who set it
+        let key = commands::match_object(&world, GOD, "Brass Key").unwrap();
+        let script = |world: &World| {
+            component::<Attributes>(world, key)
+                .and_then(|attributes| attributes.get(SCRIPT_ATTR))
+                .cloned()
+        };
+        let restored_script = script(&restored).unwrap();
+        assert_eq!(restored_script.flags, AttrFlags::WIZARD | AttrFlags::HIDDEN);
+        assert_eq!(restored_script.owner, GOD);
+        assert_eq!(Some(restored_script), script(&world));
+        let next = restored.resource_mut::<ObjectRegistry>().allocate();
+        assert_eq!(next, world.resource_mut::<ObjectRegistry>().allocate());
+    }
+
+    /// Fails the first `failures` sends, then records what it receives
+    struct FlakySink {
+        failures: Mutex<u32>,
+        received: Arc<Mutex<Vec<WriteBatch>>>,
+    }
+
+    impl BatchSink for FlakySink {
+        async fn send(&self, batch: &WriteBatch) -> Result<BatchResult, String> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err("unavailable".to_string());
+            }
+            self.received.lock().unwrap().push(batch.clone());
+            Ok(BatchResult::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writer_retries_and_drains_on_shutdown() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = FlakySink {
+            failures: Mutex::new(3),
+            received: received.clone(),
+        };
+        let (writer, task) = spawn_writer(sink, Duration::from_millis(1));
+
+        flush(&mut world, &writer);
+        mutations::apply(
+            &mut world,
+            GOD,
+            Mutation::SetAttr {
+                target: GOD,
+                name: "desc".to_string(),
+                value: "Bearded.".to_string(),
+            },
+        )
+        .unwrap();
+        flush(&mut world, &writer);
+        drop(writer);
+        task.await.unwrap();
+
+        // Every change arrives, and God's newest version wins
+        let mut delivered = WriteBatch::default();
+        for batch in received.lock().unwrap().drain(..) {
+            delivered.merge(batch);
+        }
+        assert_eq!(ids(&delivered), vec![(0, 1), (1, 2)]);
+        assert_eq!(delivered.upserts[1].attributes[0].value, "Bearded.");
+    }
+}
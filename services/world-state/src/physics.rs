@@ -0,0 +1,520 @@
+//! Physics Sync
+//!
+//! physics-service simulates every object that has a body, so that
+//! physics load scales apart from world logic. An object has a body when
+//! it stands in a room with a position, and either is a player or has a
+//! `COLLIDER` attribute (see `shared::physics`).
+//!
+//! Each tick [`sync`] works out which bodies changed and sends them to
+//! physics-service in one [`BodyBatch`]. The simulation steps at
+//! `PHYSICS_TIMESTEP` and streams back [`Frame`]s, which [`receive`]
+//! applies: dynamic bodies get their new position and rotation, and
+//! contacts go on to script handlers (see `collisions`). Players
+//! and other kinematic bodies are only ever moved by world-state, which
+//! physics-service then pushes dynamic bodies out of the way of.
+//!
+//! A background task (see [`spawn_link`]) holds the gRPC connection. When
+//! physics-service (re)connects the next sync resends every body, since
+//! it may have restarted with none.
+//!
+//! # Learning Note
+//! A body's position travels both ways, so [`PhysicsSync`] remembers the
+//! last state each side saw. Applying a frame updates that memory along
+//! with the `Position` component, so the move physics-service made is
+//! not echoed back to it as a change.
+
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+use shared::components::{Attributes, Location, ObjectId, ObjectKind, Position, Rotation};
+use shared::physics::{self, BodyDef, BODY_ATTR, COLLIDER_ATTR, PLAYER_BODY};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::objects::ObjectRegistry;
+
+/// Generated gRPC types for `shared/proto/physics.proto`
+#[allow(clippy::result_large_err)]
+pub mod proto {
+    tonic::include_proto!("physics");
+}
+
+use proto::physics_client::PhysicsClient;
+use proto::{BodyBatch, Frame, SubscribeRequest};
+
+/// Delay before reconnecting after physics-service went away
+pub const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Objects whose body may have changed
+type Tracked = Or<(
+    Added<ObjectId>,
+    Changed<ObjectKind>,
+    Changed<Location>,
+    Changed<Position>,
+    Changed<Rotation>,
+    Changed<Attributes>,
+)>;
+
+/// What physics-service knows about our bodies
+#[derive(Resource)]
+pub struct PhysicsSync {
+    changed: SystemState<(
+        Query<'static, 'static, Entity, Tracked>,
+        RemovedComponents<'static, 'static, ObjectId>,
+    )>,
+    /// The last state sent or received for each body, by entity
+    bodies: HashMap<Entity, proto::Body>,
+    /// physics-service may have none of our bodies; send them all
+    reset: bool,
+}
+
+impl FromWorld for PhysicsSync {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            changed: SystemState::new(world),
+            bodies: HashMap::new(),
+            reset: true,
+        }
+    }
+}
+
+/// Something physics-service said
+#[derive(Debug)]
+pub enum FromPhysics {
+    /// A new connection; it may not know any bodies
+    Connected,
+    /// One simulation step's results
+    Frame(Frame),
+}
+
+/// Bodies that changed since the last call, or `None` if nothing did
+///
+/// The first call, and the first after [`FromPhysics::Connected`], sends
+/// every body in a reset batch.
+pub fn sync(world: &mut World) -> Option<BodyBatch> {
+    world.init_resource::<PhysicsSync>();
+    world.resource_scope(|world, mut tracker: Mut<PhysicsSync>| {
+        let (changed, mut removed) = tracker.changed.get_mut(world);
+        let mut changed: Vec<Entity> = changed.iter().collect();
+        let removed: Vec<Entity> = removed.read().collect();
+
+        let mut batch = BodyBatch::default();
+        if std::mem::take(&mut tracker.reset) {
+            batch.reset = true;
+            tracker.bodies.clear();
+            changed = world
+                .query_filtered::<Entity, With<ObjectId>>()
+                .iter(world)
+                .collect();
+        }
+        for entity in removed {
+            if let Some(body) = tracker.bodies.remove(&entity) {
+                batch.removed.push(body.id);
+            }
+        }
+        for entity in changed {
+            match body(world, entity) {
+                Some(body) => {
+                    if tracker.bodies.get(&entity) != Some(&body) {
+                        tracker.bodies.insert(entity, body);
+                        batch.upserts.push(body);
+                    }
+                }
+                None => {
+                    if let Some(body) = tracker.bodies.remove(&entity) {
+                        batch.removed.push(body.id);
+                    }
+                }
+            }
+        }
+        batch.upserts.sort_by_key(|body| body.id);
+        batch.removed.sort_unstable();
+
+        let empty = batch.upserts.is_empty() && batch.removed.is_empty();
+        (batch.reset || !empty).then_some(batch)
+    })
+}
+
+/// Apply what physics-service sent, returning the contacts it reported
+/// for `collisions::dispatch`
+pub fn receive(world: &mut World, event: FromPhysics) -> Vec<proto::Contact> {
+    world.init_resource::<PhysicsSync>();
+    match event {
+        FromPhysics::Connected => {
+            info!("Connected to physics-service; sending every body");
+            world.resource_mut::<PhysicsSync>().reset = true;
+            Vec::new()
+        }
+        FromPhysics::Frame(frame) => {
+            world.resource_scope(|world, mut tracker: Mut<PhysicsSync>| {
+                apply_frame(world, &mut tracker, frame)
+            })
+        }
+    }
+}
+
+fn apply_frame(world: &mut World, tracker: &mut PhysicsSync, frame: Frame) -> Vec<proto::Contact> {
+    for transform in frame.transforms {
+        let Some(entity) = world
+            .resource::<ObjectRegistry>()
+            .entity(ObjectId(transform.id))
+        else {
+            continue;
+        };
+        // Only the simulation moves dynamic bodies; anything else in the
+        // frame is stale
+        let Some(body) = tracker.bodies.get_mut(&entity) else {
+            continue;
+        };
+        if body.kind() != proto::BodyKind::Dynamic {
+            continue;
+        }
+        body.position = transform.position;
+        body.rotation = transform.rotation;
+        world.entity_mut(entity).insert((
+            Position(transform.position.map_or(Vec3::ZERO, Vec3::from)),
+            Rotation(transform.rotation.map_or(Quat::IDENTITY, Quat::from)),
+        ));
+    }
+    frame.contacts
+}
+
+/// The body physics-service should have for an object, if any
+fn body(world: &World, entity: Entity) -> Option<proto::Body> {
+    let entity = world.get_entity(entity).ok()?;
+    let id = *entity.get::<ObjectId>()?;
+    let room = entity.get::<Location>()?.0;
+    let registry = world.resource::<ObjectRegistry>();
+    let in_room = registry
+        .entity(room)
+        .and_then(|room| world.get::<ObjectKind>(room))
+        == Some(&ObjectKind::Room);
+    if !in_room {
+        return None;
+    }
+    let position = entity.get::<Position>()?.0;
+    let def = match entity.get::<ObjectKind>()? {
+        ObjectKind::Player => PLAYER_BODY,
+        _ => {
+            let attribute = |name| {
+                entity
+                    .get::<Attributes>()
+                    .and_then(|attributes| attributes.get(name))
+                    .map(|attribute| attribute.value.as_str())
+            };
+            match BodyDef::parse(attribute(COLLIDER_ATTR), attribute(BODY_ATTR)) {
+                Ok(def) => def?,
+                Err(e) => {
+                    debug!("{} has no body: {}", id, e);
+                    return None;
+                }
+            }
+        }
+    };
+    let rotation = entity.get::<Rotation>().map_or(Quat::IDENTITY, |r| r.0);
+    Some(proto::Body {
+        id: id.0,
+        room: room.0,
+        kind: proto::BodyKind::from(def.kind).into(),
+        shape: Some(def.shape.into()),
+        sensor: def.sensor,
+        position: Some(position.into()),
+        rotation: Some(rotation.into()),
+    })
+}
+
+impl From<Vec3> for proto::Vec3 {
+    fn from(v: Vec3) -> Self {
+        Self {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+        }
+    }
+}
+
+impl From<proto::Vec3> for Vec3 {
+    fn from(v: proto::Vec3) -> Self {
+        Vec3::new(v.x, v.y, v.z)
+    }
+}
+
+impl From<Quat> for proto::Quat {
+    fn from(q: Quat) -> Self {
+        Self {
+            x: q.x,
+            y: q.y,
+            z: q.z,
+            w: q.w,
+        }
+    }
+}
+
+impl From<proto::Quat> for Quat {
+    fn from(q: proto::Quat) -> Self {
+        Quat::from_xyzw(q.x, q.y, q.z, q.w)
+    }
+}
+
+impl From<physics::BodyKind> for proto::BodyKind {
+    fn from(kind: physics::BodyKind) -> Self {
+        match kind {
+            physics::BodyKind::Static => Self::Static,
+            physics::BodyKind::Dynamic => Self::Dynamic,
+            physics::BodyKind::Kinematic => Self::Kinematic,
+        }
+    }
+}
+
+impl From<physics::Shape> for proto::Shape {
+    fn from(shape: physics::Shape) -> Self {
+        use proto::shape::Shape as Kind;
+        let shape = match shape {
+            physics::Shape::Ball { radius } => Kind::Ball(proto::Ball { radius }),
+            physics::Shape::Cuboid { half_extents } => Kind::Cuboid(proto::Cuboid {
+                half_extents: Some(half_extents.into()),
+            }),
+            physics::Shape::Capsule {
+                radius,
+                half_length,
+            } => Kind::Capsule(proto::Capsule {
+                radius,
+                half_length,
+            }),
+        };
+        Self { shape: Some(shape) }
+    }
+}
+
+/// Why the connection to physics-service ended
+#[derive(Debug)]
+pub enum LinkError {
+    /// Could not connect
+    Connect(tonic::transport::Error),
+    /// A call failed or the frame stream broke
+    Rpc(Box<tonic::Status>),
+    /// physics-service ended the frame stream
+    Closed,
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connect(e) => write!(f, "could not connect: {}", e),
+            Self::Rpc(status) => write!(f, "call failed: {}", status),
+            Self::Closed => write!(f, "frame stream closed"),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+impl From<tonic::transport::Error> for LinkError {
+    fn from(e: tonic::transport::Error) -> Self {
+        Self::Connect(e)
+    }
+}
+
+impl From<tonic::Status> for LinkError {
+    fn from(status: tonic::Status) -> Self {
+        Self::Rpc(Box::new(status))
+    }
+}
+
+/// Sends body batches to the link task
+pub struct PhysicsLink(mpsc::UnboundedSender<BodyBatch>);
+
+impl PhysicsLink {
+    /// Queue a batch for physics-service
+    ///
+    /// Batches queued while disconnected are dropped; reconnecting sends
+    /// every body again anyway.
+    pub fn send(&self, batch: BodyBatch) {
+        // The task only stops once this sender is dropped
+        let _ = self.0.send(batch);
+    }
+}
+
+/// Start the task that talks to physics-service at `url`, returning the
+/// world's ends of its channels
+///
+/// The task reconnects every [`RETRY_DELAY`] until it gets through, and
+/// stops once the [`PhysicsLink`] is dropped.
+pub fn spawn_link(
+    url: String,
+) -> (
+    PhysicsLink,
+    mpsc::UnboundedReceiver<FromPhysics>,
+    JoinHandle<()>,
+) {
+    let (batch_tx, mut batch_rx) = mpsc::unbounded_channel();
+    let (event_tx, event_rx) = mpsc::unbounded_channel();
+    let task = tokio::spawn(async move {
+        let mut connected = true;
+        loop {
+            match session(&url, &mut batch_rx, &event_tx, &mut connected).await {
+                Ok(()) => return,
+                // Say so once per outage rather than every retry
+                Err(e) if connected => {
+                    warn!("physics-service at {}: {}; retrying", url, e);
+                    connected = false;
+                }
+                Err(e) => debug!("physics-service at {}: {}", url, e),
+            }
+            tokio::time::sleep(RETRY_DELAY).await;
+            loop {
+                match batch_rx.try_recv() {
+                    Ok(_) => continue,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+        }
+    });
+    (PhysicsLink(batch_tx), event_rx, task)
+}
+
+/// One connection, until it fails (`Err`) or the world stops (`Ok`)
+async fn session(
+    url: &str,
+    batches: &mut mpsc::UnboundedReceiver<BodyBatch>,
+    events: &mpsc::UnboundedSender<FromPhysics>,
+    connected: &mut bool,
+) -> Result<(), LinkError> {
+    let mut client = PhysicsClient::connect(url.to_string()).await?;
+    let mut frames = client.subscribe(SubscribeRequest {}).await?.into_inner();
+    *connected = true;
+    if events.send(FromPhysics::Connected).is_err() {
+        return Ok(());
+    }
+    loop {
+        tokio::select! {
+            batch = batches.recv() => match batch {
+                Some(batch) => {
+                    client.sync_bodies(batch).await?;
+                }
+                None => return Ok(()),
+            },
+            frame = frames.message() => match frame? {
+                Some(frame) => {
+                    if events.send(FromPhysics::Frame(frame)).is_err() {
+                        return Ok(());
+                    }
+                }
+                None => return Err(LinkError::Closed),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutations::{self, Mutation};
+    use crate::objects::{bootstrap, component, GOD, ROOM_ZERO};
+
+    fn set(world: &mut World, target: ObjectId, name: &str, value: &str) {
+        let mutation = Mutation::SetAttr {
+            target,
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        mutations::apply(world, GOD, mutation).unwrap();
+    }
+
+    #[test]
+    fn test_bodies_sync_both_ways() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        let ball = mutations::apply(
+            &mut world,
+            GOD,
+            Mutation::Create {
+                kind: ObjectKind::Thing,
+                name: "Ball".to_string(),
+            },
+        )
+        .unwrap();
+        let entity = world.resource::<ObjectRegistry>().entity(ball).unwrap();
+        world
+            .entity_mut(entity)
+            .insert((Location(ROOM_ZERO), Position(Vec3::new(1.0, 2.0, 3.0))));
+        set(&mut world, ball, "collider", "ball 0.5");
+        set(&mut world, ball, "body", "dynamic");
+
+        // First sync: everything, and only things in rooms with a position
+        // and a collider (God has no position)
+        let batch = sync(&mut world).unwrap();
+        assert!(batch.reset);
+        let [body] = batch.upserts.as_slice() else {
+            panic!("{:?}", batch.upserts);
+        };
+        assert_eq!((body.id, body.room), (ball.0, ROOM_ZERO.0));
+        assert_eq!(body.kind(), proto::BodyKind::Dynamic);
+        assert_eq!(sync(&mut world), None);
+
+        // The simulation moves it; that is not sent back
+        let moved = Vec3::new(1.0, 2.0, 0.5);
+        receive(
+            &mut world,
+            FromPhysics::Frame(Frame {
+                step: 1,
+                transforms: vec![proto::Transform {
+                    id: ball.0,
+                    position: Some(moved.into()),
+                    rotation: Some(Quat::IDENTITY.into()),
+                }],
+                contacts: Vec::new(),
+            }),
+        );
+        assert_eq!(component::<Position>(&world, ball), Some(&Position(moved)));
+        assert_eq!(sync(&mut world), None);
+
+        // Changes made here are
+        set(&mut world, ball, "body", "static");
+        let batch = sync(&mut world).unwrap();
+        assert!(!batch.reset);
+        assert_eq!(batch.upserts[0].kind(), proto::BodyKind::Static);
+        assert_eq!(batch.upserts[0].position, Some(moved.into()));
+
+        // Frames don't move bodies that aren't dynamic
+        receive(
+            &mut world,
+            FromPhysics::Frame(Frame {
+                step: 2,
+                transforms: vec![proto::Transform {
+                    id: ball.0,
+                    position: Some(Vec3::ZERO.into()),
+                    rotation: None,
+                }],
+                contacts: Vec::new(),
+            }),
+        );
+        assert_eq!(component::<Position>(&world, ball), Some(&Position(moved)));
+
+        // Losing the collider or the object removes the body
+        mutations::apply(
+            &mut world,
+            GOD,
+            Mutation::ClearAttr {
+                target: ball,
+                name: "collider".to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(sync(&mut world).unwrap().removed, vec![ball.0]);
+        set(&mut world, ball, "collider", "box 1 1 1");
+        assert_eq!(sync(&mut world).unwrap().upserts.len(), 1);
+        mutations::apply(&mut world, GOD, Mutation::Destroy { target: ball }).unwrap();
+        assert_eq!(sync(&mut world).unwrap().removed, vec![ball.0]);
+
+        // A new connection gets every body again
+        receive(&mut world, FromPhysics::Connected);
+        let batch = sync(&mut world).unwrap();
+        assert!(batch.reset && batch.upserts.is_empty());
+    }
+}
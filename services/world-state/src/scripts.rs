@@ -0,0 +1,420 @@
+//! Script Revisions
+//!
+//! An object's current script lives in its `SCRIPT` attribute, and every
+//! change to it is also recorded as an immutable [`ScriptRevision`] in the
+//! object's [`ScriptHistory`]. Reverting appends a copy of an old revision
+//! instead of rewriting history, so the log always shows what ran when.
+//!
+//! New source is checked by the [`ScriptValidatorHook`] before it is
+//! accepted. A rejected script never becomes current, so the object keeps
+//! running its previous revision.
+//!
+//! The hooks that talk to script-executor don't wait for it: each hands
+//! back an [`Answer`], and the command that asked finishes once it arrives
+//! (see `commands::Reply`).
+//!
+//! # Learning Note
+//! Append-only logs are easy to reason about: nothing is ever edited in
+//! place, so persisting a revision once is enough and "undo" is just
+//! another entry.
+
+use bevy::prelude::*;
+use shared::components::ObjectId;
+use shared::records::ScriptRevisionRecord;
+use shared::scripting::{
+    DebugCommand, DebugReply, DebugStart, JobOutcome, JobOutput, QuotaReport, ScriptDiagnostic,
+    ScriptJob,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::debug::ScriptDebugger;
+use crate::locks::LockScripts;
+use crate::timers::ScriptRunner;
+
+/// How long to wait for script-executor to answer
+const EXECUTOR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One saved version of an object's script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptRevision {
+    /// 1-based revision number, unique per object
+    pub number: u32,
+    /// Full script source; empty when the script was cleared
+    pub source: String,
+    /// Player who made the change
+    pub author: ObjectId,
+    /// Unix seconds
+    pub timestamp: i64,
+    /// Free-form description of the change
+    pub message: String,
+}
+
+impl From<&ScriptRevisionRecord> for ScriptRevision {
+    fn from(record: &ScriptRevisionRecord) -> Self {
+        Self {
+            number: record.number,
+            source: record.source.clone(),
+            author: record.author,
+            timestamp: record.timestamp,
+            message: record.message.clone(),
+        }
+    }
+}
+
+impl From<&ScriptRevision> for ScriptRevisionRecord {
+    fn from(revision: &ScriptRevision) -> Self {
+        Self {
+            number: revision.number,
+            source: revision.source.clone(),
+            author: revision.author,
+            timestamp: revision.timestamp,
+            message: revision.message.clone(),
+        }
+    }
+}
+
+/// Every script revision of an object, oldest first
+#[derive(Component, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptHistory(Vec<ScriptRevision>);
+
+impl ScriptHistory {
+    /// Rebuild a history from stored revisions
+    pub fn from_revisions(mut revisions: Vec<ScriptRevision>) -> Self {
+        revisions.sort_by_key(|revision| revision.number);
+        Self(revisions)
+    }
+
+    /// All revisions, oldest first
+    pub fn revisions(&self) -> &[ScriptRevision] {
+        &self.0
+    }
+
+    /// Look up a revision by number
+    pub fn get(&self, number: u32) -> Option<&ScriptRevision> {
+        self.0.iter().find(|revision| revision.number == number)
+    }
+
+    /// The revision that is currently live
+    pub fn latest(&self) -> Option<&ScriptRevision> {
+        self.0.last()
+    }
+
+    /// Append a revision, returning its number
+    pub fn push(&mut self, source: String, author: ObjectId, message: String) -> u32 {
+        let number = self.latest().map_or(1, |revision| revision.number + 1);
+        self.0.push(ScriptRevision {
+            number,
+            source,
+            author,
+            timestamp: now(),
+            message,
+        });
+        number
+    }
+}
+
+/// An answer from script-executor that hasn't arrived yet
+///
+/// Owns everything it needs, so it can be awaited off the main loop.
+pub type Answer<T> = Pin<Box<dyn Future<Output = Result<T, String>> + Send>>;
+
+/// Checks script source before it is accepted
+pub trait ScriptValidator: Send + Sync {
+    /// Answer with a description of the problem if `source` must be rejected
+    fn validate(&self, source: &str) -> Answer<()>;
+}
+
+/// Validator used for new scripts
+///
+/// World-state cannot compile scripts itself, so in the running service
+/// this is an [`ExecutorClient`]. Without a hook every script is accepted.
+#[derive(Resource)]
+pub struct ScriptValidatorHook(pub Box<dyn ScriptValidator>);
+
+/// Reports how much script time an owner has used
+pub trait QuotaSource: Send + Sync {
+    /// Usage charged to `owner` over the last minute
+    fn report(&self, owner: ObjectId) -> Answer<QuotaReport>;
+}
+
+/// Where `@quota` gets its numbers
+#[derive(Resource)]
+pub struct QuotaSourceHook(pub Box<dyn QuotaSource>);
+
+/// Runs a script job for a command that shows its output
+///
+/// The object-safe counterpart of [`ScriptRunner`], so tests can install
+/// their own.
+pub trait ScriptCaller: Send + Sync {
+    /// Run `job` to completion
+    fn call(&self, job: &ScriptJob) -> Answer<JobOutput>;
+}
+
+/// Where `@trace` runs scripts
+#[derive(Resource)]
+pub struct ScriptCallerHook(pub Box<dyn ScriptCaller>);
+
+/// Usage charged to `owner`, if a quota source is configured
+pub fn quota_report(world: &World, owner: ObjectId) -> Option<Answer<QuotaReport>> {
+    world
+        .get_resource::<QuotaSourceHook>()
+        .map(|hook| hook.0.report(owner))
+}
+
+/// Client for script-executor's HTTP API
+///
+/// Backs the [`ScriptValidatorHook`] (`POST /validate`), the
+/// [`QuotaSourceHook`] (`GET /quota/:owner`), the timer dispatcher's
+/// [`ScriptRunner`] and the [`ScriptCallerHook`] (both `POST /jobs`) and
+/// `@debug`'s [`ScriptDebugger`] (`/debug`) and the `LockScriptHook`
+/// for `$function` locks (`POST /jobs` too). Cloning is cheap, and each
+/// [`Answer`] holds its own clone. If the executor can't be reached,
+/// scripts are rejected rather than accepted unchecked.
+#[derive(Clone)]
+pub struct ExecutorClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl ExecutorClient {
+    /// Talk to the script-executor at `base_url`
+    pub fn new(base_url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(EXECUTOR_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    async fn diagnostics(&self, source: &str) -> Result<Vec<ScriptDiagnostic>, reqwest::Error> {
+        self.client
+            .post(format!("{}/validate", self.base_url))
+            .body(source.to_string())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn quota(&self, owner: ObjectId) -> Result<QuotaReport, reqwest::Error> {
+        self.client
+            .get(format!("{}/quota/{}", self.base_url, owner.0))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn debug(request: reqwest::RequestBuilder) -> Result<DebugReply, String> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("could not reach the script executor: {}", e))?;
+        if !response.status().is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(body);
+        }
+        response.json().await.map_err(|e| e.to_string())
+    }
+}
+
+impl ScriptRunner for ExecutorClient {
+    async fn run(&self, job: &ScriptJob) -> Result<JobOutput, String> {
+        let response = self
+            .client
+            .post(format!("{}/jobs", self.base_url))
+            .json(job)
+            .send()
+            .await
+            .map_err(|e| format!("could not reach the script executor: {}", e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("script executor returned {}: {}", status, body));
+        }
+        let outcome: JobOutcome = response.json().await.map_err(|e| e.to_string())?;
+        match (outcome.output, outcome.error) {
+            (Some(output), None) => Ok(output),
+            (_, error) => Err(error.unwrap_or_else(|| "job produced no output".to_string())),
+        }
+    }
+}
+
+impl ScriptValidator for ExecutorClient {
+    fn validate(&self, source: &str) -> Answer<()> {
+        let (executor, source) = (self.clone(), source.to_string());
+        Box::pin(async move {
+            let diagnostics = executor
+                .diagnostics(&source)
+                .await
+                .map_err(|e| format!("could not reach the script validator: {}", e))?;
+            if diagnostics.is_empty() {
+                return Ok(());
+            }
+            let messages: Vec<String> = diagnostics.iter().map(ToString::to_string).collect();
+            Err(messages.join("; "))
+        })
+    }
+}
+
+impl ScriptCaller for ExecutorClient {
+    fn call(&self, job: &ScriptJob) -> Answer<JobOutput> {
+        let (executor, job) = (self.clone(), job.clone());
+        Box::pin(async move { executor.run(&job).await })
+    }
+}
+
+impl LockScripts for ExecutorClient {
+    fn call(&self, job: &ScriptJob) -> Answer<bool> {
+        let (executor, job) = (self.clone(), job.clone());
+        Box::pin(async move { Ok(executor.run(&job).await?.value == "true") })
+    }
+}
+
+impl QuotaSource for ExecutorClient {
+    fn report(&self, owner: ObjectId) -> Answer<QuotaReport> {
+        let executor = self.clone();
+        Box::pin(async move {
+            executor
+                .quota(owner)
+                .await
+                .map_err(|e| format!("could not reach the script executor: {}", e))
+        })
+    }
+}
+
+impl ScriptDebugger for ExecutorClient {
+    fn start(&self, start: &DebugStart) -> Answer<DebugReply> {
+        let url = format!("{}/debug", self.base_url);
+        Box::pin(Self::debug(self.client.post(url).json(start)))
+    }
+
+    fn command(&self, session: u64, command: &DebugCommand) -> Answer<DebugReply> {
+        let url = format!("{}/debug/{}", self.base_url, session);
+        Box::pin(Self::debug(self.client.post(url).json(command)))
+    }
+
+    fn abort(&self, session: u64) -> Answer<()> {
+        let request = self
+            .client
+            .delete(format!("{}/debug/{}", self.base_url, session));
+        Box::pin(async move {
+            request
+                .send()
+                .await
+                .map(drop)
+                .map_err(|e| format!("could not reach the script executor: {}", e))
+        })
+    }
+}
+
+/// Validate `source` with the configured hook
+///
+/// `None` without a hook, in which case every script is accepted.
+pub fn validate(world: &World, source: &str) -> Option<Answer<()>> {
+    world
+        .get_resource::<ScriptValidatorHook>()
+        .map(|hook| hook.0.validate(source))
+}
+
+/// Current time in Unix seconds
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Render Unix seconds as `YYYY-MM-DD HH:MM:SS UTC`
+pub fn format_timestamp(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86_400);
+    let secs = timestamp.rem_euclid(86_400);
+
+    // Civil-from-days (proleptic Gregorian calendar)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Line diff from `old` to `new`
+///
+/// Unchanged lines start with two spaces, removed lines with `- ` and
+/// added lines with `+ `.
+pub fn diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Longest common subsequence table, filled from the end
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            out.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old = "fn a() {\n    1\n}";
+        let new = "fn a() {\n    2\n}\nfn b() {}";
+        assert_eq!(
+            diff(old, new),
+            "  fn a() {\n-     1\n+     2\n  }\n+ fn b() {}"
+        );
+        assert_eq!(diff("", "x"), "+ x");
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_709_210_096), "2024-02-29 12:34:56 UTC");
+    }
+}
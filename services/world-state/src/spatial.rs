@@ -0,0 +1,277 @@
+//! Spatial Index
+//!
+//! Keeps a `shared::spatial::SpatialIndex` of every object standing in a
+//! room with a position, so systems can ask what is in front of or near
+//! something without scanning the room. Each object's sphere comes from
+//! its collider (see `shared::physics`), or is a small point otherwise.
+//!
+//! [`refresh`] brings the index up to date from the components that
+//! changed and runs every tick, so queries see the world as of the last
+//! tick. Systems query through [`Spatial::index`], passing
+//! [`visible_to`] as the filter when the answer goes to a player or a
+//! script.
+//!
+//! Scripts query through host functions on script-executor, which can't
+//! see the world. [`surroundings`] gives a job what its object can see
+//! instead: the objects nearest it that its owner may see, up to
+//! [`MAX_SURROUNDINGS`].
+//!
+//! # Learning Note
+//! Like `PhysicsSync`, [`Spatial`] reads change detection through a
+//! `SystemState` so that only objects whose placement may have changed
+//! are looked at each tick, which keeps an idle world's refresh close to
+//! free.
+
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+use shared::components::{Attributes, Location, ObjectId, ObjectKind, Position, Rotation};
+use shared::physics::{BodyDef, BODY_ATTR, COLLIDER_ATTR, PLAYER_BODY};
+use shared::scripting::{ModuleSource, Surroundings};
+use shared::spatial::{Placed, SpatialIndex, FORWARD, MAX_RANGE, POINT_RADIUS};
+use std::collections::HashMap;
+
+use crate::objects::{component, ObjectRegistry};
+use crate::permissions::{self, Subject};
+
+/// Most objects sent to a script as its surroundings
+pub const MAX_SURROUNDINGS: usize = 128;
+
+/// Objects whose placement may have changed
+type Tracked = Or<(
+    Added<ObjectId>,
+    Changed<ObjectKind>,
+    Changed<Location>,
+    Changed<Position>,
+    Changed<Attributes>,
+)>;
+
+/// Where every object in a room is
+#[derive(Resource)]
+pub struct Spatial {
+    changed: SystemState<(
+        Query<'static, 'static, Entity, Tracked>,
+        RemovedComponents<'static, 'static, ObjectId>,
+    )>,
+    /// The object each indexed entity is, to find it once despawned
+    indexed: HashMap<Entity, ObjectId>,
+    index: SpatialIndex,
+    /// Nothing has been indexed yet; look at every object
+    fresh: bool,
+}
+
+impl FromWorld for Spatial {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            changed: SystemState::new(world),
+            indexed: HashMap::new(),
+            index: SpatialIndex::default(),
+            fresh: true,
+        }
+    }
+}
+
+impl Spatial {
+    /// The index, as of the last [`refresh`]
+    pub fn index(&self) -> &SpatialIndex {
+        &self.index
+    }
+}
+
+/// Bring the index up to date
+pub fn refresh(world: &mut World) {
+    world.init_resource::<Spatial>();
+    world.resource_scope(|world, mut spatial: Mut<Spatial>| {
+        let (changed, mut removed) = spatial.changed.get_mut(world);
+        let mut changed: Vec<Entity> = changed.iter().collect();
+        let removed: Vec<Entity> = removed.read().collect();
+        if std::mem::take(&mut spatial.fresh) {
+            changed = world
+                .query_filtered::<Entity, With<ObjectId>>()
+                .iter(world)
+                .collect();
+        }
+        for entity in removed {
+            if let Some(id) = spatial.indexed.remove(&entity) {
+                spatial.index.remove(id);
+            }
+        }
+        for entity in changed {
+            match placement(world, entity) {
+                Some((room, placed)) => {
+                    spatial.indexed.insert(entity, placed.id);
+                    spatial.index.upsert(room, placed);
+                }
+                None => {
+                    if let Some(id) = spatial.indexed.remove(&entity) {
+                        spatial.index.remove(id);
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// The room an object stands in and its sphere, if it is in one with a
+/// position
+fn placement(world: &World, entity: Entity) -> Option<(ObjectId, Placed)> {
+    let entity = world.get_entity(entity).ok()?;
+    let id = *entity.get::<ObjectId>()?;
+    let room = entity.get::<Location>()?.0;
+    let registry = world.resource::<ObjectRegistry>();
+    let in_room = registry
+        .entity(room)
+        .and_then(|room| world.get::<ObjectKind>(room))
+        == Some(&ObjectKind::Room);
+    if !in_room {
+        return None;
+    }
+    let position = entity.get::<Position>()?.0;
+    let radius = match entity.get::<ObjectKind>()? {
+        ObjectKind::Player => PLAYER_BODY.shape.bounding_radius(),
+        _ => {
+            let attribute = |name| {
+                entity
+                    .get::<Attributes>()
+                    .and_then(|attributes| attributes.get(name))
+                    .map(|attribute| attribute.value.as_str())
+            };
+            // A bad collider is reported by physics sync; here it is
+            // simply a point
+            match BodyDef::parse(attribute(COLLIDER_ATTR), attribute(BODY_ATTR)) {
+                Ok(Some(def)) => def.shape.bounding_radius(),
+                _ => POINT_RADIUS,
+            }
+        }
+    };
+    Some((
+        room,
+        Placed {
+            id,
+            position,
+            radius,
+        },
+    ))
+}
+
+/// A query filter keeping the objects `viewer` may see
+pub fn visible_to(world: &World, viewer: ObjectId) -> impl FnMut(ObjectId) -> bool + '_ {
+    let viewer = Subject::load(world, viewer);
+    move |id| {
+        let target = Subject::load(world, id);
+        matches!((&viewer, &target), (Some(viewer), Some(target)) if permissions::can_see(viewer, target))
+    }
+}
+
+/// What `object` can see around it, for a job running `source` (with
+/// `modules`) on behalf of `owner`
+///
+/// `None` if the script can't make spatial queries, so most jobs carry
+/// nothing, or if the object isn't standing in a room.
+pub fn surroundings(
+    world: &World,
+    object: ObjectId,
+    owner: ObjectId,
+    source: &str,
+    modules: &[ModuleSource],
+) -> Option<Surroundings> {
+    let wanted = Surroundings::wanted_by(source)
+        || modules
+            .iter()
+            .any(|module| Surroundings::wanted_by(&module.source));
+    if !wanted {
+        return None;
+    }
+    let index = world.get_resource::<Spatial>()?.index();
+    let (room, me) = index.get(object)?;
+    let mut visible = visible_to(world, owner);
+    let objects = index
+        .nearest(room, me.position, MAX_SURROUNDINGS, MAX_RANGE, |id| {
+            id != object && visible(id)
+        })
+        .into_iter()
+        .filter_map(|(id, _)| index.get(id).map(|(_, placed)| placed))
+        .collect();
+    let rotation = component::<Rotation>(world, object).map_or(Quat::IDENTITY, |r| r.0);
+    Some(Surroundings {
+        position: me.position,
+        facing: (rotation * FORWARD).normalize_or(FORWARD),
+        objects,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands;
+    use crate::objects::{bootstrap, GOD, ROOM_ZERO};
+
+    fn place(world: &mut World, name: &str, position: Vec3) -> ObjectId {
+        commands::run(world, GOD, &format!("@create {}", name)).unwrap();
+        let id = commands::match_object(world, GOD, name).unwrap();
+        let entity = world.resource::<ObjectRegistry>().entity(id).unwrap();
+        world
+            .entity_mut(entity)
+            .insert((Location(ROOM_ZERO), Position(position)));
+        id
+    }
+
+    #[test]
+    fn test_index_follows_objects() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        let crate_ = place(&mut world, "Crate", Vec3::new(0.0, 5.0, 0.0));
+        let barrel = place(&mut world, "Barrel", Vec3::new(0.0, 20.0, 0.0));
+        refresh(&mut world);
+
+        let ahead = |world: &World| {
+            let index = world.resource::<Spatial>().index();
+            index
+                .raycast(ROOM_ZERO, Vec3::ZERO, FORWARD, MAX_RANGE, |_| true)
+                .map(|hit| hit.id)
+        };
+        assert_eq!(ahead(&world), Some(crate_));
+
+        // A collider makes the barrel big enough to block the way
+        commands::run(&mut world, GOD, "&collider Barrel = ball 16").unwrap();
+        refresh(&mut world);
+        assert_eq!(ahead(&world), Some(barrel));
+
+        commands::run(&mut world, GOD, "@destroy Barrel").unwrap();
+        refresh(&mut world);
+        assert_eq!(ahead(&world), Some(crate_));
+        assert_eq!(world.resource::<Spatial>().index().get(barrel), None);
+    }
+
+    #[test]
+    fn test_surroundings_respect_visibility() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        let lamp = place(&mut world, "Lamp", Vec3::ZERO);
+        let near = place(&mut world, "Near", Vec3::new(1.0, 0.0, 0.0));
+        let far = place(&mut world, "Far", Vec3::new(MAX_RANGE * 2.0, 0.0, 0.0));
+        let dark = place(&mut world, "Shadow", Vec3::new(2.0, 0.0, 0.0));
+        commands::run(&mut world, GOD, "@set Shadow = DARK").unwrap();
+        commands::run(&mut world, GOD, "@pcreate Mortal").unwrap();
+        let mortal = commands::match_object(&world, GOD, "Mortal").unwrap();
+        refresh(&mut world);
+
+        let source = "fn on_use(actor) { nearest(position(), 5) }";
+        assert_eq!(
+            surroundings(&world, lamp, GOD, "fn on_use(actor) { 1 }", &[]),
+            None
+        );
+        let seen = |owner| {
+            let surroundings = surroundings(&world, lamp, owner, source, &[]).unwrap();
+            assert_eq!(surroundings.facing, FORWARD);
+            surroundings
+                .objects
+                .iter()
+                .map(|placed| placed.id)
+                .collect::<Vec<_>>()
+        };
+        // God owns the shadow; the mortal doesn't
+        assert_eq!(seen(GOD), [near, dark]);
+        assert!(!seen(mortal).contains(&dark));
+        assert!(!seen(GOD).contains(&far));
+    }
+}
@@ -0,0 +1,60 @@
+//! Tick Statistics
+//!
+//! Counts how often the world tick runs late. The main loop is one task
+//! doing everything: ticks, gateway requests and persistence flushes. If
+//! those keep it busy for longer than [`TICK_INTERVAL`], the next tick
+//! starts late, and players feel it as lag. [`record`] is called at the
+//! start of every tick with how late it is, and `GET /stats` reports the
+//! totals as [`TickStats`].
+
+use bevy::prelude::*;
+use shared::gateway::TickStats;
+use std::time::Duration;
+
+use crate::timers::TICK_INTERVAL;
+
+/// Totals so far
+#[derive(Resource, Default)]
+struct Totals(TickStats);
+
+/// Count a tick that started `delay` after it was due
+pub fn record(world: &mut World, delay: Duration) {
+    let mut totals = world.get_resource_or_insert_with(Totals::default);
+    let stats = &mut totals.0;
+    stats.ticks += 1;
+    if delay > TICK_INTERVAL {
+        stats.overruns += 1;
+    }
+    stats.max_delay_ms = stats.max_delay_ms.max(delay.as_millis() as u64);
+}
+
+/// The totals so far
+pub fn report(world: &World) -> TickStats {
+    world
+        .get_resource::<Totals>()
+        .map(|totals| totals.0)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_late_ticks_are_overruns() {
+        let mut world = World::new();
+        assert_eq!(report(&world), TickStats::default());
+
+        record(&mut world, Duration::from_millis(3));
+        record(&mut world, TICK_INTERVAL);
+        record(&mut world, TICK_INTERVAL * 3);
+        assert_eq!(
+            report(&world),
+            TickStats {
+                ticks: 3,
+                overruns: 1,
+                max_delay_ms: 3 * TICK_INTERVAL.as_millis() as u64,
+            }
+        );
+    }
+}
@@ -0,0 +1,462 @@
+//! Script Timers
+//!
+//! When a script calls `wait(seconds, fn)` or `schedule_at(time, fn)`, the
+//! executor hands the request back with the run's result and world-state
+//! stores it as a [`ScriptTimer`] on the object that ran. Timers live in
+//! the object's [`ScriptTimers`] component, so they are written behind to
+//! persistence with the rest of the object and come back on restart.
+//!
+//! Every [`TICK_INTERVAL`] the main loop calls [`tick`], which:
+//! 1. applies the results of runs that finished since the last tick,
+//!    turning any timers they requested into [`Mutation::ScheduleTimer`]s
+//! 2. removes every timer that is due and sends a [`JobPriority::Tick`] job
+//!    for it to the [`Dispatcher`]
+//!
+//! Timers that came due while the service was down fire on the first tick
+//! after it starts. A timer is removed when it fires, not when its run
+//! finishes, so a crash in between loses that one call rather than
+//! repeating it.
+//!
+//! # Learning Note
+//! The world never waits for a script. Jobs go out over a channel and
+//! results come back over another, and the tick drains whatever has
+//! arrived. This keeps the ECS single-threaded and the tick short no
+//! matter how slow the executor is.
+
+use bevy::prelude::*;
+use shared::components::{Attributes, Flags, ObjectId, Owner};
+use shared::records::TimerRecord;
+use shared::scripting::{JobOutput, JobPriority, ScriptJob, TimerRequest};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::libraries;
+use crate::locks::SCRIPT_ATTR;
+use crate::mutations::{self, Mutation};
+use crate::spatial;
+
+/// How often the world ticks and due timers are fired
+pub const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Most timers one object may have pending
+pub const MAX_TIMERS_PER_OBJECT: usize = 32;
+
+/// A pending call to a function in an object's script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptTimer {
+    /// World-unique id, for `@halt/timer`
+    pub id: u64,
+    /// Script function to call
+    pub function: String,
+    /// When to call it, in Unix milliseconds
+    pub due: i64,
+}
+
+impl From<&TimerRecord> for ScriptTimer {
+    fn from(record: &TimerRecord) -> Self {
+        Self {
+            id: record.id,
+            function: record.function.clone(),
+            due: record.due,
+        }
+    }
+}
+
+impl From<&ScriptTimer> for TimerRecord {
+    fn from(timer: &ScriptTimer) -> Self {
+        Self {
+            id: timer.id,
+            function: timer.function.clone(),
+            due: timer.due,
+        }
+    }
+}
+
+/// Timers pending on one object, soonest first
+#[derive(Component, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptTimers(Vec<ScriptTimer>);
+
+impl ScriptTimers {
+    /// Rebuild from stored timers
+    pub fn from_timers(mut timers: Vec<ScriptTimer>) -> Self {
+        timers.sort_by_key(|timer| (timer.due, timer.id));
+        Self(timers)
+    }
+
+    /// All pending timers, soonest first
+    pub fn timers(&self) -> &[ScriptTimer] {
+        &self.0
+    }
+
+    /// Number of pending timers
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// True if a timer is due at `now`
+    pub fn any_due(&self, now: i64) -> bool {
+        self.0.first().is_some_and(|timer| timer.due <= now)
+    }
+
+    /// Add a timer, keeping the list in firing order
+    pub fn insert(&mut self, timer: ScriptTimer) {
+        let at = self
+            .0
+            .partition_point(|t| (t.due, t.id) <= (timer.due, timer.id));
+        self.0.insert(at, timer);
+    }
+
+    /// Remove one timer, returning false if it isn't here
+    pub fn remove(&mut self, id: u64) -> bool {
+        let before = self.0.len();
+        self.0.retain(|timer| timer.id != id);
+        self.0.len() != before
+    }
+
+    /// Remove every timer, returning how many there were
+    pub fn clear(&mut self) -> usize {
+        std::mem::take(&mut self.0).len()
+    }
+
+    /// Remove and return the timers due at `now`
+    pub fn take_due(&mut self, now: i64) -> Vec<ScriptTimer> {
+        let due = self.0.partition_point(|timer| timer.due <= now);
+        self.0.drain(..due).collect()
+    }
+}
+
+/// Hands out timer ids
+#[derive(Resource, Debug)]
+pub struct TimerIds {
+    next: u64,
+}
+
+impl Default for TimerIds {
+    fn default() -> Self {
+        Self { next: 1 }
+    }
+}
+
+impl TimerIds {
+    /// Reserve the next unused id
+    pub fn allocate(&mut self) -> u64 {
+        let id = self.next;
+        self.next += 1;
+        id
+    }
+
+    /// Note an id that is already in use (e.g. restored from storage)
+    pub fn observe(&mut self, id: u64) {
+        self.next = self.next.max(id + 1);
+    }
+}
+
+/// Find the object a timer is pending on
+pub fn owner_of(world: &mut World, id: u64) -> Option<ObjectId> {
+    world
+        .query::<(&ObjectId, &ScriptTimers)>()
+        .iter(world)
+        .find(|(_, timers)| timers.timers().iter().any(|timer| timer.id == id))
+        .map(|(object, _)| *object)
+}
+
+/// Runs script jobs somewhere else
+pub trait ScriptRunner: Send + Sync + 'static {
+    /// Run one job to completion
+    fn run(&self, job: &ScriptJob) -> impl Future<Output = Result<JobOutput, String>> + Send;
+}
+
+/// A run that finished, on its way back to the world
+#[derive(Debug)]
+pub struct Finished {
+    /// Object whose script ran
+    pub object: ObjectId,
+    /// Function that was called
+    pub function: String,
+    /// What the run produced, or why it failed
+    pub result: Result<JobOutput, String>,
+}
+
+/// World-side end of the script channels
+pub struct Dispatcher {
+    jobs: mpsc::UnboundedSender<(ObjectId, ScriptJob)>,
+    finished: mpsc::UnboundedReceiver<Finished>,
+}
+
+impl Dispatcher {
+    /// Send a job to be run for `object`
+    pub fn send(&self, object: ObjectId, job: ScriptJob) {
+        if self.jobs.send((object, job)).is_err() {
+            warn!("Script dispatcher has stopped; {} was not run", object);
+        }
+    }
+
+    /// Results that have arrived since the last call
+    pub fn finished(&mut self) -> Vec<Finished> {
+        let mut finished = Vec::new();
+        while let Ok(result) = self.finished.try_recv() {
+            finished.push(result);
+        }
+        finished
+    }
+}
+
+/// Start the background task that runs jobs on `runner`
+///
+/// Jobs run concurrently; the executor's own queue provides backpressure.
+/// The task exits once the [`Dispatcher`] is dropped.
+pub fn spawn_dispatcher<R: ScriptRunner>(runner: R) -> (Dispatcher, JoinHandle<()>) {
+    let (jobs, mut queued) = mpsc::unbounded_channel::<(ObjectId, ScriptJob)>();
+    let (done, finished) = mpsc::unbounded_channel();
+    let runner = Arc::new(runner);
+    let task = tokio::spawn(async move {
+        while let Some((object, job)) = queued.recv().await {
+            let runner = runner.clone();
+            let done = done.clone();
+            tokio::spawn(async move {
+                let result = runner.run(&job).await;
+                let function = job.function.unwrap_or_default();
+                // The world may have shut down; nothing to report to
+                let _ = done.send(Finished {
+                    object,
+                    function,
+                    result,
+                });
+            });
+        }
+    });
+    (Dispatcher { jobs, finished }, task)
+}
+
+/// Apply finished runs, then fire every timer due at `now` (Unix ms)
+pub fn tick(world: &mut World, dispatcher: &mut Dispatcher, now: i64) {
+    for finished in dispatcher.finished() {
+        apply(world, finished, now);
+    }
+
+    let mut due = Vec::new();
+    let mut query = world.query::<(&ObjectId, &mut ScriptTimers)>();
+    for (object, mut timers) in query.iter_mut(world) {
+        // Only touch the component (and so mark it changed) when needed
+        if timers.any_due(now) {
+            due.extend(timers.take_due(now).into_iter().map(|t| (*object, t)));
+        }
+    }
+    due.sort_by_key(|(_, timer)| (timer.due, timer.id));
+
+    for (object, timer) in due {
+        match job_for(world, object, &timer) {
+            Some(job) => dispatcher.send(object, job),
+            None => debug!(
+                "Dropping timer {} on {}: object is halted, has no script or can't import its modules",
+                timer.id, object
+            ),
+        }
+    }
+}
+
+/// Turn timers requested by a finished run into scheduled timers
+fn apply(world: &mut World, finished: Finished, now: i64) {
+    match finished.result {
+        Ok(output) => schedule(world, finished.object, output.timers, now),
+        Err(e) => warn!("{}/{} failed: {}", finished.object, finished.function, e),
+    }
+}
+
+/// Schedule the timers a run of `object`'s script requested
+pub fn schedule(world: &mut World, object: ObjectId, requests: Vec<TimerRequest>, now: i64) {
+    for request in requests {
+        // Scripts act with the privileges of their own object
+        let mutation = Mutation::ScheduleTimer {
+            target: object,
+            function: request.function,
+            due: now.saturating_add(request.delay_ms.min(i64::MAX as u64) as i64),
+        };
+        if let Err(e) = mutations::apply(world, object, mutation) {
+            warn!("{} could not set a timer: {}", object, e);
+        }
+    }
+}
+
+/// Build the job for a due timer, unless its object can't run it
+fn job_for(world: &World, object: ObjectId, timer: &ScriptTimer) -> Option<ScriptJob> {
+    background_job(world, object, &timer.function, Vec::new())
+}
+
+/// Build a job calling `function` in `object`'s script on nobody's
+/// behalf, unless the object is halted, has no script or can't import
+/// its modules
+pub fn background_job(
+    world: &World,
+    object: ObjectId,
+    function: &str,
+    args: Vec<String>,
+) -> Option<ScriptJob> {
+    let entity = world
+        .resource::<crate::objects::ObjectRegistry>()
+        .entity(object)?;
+    let entity = world.get_entity(entity).ok()?;
+    if entity.get::<Flags>()?.contains(Flags::HALT) {
+        return None;
+    }
+    let owner = entity.get::<Owner>()?.0;
+    let source = entity.get::<Attributes>()?.get(SCRIPT_ATTR)?.value.clone();
+    let modules = match libraries::resolve(world, owner, &source) {
+        Ok(modules) => modules,
+        Err(e) => {
+            warn!("{}/{} can't run: {}", object, function, e);
+            return None;
+        }
+    };
+    let surroundings = spatial::surroundings(world, object, owner, &source, &modules);
+    Some(ScriptJob {
+        owner,
+        priority: JobPriority::Tick,
+        source,
+        function: Some(function.to_string()),
+        args,
+        trace: false,
+        modules,
+        surroundings,
+    })
+}
+
+/// Current time in Unix milliseconds
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Render a time left as `12s`, `5m 03s` or `2h 07m`
+pub fn format_remaining(ms: i64) -> String {
+    let secs = (ms.max(0) as u64).div_ceil(1000);
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands;
+    use crate::objects::{bootstrap, GOD};
+    use std::sync::Mutex;
+
+    /// Records jobs and answers every run with the same output
+    struct FakeRunner {
+        jobs: Arc<Mutex<Vec<ScriptJob>>>,
+        output: JobOutput,
+    }
+
+    impl ScriptRunner for FakeRunner {
+        async fn run(&self, job: &ScriptJob) -> Result<JobOutput, String> {
+            self.jobs.lock().unwrap().push(job.clone());
+            Ok(self.output.clone())
+        }
+    }
+
+    #[test]
+    fn test_timer_order() {
+        let timer = |id, due| ScriptTimer {
+            id,
+            function: "f".to_string(),
+            due,
+        };
+        let mut timers = ScriptTimers::from_timers(vec![timer(1, 300), timer(2, 100)]);
+        timers.insert(timer(3, 200));
+        timers.insert(timer(4, 100));
+        let ids: Vec<u64> = timers.timers().iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![2, 4, 3, 1]);
+
+        assert!(!timers.any_due(99));
+        let due: Vec<u64> = timers.take_due(200).iter().map(|t| t.id).collect();
+        assert_eq!(due, vec![2, 4, 3]);
+        assert!(timers.remove(1));
+        assert!(timers.timers().is_empty());
+    }
+
+    #[test]
+    fn test_format_remaining() {
+        assert_eq!(format_remaining(-5), "0s");
+        assert_eq!(format_remaining(1), "1s");
+        assert_eq!(format_remaining(185_000), "3m 05s");
+        assert_eq!(format_remaining(7_500_000), "2h 05m");
+    }
+
+    #[tokio::test]
+    async fn test_due_timers_fire_and_reschedule() {
+        let mut world = World::new();
+        bootstrap(&mut world);
+        commands::run(&mut world, GOD, "@create Clock").unwrap();
+        commands::run(&mut world, GOD, "&script Clock = fn tick() { 1 }").unwrap();
+        let clock = commands::match_object(&world, GOD, "Clock").unwrap();
+        for (function, due) in [("tick", 1_000), ("tock", 5_000)] {
+            mutations::apply(
+                &mut world,
+                clock,
+                Mutation::ScheduleTimer {
+                    target: clock,
+                    function: function.to_string(),
+                    due,
+                },
+            )
+            .unwrap();
+        }
+
+        let jobs = Arc::new(Mutex::new(Vec::new()));
+        let runner = FakeRunner {
+            jobs: jobs.clone(),
+            output: JobOutput {
+                value: "1".to_string(),
+                timers: vec![TimerRequest {
+                    function: "tick".to_string(),
+                    delay_ms: 60_000,
+                }],
+                ..JobOutput::default()
+            },
+        };
+        let (mut dispatcher, _task) = spawn_dispatcher(runner);
+
+        tick(&mut world, &mut dispatcher, 999);
+        tick(&mut world, &mut dispatcher, 1_000);
+        while jobs.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let job = jobs.lock().unwrap()[0].clone();
+        assert_eq!(job.function.as_deref(), Some("tick"));
+        assert_eq!(job.priority, JobPriority::Tick);
+        assert_eq!(job.owner, GOD);
+
+        // The run's own `wait` comes back as a new timer on the next tick
+        let pending = |world: &World| -> Vec<(String, i64)> {
+            crate::objects::component::<ScriptTimers>(world, clock)
+                .unwrap()
+                .timers()
+                .iter()
+                .map(|t| (t.function.clone(), t.due))
+                .collect()
+        };
+        while pending(&world).len() < 2 {
+            tokio::task::yield_now().await;
+            tick(&mut world, &mut dispatcher, 2_000);
+        }
+        assert_eq!(
+            pending(&world),
+            vec![("tock".to_string(), 5_000), ("tick".to_string(), 62_000)]
+        );
+
+        // Halted objects drop their timers instead of running them
+        commands::run(&mut world, GOD, "@set Clock = HALT").unwrap();
+        tick(&mut world, &mut dispatcher, 10_000);
+        assert_eq!(pending(&world).len(), 1);
+        assert_eq!(jobs.lock().unwrap().len(), 1);
+    }
+}
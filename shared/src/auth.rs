@@ -0,0 +1,147 @@
+//! Session Tokens
+//!
+//! A session token proves which player a connection belongs to. Whoever
+//! holds the signing secret (auth-service, or a developer's shell) issues
+//! one after checking the player's credentials; the gateways only verify
+//! it, so they never see a password.
+//!
+//! ```text
+//! 42.1767225600.3f0c...e9
+//! ```
+//!
+//! The token is the player's object id, an expiry time in Unix seconds,
+//! and an HMAC-SHA256 over both, hex encoded. Nothing in it is secret;
+//! changing any part of it breaks the signature.
+//!
+//! # Learning Note
+//! A plain hash of "id.expiry" would prove nothing, since anyone can
+//! compute it. An HMAC mixes in a secret key, so only key holders can
+//! produce a valid signature, and `verify_slice` compares it in constant
+//! time so response timing doesn't leak how many bytes matched.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+
+use crate::components::ObjectId;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Secret for signing session tokens
+///
+/// Development only, like `protocol::DEV_PRIVATE_KEY`: deployments set
+/// `SESSION_SECRET` on the services that issue and verify tokens, and
+/// the gateways only fall back to this with `ALLOW_DEV_SESSIONS=1`.
+pub const DEV_SESSION_SECRET: &[u8] = b"worldengine-dev-session-secret";
+
+/// Why a session token was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// The token isn't `<id>.<expiry>.<signature>`
+    Malformed,
+    /// The signature doesn't match: forged, altered or signed with another secret
+    BadSignature,
+    /// The token expired at the given Unix time
+    Expired(u64),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "malformed session token"),
+            Self::BadSignature => write!(f, "invalid session token signature"),
+            Self::Expired(at) => write!(f, "session token expired at {}", at),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Issue a token for `player`, valid until `expires_at` (Unix seconds)
+pub fn issue(secret: &[u8], player: ObjectId, expires_at: u64) -> String {
+    let claims = format!("{}.{}", player.0, expires_at);
+    let signature = mac(secret, &claims).finalize().into_bytes();
+    format!("{}.{:x}", claims, signature)
+}
+
+/// Check `token` at time `now` (Unix seconds), returning its player
+pub fn verify(secret: &[u8], token: &str, now: u64) -> Result<ObjectId, AuthError> {
+    let (claims, signature) = token.rsplit_once('.').ok_or(AuthError::Malformed)?;
+    let (player, expires_at) = claims.split_once('.').ok_or(AuthError::Malformed)?;
+    let player: u64 = player.parse().map_err(|_| AuthError::Malformed)?;
+    let expires_at: u64 = expires_at.parse().map_err(|_| AuthError::Malformed)?;
+    let signature = decode_hex(signature).ok_or(AuthError::Malformed)?;
+
+    mac(secret, claims)
+        .verify_slice(&signature)
+        .map_err(|_| AuthError::BadSignature)?;
+    if now >= expires_at {
+        return Err(AuthError::Expired(expires_at));
+    }
+    Ok(ObjectId(player))
+}
+
+fn mac(secret: &[u8], claims: &str) -> HmacSha256 {
+    // HMAC accepts keys of any length
+    let mut mac = HmacSha256::new_from_slice(secret).expect("any key length is valid");
+    mac.update(claims.as_bytes());
+    mac
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"test secret";
+
+    #[test]
+    fn test_token_round_trip() {
+        let token = issue(SECRET, ObjectId(42), 1_000);
+        assert!(token.starts_with("42.1000."), "{}", token);
+        assert_eq!(verify(SECRET, &token, 999), Ok(ObjectId(42)));
+        assert_eq!(
+            verify(SECRET, &token, 1_000),
+            Err(AuthError::Expired(1_000))
+        );
+    }
+
+    #[test]
+    fn test_tampered_tokens_are_refused() {
+        let token = issue(SECRET, ObjectId(42), 1_000);
+        let (_, signature) = token.rsplit_once('.').unwrap();
+
+        // Someone else's player, or a later expiry, with the old signature
+        let other = format!("1.1000.{}", signature);
+        assert_eq!(verify(SECRET, &other, 0), Err(AuthError::BadSignature));
+        let longer = format!("42.9999.{}", signature);
+        assert_eq!(verify(SECRET, &longer, 0), Err(AuthError::BadSignature));
+        // Signed with a different secret
+        assert_eq!(verify(b"other", &token, 0), Err(AuthError::BadSignature));
+
+        for bad in [
+            "",
+            "42",
+            "42.1000",
+            "x.1000.00",
+            "42.1000.zz",
+            "42.1000.abc",
+        ] {
+            assert_eq!(
+                verify(SECRET, bad, 0),
+                Err(AuthError::Malformed),
+                "{:?}",
+                bad
+            );
+        }
+    }
+}
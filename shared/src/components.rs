@@ -9,13 +9,367 @@
 //! and Systems operate on them. This separation is key to Rust's approach
 //! to game development.
 
-// Uncomment when you start defining components:
-// use bevy::prelude::*;
-
-// TODO: Define your shared components here
-// Example:
-// #[derive(Component, Clone, Debug)]
-// pub struct Position {
-//     pub x: f32,
-//     pub y: f32,
-// }
+use crate::locks::LockExpr;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Stable database reference for a world object (the MUSH `#dbref`)
+///
+/// Bevy `Entity` ids are recycled and differ between processes, so every
+/// world object also carries an `ObjectId` that is used on the wire and in
+/// the database.
+#[derive(
+    Component, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct ObjectId(pub u64);
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// The basic type of a world object
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ObjectKind {
+    /// A location that can contain other objects
+    Room,
+    /// A link between two rooms
+    Exit,
+    /// Any portable or static object
+    Thing,
+    /// A connected (or connectable) character
+    Player,
+}
+
+impl ObjectKind {
+    /// Every kind, in display order
+    pub const ALL: [Self; 4] = [Self::Room, Self::Exit, Self::Thing, Self::Player];
+
+    /// Lower-case name used in storage and dumps
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Room => "room",
+            Self::Exit => "exit",
+            Self::Thing => "thing",
+            Self::Player => "player",
+        }
+    }
+
+    /// Parse a name produced by [`ObjectKind::as_str`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == name)
+    }
+}
+
+/// Display name of a world object
+///
+/// Named `ObjectName` rather than `Name` to avoid clashing with Bevy's
+/// own `Name` component in the prelude.
+#[derive(Component, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectName(pub String);
+
+/// The player that owns an object. Players own themselves.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Owner(pub ObjectId);
+
+/// The object containing this one: a room for players and dropped things,
+/// a player for carried things. Rooms have no location.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location(pub ObjectId);
+
+/// Where an exit leads
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Destination(pub ObjectId);
+
+/// Where an object is in its room's space, in world units
+///
+/// Only objects shown to graphical clients have one; text clients see
+/// [`Location`] alone.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Position(pub Vec3);
+
+// Lightyear interpolates between snapshots as `a * (1 - t) + b * t`
+
+impl std::ops::Add for Position {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl std::ops::Mul<f32> for Position {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self {
+        Self(self.0 * rhs)
+    }
+}
+
+/// Model asset a graphical client draws the object with, as a path
+/// under the client's `assets` folder (a glTF file)
+///
+/// Set from the object's `MODEL` attribute. Objects without one, or whose
+/// model fails to load, are drawn as a placeholder shape for their kind.
+#[derive(Component, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Model(pub String);
+
+/// Which way an object faces
+///
+/// Only sent to graphical clients, in state snapshots (see
+/// [`crate::snapshot`]).
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rotation(pub Quat);
+
+/// How fast an object is moving, in world units per second
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Velocity(pub Vec3);
+
+/// MUSH-style object flags
+///
+/// Stored as a bit set so checks are a single AND. Use the associated
+/// constants to build values, e.g. `Flags::WIZARD | Flags::SAFE`.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Flags(u32);
+
+impl Flags {
+    /// Full administrative privileges
+    pub const WIZARD: Self = Self(1 << 0);
+    /// May see (but not change) everything, like a read-only wizard
+    pub const ROYALTY: Self = Self(1 << 1);
+    /// May create rooms, exits and things
+    pub const BUILDER: Self = Self(1 << 2);
+    /// Hidden from room contents and `look`
+    pub const DARK: Self = Self(1 << 3);
+    /// Cannot be destroyed
+    pub const SAFE: Self = Self(1 << 4);
+    /// Anyone may examine the object and read its attributes
+    pub const VISUAL: Self = Self(1 << 5);
+    /// Scripts on this object do not run
+    pub const HALT: Self = Self(1 << 6);
+
+    /// Every named flag, in display order
+    pub const ALL: [(&'static str, Self); 7] = [
+        ("WIZARD", Self::WIZARD),
+        ("ROYALTY", Self::ROYALTY),
+        ("BUILDER", Self::BUILDER),
+        ("DARK", Self::DARK),
+        ("SAFE", Self::SAFE),
+        ("VISUAL", Self::VISUAL),
+        ("HALT", Self::HALT),
+    ];
+
+    /// No flags set
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Raw bit representation (for persistence)
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Rebuild flags from raw bits, dropping unknown bits
+    pub const fn from_bits_truncate(bits: u32) -> Self {
+        Self(bits & ((1 << Self::ALL.len()) - 1))
+    }
+
+    /// True if every flag in `other` is also set in `self`
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// True if any flag in `other` is set in `self`
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Set the given flags
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// Clear the given flags
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+
+    /// Look up a single flag by name (case-insensitive)
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(flag_name, _)| flag_name.eq_ignore_ascii_case(name))
+            .map(|(_, flag)| *flag)
+    }
+
+    /// Names of the flags that are set
+    pub fn names(self) -> impl Iterator<Item = &'static str> {
+        Self::ALL
+            .into_iter()
+            .filter(move |(_, flag)| self.contains(*flag))
+            .map(|(name, _)| name)
+    }
+}
+
+impl std::ops::BitOr for Flags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Per-attribute permission flags
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AttrFlags(u8);
+
+impl AttrFlags {
+    /// Only wizards and royalty may read the attribute
+    pub const HIDDEN: Self = Self(1 << 0);
+    /// Only wizards may change the attribute
+    pub const WIZARD: Self = Self(1 << 1);
+    /// Only the attribute's owner may change it, even if others control the object
+    pub const LOCKED: Self = Self(1 << 2);
+    /// Anyone may read the attribute
+    pub const VISUAL: Self = Self(1 << 3);
+    /// Legacy MUSH softcode, imported verbatim and never evaluated
+    pub const SOFTCODE: Self = Self(1 << 4);
+
+    /// Every named attribute flag, in display order
+    pub const ALL: [(&'static str, Self); 5] = [
+        ("HIDDEN", Self::HIDDEN),
+        ("WIZARD", Self::WIZARD),
+        ("LOCKED", Self::LOCKED),
+        ("VISUAL", Self::VISUAL),
+        ("SOFTCODE", Self::SOFTCODE),
+    ];
+
+    /// No flags set
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Raw bit representation (for persistence)
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Rebuild flags from raw bits, dropping unknown bits
+    pub const fn from_bits_truncate(bits: u8) -> Self {
+        Self(bits & ((1 << Self::ALL.len()) - 1))
+    }
+
+    /// True if every flag in `other` is also set in `self`
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Set the given flags
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// Clear the given flags
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+
+    /// Look up a single attribute flag by name (case-insensitive)
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(flag_name, _)| flag_name.eq_ignore_ascii_case(name))
+            .map(|(_, flag)| *flag)
+    }
+}
+
+impl std::ops::BitOr for AttrFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// A single named attribute value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribute {
+    /// The stored text
+    pub value: String,
+    /// Player who last set the attribute (checked by `AttrFlags::LOCKED`)
+    pub owner: ObjectId,
+    /// Visibility and write permissions
+    pub flags: AttrFlags,
+}
+
+/// Named attributes on a world object
+///
+/// Attribute names are case-insensitive and stored upper-cased, matching
+/// MUSH conventions (`&desc me=...` and `&DESC me=...` are the same).
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Attributes(BTreeMap<String, Attribute>);
+
+impl Attributes {
+    /// Normalize an attribute name to its canonical (upper-case) form
+    pub fn normalize(name: &str) -> String {
+        name.trim().to_ascii_uppercase()
+    }
+
+    /// Look up an attribute by name
+    pub fn get(&self, name: &str) -> Option<&Attribute> {
+        self.0.get(&Self::normalize(name))
+    }
+
+    /// Look up an attribute by name for modification
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Attribute> {
+        self.0.get_mut(&Self::normalize(name))
+    }
+
+    /// Insert or replace an attribute, returning the previous value
+    pub fn set(&mut self, name: &str, attribute: Attribute) -> Option<Attribute> {
+        self.0.insert(Self::normalize(name), attribute)
+    }
+
+    /// Remove an attribute, returning it if it existed
+    pub fn remove(&mut self, name: &str) -> Option<Attribute> {
+        self.0.remove(&Self::normalize(name))
+    }
+
+    /// Iterate over `(name, attribute)` pairs in name order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Attribute)> {
+        self.0.iter().map(|(name, attr)| (name.as_str(), attr))
+    }
+}
+
+/// Which action a lock guards
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum LockKind {
+    /// Default lock: passing through an exit or picking up a thing
+    Basic,
+    /// Using the object (`on_use` handlers)
+    Use,
+    /// Entering the object
+    Enter,
+}
+
+impl LockKind {
+    /// Parse a lock switch name such as `use` in `@lock/use`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "basic" | "default" => Some(Self::Basic),
+            "use" => Some(Self::Use),
+            "enter" => Some(Self::Enter),
+            _ => None,
+        }
+    }
+}
+
+/// Lock keys set on an object, keyed by the action they guard
+///
+/// A missing lock means the action is unrestricted.
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Locks(pub BTreeMap<LockKind, LockExpr>);
@@ -0,0 +1,203 @@
+//! Gateway Protocol
+//!
+//! Types the gateways exchange with world-state over HTTP. Players never
+//! talk to world-state directly: a gateway holds their connections,
+//! turns what they send into [`WorldCommand`]s, and follows world-state's
+//! change stream to keep its own copy of the world up to date.
+//!
+//! - `POST /commands` takes a list of [`WorldCommand`]s, applies them in
+//!   order and answers with [`CommandReplies`]
+//! - `GET /changes?after=<seq>&rooms=<ids>&objects=<ids>` answers with a
+//!   [`ChangeBatch`] holding every change numbered after `seq` that falls
+//!   under the [`Subscription`] (everything, without `rooms`/`objects`)
+//! - `GET /stats` answers with [`TickStats`], for load tests and
+//!   monitoring
+//!
+//! Every change world-state publishes gets the next sequence number, so a
+//! gateway only has to remember the last one it has applied. Asking from
+//! 0 (a new gateway, or one whose subscription changed) gets a full
+//! snapshot marked `reset`, as does falling too far behind or asking
+//! with sequence numbers from before a world-state restart.
+//!
+//! # Learning Note
+//! Sequence numbers make the stream easy to resume: a gateway that missed
+//! a poll simply asks again from the same place, and nothing is applied
+//! twice or skipped.
+
+use bevy::math::Vec3;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use crate::components::{ObjectId, ObjectKind};
+use crate::protocol::MoveInput;
+
+/// Something a player asked for, on behalf of `actor`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum WorldCommand {
+    /// A console line, run like a typed command
+    Console {
+        /// The player typing
+        actor: ObjectId,
+        /// The line as typed
+        line: String,
+    },
+    /// One tick of movement input
+    Move {
+        /// The player moving
+        actor: ObjectId,
+        /// Direction held this tick
+        input: MoveInput,
+    },
+}
+
+/// Result of one [`WorldCommand`]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum CommandReply {
+    /// Nothing to show (movement)
+    Done,
+    /// Text to show the player
+    Output(String),
+    /// The command failed; the message is shown to the player
+    Failed(String),
+}
+
+/// Answer to `POST /commands`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommandReplies {
+    /// The change stream already includes every effect of the commands
+    /// once it reaches this sequence number
+    pub seq: u64,
+    /// One reply per command, in order
+    pub replies: Vec<CommandReply>,
+}
+
+/// What the gateways get to know about an object
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ObjectState {
+    /// Stable object id
+    pub id: ObjectId,
+    /// Room, exit, thing or player
+    pub kind: ObjectKind,
+    /// Display name
+    pub name: String,
+    /// Containing object, if any
+    pub location: Option<ObjectId>,
+    /// Position in its room, for objects that have moved
+    pub position: Option<Vec3>,
+    /// Model asset to draw it with, if it has one
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Attribute naming an object's model asset (see `components::Model`)
+pub const MODEL_ATTR: &str = "MODEL";
+
+/// One entry in the change stream
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ObjectChange {
+    /// The object is new or changed; this is its whole current state
+    Upsert(ObjectState),
+    /// The object was destroyed
+    Removed(ObjectId),
+}
+
+/// Answer to `GET /changes`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ChangeBatch {
+    /// Sequence number of the last change included; ask for changes after
+    /// this next time
+    pub seq: u64,
+    /// The changes are a full snapshot: forget every object not in it
+    pub reset: bool,
+    /// Changes in the order they happened
+    pub changes: Vec<ObjectChange>,
+}
+
+/// Answer to `GET /stats`: how well world-state keeps up with its tick
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TickStats {
+    /// Ticks run since world-state started
+    pub ticks: u64,
+    /// Ticks that started more than a whole tick interval late, because
+    /// the main loop was busy with something else
+    pub overruns: u64,
+    /// Most a tick has been late, in milliseconds
+    pub max_delay_ms: u64,
+}
+
+/// The part of the world a gateway follows: rooms its players are
+/// interested in, and the players themselves wherever they go
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Subscription {
+    /// Rooms followed, with everything in them
+    pub rooms: BTreeSet<ObjectId>,
+    /// Objects followed wherever they are
+    pub objects: BTreeSet<ObjectId>,
+}
+
+impl Subscription {
+    /// True if an object `id` at `location` falls under the subscription
+    pub fn covers(&self, id: ObjectId, location: Option<ObjectId>) -> bool {
+        self.objects.contains(&id)
+            || self.rooms.contains(&id)
+            || location.is_some_and(|room| self.rooms.contains(&room))
+    }
+
+    /// The `rooms` and `objects` query parameters, as comma-separated ids
+    pub fn to_query(&self) -> [(&'static str, String); 2] {
+        [
+            ("rooms", join(&self.rooms)),
+            ("objects", join(&self.objects)),
+        ]
+    }
+
+    /// Parse the query parameters written by [`to_query`](Self::to_query)
+    pub fn from_query(rooms: &str, objects: &str) -> Option<Self> {
+        Some(Self {
+            rooms: split(rooms)?,
+            objects: split(objects)?,
+        })
+    }
+}
+
+fn join(ids: &BTreeSet<ObjectId>) -> String {
+    let ids: Vec<String> = ids.iter().map(|id| id.0.to_string()).collect();
+    ids.join(",")
+}
+
+fn split(ids: &str) -> Option<BTreeSet<ObjectId>> {
+    ids.split(',')
+        .filter(|id| !id.is_empty())
+        .map(|id| id.trim().parse().ok().map(ObjectId))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription_query() {
+        let subscription = Subscription {
+            rooms: BTreeSet::from([ObjectId(0), ObjectId(12)]),
+            objects: BTreeSet::from([ObjectId(42)]),
+        };
+        let [(_, rooms), (_, objects)] = subscription.to_query();
+        assert_eq!((rooms.as_str(), objects.as_str()), ("0,12", "42"));
+        assert_eq!(
+            Subscription::from_query(&rooms, &objects),
+            Some(subscription.clone())
+        );
+        assert_eq!(
+            Subscription::from_query("", ""),
+            Some(Subscription::default())
+        );
+        assert_eq!(Subscription::from_query("1,x", ""), None);
+
+        // Rooms, what is in them, and followed objects anywhere
+        assert!(subscription.covers(ObjectId(12), None));
+        assert!(subscription.covers(ObjectId(5), Some(ObjectId(0))));
+        assert!(subscription.covers(ObjectId(42), Some(ObjectId(99))));
+        assert!(!subscription.covers(ObjectId(5), Some(ObjectId(99))));
+    }
+}
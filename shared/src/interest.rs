@@ -0,0 +1,347 @@
+//! Interest Management
+//!
+//! Decides which objects each client gets to hear about, so the cost of a
+//! change grows with the players near it rather than with everyone
+//! connected. Each subscriber (a client, keyed however the caller likes)
+//! has an [`Interest`]:
+//!
+//! - [`Interest::Room`] for text clients: everything in the room they are
+//!   in, since a text client is told about the whole room
+//! - [`Interest::Area`] for graphical clients: what is in their room and
+//!   within a radius of their position
+//!
+//! [`InterestMap::refresh`] recomputes every subscriber's visible set and
+//! reports the difference as [`Visibility::Enter`] and
+//! [`Visibility::Leave`], which the gateways turn into spawns and
+//! despawns on the client.
+//!
+//! # Learning Note
+//! Checking every object against every client is O(clients × objects).
+//! A spatial grid buckets objects by [`CELL_SIZE`] cell, so an area query
+//! only looks at the few cells its circle overlaps and then checks the
+//! exact distance for the objects in them.
+
+use bevy::prelude::*;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::components::ObjectId;
+
+/// Side of a grid cell, in world units
+pub const CELL_SIZE: f32 = 16.0;
+
+/// How far a graphical client sees, in world units
+pub const VIEW_RADIUS: f32 = 32.0;
+
+/// What a subscriber wants to hear about
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interest {
+    /// Everything in a room
+    Room(ObjectId),
+    /// Objects in `room` within `radius` of `center`
+    Area {
+        /// The room the subscriber is in
+        room: ObjectId,
+        /// The subscriber's position
+        center: Vec3,
+        /// How far it sees
+        radius: f32,
+    },
+}
+
+impl Interest {
+    /// The room this interest is in
+    pub fn room(&self) -> ObjectId {
+        match *self {
+            Self::Room(room) | Self::Area { room, .. } => room,
+        }
+    }
+}
+
+/// An object coming into or going out of a subscriber's interest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    /// The subscriber should now be told about the object
+    Enter(ObjectId),
+    /// The subscriber should forget the object
+    Leave(ObjectId),
+}
+
+/// Grid cell: a room and a cell within it
+type Cell = (ObjectId, IVec2);
+
+fn cell_of(room: ObjectId, position: Vec3) -> Cell {
+    (room, (position.truncate() / CELL_SIZE).floor().as_ivec2())
+}
+
+/// Where an object is, as far as interest is concerned
+#[derive(Clone, Copy, Debug)]
+struct Placement {
+    location: Option<ObjectId>,
+    /// Objects that never moved count as being at the origin
+    position: Vec3,
+}
+
+struct Subscriber {
+    interest: Interest,
+    visible: HashSet<ObjectId>,
+}
+
+/// Objects, subscribers and what each subscriber can see
+pub struct InterestMap<K> {
+    objects: HashMap<ObjectId, Placement>,
+    /// Contents of each room
+    rooms: HashMap<ObjectId, HashSet<ObjectId>>,
+    /// Contents of each grid cell
+    grid: HashMap<Cell, HashSet<ObjectId>>,
+    subscribers: HashMap<K, Subscriber>,
+}
+
+impl<K> Default for InterestMap<K> {
+    fn default() -> Self {
+        Self {
+            objects: HashMap::new(),
+            rooms: HashMap::new(),
+            grid: HashMap::new(),
+            subscribers: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Copy + Eq + Hash + Ord> InterestMap<K> {
+    /// Record where an object is now
+    pub fn upsert(&mut self, id: ObjectId, location: Option<ObjectId>, position: Option<Vec3>) {
+        self.remove(id);
+        let placement = Placement {
+            location,
+            position: position.unwrap_or_default(),
+        };
+        if let Some(room) = location {
+            self.rooms.entry(room).or_default().insert(id);
+            let cell = cell_of(room, placement.position);
+            self.grid.entry(cell).or_default().insert(id);
+        }
+        self.objects.insert(id, placement);
+    }
+
+    /// Forget a destroyed object
+    pub fn remove(&mut self, id: ObjectId) {
+        let Some(old) = self.objects.remove(&id) else {
+            return;
+        };
+        if let Some(room) = old.location {
+            remove_from(&mut self.rooms, &room, id);
+            remove_from(&mut self.grid, &cell_of(room, old.position), id);
+        }
+    }
+
+    /// Where `id` is, if it is known and somewhere
+    pub fn location(&self, id: ObjectId) -> Option<(ObjectId, Vec3)> {
+        let placement = self.objects.get(&id)?;
+        Some((placement.location?, placement.position))
+    }
+
+    /// Set what `key` is interested in, effective at the next refresh
+    pub fn subscribe(&mut self, key: K, interest: Interest) {
+        self.subscribers
+            .entry(key)
+            .and_modify(|subscriber| subscriber.interest = interest)
+            .or_insert_with(|| Subscriber {
+                interest,
+                visible: HashSet::new(),
+            });
+    }
+
+    /// Drop a subscriber, returning what it could see
+    pub fn unsubscribe(&mut self, key: K) -> HashSet<ObjectId> {
+        self.subscribers
+            .remove(&key)
+            .map(|subscriber| subscriber.visible)
+            .unwrap_or_default()
+    }
+
+    /// True if `key` currently sees `id`
+    pub fn is_visible(&self, key: K, id: ObjectId) -> bool {
+        self.subscribers
+            .get(&key)
+            .is_some_and(|subscriber| subscriber.visible.contains(&id))
+    }
+
+    /// What `key` currently sees, in no particular order
+    pub fn visible(&self, key: K) -> impl Iterator<Item = ObjectId> + '_ {
+        self.subscribers
+            .get(&key)
+            .into_iter()
+            .flat_map(|subscriber| subscriber.visible.iter().copied())
+    }
+
+    /// Every room some subscriber is interested in
+    pub fn rooms(&self) -> BTreeSet<ObjectId> {
+        self.subscribers
+            .values()
+            .map(|subscriber| subscriber.interest.room())
+            .collect()
+    }
+
+    /// Recompute what every subscriber sees, returning what changed, by
+    /// subscriber then object id
+    pub fn refresh(&mut self) -> Vec<(K, Visibility)> {
+        let mut keys: Vec<K> = self.subscribers.keys().copied().collect();
+        keys.sort();
+        let mut changes = Vec::new();
+        for key in keys {
+            let interest = self.subscribers[&key].interest;
+            let now = self.query(interest);
+            let subscriber = self.subscribers.get_mut(&key).unwrap();
+            let mut entered: Vec<ObjectId> = now.difference(&subscriber.visible).copied().collect();
+            let mut left: Vec<ObjectId> = subscriber.visible.difference(&now).copied().collect();
+            entered.sort();
+            left.sort();
+            changes.extend(left.into_iter().map(|id| (key, Visibility::Leave(id))));
+            changes.extend(entered.into_iter().map(|id| (key, Visibility::Enter(id))));
+            subscriber.visible = now;
+        }
+        changes
+    }
+
+    /// Objects `interest` covers; the room itself is always included
+    fn query(&self, interest: Interest) -> HashSet<ObjectId> {
+        let mut visible = HashSet::new();
+        if self.objects.contains_key(&interest.room()) {
+            visible.insert(interest.room());
+        }
+        match interest {
+            Interest::Room(room) => {
+                visible.extend(self.rooms.get(&room).into_iter().flatten().copied());
+            }
+            Interest::Area {
+                room,
+                center,
+                radius,
+            } => {
+                let (_, min) = cell_of(room, center - Vec3::splat(radius));
+                let (_, max) = cell_of(room, center + Vec3::splat(radius));
+                for x in min.x..=max.x {
+                    for y in min.y..=max.y {
+                        let Some(ids) = self.grid.get(&(room, IVec2::new(x, y))) else {
+                            continue;
+                        };
+                        visible.extend(
+                            ids.iter()
+                                .copied()
+                                .filter(|id| self.objects[id].position.distance(center) <= radius),
+                        );
+                    }
+                }
+            }
+        }
+        visible
+    }
+}
+
+fn remove_from<T: Eq + Hash>(index: &mut HashMap<T, HashSet<ObjectId>>, key: &T, id: ObjectId) {
+    if let Some(ids) = index.get_mut(key) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HALL: ObjectId = ObjectId(1);
+    const YARD: ObjectId = ObjectId(2);
+
+    fn area(room: ObjectId, center: Vec3) -> Interest {
+        Interest::Area {
+            room,
+            center,
+            radius: VIEW_RADIUS,
+        }
+    }
+
+    #[test]
+    fn test_area_interest_follows_movement() {
+        let mut map = InterestMap::default();
+        map.upsert(HALL, None, None);
+        map.upsert(ObjectId(10), Some(HALL), Some(Vec3::ZERO));
+        map.upsert(ObjectId(11), Some(HALL), Some(Vec3::new(20.0, 20.0, 0.0)));
+        map.upsert(ObjectId(12), Some(HALL), Some(Vec3::new(100.0, 0.0, 0.0)));
+        // Close by, but in another room
+        map.upsert(ObjectId(13), Some(YARD), Some(Vec3::ZERO));
+
+        map.subscribe(7, area(HALL, Vec3::ZERO));
+        assert_eq!(
+            map.refresh(),
+            [
+                (7, Visibility::Enter(HALL)),
+                (7, Visibility::Enter(ObjectId(10))),
+                (7, Visibility::Enter(ObjectId(11))),
+            ]
+        );
+        assert!(map.refresh().is_empty());
+
+        // Walking east brings 12 into view and leaves 10 behind
+        map.upsert(ObjectId(10), Some(HALL), Some(Vec3::new(80.0, 0.0, 0.0)));
+        map.subscribe(7, area(HALL, Vec3::new(80.0, 0.0, 0.0)));
+        assert_eq!(
+            map.refresh(),
+            [
+                (7, Visibility::Leave(ObjectId(11))),
+                (7, Visibility::Enter(ObjectId(12))),
+            ]
+        );
+        assert!(map.is_visible(7, ObjectId(10)));
+        let mut visible: Vec<ObjectId> = map.visible(7).collect();
+        visible.sort();
+        assert_eq!(visible, [HALL, ObjectId(10), ObjectId(12)]);
+
+        // Going to the yard swaps rooms entirely
+        map.upsert(ObjectId(10), Some(YARD), Some(Vec3::ZERO));
+        map.subscribe(7, area(YARD, Vec3::ZERO));
+        let changes = map.refresh();
+        assert!(changes.contains(&(7, Visibility::Leave(HALL))));
+        assert!(changes.contains(&(7, Visibility::Enter(ObjectId(13)))));
+        assert_eq!(map.rooms(), BTreeSet::from([YARD]));
+
+        map.remove(ObjectId(13));
+        assert_eq!(map.refresh(), [(7, Visibility::Leave(ObjectId(13)))]);
+        assert_eq!(map.unsubscribe(7), HashSet::from([ObjectId(10)]));
+    }
+
+    #[test]
+    fn test_room_interest_sees_the_whole_room() {
+        let mut map = InterestMap::default();
+        map.upsert(HALL, None, None);
+        map.upsert(ObjectId(10), Some(HALL), Some(Vec3::new(1000.0, 0.0, 0.0)));
+        map.upsert(ObjectId(11), Some(HALL), None);
+        // Carried by 11, so not in the room itself
+        map.upsert(ObjectId(12), Some(ObjectId(11)), None);
+
+        map.subscribe(1, Interest::Room(HALL));
+        map.subscribe(2, area(HALL, Vec3::ZERO));
+        let changes = map.refresh();
+        let seen = |key| {
+            changes
+                .iter()
+                .filter(|(k, _)| *k == key)
+                .map(|(_, visibility)| *visibility)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            seen(1),
+            [
+                Visibility::Enter(HALL),
+                Visibility::Enter(ObjectId(10)),
+                Visibility::Enter(ObjectId(11)),
+            ]
+        );
+        assert_eq!(
+            seen(2),
+            [Visibility::Enter(HALL), Visibility::Enter(ObjectId(11))]
+        );
+    }
+}
@@ -3,8 +3,19 @@
 //! This crate contains all code that must be identical between client and server.
 //! This includes:
 //! - Protocol definitions (network messages)
+//! - Lightyear registration and configs (`net`, with the `netcode` feature)
 //! - Shared components (ECS data structures)
+//! - Session tokens (issued by auth, verified by the gateways)
+//! - Gateway protocol (commands and change stream between gateways and world-state)
+//! - Interest management (which objects each client is told about)
+//! - Lock expressions (parsed once, evaluated by the server)
+//! - Persistence records (storage snapshots shared by the services)
+//! - World dumps (portable, checksummed snapshots of the whole world)
+//! - Scripting protocol (types shared by world-state and script-executor)
+//! - Delta-compressed snapshots (what graphics-gateway sends each tick)
+//! - Spatial queries (raycasts, overlaps and nearest objects)
 //! - Shared systems (deterministic game logic)
+//! - Client-side prediction and interpolation
 //! - Physics constants and utilities
 //!
 //! # Learning Note
@@ -16,15 +27,25 @@
 #![warn(missing_docs)]
 
 // Declare modules
+pub mod auth;
 pub mod components;
+pub mod dump;
+pub mod gateway;
+pub mod interest;
+pub mod locks;
+#[cfg(feature = "netcode")]
+pub mod net;
 pub mod physics;
+pub mod prediction;
 pub mod protocol;
+pub mod records;
+pub mod scripting;
+pub mod snapshot;
+pub mod spatial;
 pub mod systems;
 
 // Re-export commonly used items for convenience
-// As you develop, you'll add items here like:
-// pub use components::{Position, Velocity};
-// pub use protocol::{PlayerInput, ServerMessage};
+pub use components::{Attributes, Flags, ObjectId, ObjectKind};
 
 #[cfg(test)]
 mod tests {
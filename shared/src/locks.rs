@@ -0,0 +1,437 @@
+//! Lock Expressions
+//!
+//! Locks decide who may use, enter, pass through or pick up an object.
+//! A lock key is a small boolean expression that is parsed once, when it
+//! is set, and stored on the object as a [`LockExpr`].
+//!
+//! Grammar (lowest to highest precedence):
+//!
+//! ```text
+//! expr    := term ('|' term)*
+//! term    := factor ('&' factor)*
+//! factor  := '!' factor | '(' expr ')' | atom
+//! atom    := '=' object        the actor IS the object
+//!          | '+' object        the actor CARRIES the object
+//!          | '@' object        pass the object's own basic lock
+//!          | '$' function      call a function in the locked object's script
+//!          | attr ':' pattern  the actor's attribute matches a `*`/`?` pattern
+//!          | object            the actor is or carries the object
+//! object  := '#' dbref | name
+//! ```
+//!
+//! For example `=#5 | +Brass Key & !guild:thieves`.
+//!
+//! Keys may nest at most [`MAX_LOCK_DEPTH`] deep, counting every `!`,
+//! `&`, `|` and parenthesis, so evaluating, printing or dropping one never
+//! recurses further than that.
+//!
+//! # Learning Note
+//! This is a classic recursive-descent parser: one function per grammar
+//! rule, each calling the rule with the next-higher precedence. Rust's
+//! `Box` lets the enum refer to itself.
+
+use crate::components::ObjectId;
+use std::fmt;
+
+/// Deepest a lock key may nest
+pub const MAX_LOCK_DEPTH: usize = 32;
+
+/// Parsed lock key
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LockExpr {
+    /// `=obj`: the actor is this object
+    Is(ObjectId),
+    /// `+obj`: the actor carries this object
+    Carries(ObjectId),
+    /// `obj`: the actor is or carries this object
+    IsOrCarries(ObjectId),
+    /// `@obj`: the actor passes this object's basic lock
+    Indirect(ObjectId),
+    /// `$function`: the locked object's script function returns true
+    Eval(String),
+    /// `attr:pattern`: the actor's attribute matches the pattern
+    Attr {
+        /// Attribute name (upper-cased)
+        name: String,
+        /// Glob pattern with `*` and `?`
+        pattern: String,
+    },
+    /// `!expr`
+    Not(Box<LockExpr>),
+    /// `a & b`
+    And(Box<LockExpr>, Box<LockExpr>),
+    /// `a | b`
+    Or(Box<LockExpr>, Box<LockExpr>),
+}
+
+/// A lock key could not be parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockParseError {
+    /// What went wrong
+    pub message: String,
+    /// Byte offset in the input where the problem was found
+    pub position: usize,
+}
+
+impl fmt::Display for LockParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at column {})", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for LockParseError {}
+
+impl LockExpr {
+    /// Parse a lock key
+    ///
+    /// `resolve` turns object names into ids; it is only called for names,
+    /// since `#123` references are resolved directly. Names are resolved
+    /// once at parse time, as in MUSH, so renaming an object later does not
+    /// change the lock.
+    pub fn parse(
+        input: &str,
+        resolve: impl Fn(&str) -> Option<ObjectId>,
+    ) -> Result<Self, LockParseError> {
+        let mut parser = Parser {
+            input,
+            pos: 0,
+            nesting: 0,
+            resolve: &resolve,
+        };
+        let (expr, _) = parser.expr()?;
+        parser.skip_whitespace();
+        if parser.pos < input.len() {
+            return Err(parser.error("unexpected input"));
+        }
+        Ok(expr)
+    }
+
+    /// Parse a lock key that only uses `#dbref` references (e.g. when loading
+    /// a lock rendered with `Display`)
+    pub fn parse_canonical(input: &str) -> Result<Self, LockParseError> {
+        Self::parse(input, |_| None)
+    }
+
+    /// Binding strength used to decide where `Display` needs parentheses
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Or(..) => 1,
+            Self::And(..) => 2,
+            _ => 3,
+        }
+    }
+
+    /// Write `self`, parenthesized if it binds looser than `min`
+    fn fmt_at(&self, f: &mut fmt::Formatter<'_>, min: u8) -> fmt::Result {
+        if self.precedence() < min {
+            write!(f, "(")?;
+            fmt::Display::fmt(self, f)?;
+            write!(f, ")")
+        } else {
+            fmt::Display::fmt(self, f)
+        }
+    }
+}
+
+/// Renders the canonical form, which parses back to the same expression
+impl fmt::Display for LockExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Is(id) => write!(f, "={}", id),
+            Self::Carries(id) => write!(f, "+{}", id),
+            Self::IsOrCarries(id) => write!(f, "{}", id),
+            Self::Indirect(id) => write!(f, "@{}", id),
+            Self::Eval(function) => write!(f, "${}", function),
+            Self::Attr { name, pattern } => write!(f, "{}:{}", name, pattern),
+            Self::Not(inner) => {
+                write!(f, "!")?;
+                inner.fmt_at(f, 3)
+            }
+            Self::And(a, b) => {
+                a.fmt_at(f, 2)?;
+                write!(f, " & ")?;
+                b.fmt_at(f, 3)
+            }
+            Self::Or(a, b) => {
+                a.fmt_at(f, 1)?;
+                write!(f, " | ")?;
+                b.fmt_at(f, 2)
+            }
+        }
+    }
+}
+
+/// A parsed expression and how deep it is (1 for an atom)
+type Parsed = Result<(LockExpr, usize), LockParseError>;
+
+/// Recursive-descent parser state
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    /// `!`s and parentheses being parsed, which bounds the recursion
+    nesting: usize,
+    resolve: &'a dyn Fn(&str) -> Option<ObjectId>,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> LockParseError {
+        LockParseError {
+            message: message.to_string(),
+            position: self.pos,
+        }
+    }
+
+    fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.pos = self.input.len() - trimmed.len();
+    }
+
+    /// Consume `c` if it is the next non-blank character
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The depth of an expression one level above `depth`, if allowed
+    fn deeper(&self, depth: usize) -> Result<usize, LockParseError> {
+        if depth >= MAX_LOCK_DEPTH {
+            return Err(self.error("lock key is nested too deeply"));
+        }
+        Ok(depth + 1)
+    }
+
+    /// Parse one level further into `!`s and parentheses
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Parsed) -> Parsed {
+        if self.nesting >= MAX_LOCK_DEPTH {
+            return Err(self.error("lock key is nested too deeply"));
+        }
+        self.nesting += 1;
+        let parsed = parse(self);
+        self.nesting -= 1;
+        parsed
+    }
+
+    fn expr(&mut self) -> Parsed {
+        let (mut left, mut depth) = self.term()?;
+        while self.eat('|') {
+            let (right, right_depth) = self.term()?;
+            depth = self.deeper(depth.max(right_depth))?;
+            left = LockExpr::Or(Box::new(left), Box::new(right));
+        }
+        Ok((left, depth))
+    }
+
+    fn term(&mut self) -> Parsed {
+        let (mut left, mut depth) = self.factor()?;
+        while self.eat('&') {
+            let (right, right_depth) = self.factor()?;
+            depth = self.deeper(depth.max(right_depth))?;
+            left = LockExpr::And(Box::new(left), Box::new(right));
+        }
+        Ok((left, depth))
+    }
+
+    fn factor(&mut self) -> Parsed {
+        if self.eat('!') {
+            let (inner, depth) = self.nested(Self::factor)?;
+            return Ok((LockExpr::Not(Box::new(inner)), self.deeper(depth)?));
+        }
+        if self.eat('(') {
+            let inner = self.nested(Self::expr)?;
+            if !self.eat(')') {
+                return Err(self.error("expected ')'"));
+            }
+            return Ok(inner);
+        }
+        Ok((self.atom()?, 1))
+    }
+
+    fn atom(&mut self) -> Result<LockExpr, LockParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let len = self
+            .rest()
+            .find(['&', '|', '!', '(', ')'])
+            .unwrap_or(self.rest().len());
+        let input = self.input;
+        let text = input[start..start + len].trim_end();
+        self.pos += len;
+
+        if text.is_empty() {
+            self.pos = start;
+            return Err(self.error("expected a lock key"));
+        }
+
+        let mut chars = text.chars();
+        let prefix = chars.next().expect("text is not empty");
+        let body = chars.as_str().trim();
+        let object = |parser: &Self, name: &str| {
+            parser.object(name).ok_or_else(|| LockParseError {
+                message: format!("no object named '{}'", name),
+                position: start,
+            })
+        };
+
+        match prefix {
+            '=' => Ok(LockExpr::Is(object(self, body)?)),
+            '+' => Ok(LockExpr::Carries(object(self, body)?)),
+            '@' => Ok(LockExpr::Indirect(object(self, body)?)),
+            '$' if is_identifier(body) => Ok(LockExpr::Eval(body.to_string())),
+            '$' => Err(LockParseError {
+                message: format!("'{}' is not a function name", body),
+                position: start,
+            }),
+            _ => match text.split_once(':') {
+                Some((name, pattern)) if is_identifier(name.trim()) => Ok(LockExpr::Attr {
+                    name: name.trim().to_ascii_uppercase(),
+                    pattern: pattern.trim().to_string(),
+                }),
+                _ => Ok(LockExpr::IsOrCarries(object(self, text)?)),
+            },
+        }
+    }
+
+    /// Resolve an object reference: `#123` directly, names via the callback
+    fn object(&self, name: &str) -> Option<ObjectId> {
+        match name.strip_prefix('#') {
+            Some(digits) => digits.parse().ok().map(ObjectId),
+            None if !name.is_empty() => (self.resolve)(name),
+            None => None,
+        }
+    }
+}
+
+/// True for names made of letters, digits and underscores
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Case-insensitive glob match supporting `*` (any run) and `?` (any one char)
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    // Iterative matcher with single-star backtracking
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(name: &str) -> Option<ObjectId> {
+        match name.to_ascii_lowercase().as_str() {
+            "alice" => Some(ObjectId(2)),
+            "brass key" => Some(ObjectId(7)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        let expr = LockExpr::parse("=alice | +brass key & !guild:thieves", resolve).unwrap();
+        assert_eq!(
+            expr,
+            LockExpr::Or(
+                Box::new(LockExpr::Is(ObjectId(2))),
+                Box::new(LockExpr::And(
+                    Box::new(LockExpr::Carries(ObjectId(7))),
+                    Box::new(LockExpr::Not(Box::new(LockExpr::Attr {
+                        name: "GUILD".to_string(),
+                        pattern: "thieves".to_string(),
+                    }))),
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for key in [
+            "(#2 | #3) & $is_member",
+            "!(@#10 & level:1*)",
+            "=#1 | +#7 & !guild:thieves",
+        ] {
+            let expr = LockExpr::parse(key, resolve).unwrap();
+            let rendered = expr.to_string();
+            assert_eq!(
+                LockExpr::parse_canonical(&rendered).unwrap(),
+                expr,
+                "{}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(LockExpr::parse("", resolve).is_err());
+        assert!(LockExpr::parse("(#1 | #2", resolve).is_err());
+        assert!(LockExpr::parse("#1 &", resolve).is_err());
+        assert!(LockExpr::parse("$not a function", resolve).is_err());
+
+        let err = LockExpr::parse("#1 | =nobody", resolve).unwrap_err();
+        assert!(err.message.contains("nobody"));
+        assert_eq!(err.position, 5);
+    }
+
+    #[test]
+    fn test_nesting_is_bounded() {
+        let nots = |n| format!("{}#1", "!".repeat(n));
+        assert!(LockExpr::parse(&nots(MAX_LOCK_DEPTH - 1), resolve).is_ok());
+        let chain = |n| vec!["#1"; n].join(" & ");
+        assert!(LockExpr::parse(&chain(MAX_LOCK_DEPTH), resolve).is_ok());
+
+        // Far more than would fit on the stack if parsed recursively
+        for key in [
+            nots(MAX_LOCK_DEPTH),
+            nots(100_000),
+            format!("{}#1{}", "(".repeat(100_000), ")".repeat(100_000)),
+            chain(100_000),
+            format!("{}#2", "#1 | (".repeat(100_000)),
+        ] {
+            let err = LockExpr::parse(&key, resolve).unwrap_err();
+            assert!(err.message.contains("nested too deeply"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("thie*", "Thieves"));
+        assert!(glob_match("?at", "cat"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*c*e", "abcde"));
+        assert!(!glob_match("a*c", "abd"));
+    }
+}
@@ -0,0 +1,125 @@
+//! Lightyear Networking
+//!
+//! Registers the [`protocol`](crate::protocol) with Lightyear and builds
+//! the client and server configurations, so both ends agree on the tick
+//! rate, the channels and what is replicated.
+//!
+//! - [`ProtocolPlugin`] must be added to both apps, after Lightyear's own
+//!   `ClientPlugins`/`ServerPlugins`
+//! - [`client_config`] connects over UDP with a connect token built from
+//!   the development key
+//! - [`server_config`] listens for netcode connections on one UDP address
+//!
+//! Only compiled with the `netcode` feature, so services that never open
+//! a game socket don't build Lightyear.
+//!
+//! # Learning Note
+//! Lightyear identifies components and messages by the order they are
+//! registered in. Registering them in one shared plugin is what keeps the
+//! client's numbering identical to the server's.
+
+use bevy::prelude::*;
+use lightyear::prelude::*;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::components::{Model, ObjectId, ObjectKind, ObjectName, Position, Velocity};
+use crate::physics::PHYSICS_TIMESTEP;
+use crate::protocol::{
+    ConsoleInput, ConsoleOutput, InputMessage, LastInput, Login, PlayerId, SnapshotAck,
+    StateSnapshot, DEV_PRIVATE_KEY, PROTOCOL_ID,
+};
+
+/// Ordered, reliable channel for console traffic
+#[derive(Channel)]
+pub struct ReliableChannel;
+
+/// Unreliable channel for movement inputs; late packets are dropped, and
+/// the inputs they carried are resent in the next message anyway
+#[derive(Channel)]
+pub struct InputChannel;
+
+/// Unreliable channel for state snapshots and their acks; only the newest
+/// matters, and each one can be decoded without the ones lost before it
+#[derive(Channel)]
+pub struct SnapshotChannel;
+
+/// Registers channels, messages and replicated components
+pub struct ProtocolPlugin;
+
+impl Plugin for ProtocolPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_channel::<ReliableChannel>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+            ..default()
+        });
+        app.add_channel::<InputChannel>(ChannelSettings {
+            mode: ChannelMode::SequencedUnreliable,
+            ..default()
+        });
+        app.add_channel::<SnapshotChannel>(ChannelSettings {
+            mode: ChannelMode::SequencedUnreliable,
+            ..default()
+        });
+
+        app.register_message::<ConsoleInput>(ChannelDirection::ClientToServer);
+        app.register_message::<ConsoleOutput>(ChannelDirection::ServerToClient);
+        app.register_message::<InputMessage>(ChannelDirection::ClientToServer);
+        app.register_message::<Login>(ChannelDirection::ClientToServer);
+        app.register_message::<StateSnapshot>(ChannelDirection::ServerToClient);
+        app.register_message::<SnapshotAck>(ChannelDirection::ClientToServer);
+
+        app.register_component::<ObjectId>(ChannelDirection::ServerToClient);
+        app.register_component::<ObjectKind>(ChannelDirection::ServerToClient);
+        app.register_component::<ObjectName>(ChannelDirection::ServerToClient);
+        app.register_component::<Model>(ChannelDirection::ServerToClient);
+        app.register_component::<PlayerId>(ChannelDirection::ServerToClient);
+        app.register_component::<Position>(ChannelDirection::ServerToClient);
+        app.register_component::<Velocity>(ChannelDirection::ServerToClient);
+        app.register_component::<LastInput>(ChannelDirection::ServerToClient);
+    }
+}
+
+/// Settings both ends must share: one tick per physics step
+pub fn shared_config() -> SharedConfig {
+    SharedConfig {
+        tick: TickConfig::new(Duration::from_secs_f32(PHYSICS_TIMESTEP)),
+        ..default()
+    }
+}
+
+/// Server listening for netcode clients on `addr`
+pub fn server_config(addr: SocketAddr) -> server::ServerConfig {
+    server::ServerConfig {
+        shared: shared_config(),
+        net: vec![server::NetConfig::Netcode {
+            config: server::NetcodeConfig::default()
+                .with_protocol_id(PROTOCOL_ID)
+                .with_key(DEV_PRIVATE_KEY),
+            io: server::IoConfig::from_transport(server::ServerTransport::UdpSocket(addr)),
+        }],
+        ..default()
+    }
+}
+
+/// Client `client_id` connecting to the server at `server_addr`
+///
+/// The client signs its own connect token with the development key, so
+/// this only reaches servers that accept that key.
+pub fn client_config(server_addr: SocketAddr, client_id: u64) -> client::ClientConfig {
+    let local = SocketAddr::from(([0, 0, 0, 0], 0));
+    client::ClientConfig {
+        shared: shared_config(),
+        net: client::NetConfig::Netcode {
+            auth: client::Authentication::Manual {
+                server_addr,
+                client_id,
+                private_key: DEV_PRIVATE_KEY,
+                protocol_id: PROTOCOL_ID,
+            },
+            config: client::NetcodeConfig::default(),
+            io: client::IoConfig::from_transport(client::ClientTransport::UdpSocket(local)),
+        },
+        ..default()
+    }
+}
@@ -3,6 +3,18 @@
 //! Defines physics constants and helper functions that must be identical
 //! on both client and server for deterministic physics simulation.
 //!
+//! Also describes the bodies physics-service simulates. Builders give an
+//! object a body with two attributes:
+//!
+//! - `COLLIDER`: its shape, `ball <radius>`, `box <x> <y> <z>` (full
+//!   size) or `capsule <radius> <length>` (length of the straight part)
+//! - `BODY`: how it moves, `static` (the default), `dynamic`, `kinematic`
+//!   or `trigger` (a static sensor, for pressure plates and the like)
+//!
+//! Players always have [`PLAYER_BODY`], whatever their attributes say.
+//! No collider may reach further than [`MAX_COLLIDER_RADIUS`] from its
+//! object's position.
+//!
 //! # Learning Note
 //! Constants in Rust use `const` (compile-time) or `static` (runtime).
 //! For game physics, compile-time constants are preferred for performance.
@@ -16,4 +28,225 @@ pub const GRAVITY: f32 = 9.81;
 /// Physics timestep (fixed update rate in seconds)
 pub const PHYSICS_TIMESTEP: f32 = 1.0 / 60.0;
 
-// TODO: Add physics helper functions here
+use bevy::math::Vec3;
+use std::fmt;
+
+/// Attribute holding an object's collider shape
+pub const COLLIDER_ATTR: &str = "COLLIDER";
+
+/// Attribute saying how an object's body moves
+pub const BODY_ATTR: &str = "BODY";
+
+/// Farthest a collider may reach from its center, in world units
+pub const MAX_COLLIDER_RADIUS: f32 = 32.0;
+
+/// Every player's body: an upright capsule moved by world-state
+pub const PLAYER_BODY: BodyDef = BodyDef {
+    kind: BodyKind::Kinematic,
+    shape: Shape::Capsule {
+        radius: 0.4,
+        half_length: 0.5,
+    },
+    sensor: false,
+};
+
+/// How a body moves
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BodyKind {
+    /// Never moves
+    Static,
+    /// Moved by the simulation
+    Dynamic,
+    /// Moved by world-state only, but pushes dynamic bodies
+    Kinematic,
+}
+
+/// A collider shape, centered on the object's position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    /// A sphere
+    Ball {
+        /// Radius
+        radius: f32,
+    },
+    /// A box
+    Cuboid {
+        /// Half the size along each axis
+        half_extents: Vec3,
+    },
+    /// An upright capsule
+    Capsule {
+        /// Radius
+        radius: f32,
+        /// Half the length of the straight part
+        half_length: f32,
+    },
+}
+
+impl Shape {
+    /// Radius of the smallest sphere around the shape's center that holds it
+    pub fn bounding_radius(&self) -> f32 {
+        match *self {
+            Self::Ball { radius } => radius,
+            Self::Cuboid { half_extents } => half_extents.length(),
+            Self::Capsule {
+                radius,
+                half_length,
+            } => radius + half_length,
+        }
+    }
+}
+
+/// Everything physics-service needs to know about a body besides where
+/// it is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BodyDef {
+    /// How it moves
+    pub kind: BodyKind,
+    /// Its collider
+    pub shape: Shape,
+    /// Reports contacts without pushing anything
+    pub sensor: bool,
+}
+
+/// Why an object's physics attributes don't describe a body
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BodyParseError {
+    /// `COLLIDER` is not a shape this understands, or is too big
+    BadShape(String),
+    /// `BODY` is not a kind of body
+    BadKind(String),
+}
+
+impl fmt::Display for BodyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadShape(shape) => write!(
+                f,
+                "bad collider '{}': expected ball <r>, box <x> <y> <z> or capsule <r> <length> \
+                 reaching at most {} from its center",
+                shape, MAX_COLLIDER_RADIUS
+            ),
+            Self::BadKind(kind) => write!(
+                f,
+                "bad body '{}': expected static, dynamic, kinematic or trigger",
+                kind
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BodyParseError {}
+
+impl BodyDef {
+    /// The body described by an object's `COLLIDER` and `BODY` attributes,
+    /// or `None` without a collider
+    pub fn parse(
+        collider: Option<&str>,
+        body: Option<&str>,
+    ) -> Result<Option<Self>, BodyParseError> {
+        let Some(collider) = collider else {
+            return Ok(None);
+        };
+        let shape = parse_shape(collider)
+            .filter(|shape| shape.bounding_radius() <= MAX_COLLIDER_RADIUS)
+            .ok_or_else(|| BodyParseError::BadShape(collider.to_string()))?;
+        let body = body.unwrap_or("static");
+        let (kind, sensor) = match body.trim().to_lowercase().as_str() {
+            "static" => (BodyKind::Static, false),
+            "dynamic" => (BodyKind::Dynamic, false),
+            "kinematic" => (BodyKind::Kinematic, false),
+            "trigger" => (BodyKind::Static, true),
+            _ => return Err(BodyParseError::BadKind(body.to_string())),
+        };
+        Ok(Some(Self {
+            kind,
+            shape,
+            sensor,
+        }))
+    }
+}
+
+fn parse_shape(text: &str) -> Option<Shape> {
+    let mut words = text.split_whitespace();
+    let name = words.next()?.to_lowercase();
+    let sizes = words
+        .map(|word| {
+            word.parse::<f32>()
+                .ok()
+                .filter(|size| size.is_finite() && *size > 0.0)
+        })
+        .collect::<Option<Vec<f32>>>()?;
+    match (name.as_str(), sizes.as_slice()) {
+        ("ball", &[radius]) => Some(Shape::Ball { radius }),
+        ("box", &[x, y, z]) => Some(Shape::Cuboid {
+            half_extents: Vec3::new(x, y, z) / 2.0,
+        }),
+        ("capsule", &[radius, length]) => Some(Shape::Capsule {
+            radius,
+            half_length: length / 2.0,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_body() {
+        assert_eq!(BodyDef::parse(None, Some("dynamic")), Ok(None));
+        assert_eq!(
+            BodyDef::parse(Some("ball 0.5"), None),
+            Ok(Some(BodyDef {
+                kind: BodyKind::Static,
+                shape: Shape::Ball { radius: 0.5 },
+                sensor: false,
+            }))
+        );
+        assert_eq!(
+            BodyDef::parse(Some("Box 2 2 0.2"), Some(" Trigger ")),
+            Ok(Some(BodyDef {
+                kind: BodyKind::Static,
+                shape: Shape::Cuboid {
+                    half_extents: Vec3::new(1.0, 1.0, 0.1)
+                },
+                sensor: true,
+            }))
+        );
+        let capsule = BodyDef::parse(Some("capsule 0.4 1"), Some("dynamic")).unwrap();
+        assert_eq!(
+            capsule.map(|body| (body.kind, body.shape)),
+            Some((
+                BodyKind::Dynamic,
+                Shape::Capsule {
+                    radius: 0.4,
+                    half_length: 0.5
+                }
+            ))
+        );
+
+        assert!(BodyDef::parse(Some("ball 32"), None).is_ok());
+        for bad in [
+            "ball",
+            "ball -1",
+            "box 1 1",
+            "cone 1 2",
+            "ball NaN",
+            "",
+            "ball 1e30",
+            "box 64 1 1",
+            "capsule 1 63",
+        ] {
+            assert!(matches!(
+                BodyDef::parse(Some(bad), None),
+                Err(BodyParseError::BadShape(_))
+            ));
+        }
+        assert_eq!(
+            BodyDef::parse(Some("ball 1"), Some("floaty")),
+            Err(BodyParseError::BadKind("floaty".to_string()))
+        );
+    }
+}
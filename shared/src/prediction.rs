@@ -0,0 +1,331 @@
+//! Client-Side Prediction
+//!
+//! A client that waited for the server before moving its own avatar would
+//! feel a full round trip of lag on every key press. Instead it predicts:
+//!
+//! 1. Every fixed tick, the [`Predictor`] applies the local input at once
+//!    with the same [`apply_input`] the server runs, and keeps the input
+//!    and the predicted state in its history, by tick.
+//! 2. The server replies, a round trip later, with the avatar's state and
+//!    the tick of the last input it applied ([`LastInput`]).
+//! 3. [`Predictor::reconcile`] compares that with what it predicted for the
+//!    same tick. Usually they agree and the entry is dropped. When they
+//!    don't (the server moved the avatar for a reason the client couldn't
+//!    know), it rewinds to the server's state and replays the inputs the
+//!    server hasn't seen yet.
+//!
+//! The replay fixes the state in one step, but jumping the drawn avatar
+//! there would look like a glitch, so the jump is kept as a correction
+//! offset that decays over the next few ticks ([`CORRECTION_DECAY`]).
+//!
+//! Other players' avatars aren't predicted; an [`Interpolator`] draws them
+//! slightly in the past, between two states the server has already sent.
+//!
+//! [`LastInput`]: crate::protocol::LastInput
+//!
+//! # Learning Note
+//! Prediction only works because movement is deterministic: the client
+//! and server run the same code on the same `f32` inputs and get the same
+//! bits, so an exact comparison is possible and corrections only happen
+//! when the server actually knows something the client doesn't.
+
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::components::{Position, Velocity};
+use crate::protocol::{MoveInput, TickInput};
+use crate::systems::apply_input;
+
+/// Predicted ticks kept for reconciliation (two seconds at 60 Hz)
+pub const HISTORY_LEN: usize = 120;
+
+/// Largest distance between prediction and server state still counted as
+/// agreement
+pub const RECONCILE_EPSILON: f32 = 1e-3;
+
+/// Fraction of the correction offset left after each tick
+pub const CORRECTION_DECAY: f32 = 0.75;
+
+/// How far in the past other players are drawn, in seconds
+pub const INTERPOLATION_DELAY: f64 = 0.1;
+
+/// The part of an avatar that movement changes
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MoveState {
+    /// Where the avatar is
+    pub position: Position,
+    /// How it is moving
+    pub velocity: Velocity,
+}
+
+impl MoveState {
+    /// The state one tick of `input` later
+    pub fn step(mut self, input: MoveInput) -> Self {
+        apply_input(&mut self.position, &mut self.velocity, input);
+        self
+    }
+
+    fn distance(&self, other: &Self) -> f32 {
+        self.position.0.distance(other.position.0)
+    }
+}
+
+/// One predicted tick
+#[derive(Clone, Copy, Debug)]
+struct Prediction {
+    tick: u32,
+    input: MoveInput,
+    /// State after applying `input`
+    state: MoveState,
+}
+
+/// Predicts the local player's movement and reconciles it with the server
+#[derive(Clone, Debug)]
+pub struct Predictor {
+    tick: u32,
+    state: MoveState,
+    history: VecDeque<Prediction>,
+    correction: Vec3,
+}
+
+impl Predictor {
+    /// Start predicting from `state`, as of tick 0
+    pub fn new(state: MoveState) -> Self {
+        Self {
+            tick: 0,
+            state,
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            correction: Vec3::ZERO,
+        }
+    }
+
+    /// The last tick predicted
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    /// The predicted state as of [`tick`](Self::tick)
+    pub fn state(&self) -> MoveState {
+        self.state
+    }
+
+    /// Where to draw the avatar: the prediction plus what is left of the
+    /// last correction
+    pub fn displayed(&self) -> Vec3 {
+        self.state.position.0 + self.correction
+    }
+
+    /// Predict the next tick of `input`, returning it numbered for sending
+    pub fn predict(&mut self, input: MoveInput) -> TickInput {
+        self.tick += 1;
+        self.state = self.state.step(input);
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(Prediction {
+            tick: self.tick,
+            input,
+            state: self.state,
+        });
+
+        self.correction *= CORRECTION_DECAY;
+        if self.correction.length() < RECONCILE_EPSILON {
+            self.correction = Vec3::ZERO;
+        }
+        TickInput {
+            tick: self.tick,
+            input,
+        }
+    }
+
+    /// The last `n` inputs, oldest first
+    pub fn recent(&self, n: usize) -> Vec<TickInput> {
+        let skip = self.history.len().saturating_sub(n);
+        self.history
+            .iter()
+            .skip(skip)
+            .map(|prediction| TickInput {
+                tick: prediction.tick,
+                input: prediction.input,
+            })
+            .collect()
+    }
+
+    /// Take the server's `state` after applying input `acked`
+    ///
+    /// Returns true if the prediction was wrong and inputs were replayed.
+    pub fn reconcile(&mut self, acked: u32, state: MoveState) -> bool {
+        while self.history.front().is_some_and(|p| p.tick < acked) {
+            self.history.pop_front();
+        }
+        // With the tick still in the history, agreement needs no replay;
+        // a tick already dropped can't be checked, so replay regardless
+        if let Some(predicted) = self.history.front().filter(|p| p.tick == acked) {
+            if predicted.state.distance(&state) <= RECONCILE_EPSILON {
+                self.history.pop_front();
+                return false;
+            }
+            self.history.pop_front();
+        }
+
+        let before = self.state;
+        let mut replayed = state;
+        for prediction in self.history.iter_mut() {
+            replayed = replayed.step(prediction.input);
+            prediction.state = replayed;
+        }
+        self.state = replayed;
+        let moved = before.position.0 - replayed.position.0;
+        self.correction += moved;
+        moved.length() > RECONCILE_EPSILON
+    }
+}
+
+/// Draws a remote object between the last two states received
+#[derive(Clone, Debug, Default)]
+pub struct Interpolator {
+    /// Receive time and position, oldest first
+    snapshots: VecDeque<(f64, Vec3)>,
+}
+
+impl Interpolator {
+    /// Record `position`, received at `time` seconds
+    pub fn push(&mut self, time: f64, position: Vec3) {
+        self.snapshots.push_back((time, position));
+    }
+
+    /// Where to draw the object at `now`: [`INTERPOLATION_DELAY`] in the
+    /// past, so there is usually a snapshot on either side
+    ///
+    /// Holds the newest position rather than extrapolating when snapshots
+    /// stop coming.
+    pub fn sample(&mut self, now: f64) -> Option<Vec3> {
+        let at = now - INTERPOLATION_DELAY;
+        while self.snapshots.len() > 2 && self.snapshots[1].0 <= at {
+            self.snapshots.pop_front();
+        }
+        let (t0, p0) = *self.snapshots.front()?;
+        match self.snapshots.get(1) {
+            Some(&(t1, p1)) if at > t0 && t1 > t0 => {
+                let t = ((at - t0) / (t1 - t0)).min(1.0) as f32;
+                Some(p0.lerp(p1, t))
+            }
+            _ if at >= t0 => Some(self.snapshots.back()?.1),
+            _ => Some(p0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::INPUT_REDUNDANCY;
+    use std::collections::BTreeMap;
+
+    /// One-way latency, in ticks (100 ms at 60 Hz)
+    const LATENCY: u32 = 6;
+
+    /// Ticks a correction may take to fade out
+    const CORRECTION_TICKS: u32 = 15;
+
+    /// The server's view of one avatar
+    #[derive(Default)]
+    struct Server {
+        state: MoveState,
+        last: u32,
+        /// State after each input applied, to compare the client against
+        states: BTreeMap<u32, MoveState>,
+    }
+
+    impl Server {
+        fn receive(&mut self, inputs: &[TickInput]) {
+            for input in inputs {
+                if input.tick <= self.last {
+                    continue;
+                }
+                self.state = self.state.step(input.input);
+                self.last = input.tick;
+                self.states.insert(input.tick, self.state);
+            }
+        }
+    }
+
+    fn walk(tick: u32) -> MoveInput {
+        match tick {
+            0..100 => MoveInput { x: 1, y: 0 },
+            100..200 => MoveInput { x: 1, y: 1 },
+            _ => MoveInput::default(),
+        }
+    }
+
+    #[test]
+    fn test_prediction_corrects_under_latency() {
+        const SHOVE_AT: u32 = 150;
+        let mut client = Predictor::new(MoveState::default());
+        let mut predicted = BTreeMap::new();
+        let mut server = Server::default();
+        let mut to_server: VecDeque<(u32, Vec<TickInput>)> = VecDeque::new();
+        let mut to_client: VecDeque<(u32, u32, MoveState)> = VecDeque::new();
+        let mut corrected_at = None;
+
+        for now in 1..300 {
+            // Client ticks line up with `now`
+            let sent = client.predict(walk(now));
+            predicted.insert(sent.tick, client.state());
+            // Every fifth message is lost; resent inputs cover for it
+            if now % 5 != 0 {
+                to_server.push_back((now + LATENCY, client.recent(INPUT_REDUNDANCY)));
+            }
+
+            while to_server.front().is_some_and(|(at, _)| *at <= now) {
+                let (_, inputs) = to_server.pop_front().unwrap();
+                server.receive(&inputs);
+            }
+            if now == SHOVE_AT {
+                // Something only the server knows about moves the avatar
+                server.state.position.0.x -= 2.0;
+            }
+            to_client.push_back((now + LATENCY, server.last, server.state));
+
+            while to_client.front().is_some_and(|(at, ..)| *at <= now) {
+                let (_, acked, state) = to_client.pop_front().unwrap();
+                if client.reconcile(acked, state) {
+                    assert!(corrected_at.is_none(), "corrected twice");
+                    corrected_at = Some(now);
+                }
+            }
+
+            // The drawn avatar catches up with the replayed prediction
+            // within a few ticks
+            if corrected_at.is_some_and(|at| now >= at + CORRECTION_TICKS) {
+                let off = client.displayed().distance(client.state().position.0);
+                assert!(off < 0.05, "still {} off at tick {}", off, now);
+            }
+        }
+
+        // The client hears of the shove one trip after it happened
+        let corrected = corrected_at.unwrap();
+        assert_eq!(corrected, SHOVE_AT + LATENCY);
+        // Predictions were exact before the shove and after the replay
+        for (tick, truth) in &server.states {
+            if *tick <= SHOVE_AT - LATENCY || *tick > corrected {
+                let state = predicted[tick];
+                assert!(state.distance(truth) <= RECONCILE_EPSILON, "tick {}", tick);
+            }
+        }
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let mut remote = Interpolator::default();
+        assert_eq!(remote.sample(0.0), None);
+        remote.push(1.0, Vec3::ZERO);
+        remote.push(1.1, Vec3::X);
+        remote.push(1.2, Vec3::X * 2.0);
+        assert_eq!(remote.sample(1.05), Some(Vec3::ZERO));
+        assert!(remote.sample(1.25).unwrap().distance(Vec3::X * 1.5) < 1e-4);
+        // No newer snapshot: hold the last one
+        assert_eq!(remote.sample(2.0), Some(Vec3::X * 2.0));
+        assert_eq!(remote.snapshots.len(), 2);
+    }
+}
@@ -4,11 +4,107 @@
 //! between the client and server. Using a shared protocol ensures both
 //! sides speak the same language.
 //!
+//! Everything here is plain serde data, so any service can use it. The
+//! Lightyear side — channels, and which components and messages are
+//! replicated in which direction — is registered by
+//! `shared::net::ProtocolPlugin`, behind the `netcode` feature.
+//!
+//! Replicated components come from [`crate::components`]: `ObjectId`,
+//! `ObjectKind`, `ObjectName`, `Model`, `Position` and `Velocity`, plus
+//! [`PlayerId`] and [`LastInput`] on the entity a client controls.
+//!
+//! Clients send movement as [`InputMessage`]s: one [`MoveInput`] per
+//! fixed tick, numbered by the client's tick. Each message repeats the
+//! last few inputs, so a lost packet rarely loses an input.
+//!
+//! graphics-gateway expects a [`Login`] before anything else; the
+//! standalone server ignores it. Instead of replicating `Position` and
+//! [`LastInput`], it sends each client a [`StateSnapshot`] per tick,
+//! delta-compressed by [`crate::snapshot`], and the client answers each
+//! one with a [`SnapshotAck`].
+//!
 //! # Learning Note
 //! In Rust, the networking layer needs to serialize/deserialize data.
 //! Lightyear handles this with bincode, but you'll define the structures here.
 
-// TODO: Define your protocol messages here
-// Example:
-// #[derive(Serialize, Deserialize, Clone, Debug)]
-// pub struct PlayerInput { ... }
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Identifies this game's netcode traffic; clients with another id are
+/// refused during the handshake
+pub const PROTOCOL_ID: u64 = 0x4d55_5348_0000_0001;
+
+/// UDP port graphical clients connect to
+pub const DEFAULT_PORT: u16 = 5000;
+
+/// Key signing netcode connect tokens
+///
+/// Development only: anyone with the key can mint tokens. Deployments
+/// hand out tokens signed with a private key instead.
+pub const DEV_PRIVATE_KEY: [u8; 32] = [0; 32];
+
+/// The netcode client id of the player controlling an entity
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PlayerId(pub u64);
+
+/// A session token (see [`crate::auth`]), sent once after connecting to
+/// graphics-gateway to say which player this connection is
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Login(pub String);
+
+/// A line typed into the client's console, run as a command
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsoleInput(pub String);
+
+/// Text for the client's console: command output and world messages
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsoleOutput(pub String);
+
+/// Inputs resent in every [`InputMessage`]
+pub const INPUT_REDUNDANCY: usize = 4;
+
+/// Movement keys held during one tick, each axis -1, 0 or 1
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveInput {
+    /// Left (-1) or right (1)
+    pub x: i8,
+    /// Down (-1) or up (1)
+    pub y: i8,
+}
+
+impl MoveInput {
+    /// Unit direction of travel, or zero when standing still
+    pub fn direction(self) -> Vec2 {
+        Vec2::new(self.x.signum() as f32, self.y.signum() as f32).normalize_or_zero()
+    }
+}
+
+/// A client's input for one of its ticks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TickInput {
+    /// Client tick the input was sampled on; increases by one per tick
+    pub tick: u32,
+    /// Keys held
+    pub input: MoveInput,
+}
+
+/// The inputs a client sampled most recently, oldest first
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputMessage(pub Vec<TickInput>);
+
+/// Tick of the last input the server applied to this avatar
+///
+/// Replicated along with the avatar's position, so the client knows which
+/// of its predictions the position confirms.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastInput(pub u32);
+
+/// Positions and rotations of the objects a client sees, encoded by
+/// [`crate::snapshot::DeltaEncoder`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateSnapshot(pub Vec<u8>);
+
+/// The client has decoded the [`StateSnapshot`] with this id, so later
+/// ones may be encoded against it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotAck(pub u32);
@@ -0,0 +1,202 @@
+//! Persistence Records
+//!
+//! Plain-data snapshots of world objects as they are stored and sent
+//! between world-state and persistence-service. Records hold no Bevy
+//! types, so they serialize cleanly to JSON and map directly onto
+//! database rows.
+//!
+//! # Learning Note
+//! ECS components are optimized for the running simulation; records are
+//! optimized for storage. Keeping the two separate means the database
+//! schema doesn't have to change every time a component is refactored.
+
+use crate::components::{LockKind, ObjectId, ObjectKind};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// One world object with everything needed to rebuild it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ObjectRecord {
+    /// Stable object id
+    pub id: ObjectId,
+    /// Room, exit, thing or player
+    pub kind: ObjectKind,
+    /// Display name
+    pub name: String,
+    /// Owning player
+    pub owner: ObjectId,
+    /// Containing object, if any
+    pub location: Option<ObjectId>,
+    /// Where an exit leads, if this is a linked exit
+    pub destination: Option<ObjectId>,
+    /// Raw `Flags` bits
+    pub flags: u32,
+    /// Lock keys in canonical (`#dbref`) form
+    pub locks: BTreeMap<LockKind, String>,
+    /// Attributes other than the script
+    pub attributes: Vec<AttributeRecord>,
+    /// Script source, stored separately from ordinary attributes
+    pub script: Option<String>,
+    /// Player who last set the script; the object's owner if not recorded
+    #[serde(default)]
+    pub script_owner: Option<ObjectId>,
+    /// Raw `AttrFlags` bits of the script attribute
+    #[serde(default)]
+    pub script_flags: u8,
+    /// Every saved revision of the script, oldest first
+    #[serde(default)]
+    pub script_revisions: Vec<ScriptRevisionRecord>,
+    /// Script calls waiting to be made, soonest first
+    #[serde(default)]
+    pub timers: Vec<TimerRecord>,
+    /// Monotonic version; stores ignore writes older than what they hold
+    pub version: u64,
+}
+
+/// One attribute of an object
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AttributeRecord {
+    /// Upper-cased attribute name
+    pub name: String,
+    /// Stored text
+    pub value: String,
+    /// Player who last set it
+    pub owner: ObjectId,
+    /// Raw `AttrFlags` bits
+    pub flags: u8,
+}
+
+/// One saved revision of an object's script
+///
+/// Revisions never change once written, so stores only ever insert them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ScriptRevisionRecord {
+    /// 1-based revision number, unique per object
+    pub number: u32,
+    /// Full source; empty when the script was cleared
+    pub source: String,
+    /// Player who made the change
+    pub author: ObjectId,
+    /// Unix seconds
+    pub timestamp: i64,
+    /// Description of the change
+    pub message: String,
+}
+
+/// A pending deferred call to a function in the object's script
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TimerRecord {
+    /// World-unique timer id
+    pub id: u64,
+    /// Script function to call
+    pub function: String,
+    /// When to call it, in Unix milliseconds
+    pub due: i64,
+}
+
+/// A group of writes applied together
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct WriteBatch {
+    /// Objects to insert or update
+    pub upserts: Vec<ObjectRecord>,
+    /// Objects to remove
+    pub deletes: Vec<ObjectId>,
+}
+
+impl WriteBatch {
+    /// True when there is nothing to write
+    pub fn is_empty(&self) -> bool {
+        self.upserts.is_empty() && self.deletes.is_empty()
+    }
+
+    /// Fold a later batch into this one
+    ///
+    /// For each object only the highest version is kept, and a delete
+    /// drops any pending upsert of the same object, so a merged batch
+    /// has the same effect as applying both in order.
+    pub fn merge(&mut self, newer: WriteBatch) {
+        for record in newer.upserts {
+            self.deletes.retain(|id| *id != record.id);
+            match self.upserts.iter_mut().find(|r| r.id == record.id) {
+                Some(existing) if existing.version < record.version => *existing = record,
+                Some(_) => {}
+                None => self.upserts.push(record),
+            }
+        }
+        for id in newer.deletes {
+            self.upserts.retain(|r| r.id != id);
+            if !self.deletes.contains(&id) {
+                self.deletes.push(id);
+            }
+        }
+    }
+}
+
+/// What a store did with a [`WriteBatch`]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchResult {
+    /// Upserts that were written
+    pub applied: usize,
+    /// Upserts ignored because the store already held that version or newer
+    pub skipped: usize,
+    /// Objects removed
+    pub deleted: usize,
+}
+
+/// A login account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AccountRecord {
+    /// Database id
+    pub id: i64,
+    /// Unique login name
+    pub username: String,
+    /// Password hash produced by auth-service (never the plain password)
+    pub password_hash: String,
+    /// Creation time in Unix seconds
+    pub created_at: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: u64, version: u64) -> ObjectRecord {
+        ObjectRecord {
+            id: ObjectId(id),
+            kind: ObjectKind::Thing,
+            name: format!("v{}", version),
+            owner: ObjectId(1),
+            location: None,
+            destination: None,
+            flags: 0,
+            locks: BTreeMap::new(),
+            attributes: Vec::new(),
+            script: None,
+            script_owner: None,
+            script_flags: 0,
+            script_revisions: Vec::new(),
+            timers: Vec::new(),
+            version,
+        }
+    }
+
+    #[test]
+    fn test_merge_keeps_latest() {
+        let mut batch = WriteBatch {
+            upserts: vec![record(2, 3), record(3, 1)],
+            deletes: Vec::new(),
+        };
+        batch.merge(WriteBatch {
+            upserts: vec![record(2, 4), record(4, 1)],
+            deletes: vec![ObjectId(3)],
+        });
+        batch.merge(WriteBatch {
+            upserts: vec![record(2, 2)],
+            deletes: vec![ObjectId(3)],
+        });
+
+        let upserts: Vec<_> = batch.upserts.iter().map(|r| (r.id.0, r.version)).collect();
+        assert_eq!(upserts, vec![(2, 4), (4, 1)]);
+        assert_eq!(batch.deletes, vec![ObjectId(3)]);
+    }
+}
@@ -0,0 +1,381 @@
+//! Scripting Protocol
+//!
+//! Types exchanged between world-state and script-executor about object
+//! scripts. World-state never compiles scripts itself; it asks the
+//! executor and reports what comes back to the player.
+
+use crate::components::ObjectId;
+use crate::spatial::{Placed, Vec3};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// One problem found in a script
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ScriptDiagnostic {
+    /// 1-based line, or 0 if the problem has no single location
+    pub line: usize,
+    /// 1-based column, or 0 if unknown
+    pub column: usize,
+    /// What is wrong
+    pub message: String,
+}
+
+impl fmt::Display for ScriptDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (0, _) => f.write_str(&self.message),
+            (line, 0) => write!(f, "line {}: {}", line, self.message),
+            (line, column) => write!(f, "line {}, column {}: {}", line, column, self.message),
+        }
+    }
+}
+
+/// Whether an owner's scripts may run right now
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum QuotaStatus {
+    /// Under quota
+    Ok,
+    /// Over quota; new runs are refused until usage ages out of the window
+    Throttled {
+        /// Milliseconds until runs are likely to be admitted again
+        retry_after_ms: u64,
+    },
+    /// Repeatedly over quota; nothing runs until the suspension ends
+    Suspended {
+        /// Milliseconds until the suspension ends
+        remaining_ms: u64,
+    },
+}
+
+/// Script resource usage charged to one owner over the last minute
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct QuotaReport {
+    /// Player the usage is charged to
+    pub owner: ObjectId,
+    /// Rhai operations used
+    pub ops_used: u64,
+    /// Rhai operations allowed per minute
+    pub ops_limit: u64,
+    /// Wall-clock milliseconds used
+    pub wall_ms_used: u64,
+    /// Wall-clock milliseconds allowed per minute
+    pub wall_ms_limit: u64,
+    /// Whether scripts may run
+    pub status: QuotaStatus,
+}
+
+/// Scheduling class of a script job
+///
+/// Ordered so that `Interactive > Tick`: workers always take queued
+/// interactive jobs before tick jobs.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum JobPriority {
+    /// Periodic and timer-driven scripts
+    Tick,
+    /// Scripts a player is waiting on (commands, `on_use`, ...)
+    Interactive,
+}
+
+/// A request to run a script
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScriptJob {
+    /// Player the run is charged to
+    pub owner: ObjectId,
+    /// Queue position relative to other jobs
+    pub priority: JobPriority,
+    /// Script source
+    pub source: String,
+    /// Function to call; `None` evaluates the script itself
+    #[serde(default)]
+    pub function: Option<String>,
+    /// Arguments for `function`
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Record every host function call in the output's trace
+    #[serde(default)]
+    pub trace: bool,
+    /// Library modules the script may `import`
+    #[serde(default)]
+    pub modules: Vec<ModuleSource>,
+    /// What the object can see around it, for the spatial query host
+    /// functions; only sent to scripts that call them
+    #[serde(default)]
+    pub surroundings: Option<Surroundings>,
+}
+
+/// Host functions answered from a job's [`Surroundings`]
+pub const SPATIAL_FUNCTIONS: &[&str] = &[
+    "position",
+    "facing",
+    "raycast",
+    "overlap_sphere",
+    "overlap_box",
+    "nearest",
+];
+
+/// The part of a room a script's object can see, resolved by world-state
+/// for a job
+///
+/// Positions are in the room's coordinates.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Surroundings {
+    /// Where the object is
+    pub position: Vec3,
+    /// Which way it faces, as a unit vector
+    pub facing: Vec3,
+    /// Nearby objects its owner may see, nearest first, leaving out the
+    /// object itself
+    pub objects: Vec<Placed>,
+}
+
+impl Surroundings {
+    /// Whether `source` may call a spatial query host function
+    ///
+    /// A textual check, so world-state only gathers surroundings for
+    /// scripts that could use them.
+    pub fn wanted_by(source: &str) -> bool {
+        SPATIAL_FUNCTIONS.iter().any(|function| {
+            source
+                .match_indices(function)
+                .any(|(at, _)| source[at + function.len()..].trim_start().starts_with('('))
+        })
+    }
+}
+
+/// Source of a library module, resolved by world-state for a job
+///
+/// A revision's source never changes, so `(object, revision)` identifies
+/// the compiled module and is what script-executor caches it under.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ModuleSource {
+    /// Import path as written in the script, e.g. `lib/combat` or
+    /// `lib/combat@3`
+    pub path: String,
+    /// Library object the source comes from
+    pub object: ObjectId,
+    /// Script revision of the library object
+    pub revision: u32,
+    /// Module source
+    pub source: String,
+}
+
+/// A script's request to call one of its functions later
+///
+/// Produced by the `wait` and `schedule_at` host functions. World-state
+/// turns each request into a persisted timer on the object that ran.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TimerRequest {
+    /// Script function to call when the timer fires
+    pub function: String,
+    /// Milliseconds from the end of the run until it fires
+    pub delay_ms: u64,
+}
+
+/// Which script function wrote an output line
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    /// `print(...)`
+    Print,
+    /// `debug(...)`, which renders values the way they would be written in
+    /// source
+    Debug,
+}
+
+/// One line a script wrote
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OutputLine {
+    /// Where it came from
+    pub stream: OutputStream,
+    /// What was written
+    pub text: String,
+    /// 1-based line of the call, or 0 if unknown
+    #[serde(default)]
+    pub line: usize,
+}
+
+/// One call a script made to a host function
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HostCall {
+    /// Function name
+    pub function: String,
+    /// Arguments, rendered as text
+    pub args: Vec<String>,
+    /// Return value, or the error the call raised
+    pub result: Result<String, String>,
+}
+
+/// Everything a run did besides returning its value
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExecutionTrace {
+    /// `print` and `debug` lines, in order
+    pub output: Vec<OutputLine>,
+    /// Lines dropped after the output limit was reached
+    #[serde(default)]
+    pub dropped_lines: usize,
+    /// Rhai operations performed
+    pub ops: u64,
+    /// Wall-clock time the run was charged for, in microseconds
+    pub duration_us: u64,
+    /// Host function calls, in order, when the job asked for a trace
+    #[serde(default)]
+    pub host_calls: Option<Vec<HostCall>>,
+}
+
+/// What a successful run produced
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct JobOutput {
+    /// The script's return value, rendered as text
+    pub value: String,
+    /// Effects: calls the script asked to have made later
+    #[serde(default)]
+    pub timers: Vec<TimerRequest>,
+    /// Output, usage and (optionally) host calls
+    #[serde(default)]
+    pub trace: ExecutionTrace,
+}
+
+/// Reply to a submitted [`ScriptJob`]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct JobOutcome {
+    /// Id the job ran under
+    pub id: u64,
+    /// What the run produced, if it succeeded
+    pub output: Option<JobOutput>,
+    /// Why it failed, otherwise
+    pub error: Option<String>,
+}
+
+/// Where a debug session pauses
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(tag = "at", rename_all = "snake_case")]
+pub enum Breakpoint {
+    /// The first statement on a 1-based source line
+    Line {
+        /// Source line
+        line: usize,
+    },
+    /// Every call to a function
+    Function {
+        /// Function name
+        name: String,
+    },
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Line { line } => write!(f, "line {}", line),
+            Self::Function { name } => write!(f, "{}()", name),
+        }
+    }
+}
+
+/// Request to run a job under the debugger
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DebugStart {
+    /// What to run
+    pub job: ScriptJob,
+    /// Where to pause; with none, the session pauses at the first statement
+    #[serde(default)]
+    pub breakpoints: Vec<Breakpoint>,
+}
+
+/// What a paused debug session should do next
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum DebugCommand {
+    /// Run until the next breakpoint
+    Continue,
+    /// Pause at the next statement or expression, entering function calls
+    Step,
+    /// Pause at the next statement, stepping over function calls
+    Next,
+    /// Run until the current function returns
+    Finish,
+    /// Add a breakpoint and stay paused
+    Break {
+        /// Breakpoint to add
+        breakpoint: Breakpoint,
+    },
+    /// Remove a breakpoint and stay paused
+    Clear {
+        /// Breakpoint to remove
+        breakpoint: Breakpoint,
+    },
+    /// Stop the run
+    Abort,
+}
+
+/// One function call on a paused session's call stack
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DebugFrame {
+    /// Function name
+    pub function: String,
+    /// Arguments, rendered as text
+    pub args: Vec<String>,
+    /// Line of the call, or 0 if unknown
+    pub line: usize,
+}
+
+/// One variable in scope where a session paused
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DebugVariable {
+    /// Variable name
+    pub name: String,
+    /// Rhai type name
+    pub type_name: String,
+    /// Value, rendered as text
+    pub value: String,
+    /// Declared with `const`
+    pub constant: bool,
+}
+
+/// Where a debug session stands
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum DebugState {
+    /// Waiting for a [`DebugCommand`]
+    Paused {
+        /// Why it paused (`step`, `breakpoint`, `returned ...`, `failed: ...`)
+        reason: String,
+        /// 1-based line, or 0 if unknown
+        line: usize,
+        /// 1-based column, or 0 if unknown
+        column: usize,
+        /// Active calls, innermost first
+        stack: Vec<DebugFrame>,
+        /// Variables in scope, in declaration order
+        scope: Vec<DebugVariable>,
+        /// Breakpoints currently set
+        breakpoints: Vec<Breakpoint>,
+    },
+    /// The run completed; the session is over
+    Finished {
+        /// The script's return value
+        value: String,
+    },
+    /// The run failed or was aborted; the session is over
+    Failed {
+        /// What went wrong
+        error: String,
+    },
+}
+
+impl DebugState {
+    /// True once the session has ended
+    pub fn is_over(&self) -> bool {
+        !matches!(self, Self::Paused { .. })
+    }
+}
+
+/// Reply to every debug request
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DebugReply {
+    /// Session id, for follow-up commands
+    pub session: u64,
+    /// Where the session stands now
+    pub state: DebugState,
+}
@@ -0,0 +1,633 @@
+//! Delta-Compressed Snapshots
+//!
+//! Each tick graphics-gateway sends every client a [`Snapshot`]: the
+//! position and rotation of each object the client can see. Sent whole,
+//! a hundred objects is several kilobytes per client per tick. Three
+//! things shrink it:
+//!
+//! - **Quantization.** Positions are stored in steps of
+//!   1/[`POSITION_SCALE`] of a world unit. Rotations keep their three
+//!   smallest quaternion components at [`ROTATION_BITS`] each, since the
+//!   largest follows from the other three.
+//! - **Baselines.** The client acknowledges each snapshot it decodes.
+//!   The [`DeltaEncoder`] encodes the next one against the newest
+//!   snapshot acknowledged so far, which the client is known to have.
+//!   Objects that haven't changed since then are left out entirely.
+//! - **Bit packing.** For objects that did change, each field gets a bit
+//!   saying whether it changed. Changed positions are sent as the
+//!   difference from the baseline, in as few bits as that difference
+//!   needs.
+//!
+//! The client's [`DeltaDecoder`] keeps the snapshots it may still be
+//! asked to build on. If a packet is lost, nothing has to be resent: the
+//! next snapshot is still encoded against something the client has.
+//!
+//! # Learning Note
+//! An unreliable channel can lose or reorder any packet. Because deltas
+//! are only taken against acknowledged snapshots, every packet can be
+//! decoded by itself. Losing one only means later packets carry slightly
+//! larger deltas until an ack gets through.
+
+use bevy::prelude::*;
+use std::collections::{BTreeMap, VecDeque};
+use std::f32::consts::SQRT_2;
+use std::fmt;
+
+use crate::components::ObjectId;
+
+/// Position steps per world unit
+pub const POSITION_SCALE: f32 = 64.0;
+
+/// Bits per stored quaternion component
+pub const ROTATION_BITS: u32 = 10;
+
+/// How many snapshots back a baseline may be before the encoder falls
+/// back to sending everything
+pub const SNAPSHOT_HISTORY: u32 = 64;
+
+/// Bytes an object costs sent as plain floats: id, position and rotation
+pub const FULL_STATE_BYTES: usize = 8 + 3 * 4 + 4 * 4;
+
+/// Field widths for object id gaps, smallest first
+const ID_WIDTHS: [u32; 4] = [4, 10, 20, 64];
+
+/// Field widths for counts and coordinate differences
+const VALUE_WIDTHS: [u32; 4] = [4, 8, 16, 32];
+
+/// An object's position and rotation as sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuantizedState {
+    /// Position in 1/[`POSITION_SCALE`] steps
+    pub position: IVec3,
+    /// Rotation packed as the index of its largest component, then the
+    /// other three
+    pub rotation: u32,
+}
+
+impl QuantizedState {
+    /// Quantize a position and rotation
+    pub fn new(position: Vec3, rotation: Quat) -> Self {
+        Self {
+            position: (position * POSITION_SCALE).round().as_ivec3(),
+            rotation: pack_rotation(rotation),
+        }
+    }
+
+    /// The position, to within half a step
+    pub fn position(&self) -> Vec3 {
+        self.position.as_vec3() / POSITION_SCALE
+    }
+
+    /// The rotation, normalized
+    pub fn rotation(&self) -> Quat {
+        unpack_rotation(self.rotation)
+    }
+}
+
+/// The visible objects' state at one tick, as one client sees it
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Numbered from 1, increasing by one per snapshot sent to a client
+    pub id: u32,
+    /// Tick of the last input applied to the client's avatar, confirming
+    /// its predictions up to there
+    pub last_input: u32,
+    /// Every object the client sees
+    pub objects: BTreeMap<ObjectId, QuantizedState>,
+}
+
+/// Why a snapshot couldn't be decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The packet ends in the middle of a field
+    Truncated,
+    /// The packet builds on a snapshot this client doesn't have
+    UnknownBaseline(u32),
+    /// A newer snapshot has already been decoded
+    Stale(u32),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "snapshot is truncated"),
+            Self::UnknownBaseline(id) => write!(f, "unknown baseline snapshot {}", id),
+            Self::Stale(id) => write!(f, "snapshot {} is older than the latest", id),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// Encode `snapshot`, as a delta against `baseline` if given
+///
+/// Layout, most significant bit first:
+///
+/// - snapshot id (32 bits), then whether there is a baseline (1 bit) and,
+///   if so, how many snapshots back it is
+/// - `last_input` (32 bits)
+/// - the number of new or changed objects, then each one: the gap from
+///   the previous object's id, then either its whole state (new objects)
+///   or a changed bit per field followed by the field's difference
+/// - the number of objects gone since the baseline, then their id gaps
+pub fn encode(baseline: Option<&Snapshot>, snapshot: &Snapshot) -> Vec<u8> {
+    let mut out = BitWriter::default();
+    out.write(snapshot.id as u64, 32);
+    out.write(baseline.is_some() as u64, 1);
+    if let Some(baseline) = baseline {
+        write_value(
+            &mut out,
+            snapshot.id.wrapping_sub(baseline.id) as u64,
+            &VALUE_WIDTHS,
+        );
+    }
+    out.write(snapshot.last_input as u64, 32);
+
+    let empty = BTreeMap::new();
+    let before = baseline.map_or(&empty, |baseline| &baseline.objects);
+    let changed: Vec<_> = snapshot
+        .objects
+        .iter()
+        .filter(|(id, state)| before.get(id) != Some(state))
+        .collect();
+    write_value(&mut out, changed.len() as u64, &VALUE_WIDTHS);
+    let mut previous = 0;
+    for (id, state) in changed {
+        write_value(&mut out, id.0 - previous, &ID_WIDTHS);
+        previous = id.0;
+        match before.get(id) {
+            Some(old) => write_delta(&mut out, old, state),
+            None => {
+                for axis in state.position.to_array() {
+                    write_value(&mut out, zigzag(axis) as u64, &VALUE_WIDTHS);
+                }
+                out.write(state.rotation as u64, 32);
+            }
+        }
+    }
+
+    let removed: Vec<_> = before
+        .keys()
+        .filter(|id| !snapshot.objects.contains_key(id))
+        .collect();
+    write_value(&mut out, removed.len() as u64, &VALUE_WIDTHS);
+    let mut previous = 0;
+    for id in removed {
+        write_value(&mut out, id.0 - previous, &ID_WIDTHS);
+        previous = id.0;
+    }
+    out.bytes
+}
+
+/// Decode a snapshot written by [`encode`], looking its baseline up by
+/// id with `baseline`
+pub fn decode<'a>(
+    bytes: &[u8],
+    baseline: impl FnOnce(u32) -> Option<&'a Snapshot>,
+) -> Result<Snapshot, SnapshotError> {
+    let mut input = BitReader::new(bytes);
+    let id = input.read(32)? as u32;
+    let mut objects = BTreeMap::new();
+    if input.read(1)? == 1 {
+        let back = read_value(&mut input, &VALUE_WIDTHS)? as u32;
+        let baseline_id = id.wrapping_sub(back);
+        objects = baseline(baseline_id)
+            .ok_or(SnapshotError::UnknownBaseline(baseline_id))?
+            .objects
+            .clone();
+    }
+    let last_input = input.read(32)? as u32;
+
+    let changed = read_value(&mut input, &VALUE_WIDTHS)?;
+    let mut previous = 0u64;
+    for _ in 0..changed {
+        previous = previous.wrapping_add(read_value(&mut input, &ID_WIDTHS)?);
+        let id = ObjectId(previous);
+        let state = match objects.get(&id) {
+            Some(old) => read_delta(&mut input, old)?,
+            None => {
+                let mut axes = [0; 3];
+                for axis in &mut axes {
+                    *axis = unzigzag(read_value(&mut input, &VALUE_WIDTHS)? as u32);
+                }
+                QuantizedState {
+                    position: IVec3::from_array(axes),
+                    rotation: input.read(32)? as u32,
+                }
+            }
+        };
+        objects.insert(id, state);
+    }
+
+    let removed = read_value(&mut input, &VALUE_WIDTHS)?;
+    let mut previous = 0u64;
+    for _ in 0..removed {
+        previous = previous.wrapping_add(read_value(&mut input, &ID_WIDTHS)?);
+        objects.remove(&ObjectId(previous));
+    }
+    Ok(Snapshot {
+        id,
+        last_input,
+        objects,
+    })
+}
+
+fn write_delta(out: &mut BitWriter, old: &QuantizedState, new: &QuantizedState) {
+    for (old, new) in old
+        .position
+        .to_array()
+        .into_iter()
+        .zip(new.position.to_array())
+    {
+        out.write((old != new) as u64, 1);
+        if old != new {
+            write_value(out, zigzag(new.wrapping_sub(old)) as u64, &VALUE_WIDTHS);
+        }
+    }
+    out.write((old.rotation != new.rotation) as u64, 1);
+    if old.rotation != new.rotation {
+        out.write(new.rotation as u64, 32);
+    }
+}
+
+fn read_delta(
+    input: &mut BitReader,
+    old: &QuantizedState,
+) -> Result<QuantizedState, SnapshotError> {
+    let mut axes = old.position.to_array();
+    for axis in &mut axes {
+        if input.read(1)? == 1 {
+            let delta = unzigzag(read_value(input, &VALUE_WIDTHS)? as u32);
+            *axis = axis.wrapping_add(delta);
+        }
+    }
+    let rotation = match input.read(1)? {
+        1 => input.read(32)? as u32,
+        _ => old.rotation,
+    };
+    Ok(QuantizedState {
+        position: IVec3::from_array(axes),
+        rotation,
+    })
+}
+
+/// Server side of one client's snapshot stream
+///
+/// Remembers what was sent until the client acknowledges it, and encodes
+/// each new snapshot against the newest acknowledged one.
+#[derive(Debug, Default)]
+pub struct DeltaEncoder {
+    last_id: u32,
+    /// Sent but not yet acknowledged, oldest first
+    sent: VecDeque<Snapshot>,
+    /// Newest snapshot the client has acknowledged
+    acked: Option<Snapshot>,
+}
+
+impl DeltaEncoder {
+    /// Number and encode the next snapshot
+    pub fn encode(
+        &mut self,
+        last_input: u32,
+        objects: BTreeMap<ObjectId, QuantizedState>,
+    ) -> (u32, Vec<u8>) {
+        self.last_id += 1;
+        let snapshot = Snapshot {
+            id: self.last_id,
+            last_input,
+            objects,
+        };
+        // The client only keeps so many snapshots; past that, start over
+        let baseline = self
+            .acked
+            .as_ref()
+            .filter(|acked| snapshot.id - acked.id < SNAPSHOT_HISTORY);
+        let bytes = encode(baseline, &snapshot);
+        self.sent.push_back(snapshot);
+        while self.sent.len() > SNAPSHOT_HISTORY as usize {
+            self.sent.pop_front();
+        }
+        (self.last_id, bytes)
+    }
+
+    /// The client has decoded snapshot `id`
+    ///
+    /// Acks for snapshots older than the current baseline, or no longer
+    /// remembered, are ignored.
+    pub fn ack(&mut self, id: u32) {
+        if self.acked.as_ref().is_some_and(|acked| acked.id >= id) {
+            return;
+        }
+        while let Some(sent) = self.sent.pop_front() {
+            if sent.id == id {
+                self.acked = Some(sent);
+                return;
+            }
+            if sent.id > id {
+                self.sent.push_front(sent);
+                return;
+            }
+        }
+    }
+
+    /// Id of the current baseline, if the client has acknowledged any
+    pub fn baseline(&self) -> Option<u32> {
+        self.acked.as_ref().map(|acked| acked.id)
+    }
+}
+
+/// Client side of a snapshot stream
+///
+/// Keeps the snapshots the server may still use as baselines. Ack each
+/// decoded snapshot's id back to the server.
+#[derive(Debug, Default)]
+pub struct DeltaDecoder {
+    /// Decoded snapshots, oldest first; the last is the latest
+    received: VecDeque<Snapshot>,
+}
+
+impl DeltaDecoder {
+    /// Decode the next snapshot from the server
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<&Snapshot, SnapshotError> {
+        let latest = self.latest().map_or(0, |latest| latest.id);
+        let mut baseline_id = None;
+        let snapshot = decode(bytes, |id| {
+            baseline_id = Some(id);
+            self.received.iter().find(|snapshot| snapshot.id == id)
+        })?;
+        if snapshot.id <= latest {
+            return Err(SnapshotError::Stale(snapshot.id));
+        }
+        // The server's baseline only moves forward, so nothing older than
+        // this one will be asked for again
+        let oldest = baseline_id
+            .unwrap_or(0)
+            .max(snapshot.id.saturating_sub(SNAPSHOT_HISTORY));
+        self.received.retain(|kept| kept.id >= oldest);
+        self.received.push_back(snapshot);
+        Ok(self.received.back().unwrap())
+    }
+
+    /// The newest snapshot decoded
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.received.back()
+    }
+}
+
+/// Map signed differences to unsigned ones so small values of either
+/// sign need few bits: 0, -1, 1, -2, ... become 0, 1, 2, 3, ...
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn unzigzag(value: u32) -> i32 {
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+}
+
+/// Write `value` as a 2-bit width class then the value in that width
+fn write_value(out: &mut BitWriter, value: u64, widths: &[u32; 4]) {
+    let class = widths
+        .iter()
+        .position(|&width| width == 64 || value < 1 << width)
+        .unwrap_or(3);
+    out.write(class as u64, 2);
+    out.write(value, widths[class]);
+}
+
+fn read_value(input: &mut BitReader, widths: &[u32; 4]) -> Result<u64, SnapshotError> {
+    let class = input.read(2)? as usize;
+    input.read(widths[class])
+}
+
+/// Largest quaternion component stored last of four, so packing
+/// "smallest three" leaves it to be recomputed
+fn pack_rotation(rotation: Quat) -> u32 {
+    let rotation = match rotation.length_squared() {
+        length if length.is_finite() && length > 0.0 => rotation.normalize(),
+        _ => Quat::IDENTITY,
+    };
+    let components = rotation.to_array();
+    let largest = (0..4)
+        .max_by(|&a, &b| components[a].abs().total_cmp(&components[b].abs()))
+        .unwrap_or(3);
+    // q and -q are the same rotation; pick the one whose largest is positive
+    let sign = components[largest].signum();
+    let max = ((1 << ROTATION_BITS) - 1) as f32;
+    let mut packed = largest as u32;
+    for (i, component) in components.into_iter().enumerate() {
+        if i != largest {
+            // The others lie within ±1/√2
+            let unit = (component * sign * SQRT_2 + 1.0) / 2.0;
+            packed = packed << ROTATION_BITS | (unit.clamp(0.0, 1.0) * max).round() as u32;
+        }
+    }
+    packed
+}
+
+fn unpack_rotation(packed: u32) -> Quat {
+    let max = ((1 << ROTATION_BITS) - 1) as f32;
+    let mask = (1 << ROTATION_BITS) - 1;
+    let largest = (packed >> (3 * ROTATION_BITS)) as usize & 3;
+    let mut components = [0.0; 4];
+    let mut shift = 3 * ROTATION_BITS;
+    for (i, component) in components.iter_mut().enumerate() {
+        if i != largest {
+            shift -= ROTATION_BITS;
+            let unit = ((packed >> shift) & mask) as f32 / max;
+            *component = (unit * 2.0 - 1.0) / SQRT_2;
+        }
+    }
+    let rest: f32 = components.iter().map(|c| c * c).sum();
+    components[largest] = (1.0 - rest).max(0.0).sqrt();
+    Quat::from_array(components).normalize()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    /// Append the low `width` bits of `value`, most significant first
+    fn write(&mut self, value: u64, width: u32) {
+        for bit in (0..width).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> bit) & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    bits: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, bits: 0 }
+    }
+
+    fn read(&mut self, width: u32) -> Result<u64, SnapshotError> {
+        let mut value = 0;
+        for _ in 0..width {
+            let byte = self
+                .bytes
+                .get(self.bits / 8)
+                .ok_or(SnapshotError::Truncated)?;
+            value = value << 1 | ((byte >> (7 - self.bits % 8)) & 1) as u64;
+            self.bits += 1;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 100 objects on a grid, the first `walkers` of them walking in
+    /// circles and turning to face where they go
+    fn scene(tick: u32, walkers: u64) -> BTreeMap<ObjectId, QuantizedState> {
+        (0..100u64)
+            .map(|i| {
+                let home = Vec3::new((i % 10) as f32 * 8.0, (i / 10) as f32 * 8.0, 0.0);
+                let (position, rotation) = if i < walkers {
+                    let angle = tick as f32 / 60.0 + i as f32;
+                    (
+                        home + Vec3::new(angle.cos(), angle.sin(), 0.0) * 3.0,
+                        Quat::from_rotation_z(angle),
+                    )
+                } else {
+                    (home, Quat::from_rotation_z(i as f32))
+                };
+                (ObjectId(i * 3 + 1), QuantizedState::new(position, rotation))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_quantization_error() {
+        let position = Vec3::new(12.345, -678.9, 0.01);
+        let state = QuantizedState::new(position, Quat::IDENTITY);
+        assert!((state.position() - position).abs().max_element() <= 0.5 / POSITION_SCALE);
+
+        for i in 0..100 {
+            let axis = Vec3::new(i as f32, 1.0, -(i as f32) / 3.0).normalize();
+            let rotation = Quat::from_axis_angle(axis, i as f32 * 0.37);
+            let unpacked = QuantizedState::new(Vec3::ZERO, rotation).rotation();
+            assert!(unpacked.angle_between(rotation) < 0.005, "{}", i);
+        }
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let baseline = Snapshot {
+            id: 7,
+            last_input: 40,
+            objects: scene(0, 10),
+        };
+        let mut current = Snapshot {
+            id: 9,
+            last_input: 42,
+            objects: scene(30, 10),
+        };
+        current.objects.remove(&ObjectId(4));
+        current.objects.insert(
+            ObjectId(u64::MAX),
+            QuantizedState::new(Vec3::splat(-5.0), Quat::IDENTITY),
+        );
+
+        let bytes = encode(Some(&baseline), &current);
+        assert_eq!(decode(&bytes, |_| Some(&baseline)), Ok(current.clone()));
+        assert_eq!(
+            decode(&bytes, |_| None),
+            Err(SnapshotError::UnknownBaseline(7))
+        );
+        assert_eq!(
+            decode(&bytes[..bytes.len() - 1], |_| Some(&baseline)),
+            Err(SnapshotError::Truncated)
+        );
+        let full = encode(None, &current);
+        assert_eq!(decode(&full, |_| None), Ok(current));
+        assert!(bytes.len() < full.len() / 4);
+    }
+
+    #[test]
+    fn test_bytes_saved_in_100_object_scene() {
+        const TICKS: u32 = 300;
+        // Acks arrive 6 ticks after their snapshot was sent
+        const ACK_DELAY: u32 = 6;
+
+        let mut encoder = DeltaEncoder::default();
+        let mut decoder = DeltaDecoder::default();
+        let mut acks = VecDeque::new();
+        let mut sent = 0;
+        for tick in 0..TICKS {
+            while acks.front().is_some_and(|&(due, _)| due <= tick) {
+                encoder.ack(acks.pop_front().unwrap().1);
+            }
+            let objects = scene(tick, 20);
+            let (id, bytes) = encoder.encode(tick, objects.clone());
+            sent += bytes.len();
+            // Every tenth packet is lost
+            if tick % 10 == 9 {
+                continue;
+            }
+            let snapshot = decoder.decode(&bytes).unwrap();
+            assert_eq!((snapshot.id, &snapshot.objects), (id, &objects));
+            acks.push_back((tick + ACK_DELAY, id));
+        }
+
+        // Under 5% of sending every object's full state every tick
+        let uncompressed = TICKS as usize * 100 * FULL_STATE_BYTES;
+        let ratio = sent as f64 / uncompressed as f64;
+        assert!(
+            ratio < 0.05,
+            "{} bytes instead of {} ({:.1}%)",
+            sent,
+            uncompressed,
+            100.0 * ratio
+        );
+
+        // A scene standing still costs little more than the header
+        let (id, bytes) = encoder.encode(TICKS, scene(0, 0));
+        decoder.decode(&bytes).unwrap();
+        encoder.ack(id);
+        let (_, bytes) = encoder.encode(TICKS + 1, scene(0, 0));
+        assert!(bytes.len() <= 12, "{}", bytes.len());
+    }
+
+    #[test]
+    fn test_missing_acks_fall_back_to_full_snapshots() {
+        let mut encoder = DeltaEncoder::default();
+        let mut decoder = DeltaDecoder::default();
+        let (first, bytes) = encoder.encode(0, scene(0, 5));
+        decoder.decode(&bytes).unwrap();
+        encoder.ack(first);
+
+        // Acks stop getting through; deltas stay decodable until the
+        // baseline is too old, then whole snapshots are sent again
+        let mut last = Vec::new();
+        for tick in 1..=SNAPSHOT_HISTORY {
+            let (_, bytes) = encoder.encode(tick, scene(tick, 5));
+            assert!(decoder.decode(&bytes).is_ok(), "{}", tick);
+            last = bytes;
+        }
+        assert!(decode(&last, |_| None).is_ok());
+        assert_eq!(encoder.baseline(), Some(first));
+        assert_eq!(
+            decoder.decode(&last),
+            Err(SnapshotError::Stale(SNAPSHOT_HISTORY + 1))
+        );
+
+        // A late ack for something forgotten changes nothing
+        encoder.ack(first - 1);
+        assert_eq!(encoder.baseline(), Some(first));
+    }
+}
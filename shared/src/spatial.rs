@@ -0,0 +1,496 @@
+//! Spatial Queries
+//!
+//! An index of where objects are, answering the questions scripts and
+//! server systems ask about their surroundings:
+//!
+//! - [`SpatialIndex::raycast`]: the first object along a ray
+//! - [`SpatialIndex::overlap_sphere`] and [`SpatialIndex::overlap_box`]:
+//!   every object touching a volume
+//! - [`SpatialIndex::nearest`]: the `k` objects closest to a point
+//!
+//! Objects are bounding spheres: [`Placed`] holds a position and a radius
+//! (see `physics::Shape::bounding_radius`, or [`POINT_RADIUS`] for
+//! objects without a collider). Colliders are never bigger than
+//! `physics::MAX_COLLIDER_RADIUS`, and the columns a sphere is filed
+//! under are worked out as if it were no bigger either, so a bad radius
+//! can't make the index touch an unbounded number of columns. Each room is its own space, since every
+//! room's coordinates start at the origin, so every query names a room.
+//!
+//! Every query takes a filter, which is how callers leave out objects the
+//! asker may not see. No query reaches further than [`MAX_RANGE`] from
+//! where it starts, so one query's cost is bounded however it is called.
+//!
+//! # Learning Note
+//! Like `interest`, the index is a grid of [`CELL_SIZE`] columns, which
+//! suits worlds that are wide rather than tall. An object goes in every
+//! column its sphere overlaps, so a query only has to look in the columns
+//! its own volume overlaps. A ray walks the columns it crosses in order
+//! (a 2D DDA), which lets it stop at the first column holding a hit
+//! rather than testing everything along its length.
+
+use bevy::math::{IVec2, Vec2};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::components::ObjectId;
+use crate::physics::MAX_COLLIDER_RADIUS;
+
+// Re-exported so script-executor can build queries without depending on
+// bevy itself
+pub use bevy::math::Vec3;
+
+/// Side of a grid column, in world units
+pub const CELL_SIZE: f32 = 4.0;
+
+/// Farthest any query reaches from where it starts, in world units
+pub const MAX_RANGE: f32 = 64.0;
+
+/// Radius of objects without a collider
+pub const POINT_RADIUS: f32 = 0.5;
+
+/// Which way an object with no rotation faces: north
+pub const FORWARD: Vec3 = Vec3::Y;
+
+/// An object's bounding sphere
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Placed {
+    /// The object
+    pub id: ObjectId,
+    /// Its center
+    pub position: Vec3,
+    /// Its radius
+    pub radius: f32,
+}
+
+impl Placed {
+    /// Distance from `point` to the sphere's surface, or 0 inside it
+    pub fn distance_to(&self, point: Vec3) -> f32 {
+        (self.position.distance(point) - self.radius).max(0.0)
+    }
+}
+
+/// The first object a ray met
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    /// The object
+    pub id: ObjectId,
+    /// How far along the ray it was met
+    pub distance: f32,
+    /// Where the ray met its surface
+    pub point: Vec3,
+}
+
+/// Grid column: a room and a column within it
+type Cell = (ObjectId, IVec2);
+
+fn cell_of(room: ObjectId, position: Vec3) -> Cell {
+    (room, column(position.truncate()))
+}
+
+fn column(point: Vec2) -> IVec2 {
+    (point / CELL_SIZE).floor().as_ivec2()
+}
+
+/// The first and last columns a sphere is filed under
+fn span(placed: &Placed) -> (IVec2, IVec2) {
+    let reach = Vec2::splat(placed.radius.clamp(0.0, MAX_COLLIDER_RADIUS));
+    let center = placed.position.truncate();
+    (column(center - reach), column(center + reach))
+}
+
+/// Where objects are, by room
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    objects: HashMap<ObjectId, (ObjectId, Placed)>,
+    grid: HashMap<Cell, HashSet<ObjectId>>,
+}
+
+impl SpatialIndex {
+    /// Record that `placed` is in `room` now
+    pub fn upsert(&mut self, room: ObjectId, placed: Placed) {
+        self.remove(placed.id);
+        let (min, max) = span(&placed);
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                self.grid
+                    .entry((room, IVec2::new(x, y)))
+                    .or_default()
+                    .insert(placed.id);
+            }
+        }
+        self.objects.insert(placed.id, (room, placed));
+    }
+
+    /// Forget an object that was destroyed or left every room
+    pub fn remove(&mut self, id: ObjectId) {
+        let Some((room, old)) = self.objects.remove(&id) else {
+            return;
+        };
+        let (min, max) = span(&old);
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                let cell = (room, IVec2::new(x, y));
+                if let Some(ids) = self.grid.get_mut(&cell) {
+                    ids.remove(&id);
+                    if ids.is_empty() {
+                        self.grid.remove(&cell);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The room an object is in and its bounding sphere, if indexed
+    pub fn get(&self, id: ObjectId) -> Option<(ObjectId, Placed)> {
+        self.objects.get(&id).copied()
+    }
+
+    /// The first object from `origin` along `direction`, within
+    /// `max_distance` (at most [`MAX_RANGE`])
+    ///
+    /// A ray starting inside an object hits it at distance 0.
+    pub fn raycast(
+        &self,
+        room: ObjectId,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        mut filter: impl FnMut(ObjectId) -> bool,
+    ) -> Option<RayHit> {
+        let direction = direction.try_normalize()?;
+        let max_distance = max_distance.min(MAX_RANGE);
+        if !origin.is_finite() || max_distance.is_nan() || max_distance < 0.0 {
+            return None;
+        }
+
+        let mut cell = column(origin.truncate());
+        let step = IVec2::new(axis_step(direction.x), axis_step(direction.y));
+        // Distance along the ray to the next column boundary on each axis,
+        // and between boundaries
+        let boundary = |cell: i32, step: i32, origin: f32, direction: f32| match step {
+            0 => f32::INFINITY,
+            1 => ((cell + 1) as f32 * CELL_SIZE - origin) / direction,
+            _ => (cell as f32 * CELL_SIZE - origin) / direction,
+        };
+        let mut next = Vec2::new(
+            boundary(cell.x, step.x, origin.x, direction.x),
+            boundary(cell.y, step.y, origin.y, direction.y),
+        );
+        let delta = Vec2::new(CELL_SIZE / direction.x.abs(), CELL_SIZE / direction.y.abs());
+
+        let mut tested = HashSet::new();
+        let mut best: Option<RayHit> = None;
+        loop {
+            for &id in self.grid.get(&(room, cell)).into_iter().flatten() {
+                if !tested.insert(id) || !filter(id) {
+                    continue;
+                }
+                let (_, placed) = self.objects[&id];
+                let Some(distance) = ray_sphere(origin, direction, placed) else {
+                    continue;
+                };
+                let closer = best.is_none_or(|hit| (distance, id) < (hit.distance, hit.id));
+                if distance <= max_distance && closer {
+                    best = Some(RayHit {
+                        id,
+                        distance,
+                        point: origin + direction * distance,
+                    });
+                }
+            }
+            // Nothing in a later column can be nearer than its boundary
+            let exit = next.min_element();
+            if best.is_some_and(|hit| hit.distance <= exit) || exit > max_distance {
+                return best;
+            }
+            if next.x < next.y {
+                cell.x += step.x;
+                next.x += delta.x;
+            } else {
+                cell.y += step.y;
+                next.y += delta.y;
+            }
+        }
+    }
+
+    /// Objects whose spheres touch the sphere at `center`, by id
+    ///
+    /// `radius` is capped at [`MAX_RANGE`].
+    pub fn overlap_sphere(
+        &self,
+        room: ObjectId,
+        center: Vec3,
+        radius: f32,
+        filter: impl FnMut(ObjectId) -> bool,
+    ) -> Vec<ObjectId> {
+        let mut ids: Vec<ObjectId> = self
+            .within(room, center, radius, filter)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Objects whose spheres touch the box from `min` to `max`, by id
+    ///
+    /// The box is cut down to [`MAX_RANGE`] on each side of its center.
+    pub fn overlap_box(
+        &self,
+        room: ObjectId,
+        min: Vec3,
+        max: Vec3,
+        mut filter: impl FnMut(ObjectId) -> bool,
+    ) -> Vec<ObjectId> {
+        let center = (min + max) / 2.0;
+        let reach = Vec3::splat(MAX_RANGE);
+        let (min, max) = (min.max(center - reach), max.min(center + reach));
+        let mut ids: Vec<ObjectId> = self
+            .candidates(room, min, max)
+            .into_iter()
+            .filter(|id| {
+                let (_, placed) = self.objects[id];
+                let closest = placed.position.clamp(min, max);
+                closest.distance(placed.position) <= placed.radius && filter(*id)
+            })
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Up to `k` objects within `max_distance` (at most [`MAX_RANGE`]) of
+    /// `point`, nearest first, with their distances
+    ///
+    /// Distances are to the objects' surfaces, so a large object can come
+    /// before a small one whose center is closer.
+    pub fn nearest(
+        &self,
+        room: ObjectId,
+        point: Vec3,
+        k: usize,
+        max_distance: f32,
+        mut filter: impl FnMut(ObjectId) -> bool,
+    ) -> Vec<(ObjectId, f32)> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let max_distance = max_distance.min(MAX_RANGE);
+        // Widen the search until it holds k objects; anything outside it
+        // is farther than everything inside
+        let mut radius = CELL_SIZE.min(max_distance);
+        loop {
+            let mut found = self.within(room, point, radius, &mut filter);
+            if found.len() >= k || radius >= max_distance {
+                found.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+                found.truncate(k);
+                return found;
+            }
+            radius = (radius * 2.0).min(max_distance);
+        }
+    }
+
+    /// Objects within `radius` of `center`, with their distances
+    fn within(
+        &self,
+        room: ObjectId,
+        center: Vec3,
+        radius: f32,
+        mut filter: impl FnMut(ObjectId) -> bool,
+    ) -> Vec<(ObjectId, f32)> {
+        let radius = radius.min(MAX_RANGE);
+        if !center.is_finite() || radius.is_nan() || radius < 0.0 {
+            return Vec::new();
+        }
+        let reach = Vec3::splat(radius);
+        self.candidates(room, center - reach, center + reach)
+            .into_iter()
+            .filter_map(|id| {
+                let distance = self.objects[&id].1.distance_to(center);
+                (distance <= radius && filter(id)).then_some((id, distance))
+            })
+            .collect()
+    }
+
+    /// Objects in the columns the box from `min` to `max` overlaps
+    fn candidates(&self, room: ObjectId, min: Vec3, max: Vec3) -> HashSet<ObjectId> {
+        let mut ids = HashSet::new();
+        if !min.is_finite() || !max.is_finite() {
+            return ids;
+        }
+        let (_, low) = cell_of(room, min);
+        let (_, high) = cell_of(room, max);
+        for x in low.x..=high.x {
+            for y in low.y..=high.y {
+                ids.extend(
+                    self.grid
+                        .get(&(room, IVec2::new(x, y)))
+                        .into_iter()
+                        .flatten(),
+                );
+            }
+        }
+        ids
+    }
+}
+
+fn axis_step(direction: f32) -> i32 {
+    if direction > 0.0 {
+        1
+    } else if direction < 0.0 {
+        -1
+    } else {
+        0
+    }
+}
+
+/// How far along a ray (with a unit `direction`) it meets a sphere
+fn ray_sphere(origin: Vec3, direction: Vec3, sphere: Placed) -> Option<f32> {
+    let offset = origin - sphere.position;
+    let along = offset.dot(direction);
+    let outside = offset.length_squared() - sphere.radius * sphere.radius;
+    if outside <= 0.0 {
+        return Some(0.0);
+    }
+    // Starting outside and pointing away
+    if along > 0.0 {
+        return None;
+    }
+    let discriminant = along * along - outside;
+    (discriminant >= 0.0).then(|| -along - discriminant.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HALL: ObjectId = ObjectId(1);
+    const YARD: ObjectId = ObjectId(2);
+
+    fn place(index: &mut SpatialIndex, room: ObjectId, id: u64, position: Vec3, radius: f32) {
+        index.upsert(
+            room,
+            Placed {
+                id: ObjectId(id),
+                position,
+                radius,
+            },
+        );
+    }
+
+    fn index() -> SpatialIndex {
+        let mut index = SpatialIndex::default();
+        place(&mut index, HALL, 10, Vec3::new(0.0, 10.0, 0.0), 1.0);
+        place(&mut index, HALL, 11, Vec3::new(0.0, 20.0, 0.0), 1.0);
+        place(&mut index, HALL, 12, Vec3::new(3.0, 3.0, 0.0), 0.5);
+        // A pillar wide enough to span several columns
+        place(&mut index, HALL, 13, Vec3::new(-30.0, -30.0, 0.0), 6.0);
+        // Right where 10 is, but in another room
+        place(&mut index, YARD, 20, Vec3::new(0.0, 10.0, 0.0), 1.0);
+        index
+    }
+
+    #[test]
+    fn test_raycast() {
+        let mut index = index();
+        let all = |_| true;
+
+        let hit = index.raycast(HALL, Vec3::ZERO, FORWARD, 50.0, all).unwrap();
+        assert_eq!(hit.id, ObjectId(10));
+        assert!((hit.distance - 9.0).abs() < 1e-4);
+        assert!(hit.point.abs_diff_eq(Vec3::new(0.0, 9.0, 0.0), 1e-4));
+
+        // Hidden objects are looked through; range cuts the ray short
+        let hit = index.raycast(HALL, Vec3::ZERO, FORWARD, 50.0, |id| id != ObjectId(10));
+        assert_eq!(hit.map(|hit| hit.id), Some(ObjectId(11)));
+        assert_eq!(index.raycast(HALL, Vec3::ZERO, FORWARD, 5.0, all), None);
+
+        // Diagonally, across many columns, into the big pillar
+        let hit = index
+            .raycast(HALL, Vec3::ZERO, Vec3::new(-1.0, -1.0, 0.0), 64.0, all)
+            .unwrap();
+        assert_eq!(hit.id, ObjectId(13));
+        assert!((hit.distance - (30.0 * 2f32.sqrt() - 6.0)).abs() < 1e-3);
+
+        // Straight down finds nothing; a zero direction is no ray
+        assert_eq!(
+            index.raycast(HALL, Vec3::ZERO, Vec3::NEG_Z, 50.0, all),
+            None
+        );
+        assert_eq!(index.raycast(HALL, Vec3::ZERO, Vec3::ZERO, 50.0, all), None);
+
+        index.remove(ObjectId(10));
+        let hit = index.raycast(HALL, Vec3::ZERO, FORWARD, 50.0, all);
+        assert_eq!(hit.map(|hit| hit.id), Some(ObjectId(11)));
+    }
+
+    #[test]
+    fn test_overlap_and_nearest() {
+        let mut index = index();
+        let all = |_| true;
+
+        assert_eq!(
+            index.overlap_sphere(HALL, Vec3::ZERO, 9.5, all),
+            [ObjectId(10), ObjectId(12)]
+        );
+        assert_eq!(
+            index.overlap_box(
+                HALL,
+                Vec3::new(-1.0, 0.0, -1.0),
+                Vec3::new(4.0, 9.2, 1.0),
+                all
+            ),
+            [ObjectId(10), ObjectId(12)]
+        );
+        assert_eq!(
+            index.overlap_box(
+                HALL,
+                Vec3::new(-26.0, -26.0, -1.0),
+                Vec3::new(-24.0, -24.0, 1.0),
+                all
+            ),
+            [ObjectId(13)]
+        );
+        assert_eq!(
+            index.overlap_sphere(YARD, Vec3::ZERO, 9.5, all),
+            [ObjectId(20)]
+        );
+
+        let nearest: Vec<ObjectId> = index
+            .nearest(HALL, Vec3::ZERO, 3, MAX_RANGE, all)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(nearest, [ObjectId(12), ObjectId(10), ObjectId(11)]);
+        let nearest = index.nearest(HALL, Vec3::ZERO, 10, 15.0, |id| id != ObjectId(12));
+        assert_eq!(nearest, [(ObjectId(10), 9.0)]);
+
+        // Moving an object moves it between columns
+        place(&mut index, HALL, 11, Vec3::new(1.0, 1.0, 0.0), 1.0);
+        assert_eq!(
+            index.nearest(HALL, Vec3::ZERO, 1, MAX_RANGE, all)[0].0,
+            ObjectId(11)
+        );
+        assert!(index
+            .overlap_sphere(HALL, Vec3::new(0.0, 20.0, 0.0), 1.0, all)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_huge_spheres_span_bounded_columns() {
+        let mut index = SpatialIndex::default();
+        let side = (2.0 * MAX_COLLIDER_RADIUS / CELL_SIZE) as usize + 2;
+        for radius in [1e30, f32::INFINITY, f32::NAN] {
+            place(&mut index, HALL, 30, Vec3::new(1e9, -1e9, 0.0), radius);
+            assert!(index.grid.len() <= side * side, "{}", index.grid.len());
+            index.remove(ObjectId(30));
+            assert!(index.grid.is_empty());
+        }
+
+        // Still found where it stands
+        place(&mut index, HALL, 31, Vec3::ZERO, 1e30);
+        assert_eq!(
+            index.overlap_sphere(HALL, Vec3::ZERO, 1.0, |_| true),
+            [ObjectId(31)]
+        );
+    }
+}
@@ -3,13 +3,29 @@
 //! Systems are functions that operate on components. Systems defined here
 //! run on BOTH client and server to ensure deterministic behavior.
 //!
+//! Player movement is the first: the server applies each input a client
+//! sends with [`apply_input`], and the client runs the very same function
+//! to predict where its avatar will be (see [`crate::prediction`]). Given
+//! the same inputs from the same state, both get bit-identical results.
+//!
 //! # Learning Note
 //! In networked games, certain logic must be identical on client and server
 //! (like physics simulation). Sharing systems ensures consistency and is
 //! a great way to learn about code reuse in Rust.
 
-// TODO: Define shared systems here
-// Example:
-// pub fn movement_system(query: Query<(&Velocity, &mut Position)>) {
-//     // Movement logic that runs identically on both client and server
-// }
+use crate::components::{Position, Velocity};
+use crate::physics::{MAX_VELOCITY, PHYSICS_TIMESTEP};
+use crate::protocol::MoveInput;
+
+/// Walking speed, in world units per second
+pub const MOVE_SPEED: f32 = 5.0;
+
+/// Advance a player by one fixed tick of `input`
+///
+/// The input sets the velocity directly; there is no acceleration, so a
+/// released key stops the player on the next tick.
+pub fn apply_input(position: &mut Position, velocity: &mut Velocity, input: MoveInput) {
+    let speed = MOVE_SPEED.min(MAX_VELOCITY);
+    velocity.0 = input.direction().extend(0.0) * speed;
+    position.0 += velocity.0 * PHYSICS_TIMESTEP;
+}
@@ -0,0 +1,345 @@
+//! Simulated Players
+//!
+//! Each bot is a headless game client, made of `client::NetClientPlugin`
+//! plus [`BotPlugin`]. It runs as its own Bevy app on its own thread, and
+//! it plays roughly like a person would:
+//!
+//! 1. connect to graphics-gateway over UDP and log in with its token
+//! 2. depending on its [`Behavior`], wander around (a new direction, or
+//!    standing still, every few seconds) and/or chat (a console command
+//!    every few seconds)
+//! 3. stop after the test's duration and hand back its [`BotStats`]
+//!
+//! It measures two latencies:
+//!
+//! - **command**: from sending a console line until its reply arrives.
+//!   Replies come back in order on the reliable channel, so the oldest
+//!   unanswered line is the one answered.
+//! - **input**: from predicting a movement tick until a snapshot confirms
+//!   world-state applied it.
+
+use bevy::app::{AppExit, ScheduleRunnerPlugin};
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use client::{LocalInput, NetClientPlugin, Prediction, SessionToken};
+use lightyear::prelude::client::{ConnectionManager, DisconnectEvent, MessageEvent};
+use shared::net::ReliableChannel;
+use shared::physics::PHYSICS_TIMESTEP;
+use shared::protocol::{ConsoleInput, ConsoleOutput, LastInput, MoveInput};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// How long a bot may take to connect and log in
+pub const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a console line may go unanswered before it counts as lost
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time between chat lines, before jitter
+pub const CHAT_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Time between changes of direction, before jitter
+pub const WANDER_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Predicted ticks remembered while waiting for confirmation
+const SENT_TICKS: usize = 256;
+
+/// Things bots say, in turn
+const CHAT_LINES: [&str; 4] = [
+    "Hello there!",
+    "Anyone around?",
+    "Nice weather in Room Zero.",
+    "Just passing through.",
+];
+
+/// What a bot does once logged in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Behavior {
+    /// Walks around and never talks
+    Wander,
+    /// Stands still and talks
+    Chat,
+    /// Does both
+    Both,
+}
+
+impl Behavior {
+    /// Behaviors handed out to bots in turn
+    pub const ALL: [Self; 3] = [Self::Wander, Self::Chat, Self::Both];
+
+    fn moves(self) -> bool {
+        matches!(self, Self::Wander | Self::Both)
+    }
+
+    fn chats(self) -> bool {
+        matches!(self, Self::Chat | Self::Both)
+    }
+}
+
+/// Something that went wrong for a bot
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BotError {
+    /// The gateway refused the session token
+    LoginRefused,
+    /// Not logged in within [`LOGIN_TIMEOUT`]
+    LoginTimeout,
+    /// The connection dropped during the test
+    Disconnected,
+    /// A console line got no reply within [`REPLY_TIMEOUT`]
+    ReplyTimeout,
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LoginRefused => write!(f, "login refused"),
+            Self::LoginTimeout => write!(f, "login timed out"),
+            Self::Disconnected => write!(f, "disconnected"),
+            Self::ReplyTimeout => write!(f, "reply timed out"),
+        }
+    }
+}
+
+/// What one bot measured
+#[derive(Resource, Clone, Debug, Default)]
+pub struct BotStats {
+    /// Whether it ever logged in
+    pub logged_in: bool,
+    /// Console round trips
+    pub command_latency: Vec<Duration>,
+    /// Movement confirmations
+    pub input_latency: Vec<Duration>,
+    /// Errors by kind
+    pub errors: BTreeMap<BotError, u64>,
+}
+
+impl BotStats {
+    fn error(&mut self, error: BotError) {
+        *self.errors.entry(error).or_default() += 1;
+    }
+}
+
+/// One bot's settings
+#[derive(Clone, Debug)]
+pub struct BotConfig {
+    /// graphics-gateway's UDP address
+    pub server_addr: SocketAddr,
+    /// Netcode client id, unique among the bots
+    pub client_id: u64,
+    /// Session token for the bot's player
+    pub token: String,
+    /// What it does
+    pub behavior: Behavior,
+    /// Console line sent to chat, with `{}` replaced by what is said
+    pub chat_command: String,
+    /// How long it plays
+    pub duration: Duration,
+}
+
+/// Play as one bot until the test is over, returning what it measured
+pub fn run(config: BotConfig) -> BotStats {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f32(
+            PHYSICS_TIMESTEP,
+        ))),
+        StatesPlugin,
+    ))
+    .add_plugins(NetClientPlugin {
+        server_addr: config.server_addr,
+        client_id: config.client_id,
+    })
+    .insert_resource(SessionToken(config.token.clone()))
+    .add_plugins(BotPlugin(config));
+    app.run();
+    app.world_mut()
+        .remove_resource::<BotStats>()
+        .unwrap_or_default()
+}
+
+/// Scripted behavior and measurements for a headless client
+pub struct BotPlugin(pub BotConfig);
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        let now = Instant::now();
+        app.insert_resource(Bot {
+            behavior: self.0.behavior,
+            chat_command: self.0.chat_command.clone(),
+            started: now,
+            until: now + self.0.duration,
+            rng: Rng(self.0.client_id | 1),
+            next_chat: now,
+            next_turn: now,
+            said: 0,
+            unanswered: VecDeque::new(),
+            sent: VecDeque::new(),
+        })
+        .init_resource::<BotStats>()
+        .add_systems(
+            Update,
+            (
+                read_replies,
+                confirm_inputs,
+                (wander, chat, expire),
+                watch_connection,
+            )
+                .chain(),
+        )
+        .add_systems(FixedUpdate, track_inputs);
+    }
+}
+
+#[derive(Resource)]
+struct Bot {
+    behavior: Behavior,
+    chat_command: String,
+    started: Instant,
+    until: Instant,
+    rng: Rng,
+    next_chat: Instant,
+    next_turn: Instant,
+    /// Chat lines sent so far
+    said: usize,
+    /// When each unanswered console line was sent, oldest first
+    unanswered: VecDeque<Instant>,
+    /// Predicted ticks and when they were predicted, oldest first
+    sent: VecDeque<(u32, Instant)>,
+}
+
+/// xorshift64; bots only need to look a little random
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// `base`, give or take half of it
+    fn jitter(&mut self, base: Duration) -> Duration {
+        base / 2 + base.mul_f64((self.next() % 1000) as f64 / 1000.0)
+    }
+
+    fn axis(&mut self) -> i8 {
+        (self.next() % 3) as i8 - 1
+    }
+}
+
+fn read_replies(
+    mut output: EventReader<MessageEvent<ConsoleOutput>>,
+    mut bot: ResMut<Bot>,
+    mut stats: ResMut<BotStats>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for message in output.read() {
+        let text = &message.message().0;
+        if !stats.logged_in {
+            if text.starts_with("Logged in as") {
+                stats.logged_in = true;
+            } else {
+                warn!("Login refused: {}", text);
+                stats.error(BotError::LoginRefused);
+                exit.send(AppExit::Success);
+            }
+            continue;
+        }
+        if let Some(sent) = bot.unanswered.pop_front() {
+            stats.command_latency.push(sent.elapsed());
+        }
+    }
+}
+
+/// Remember when each tick was predicted
+fn track_inputs(mut bot: ResMut<Bot>, avatars: Query<&Prediction>) {
+    let Ok(prediction) = avatars.get_single() else {
+        return;
+    };
+    let tick = prediction.0.tick();
+    if bot.sent.back().is_none_or(|&(last, _)| tick > last) {
+        if bot.sent.len() == SENT_TICKS {
+            bot.sent.pop_front();
+        }
+        bot.sent.push_back((tick, Instant::now()));
+    }
+}
+
+fn confirm_inputs(
+    mut bot: ResMut<Bot>,
+    mut stats: ResMut<BotStats>,
+    avatars: Query<&LastInput, (With<Prediction>, Changed<LastInput>)>,
+) {
+    for confirmed in &avatars {
+        while let Some(&(tick, sent)) = bot.sent.front() {
+            if tick > confirmed.0 {
+                break;
+            }
+            bot.sent.pop_front();
+            if tick == confirmed.0 {
+                stats.input_latency.push(sent.elapsed());
+            }
+        }
+    }
+}
+
+fn wander(mut bot: ResMut<Bot>, stats: Res<BotStats>, mut input: ResMut<LocalInput>) {
+    let now = Instant::now();
+    if !stats.logged_in || !bot.behavior.moves() || now < bot.next_turn {
+        return;
+    }
+    input.0 = MoveInput {
+        x: bot.rng.axis(),
+        y: bot.rng.axis(),
+    };
+    bot.next_turn = now + bot.rng.jitter(WANDER_INTERVAL);
+}
+
+fn chat(mut bot: ResMut<Bot>, stats: Res<BotStats>, mut connection: ResMut<ConnectionManager>) {
+    let now = Instant::now();
+    if !stats.logged_in || !bot.behavior.chats() || now < bot.next_chat {
+        return;
+    }
+    let line = CHAT_LINES[bot.said % CHAT_LINES.len()];
+    let mut message = ConsoleInput(bot.chat_command.replace("{}", line));
+    match connection.send_message::<ReliableChannel, _>(&mut message) {
+        Ok(()) => bot.unanswered.push_back(now),
+        Err(e) => warn!("Failed to send console line: {}", e),
+    }
+    bot.said += 1;
+    bot.next_chat = now + bot.rng.jitter(CHAT_INTERVAL);
+}
+
+/// Give up on replies that took too long
+fn expire(mut bot: ResMut<Bot>, mut stats: ResMut<BotStats>) {
+    while bot
+        .unanswered
+        .front()
+        .is_some_and(|sent| sent.elapsed() > REPLY_TIMEOUT)
+    {
+        bot.unanswered.pop_front();
+        stats.error(BotError::ReplyTimeout);
+    }
+}
+
+/// Stop on disconnects, failed logins and at the end of the test
+fn watch_connection(
+    mut disconnects: EventReader<DisconnectEvent>,
+    bot: Res<Bot>,
+    mut stats: ResMut<BotStats>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let now = Instant::now();
+    if disconnects.read().next().is_some() {
+        stats.error(BotError::Disconnected);
+        exit.send(AppExit::Success);
+    } else if !stats.logged_in && now - bot.started > LOGIN_TIMEOUT {
+        stats.error(BotError::LoginTimeout);
+        exit.send(AppExit::Success);
+    } else if now >= bot.until {
+        exit.send(AppExit::Success);
+    }
+}
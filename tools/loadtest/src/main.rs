@@ -0,0 +1,138 @@
+//! Load Tester - simulated players against a running world
+//!
+//! Starts many headless bot clients against graphics-gateway, lets them
+//! wander and chat for a while, and reports command and input latency,
+//! errors, and whether world-state's tick kept up. A run goes:
+//!
+//! 1. create one player per bot through world-state (see `players`)
+//! 2. start the bots a little apart, each on its own thread (see `bot`)
+//! 3. wait for them all, then destroy their players and print the
+//!    [`report::Report`]
+//!
+//! Configuration:
+//! - `BOTS`: number of bots (default 10)
+//! - `DURATION_SECS`: how long each bot plays (default 60)
+//! - `RAMP_UP_SECS`: time over which bots are started (default 5)
+//! - `GAME_ADDR`: graphics-gateway's UDP address (default `127.0.0.1:5000`)
+//! - `WORLD_STATE_URL`: world-state's HTTP API (default `http://localhost:8080`)
+//! - `SESSION_SECRET`: secret session tokens are signed with (a
+//!   development secret if unset; must match graphics-gateway's)
+//! - `WIZARD_ID`: wizard that creates and destroys bot players (default 1)
+//! - `BOT_PREFIX`: bot player names are this plus a number (default `bot`)
+//! - `CHAT_COMMAND`: console line bots chat with, `{}` standing for what
+//!   they say (default `&SAID me = {}`)
+//!
+//! # Learning Note
+//! Each bot is a whole Bevy app, and `App::run` blocks until it exits,
+//! so bots get plain threads rather than tokio tasks. The async runtime
+//! only does the HTTP calls around them.
+
+use shared::auth::{self, DEV_SESSION_SECRET};
+use shared::protocol::DEFAULT_PORT;
+use shared::ObjectId;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+mod bot;
+mod players;
+mod report;
+
+use bot::{Behavior, BotConfig};
+use players::WorldAdmin;
+use report::Report;
+
+/// Extra time bot tokens stay valid past the end of the test
+const TOKEN_MARGIN: Duration = Duration::from_secs(300);
+
+/// Netcode client ids given to bots start here, clear of real clients
+const FIRST_CLIENT_ID: u64 = 1 << 32;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "loadtest=info,warn".into()),
+        )
+        .init();
+
+    dotenvy::dotenv().ok();
+
+    let bots: usize = env_or("BOTS", 10);
+    let duration = Duration::from_secs(env_or("DURATION_SECS", 60));
+    let ramp_up = Duration::from_secs(env_or("RAMP_UP_SECS", 5));
+    let server_addr = env_or(
+        "GAME_ADDR",
+        SocketAddr::from(([127, 0, 0, 1], DEFAULT_PORT)),
+    );
+    let world_url =
+        std::env::var("WORLD_STATE_URL").unwrap_or_else(|_| "http://localhost:8080".to_string());
+    let secret = match std::env::var("SESSION_SECRET") {
+        Ok(secret) => secret.into_bytes(),
+        Err(_) => DEV_SESSION_SECRET.to_vec(),
+    };
+    let wizard = ObjectId(env_or("WIZARD_ID", 1));
+    let prefix = std::env::var("BOT_PREFIX").unwrap_or_else(|_| "bot".to_string());
+    let chat_command =
+        std::env::var("CHAT_COMMAND").unwrap_or_else(|_| "&SAID me = {}".to_string());
+
+    info!(
+        "🚀 Load test: {} bots for {:?} against {}",
+        bots, duration, server_addr
+    );
+
+    let admin = WorldAdmin::new(&world_url, wizard);
+    let names: Vec<String> = (0..bots).map(|i| format!("{}{}", prefix, i)).collect();
+    let players = admin.create_players(&names).await?;
+    info!("Created {} bot players", players.len());
+
+    let before = admin.stats().await?;
+    let expires_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()
+        + (ramp_up + duration + bot::LOGIN_TIMEOUT + TOKEN_MARGIN).as_secs();
+    let started = Instant::now();
+    let mut handles = Vec::with_capacity(bots);
+    for (i, &player) in players.iter().enumerate() {
+        let config = BotConfig {
+            server_addr,
+            client_id: FIRST_CLIENT_ID + i as u64,
+            token: auth::issue(&secret, player, expires_at),
+            behavior: Behavior::ALL[i % Behavior::ALL.len()],
+            chat_command: chat_command.clone(),
+            duration,
+        };
+        handles.push(std::thread::spawn(move || bot::run(config)));
+        tokio::time::sleep(ramp_up / bots.max(1) as u32).await;
+    }
+
+    let stats = tokio::task::spawn_blocking(move || {
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().ok())
+            .collect::<Vec<_>>()
+    })
+    .await?;
+    if stats.len() < bots {
+        warn!("{} bots panicked", bots - stats.len());
+    }
+    let after = admin.stats().await?;
+    let report = Report::new(&stats, started.elapsed(), before, after);
+
+    match admin.destroy_players(&players).await {
+        Ok(0) => info!("Destroyed {} bot players", players.len()),
+        Ok(failed) => warn!("{} bot players could not be destroyed", failed),
+        Err(e) => warn!("Failed to destroy bot players: {}", e),
+    }
+
+    println!("{}", report);
+    Ok(())
+}
+
+/// The variable `name` parsed as `T`, or `default` if unset or invalid
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
@@ -0,0 +1,152 @@
+//! Bot Players
+//!
+//! Bots need player objects to log in as. Before the test, one wizard
+//! creates them all through world-state's `POST /commands` with
+//! `@pcreate`; afterwards the same wizard `@destroy`s them again, so
+//! repeated runs don't pile up players. The tick statistics the report
+//! compares come from `GET /stats` on the same API.
+
+use shared::gateway::{CommandReplies, CommandReply, TickStats, WorldCommand};
+use shared::ObjectId;
+use std::fmt;
+use std::time::Duration;
+
+/// How long to wait for world-state before giving up on a request
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Why bot players could not be set up
+#[derive(Debug)]
+pub enum SetupError {
+    /// World-state could not be reached or answered with an error
+    Http(reqwest::Error),
+    /// World-state answered a different number of commands than were sent
+    MissingReplies(usize, usize),
+    /// A player could not be created
+    Refused(String),
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(e) => write!(f, "world-state request failed: {}", e),
+            Self::MissingReplies(sent, got) => {
+                write!(f, "sent {} commands but got {} replies", sent, got)
+            }
+            Self::Refused(reply) => write!(f, "could not create a bot player: {}", reply),
+        }
+    }
+}
+
+impl std::error::Error for SetupError {}
+
+impl From<reqwest::Error> for SetupError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+/// The id in `@create`'s reply, "Created: Name(#N)."
+pub fn created_id(reply: &str) -> Option<ObjectId> {
+    let id = reply.strip_prefix("Created: ")?.rsplit_once("(#")?.1;
+    id.strip_suffix(").")?.parse().ok().map(ObjectId)
+}
+
+/// World-state's HTTP API, acting as one wizard
+pub struct WorldAdmin {
+    client: reqwest::Client,
+    base_url: String,
+    wizard: ObjectId,
+}
+
+impl WorldAdmin {
+    /// Talk to world-state at `base_url` as `wizard`
+    pub fn new(base_url: &str, wizard: ObjectId) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            wizard,
+        }
+    }
+
+    /// Create a player for each name, in one batch
+    pub async fn create_players(&self, names: &[String]) -> Result<Vec<ObjectId>, SetupError> {
+        let lines = names.iter().map(|name| format!("@pcreate {}", name));
+        let replies = self.run(lines).await?;
+        replies
+            .iter()
+            .map(|reply| match reply {
+                CommandReply::Output(text) => {
+                    created_id(text).ok_or_else(|| SetupError::Refused(text.clone()))
+                }
+                CommandReply::Done => Err(SetupError::Refused("no reply".to_string())),
+                CommandReply::Failed(text) => Err(SetupError::Refused(text.clone())),
+            })
+            .collect()
+    }
+
+    /// Destroy the given players, returning how many could not be
+    pub async fn destroy_players(&self, players: &[ObjectId]) -> Result<usize, SetupError> {
+        let lines = players.iter().map(|player| format!("@destroy {}", player));
+        let replies = self.run(lines).await?;
+        Ok(replies
+            .iter()
+            .filter(|reply| matches!(reply, CommandReply::Failed(_)))
+            .count())
+    }
+
+    /// World-state's tick statistics so far
+    pub async fn stats(&self) -> Result<TickStats, reqwest::Error> {
+        self.client
+            .get(format!("{}/stats", self.base_url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn run(
+        &self,
+        lines: impl Iterator<Item = String>,
+    ) -> Result<Vec<CommandReply>, SetupError> {
+        let commands: Vec<WorldCommand> = lines
+            .map(|line| WorldCommand::Console {
+                actor: self.wizard,
+                line,
+            })
+            .collect();
+        let replies: CommandReplies = self
+            .client
+            .post(format!("{}/commands", self.base_url))
+            .json(&commands)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if replies.replies.len() != commands.len() {
+            return Err(SetupError::MissingReplies(
+                commands.len(),
+                replies.replies.len(),
+            ));
+        }
+        Ok(replies.replies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_created_id() {
+        assert_eq!(created_id("Created: bot-3(#42)."), Some(ObjectId(42)));
+        assert_eq!(created_id("Created: odd (#1) name(#7)."), Some(ObjectId(7)));
+        assert_eq!(created_id("Permission denied."), None);
+        assert_eq!(created_id("Created: bot(#x)."), None);
+    }
+}
@@ -0,0 +1,172 @@
+//! Load Test Report
+//!
+//! Adds up what every bot measured, plus world-state's tick statistics
+//! from before and after the run, into one [`Report`] to print.
+
+use shared::gateway::TickStats;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::bot::{BotError, BotStats};
+
+/// Latency samples from every bot
+#[derive(Debug, Default)]
+pub struct Samples(Vec<Duration>);
+
+impl Samples {
+    /// The sample `p` percent of the way up, by nearest rank, or zero
+    /// without samples
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.0.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (p / 100.0 * self.0.len() as f64).ceil() as usize;
+        self.0[rank.clamp(1, self.0.len()) - 1]
+    }
+
+    fn extend(&mut self, samples: &[Duration]) {
+        self.0.extend_from_slice(samples);
+        self.0.sort();
+    }
+}
+
+impl fmt::Display for Samples {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |p| self.percentile(p).as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{:>6} samples  p50 {:>7.1} ms  p90 {:>7.1} ms  p99 {:>7.1} ms  max {:>7.1} ms",
+            self.0.len(),
+            ms(50.0),
+            ms(90.0),
+            ms(99.0),
+            ms(100.0)
+        )
+    }
+}
+
+/// The outcome of a load test
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Bots started
+    pub bots: usize,
+    /// Bots that logged in
+    pub logged_in: usize,
+    /// How long the bots played
+    pub duration: Duration,
+    /// Console round trips
+    pub command_latency: Samples,
+    /// Movement confirmations
+    pub input_latency: Samples,
+    /// Errors by kind, across all bots
+    pub errors: BTreeMap<BotError, u64>,
+    /// World-state's tick statistics over the run; `max_delay_ms` is the
+    /// worst since it started
+    pub ticks: TickStats,
+}
+
+impl Report {
+    /// Add up every bot's measurements and the change in tick statistics
+    pub fn new(bots: &[BotStats], duration: Duration, before: TickStats, after: TickStats) -> Self {
+        let mut report = Self {
+            bots: bots.len(),
+            duration,
+            ticks: TickStats {
+                ticks: after.ticks.saturating_sub(before.ticks),
+                overruns: after.overruns.saturating_sub(before.overruns),
+                max_delay_ms: after.max_delay_ms,
+            },
+            ..Self::default()
+        };
+        for bot in bots {
+            report.logged_in += bot.logged_in as usize;
+            report.command_latency.extend(&bot.command_latency);
+            report.input_latency.extend(&bot.input_latency);
+            for (error, count) in &bot.errors {
+                *report.errors.entry(*error).or_default() += count;
+            }
+        }
+        report
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} bots for {:.0} s, {} logged in",
+            self.bots,
+            self.duration.as_secs_f64(),
+            self.logged_in
+        )?;
+        writeln!(f, "command latency {}", self.command_latency)?;
+        writeln!(f, "input latency   {}", self.input_latency)?;
+        if self.errors.is_empty() {
+            writeln!(f, "errors          none")?;
+        }
+        for (error, count) in &self.errors {
+            writeln!(f, "errors          {:>6} {}", count, error)?;
+        }
+        write!(
+            f,
+            "world-state     {} ticks, {} overruns, worst delay {} ms",
+            self.ticks.ticks, self.ticks.overruns, self.ticks.max_delay_ms
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_report_adds_up_bots() {
+        let bots = [
+            BotStats {
+                logged_in: true,
+                command_latency: (1..=50).map(ms).collect(),
+                input_latency: vec![ms(30)],
+                errors: BTreeMap::from([(BotError::ReplyTimeout, 2)]),
+            },
+            BotStats {
+                logged_in: true,
+                command_latency: (51..=100).map(ms).collect(),
+                input_latency: Vec::new(),
+                errors: BTreeMap::from([(BotError::ReplyTimeout, 1)]),
+            },
+            BotStats {
+                errors: BTreeMap::from([(BotError::LoginTimeout, 1)]),
+                ..BotStats::default()
+            },
+        ];
+        let before = TickStats {
+            ticks: 100,
+            overruns: 1,
+            max_delay_ms: 150,
+        };
+        let after = TickStats {
+            ticks: 700,
+            overruns: 4,
+            max_delay_ms: 320,
+        };
+        let report = Report::new(&bots, Duration::from_secs(60), before, after);
+
+        assert_eq!((report.bots, report.logged_in), (3, 2));
+        assert_eq!(report.command_latency.percentile(50.0), ms(50));
+        assert_eq!(report.command_latency.percentile(99.0), ms(99));
+        assert_eq!(report.command_latency.percentile(100.0), ms(100));
+        assert_eq!(report.input_latency.percentile(0.0), ms(30));
+        assert_eq!(Samples::default().percentile(50.0), Duration::ZERO);
+        assert_eq!(
+            report.errors,
+            BTreeMap::from([(BotError::LoginTimeout, 1), (BotError::ReplyTimeout, 3)])
+        );
+        assert_eq!((report.ticks.ticks, report.ticks.overruns), (600, 3));
+        assert!(report.to_string().contains("600 ticks, 3 overruns"));
+    }
+}